@@ -0,0 +1,43 @@
+// Throughput of evaluation and solving on the standard models, with
+// criterion. Run with `cargo bench`, or `cargo bench --features rayon` to
+// measure the parallel sweeps.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use complete_iter::bench::{standard_models, BenchModel};
+use complete_iter::Agent;
+
+const SWEEPS: u32 = 10;
+
+fn evaluation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("evaluation");
+
+    for model in standard_models(10_000) {
+        let mut agent: Agent = Agent::init_random(model.build());
+        let n_states = agent.get_evaluation().len();
+        agent.evaluate_policy(0.9, 0., 1);
+
+        group.throughput(Throughput::Elements((n_states as u64)*(SWEEPS as u64)));
+        group.bench_function(BenchmarkId::from_parameter(model.name()), |b| b.iter(|| agent.evaluate_policy(0.9, 0., SWEEPS)));
+    }
+
+    group.finish();
+}
+
+fn solve(c: &mut Criterion) {
+    let mut group = c.benchmark_group("solve");
+    group.sample_size(10);
+
+    for model in [BenchModel::Grid(30, 30), BenchModel::Gambler(500)] {
+        group.bench_function(BenchmarkId::from_parameter(model.name()), |b| b.iter_batched(
+            || Agent::init_random(model.build()),
+            |mut agent: Agent| agent.deterministic_policy_improvement(0.9, 1e-6, 100, 1000),
+            criterion::BatchSize::LargeInput,
+        ));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, evaluation, solve);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+/*
+    Runs the HTTP solver service
+
+    cargo run --example solver_service --features http -- 127.0.0.1:8080
+*/
+
+use std::env;
+use std::sync::Arc;
+
+use complete_iter::service::SolverService;
+
+fn main() {
+
+    let addr = env::args().nth(1).unwrap_or("127.0.0.1:8080".to_string());
+    println!("Solver service listening on {}", addr);
+
+    if let Err(error) = Arc::new(SolverService::new()).serve(&addr) {
+        eprintln!("Service stopped: {}", error);
+    }
+
+}
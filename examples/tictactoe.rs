@@ -2,9 +2,13 @@
     Game TicTacToe
 */
 
-use std::io;
+use std::io::BufRead;
+use std::{fmt, io};
 
-use complete_iter::{models, Agent};
+use complete_iter::envs::Environment;
+use complete_iter::games::{self, Outcome, Player, TurnBasedGame, UniformOpponent};
+use complete_iter::Agent;
+use rand::Rng;
 
 #[derive(Copy, Clone)]
 enum  Mark {
@@ -24,29 +28,13 @@ impl Mark {
     }
     
     fn is_equal(&self, other: Mark) -> bool {
-        match self {
-            Mark::Cross => {
-                match other {
-                    Mark::Cross => true,
-                    _ => false
-                }
-            },
-            Mark::Circle => {
-                match other {
-                    Mark::Circle => true,
-                    _ => false
-                }
-            },
-            Mark::Empty => {
-                match other {
-                    Mark::Empty => true,
-                    _ => false
-                }
-            }
-        }
+        matches!(
+            (self, other),
+            (Mark::Cross, Mark::Cross) | (Mark::Circle, Mark::Circle) | (Mark::Empty, Mark::Empty)
+        )
     }
     
-    fn to_string(&self) -> String {
+    fn symbol(self) -> String {
         match self {
             Mark::Cross => "X".to_string(),
             Mark::Circle => "O".to_string(),
@@ -74,6 +62,7 @@ impl Mark {
     
 }
 
+#[derive(Clone)]
 struct TicTacBoard{
     board: [[Mark; 3]; 3],
     actions: [String; 9]
@@ -113,16 +102,15 @@ impl TicTacBoard {
                 _ => ()
             };
 
-            remaining = remaining / 3;
+            remaining /= 3;
             game.apply_action(&action, Mark::from_id(cell_id));
         }
 
-        let player;
-        if n_circle > n_cross {
-            player = Mark::Cross;
+        let player = if n_circle > n_cross {
+            Mark::Cross
         } else {
-            player = Mark::Circle;
-        }
+            Mark::Circle
+        };
 
         return (game, player)
 
@@ -158,13 +146,13 @@ impl TicTacBoard {
         return output
     }
 
-    pub fn apply_action(&mut self, action: &String, player: Mark) {
+    pub fn apply_action(&mut self, action: &str, player: Mark) {
         let row: usize = action[1..2].parse().unwrap();
         let col: usize = action[3..4].parse().unwrap();
         self.board[row][col] = player;
     }
 
-    pub fn roll_back(&mut self, action: &String) {
+    pub fn roll_back(&mut self, action: &str) {
         let row: usize = action[1..2].parse().unwrap();
         let col: usize = action[3..4].parse().unwrap();
         self.board[row][col] = Mark::Empty;
@@ -205,12 +193,24 @@ impl TicTacBoard {
 
     }
 
-    // Prints the current state of the game
-    pub fn to_string(&self) {
+    // Circles play first, so it is their turn when the counts are equal
+    fn current_mark(&self) -> Mark {
+        let n_circle = self.board.iter().flatten().filter(|cell| cell.is_equal(Mark::Circle)).count();
+        let n_cross = self.board.iter().flatten().filter(|cell| cell.is_equal(Mark::Cross)).count();
+
+        return if n_circle > n_cross {Mark::Cross} else {Mark::Circle}
+    }
+
+
+}
+
+impl fmt::Display for TicTacBoard {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut print_str = String::new();
         for i in 0..3 {
             for j in 0..3 {
-                print_str.push_str(&self.board[i][j].to_string());
+                print_str.push_str(&self.board[i][j].symbol());
                 
                 if j < 2 {
                     print_str.push_str(" | ");
@@ -221,203 +221,197 @@ impl TicTacBoard {
                 print_str.push_str("\n---------\n");
             }
         }
-        println!("\n{}\n", print_str);
+        return write!(f, "{}", print_str)
     }
 
 }
 
-fn main() {
-
-    let mut ids_seen: Vec<i64> = Vec::new();
-    let mut ids_done: Vec<i64> = Vec::new();
-    let mut links: Vec<models::StateLink> = Vec::new();
+impl TurnBasedGame for TicTacBoard {
 
-    ids_seen.push(0);
-
-    loop {
-        let id = match ids_seen.pop() {
-            Some(number) => number,
-            None => break
-        };
+    type Move = String;
 
-        if ids_done.contains(&id) {
-            continue;
+    fn current_player(&self) -> Player {
+        match self.current_mark() {
+            Mark::Circle => Player::First,
+            _ => Player::Second
         }
-
-        let (mut game, player) = TicTacBoard::from_id(id);
-        add_links(&mut game, &mut links, player, &mut ids_seen);
-
-        ids_done.push(id);
     }
 
-    let tic_tac_state = models::SystemState::create_and_build(links);
-    let mut tic_tac_agent = Agent::init_random(tic_tac_state);
-    tic_tac_agent.deterministic_policy_improvement(1., 0.01, 100, 100);
-
-    /*
-    // Let's see the AI play
-
-    let mut game = TicTacBoard::new();
-    let mut player = Mark::Circle;
-
-    game.to_string();
-
-    loop {
-
-        let next_action = match tic_tac_agent.get_best_action(game.get_state_id()) {
-            Some((action, _)) => action,
-            None => break,
-        };
+    fn legal_moves(&self) -> Vec<String> {
+        if self.is_terminal() {
+            return Vec::new()
+        }
+        return self.possible_actions()
+    }
 
-        game.apply_action(next_action, player);
-        
-        player = player.flip();
+    fn apply(&mut self, game_move: &String) {
+        let player = self.current_mark();
+        self.apply_action(game_move, player);
+    }
 
-        game.to_string();
+    fn undo(&mut self, game_move: &String) {
+        self.roll_back(game_move);
+    }
 
-        println!("X has won: {}", game.has_won(Mark::Cross));
-        println!("O has won: {}\n", game.has_won(Mark::Circle));
+    fn is_terminal(&self) -> bool {
+        return self.outcome().is_some()
+    }
 
+    fn outcome(&self) -> Option<Outcome> {
+        if self.has_won(Mark::Circle) {
+            Some(Outcome::Win(Player::First))
+        } else if self.has_won(Mark::Cross) {
+            Some(Outcome::Win(Player::Second))
+        } else if self.possible_actions().is_empty() {
+            Some(Outcome::Draw)
+        } else {
+            None
+        }
     }
-    */
 
-    play_with_agent(&tic_tac_agent);
+    fn state_id(&self) -> i64 {
+        return self.get_state_id()
+    }
 
 }
 
-// Considers a random adversary policy
-fn add_links(game: &mut TicTacBoard, links: &mut Vec<models::StateLink>, player: Mark, ids_seen: &mut Vec<i64>) {
-
-    if game.has_won(player) || game.has_won(player.flip()) {
-        return;
-    }
-    
-    let id_prev = game.get_state_id();
-    let actions = game.possible_actions();
-
-    for action_player in &actions {
+// The game as seen by the bot: it plays circles against a random adversary,
+// the same adversary its model is built with
+struct TicTacToeEnv {
+    game: TicTacBoard,
+    rng: rand::rngs::ThreadRng,
+}
 
-        game.apply_action(action_player, player);
-        let id_next = game.get_state_id();
-        ids_seen.push(id_next);
+impl Environment for TicTacToeEnv {
 
-        if game.has_won(player) {
-            links.push(models::StateLink(id_prev, id_next, action_player.to_string(), 1., 1.));
+    type State = i64;
+    type Action = String;
 
-        } else {
-
-            for action_adv in &actions {
-                if *action_player != *action_adv {
+    fn reset(&mut self) -> i64 {
+        self.game = TicTacBoard::new();
+        return self.game.get_state_id()
+    }
 
-                    game.apply_action(action_adv, player.flip());
-                    let id_next = game.get_state_id();
-                    let reward = if game.has_won(player.flip()) {-1.} else {0.};
-                    let prob = 1./(actions.len() - 1) as f64;
+    fn step(&mut self, action: String) -> (i64, f64, bool) {
+        let bot = Mark::Circle;
 
-                    links.push(models::StateLink(id_prev, id_next, action_player.to_string(), prob, reward));
+        self.game.apply_action(&action, bot);
+        if self.game.has_won(bot) {
+            return (self.game.get_state_id(), 1., true)
+        }
 
-                    ids_seen.push(id_next);
-                    game.roll_back(action_adv);
-                }
-            }
+        let replies = self.game.possible_actions();
+        if replies.is_empty() {
+            return (self.game.get_state_id(), 0., true)
+        }
 
+        let reply = &replies[self.rng.random_range(0..replies.len())];
+        self.game.apply_action(reply, bot.flip());
+        if self.game.has_won(bot.flip()) {
+            return (self.game.get_state_id(), -1., true)
         }
 
-        game.roll_back(action_player);
+        return (self.game.get_state_id(), 0., self.game.possible_actions().is_empty())
+    }
 
+    fn legal_actions(&self, state: &i64) -> Vec<String> {
+        let (game, _) = TicTacBoard::from_id(*state);
+        if game.has_won(Mark::Circle) || game.has_won(Mark::Cross) {
+            return Vec::new()
+        }
+        return game.possible_actions()
     }
 
 }
 
+fn main() {
 
-pub fn play_with_agent(tic_tac_agent: &Agent) {
-
-    loop {
-        
-        let mut game = TicTacBoard::new();
-        let bot = Mark::Circle;
-        let human = Mark::Cross;
-
-        println!("A new game started against the bot.");
+    // The bot plays circles against a random adversary
+    let tic_tac_state = games::build_model(&TicTacBoard::new(), Player::First, &UniformOpponent);
+    let mut tic_tac_agent = Agent::init_random(tic_tac_state);
+    tic_tac_agent.deterministic_policy_improvement(1., 0.01, 100, 100);
 
-        game.to_string();
+    // Let the bot play the adversary it was trained against
+    let mut env = TicTacToeEnv {game: TicTacBoard::new(), rng: rand::rng()};
+    let n_games = 1000;
+    let (mut wins, mut losses) = (0, 0);
+
+    for _ in 0..n_games {
+        let mut state = env.reset();
+        while let Some((action, _)) = tic_tac_agent.get_best_action(state) {
+            let (next_state, reward, done) = env.step(action.clone());
+            if done {
+                if reward > 0. { wins += 1; }
+                if reward < 0. { losses += 1; }
+                break;
+            }
+            state = next_state;
+        }
+    }
 
-        loop {
+    println!("Against a random adversary the bot won {} of {} games and lost {}.\n", wins, n_games, losses);
 
-            let next_action = match tic_tac_agent.get_best_action(game.get_state_id()) {
-                Some((action, _)) => action,
-                None => break,
-            };
+    /*
+    // Let's see the AI play
 
-            println!("The bot played at {}", next_action);
+    let mut game = TicTacBoard::new();
+    let mut player = Mark::Circle;
 
-            game.apply_action(next_action, bot);
+    println!("\n{}\n", game);
 
-            game.to_string();
+    loop {
 
-            if game.has_won(bot) {
-                println!("\nThe bot won!!");
-                break;
-            }
+        let next_action = match tic_tac_agent.get_best_action(game.get_state_id()) {
+            Some((action, _)) => action,
+            None => break,
+        };
 
-            loop {
-                
-                let possible_actions = game.possible_actions();
+        game.apply_action(next_action, player);
+        
+        player = player.flip();
 
-                println!("\nYour turn, please type one of the following actions: \n{:?}", &possible_actions);
+        println!("\n{}\n", game);
 
-                let mut play = String::new();
+        println!("X has won: {}", game.has_won(Mark::Cross));
+        println!("O has won: {}\n", game.has_won(Mark::Circle));
 
-                match io::stdin().read_line(&mut play) {
-                    Ok(_) => (),
-                    Err(_) => {
-                        println!("Incorrect input, please try again.");
-                        continue
-                    },
-                };
+    }
+    */
 
-                if possible_actions.contains(&play.trim().to_string()) {
+    play_with_agent(&tic_tac_agent);
 
-                    println!("You played at {}", &play);
+}
 
-                    game.apply_action(&play, human);
+pub fn play_with_agent(tic_tac_agent: &Agent) {
 
-                    game.to_string();
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = io::stdout();
 
-                    break;
+    loop {
 
-                } else {
-                    println!("Please try again with a valid play.");
-                }
-            
-            }
+        println!("A new game started against the bot.");
 
-            if game.has_won(human) {
-                println!("\nYou won!!");
-                break;
-            }
+        let mut game = TicTacBoard::new();
+        let outcome = games::play_interactive(tic_tac_agent, &mut game, Player::First, 1., &mut input, &mut output);
 
+        if !matches!(outcome, Ok(Some(_))) {
+            break;
         }
 
-        println!("Eng of game! Wanna play again? (y/n)");
+        println!("End of game! Wanna play again? (y/n)");
 
         let mut answer = String::new();
 
-        match io::stdin().read_line(&mut answer) {
+        match input.read_line(&mut answer) {
             Ok(_) => (),
-            Err(_) => {
-                println!("Incorrect input, please try again.");
-                continue
-            },
+            Err(_) => break,
         };
 
-        if answer.trim() == "y" {
-            continue;
-        } else {
+        if answer.trim() != "y" {
             break;
         }
 
     }
-    
 
 }
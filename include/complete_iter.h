@@ -0,0 +1,103 @@
+#ifndef COMPLETE_ITER_H
+#define COMPLETE_ITER_H
+
+/* Generated with cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define CI_OK 0
+
+#define CI_ERR_NULL -1
+
+#define CI_ERR_INVALID -2
+
+#define CI_ERR_NOT_FOUND -3
+
+#define CI_ERR_PANIC -4
+
+// Opaque handle to an agent, owns its model
+typedef struct CiAgent CiAgent;
+
+// Opaque handle to a built model
+typedef struct CiModel CiModel;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Builds a model from parallel link arrays of length n_links.
+// Returns NULL if a required pointer is NULL or an action is not valid UTF-8.
+//
+// # Safety
+// Every non-NULL pointer must reference n_links readable elements and every
+// action must be a NUL-terminated string.
+struct CiModel *ci_model_new(const int64_t *prev_states,
+                             const int64_t *next_states,
+                             const char *const *actions,
+                             const double *probs,
+                             const double *rewards,
+                             uintptr_t n_links);
+
+// Number of states in the model, 0 for a NULL handle
+//
+// # Safety
+// model must be NULL or a live handle returned by ci_model_new.
+uintptr_t ci_model_n_states(const struct CiModel *model);
+
+// Releases a model that was not handed over to ci_agent_new
+//
+// # Safety
+// model must be NULL or a live handle returned by ci_model_new.
+void ci_model_free(struct CiModel *model);
+
+// Creates an agent with a uniform random policy.
+// Takes ownership of the model, whose handle must not be used afterwards.
+//
+// # Safety
+// model must be NULL or a live handle returned by ci_model_new.
+struct CiAgent *ci_agent_new(struct CiModel *model);
+
+// Releases an agent and its model
+//
+// # Safety
+// agent must be NULL or a live handle returned by ci_agent_new.
+void ci_agent_free(struct CiAgent *agent);
+
+// Runs deterministic policy improvement on the agent
+//
+// # Safety
+// agent must be NULL or a live handle returned by ci_agent_new.
+int ci_agent_solve(struct CiAgent *agent,
+                   double gamma,
+                   double epsilon,
+                   uint32_t policy_iters,
+                   uint32_t eval_iters);
+
+// Writes the current evaluation of a state into value
+//
+// # Safety
+// agent must be NULL or a live handle returned by ci_agent_new and value must
+// be NULL or point to writable memory.
+int ci_agent_value(const struct CiAgent *agent, int64_t state_id, double *value);
+
+// Copies the best action of a state, NUL-terminated, into buffer.
+// Returns the length of the action name (without the NUL) like snprintf does;
+// nothing is written when buffer_len is not larger than that length.
+// Returns CI_ERR_NOT_FOUND for unknown states and states without actions.
+//
+// # Safety
+// agent must be NULL or a live handle returned by ci_agent_new and buffer must
+// be NULL or point to buffer_len writable bytes.
+int ci_agent_best_action(const struct CiAgent *agent,
+                         int64_t state_id,
+                         char *buffer,
+                         uintptr_t buffer_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* COMPLETE_ITER_H */
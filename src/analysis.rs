@@ -0,0 +1,514 @@
+// Quantities derived from the chain a policy induces on the model, as opposed
+// to the values the solvers compute.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::collections::HashMap;
+use crate::numeric::Real;
+use crate::{helper, linalg, Agent};
+
+// Probability mass, or change of an iterated quantity, below which a
+// propagation stops
+const TOLERANCE: f64 = 1e-12;
+const MAX_STEPS: u32 = 100_000;
+
+// Chain induced by the current policy, in state id order, the successors of
+// each state stored as a sparse vector of positions with positive probability
+pub(crate) struct PolicyChain<F: Real> {
+    pub(crate) ids: Vec<i64>,
+    pub(crate) successors: Vec<Vec<(usize,F)>>,
+}
+
+// Closed classes of the chain a policy induces, which it never leaves once
+// entered, and the probability of ending in each of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Absorption<F: Real = f64> {
+    // Sorted ids of each class, the classes sorted by their first id.
+    // Terminal states are classes of their own.
+    pub classes: Vec<Vec<i64>>,
+    // Probability of each class, in the order of classes, from every state
+    pub probabilities: HashMap<i64,Vec<F>>,
+}
+
+// Chain a policy induces and its expected immediate rewards, for analyses
+// done elsewhere, e.g. of the spectrum or mixing time of the chain. Rows,
+// columns and rewards follow the order of ids.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InducedChain<F: Real = f64> {
+    // Sorted state ids
+    pub ids: Vec<i64>,
+    // (row, column, probability) of the positive transition probabilities,
+    // sorted by row and column. Rows of terminal states are empty.
+    pub triplets: Vec<(usize,usize,F)>,
+    pub rewards: Vec<F>,
+}
+
+impl<F: Real> InducedChain<F> {
+
+    pub fn n_states(&self) -> usize {
+        return self.ids.len()
+    }
+
+    // Same structure as Agent::calc_policy_transitions
+    pub fn to_transitions(&self) -> HashMap<i64,HashMap<i64,F>> {
+        let mut transitions: HashMap<i64,HashMap<i64,F>> = self.ids.iter().map(|id| (*id, HashMap::new())).collect();
+        for (row, col, prob) in &self.triplets {
+            transitions.get_mut(&self.ids[*row]).unwrap().insert(self.ids[*col], *prob);
+        }
+        return transitions
+    }
+
+    pub fn get_rewards(&self) -> HashMap<i64,F> {
+        return linalg::from_dense_vector(&self.rewards, &self.ids)
+    }
+
+    #[cfg(feature = "ndarray")]
+    pub fn to_array2(&self) -> ndarray::Array2<F> {
+        let mut matrix = ndarray::Array2::zeros((self.n_states(), self.n_states()));
+        for (row, col, prob) in &self.triplets {
+            matrix[[*row, *col]] = *prob;
+        }
+        return matrix
+    }
+
+    #[cfg(feature = "nalgebra")]
+    pub fn to_csr(&self) -> nalgebra_sparse::CsrMatrix<F> where F: nalgebra::Scalar {
+        let mut offsets: Vec<usize> = vec![0; self.n_states() + 1];
+        for (row, _, _) in &self.triplets {
+            offsets[*row + 1] += 1;
+        }
+        for row in 0..self.n_states() {
+            offsets[row + 1] += offsets[row];
+        }
+        let columns: Vec<usize> = self.triplets.iter().map(|(_, col, _)| *col).collect();
+        let values: Vec<F> = self.triplets.iter().map(|(_, _, prob)| *prob).collect();
+
+        return nalgebra_sparse::CsrMatrix::try_from_csr_data(self.n_states(), self.n_states(), offsets, columns, values)
+            .expect("triplets are sorted and in bounds")
+    }
+
+}
+
+impl<F: Real> PolicyChain<F> {
+
+    fn len(&self) -> usize {
+        return self.ids.len()
+    }
+
+    fn predecessors(&self) -> Vec<Vec<usize>> {
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); self.len()];
+        for (pos, successors) in self.successors.iter().enumerate() {
+            for (pos_next, _) in successors {
+                predecessors[*pos_next].push(pos);
+            }
+        }
+        return predecessors
+    }
+
+    // Positions with a path to one of the sources, the path only going
+    // through states that are not blocked (sources are always included)
+    fn backward_reachable(&self, sources: &[bool], blocked: &[bool], predecessors: &[Vec<usize>]) -> Vec<bool> {
+        let mut reached: Vec<bool> = sources.to_vec();
+        let mut pending: Vec<usize> = (0..self.len()).filter(|pos| sources[*pos]).collect();
+
+        while let Some(pos) = pending.pop() {
+            for prev in &predecessors[pos] {
+                if !reached[*prev] && !blocked[*prev] {
+                    reached[*prev] = true;
+                    pending.push(*prev);
+                }
+            }
+        }
+
+        return reached
+    }
+
+    // Probability of ever reaching a target. States reaching the targets with
+    // probability 0 or 1 are found on the graph, the others are iterated.
+    pub(crate) fn reach_probabilities(&self, targets: &[bool]) -> Vec<F> {
+        let predecessors = self.predecessors();
+        let no_block = vec![false; self.len()];
+
+        let prob_0: Vec<bool> = self.backward_reachable(targets, &no_block, &predecessors).iter().map(|reached| !reached).collect();
+        let prob_1: Vec<bool> = self.backward_reachable(&prob_0, targets, &predecessors).iter().map(|reached| !reached).collect();
+
+        let mut probs: Vec<F> = prob_1.iter().map(|sure| if *sure {F::one()} else {F::zero()}).collect();
+        let undecided: Vec<usize> = (0..self.len()).filter(|pos| !prob_0[*pos] && !prob_1[*pos]).collect();
+
+        self.iterate(&mut probs, &undecided, F::zero());
+        return probs
+    }
+
+    // Gauss-Seidel sweeps of x(s) = constant + sum_s' P(s'|s) x(s') over
+    // the given positions, until the largest relative change is negligible
+    fn iterate(&self, values: &mut [F], positions: &[usize], constant: F) {
+        let tolerance = F::from_f64(TOLERANCE);
+        let mut counter: u32 = 0;
+
+        while !positions.is_empty() && counter < MAX_STEPS {
+            let mut delta = F::zero();
+            for pos in positions {
+                let new_value = constant + helper::sparse_dot_dense_compensated(&self.successors[*pos], values);
+                delta = delta.max((new_value - values[*pos]).abs()/(F::one() + new_value.abs()));
+                values[*pos] = new_value;
+            }
+
+            counter += 1;
+            if delta < tolerance {
+                break
+            }
+        }
+    }
+
+    // Expected number of steps to a target, infinite from states that may
+    // never reach one
+    pub(crate) fn hitting_times(&self, targets: &[bool]) -> Vec<F> {
+        let probs = self.reach_probabilities(targets);
+
+        // Successors of states reaching the targets surely do as well
+        let sure: Vec<usize> = (0..self.len()).filter(|pos| !targets[*pos] && probs[*pos] == F::one()).collect();
+        let mut times: Vec<F> = (0..self.len())
+            .map(|pos| if targets[pos] || probs[pos] == F::one() {F::zero()} else {F::infinity()})
+            .collect();
+
+        self.iterate(&mut times, &sure, F::one());
+        return times
+    }
+
+    // Strongly connected components, with Tarjan's algorithm unrolled on an
+    // explicit stack
+    pub(crate) fn components(&self) -> Vec<Vec<usize>> {
+        let n = self.len();
+        let mut index: Vec<Option<usize>> = vec![None; n];
+        let mut low: Vec<usize> = vec![0; n];
+        let mut on_stack: Vec<bool> = vec![false; n];
+        let mut stack: Vec<usize> = Vec::new();
+        let mut components: Vec<Vec<usize>> = Vec::new();
+        let mut next_index: usize = 0;
+
+        for root in 0..n {
+            if index[root].is_some() {
+                continue;
+            }
+
+            // Each call holds a state and the number of its successors already visited
+            let mut calls: Vec<(usize, usize)> = vec![(root, 0)];
+            index[root] = Some(next_index);
+            low[root] = next_index;
+            next_index += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some((pos, n_visited)) = calls.last().cloned() {
+                if n_visited < self.successors[pos].len() {
+                    calls.last_mut().unwrap().1 += 1;
+                    let pos_next = self.successors[pos][n_visited].0;
+
+                    match index[pos_next] {
+                        None => {
+                            index[pos_next] = Some(next_index);
+                            low[pos_next] = next_index;
+                            next_index += 1;
+                            stack.push(pos_next);
+                            on_stack[pos_next] = true;
+                            calls.push((pos_next, 0));
+                        },
+                        Some(index_next) if on_stack[pos_next] => low[pos] = low[pos].min(index_next),
+                        Some(_) => (),
+                    }
+                    continue;
+                }
+
+                calls.pop();
+                if let Some((parent, _)) = calls.last() {
+                    low[*parent] = low[*parent].min(low[pos]);
+                }
+
+                if Some(low[pos]) == index[pos] {
+                    let mut component: Vec<usize> = Vec::new();
+                    while let Some(member) = stack.pop() {
+                        on_stack[member] = false;
+                        component.push(member);
+                        if member == pos {
+                            break;
+                        }
+                    }
+                    component.sort();
+                    components.push(component);
+                }
+            }
+        }
+
+        return components
+    }
+
+    // Components without transitions leaving them, sorted by first position
+    pub(crate) fn closed_classes(&self) -> Vec<Vec<usize>> {
+        let components = self.components();
+
+        let mut component_of: Vec<usize> = vec![0; self.len()];
+        for (component_id, component) in components.iter().enumerate() {
+            for pos in component {
+                component_of[*pos] = component_id;
+            }
+        }
+
+        let mut closed: Vec<Vec<usize>> = components.iter().enumerate()
+            .filter(|(component_id, component)| component.iter()
+                .all(|pos| self.successors[*pos].iter().all(|(pos_next, _)| component_of[*pos_next] == *component_id)))
+            .map(|(_, component)| component.clone())
+            .collect();
+        closed.sort();
+
+        return closed
+    }
+
+}
+
+impl<F: Real> Agent<F> {
+
+    pub(crate) fn policy_chain(&self) -> PolicyChain<F> {
+        let model = self.system_state.get_dense();
+        let policy = &self.policy;
+
+        let successors: Vec<Vec<(usize,F)>> = policy.iter().enumerate()
+            .map(|(pos, probs)| helper::to_sparse(probs.iter().enumerate()
+                .filter(|(_, prob)| **prob > F::zero())
+                .flat_map(|(action, prob)| model.get_successors(pos, action).iter()
+                    .filter(|(_, prob_next)| *prob_next > F::zero())
+                    .map(move |(pos_next, prob_next)| (*pos_next, *prob*(*prob_next))))))
+            .collect();
+
+        return PolicyChain {ids: model.get_ids().clone(), successors}
+    }
+
+    // Transition probabilities and expected rewards of the current policy,
+    // the matrices the evaluations solve with
+    pub fn induced_chain(&self) -> InducedChain<F> {
+        let model = self.system_state.get_dense();
+        let policy = &self.policy;
+        let rewards: Vec<F> = policy.iter().enumerate()
+            .map(|(pos, probs)| helper::compensated_sum(probs.iter().enumerate().map(|(action, prob)| *prob*model.get_reward(pos, action))))
+            .collect();
+
+        let PolicyChain {ids, successors} = self.policy_chain();
+        let triplets: Vec<(usize,usize,F)> = successors.iter().enumerate()
+            .flat_map(|(row, successors)| successors.iter().map(move |(col, prob)| (row, *col, *prob)))
+            .collect();
+
+        return InducedChain {ids, triplets, rewards}
+    }
+
+    // Discounted expected number of visits of each state under the current
+    // policy, when the first state is drawn from start_dist: the sum over t of
+    // gamma^t P(s_t = s). Terminal states are counted on arrival.
+    //
+    // The mass of the start distribution is pushed through the chain until
+    // what is left is negligible. With gamma = 1 the counts only exist if the
+    // policy ends episodes, otherwise they stop growing at a step limit.
+    pub fn visitation_frequencies(&self, start_dist: &HashMap<i64,F>, gamma: F) -> HashMap<i64,F> {
+
+        let PolicyChain {ids, successors} = self.policy_chain();
+
+        // States outside the model can't be visited
+        let mut mass = linalg::to_dense_vector(start_dist, &ids);
+        let mut visits = mass.clone();
+
+        let tolerance = F::from_f64(TOLERANCE);
+        let mut counter: u32 = 0;
+
+        loop {
+            let mut next_mass = vec![F::zero(); ids.len()];
+            for (pos, state_mass) in mass.iter().enumerate() {
+                if *state_mass == F::zero() {
+                    continue;
+                }
+                for (pos_next, prob) in &successors[pos] {
+                    next_mass[*pos_next] += gamma*(*state_mass)*(*prob);
+                }
+            }
+
+            let total: F = helper::compensated_sum(next_mass.iter().cloned());
+            for (visit, state_mass) in visits.iter_mut().zip(&next_mass) {
+                *visit += *state_mass;
+            }
+            mass = next_mass;
+
+            counter += 1;
+
+            if (total < tolerance) || (counter == MAX_STEPS) {
+                break
+            }
+        }
+
+        return linalg::from_dense_vector(&visits, &ids)
+    }
+
+    // Probability of ever reaching one of the targets under the current
+    // policy, 1 in the targets themselves. States that reach them surely or
+    // never are found exactly from the graph of the chain.
+    pub fn reach_probability(&self, targets: &[i64]) -> HashMap<i64,F> {
+        let chain = self.policy_chain();
+        let is_target: Vec<bool> = chain.ids.iter().map(|id| targets.contains(id)).collect();
+        return linalg::from_dense_vector(&chain.reach_probabilities(&is_target), &chain.ids)
+    }
+
+    // Expected number of steps under the current policy until one of the
+    // targets is reached, e.g. the mean time to failure with the failure
+    // states as targets. Zero in the targets, infinite in states that may
+    // never reach them.
+    pub fn expected_hitting_times(&self, targets: &[i64]) -> HashMap<i64,F> {
+        let chain = self.policy_chain();
+        let is_target: Vec<bool> = chain.ids.iter().map(|id| targets.contains(id)).collect();
+        return linalg::from_dense_vector(&chain.hitting_times(&is_target), &chain.ids)
+    }
+
+    // Expected number of steps until the policy enters a closed class (see
+    // absorption_probabilities), infinite when that may never happen
+    pub fn expected_absorption_times(&self) -> HashMap<i64,F> {
+        let chain = self.policy_chain();
+        let mut absorbed = vec![false; chain.len()];
+        for pos in chain.closed_classes().into_iter().flatten() {
+            absorbed[pos] = true;
+        }
+        return linalg::from_dense_vector(&chain.hitting_times(&absorbed), &chain.ids)
+    }
+
+    pub fn absorption_probabilities(&self) -> Absorption<F> {
+        let chain = self.policy_chain();
+        let closed = chain.closed_classes();
+
+        // A closed class is never left, reaching it is being absorbed in it
+        let class_probs: Vec<Vec<F>> = closed.iter()
+            .map(|class| {
+                let mut in_class = vec![false; chain.len()];
+                for pos in class {
+                    in_class[*pos] = true;
+                }
+                chain.reach_probabilities(&in_class)
+            }).collect();
+
+        let probabilities: HashMap<i64,Vec<F>> = chain.ids.iter().enumerate()
+            .map(|(pos, id)| (*id, class_probs.iter().map(|probs| probs[pos]).collect()))
+            .collect();
+        let classes: Vec<Vec<i64>> = closed.iter()
+            .map(|class| class.iter().map(|pos| chain.ids[*pos]).collect())
+            .collect();
+
+        return Absorption {classes, probabilities}
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::models;
+
+    #[test]
+    fn visitation_frequencies_test() {
+        // The first state loops on itself with probability 0.9 before moving on
+        let links = vec![
+            models::StateLink(0, 0, "Wait".to_string(), 0.9, 0.),
+            models::StateLink(0, 1, "Wait".to_string(), 0.1, 0.),
+            models::StateLink(1, 2, "Go".to_string(), 1., 1.),
+        ];
+        let agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        let start = HashMap::from([(0, 1.)]);
+
+        let visits = agent.visitation_frequencies(&start, 1.);
+        assert!((visits.get(&0).unwrap() - 10.).abs() < 1e-9);
+        assert!((visits.get(&1).unwrap() - 1.).abs() < 1e-9);
+        assert!((visits.get(&2).unwrap() - 1.).abs() < 1e-9);
+
+        // Discounted, the loop gives 1/(1 - 0.45) visits of the first state
+        let visits = agent.visitation_frequencies(&start, 0.5);
+        let expected_0 = 1./0.55;
+        assert!((visits.get(&0).unwrap() - expected_0).abs() < 1e-9);
+        assert!((visits.get(&1).unwrap() - 0.05*expected_0).abs() < 1e-9);
+        assert!((visits.get(&2).unwrap() - 0.025*expected_0).abs() < 1e-9);
+
+        // Splitting the start mass splits the visits
+        let visits = agent.visitation_frequencies(&HashMap::from([(0, 0.5), (1, 0.5), (7, 1.)]), 1.);
+        assert!((visits.get(&1).unwrap() - 1.).abs() < 1e-9);
+        assert_eq!(visits.get(&7), None);
+    }
+
+    #[test]
+    fn hitting_and_absorption_test() {
+        // From 0: a self loop, a terminal state, and a cycle between 2 and 3.
+        // State 5 waits before reaching 0.
+        let links = vec![
+            models::StateLink(0, 1, "Go".to_string(), 0.5, 0.),
+            models::StateLink(0, 2, "Go".to_string(), 0.3, 0.),
+            models::StateLink(0, 4, "Go".to_string(), 0.2, 0.),
+            models::StateLink(1, 1, "Stay".to_string(), 1., 0.),
+            models::StateLink(2, 3, "Cycle".to_string(), 1., 0.),
+            models::StateLink(3, 2, "Cycle".to_string(), 1., 0.),
+            models::StateLink(5, 5, "Wait".to_string(), 0.75, 0.),
+            models::StateLink(5, 0, "Wait".to_string(), 0.25, 0.),
+        ];
+        let agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+
+        let absorption = agent.absorption_probabilities();
+        assert_eq!(absorption.classes, vec![vec![1], vec![2, 3], vec![4]]);
+        let probs_0 = absorption.probabilities.get(&0).unwrap();
+        let probs_5 = absorption.probabilities.get(&5).unwrap();
+        for (prob, expected) in probs_0.iter().chain(probs_5).zip([0.5, 0.3, 0.2, 0.5, 0.3, 0.2]) {
+            assert!((prob - expected).abs() < 1e-9);
+        }
+        assert_eq!(absorption.probabilities.get(&3).unwrap(), &vec![0., 1., 0.]);
+
+        let times = agent.expected_absorption_times();
+        assert!((times.get(&5).unwrap() - 5.).abs() < 1e-9);
+        assert_eq!(*times.get(&2).unwrap(), 0.);
+
+        // Reaching 0 from 5 takes 4 steps on average, the terminal state is not sure
+        let times = agent.expected_hitting_times(&[0]);
+        assert!((times.get(&5).unwrap() - 4.).abs() < 1e-9);
+        assert_eq!(*times.get(&0).unwrap(), 0.);
+        assert!(times.get(&1).unwrap().is_infinite());
+
+        let probs = agent.reach_probability(&[4]);
+        assert!((probs.get(&5).unwrap() - 0.2).abs() < 1e-9);
+        assert_eq!(*probs.get(&4).unwrap(), 1.);
+        assert_eq!(*probs.get(&1).unwrap(), 0.);
+        let probs = agent.reach_probability(&[2, 4]);
+        assert!((probs.get(&0).unwrap() - 0.5).abs() < 1e-9);
+        assert_eq!(*probs.get(&3).unwrap(), 1.);
+
+        let times = agent.expected_hitting_times(&[4]);
+        assert!(times.get(&0).unwrap().is_infinite());
+        assert!(times.get(&5).unwrap().is_infinite());
+    }
+
+    #[test]
+    fn induced_chain_test() {
+        let links = vec![
+            models::StateLink(0, 0, "Wait".to_string(), 0.9, 0.),
+            models::StateLink(0, 1, "Wait".to_string(), 0.1, 0.),
+            models::StateLink(0, 1, "Go".to_string(), 1., 4.),
+            models::StateLink(1, 2, "Go".to_string(), 1., 1.),
+        ];
+        let agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        let chain = agent.induced_chain();
+
+        assert_eq!(chain.ids, vec![0, 1, 2]);
+        assert_eq!(chain.triplets, vec![(0, 0, 0.45), (0, 1, 0.55), (1, 2, 1.)]);
+        assert_eq!(chain.rewards, vec![2., 1., 0.]);
+        assert_eq!(chain.get_rewards(), agent.calc_policy_rewards());
+        for (id, successors) in agent.calc_policy_transitions() {
+            for (id_next, prob) in successors.iter().filter(|(_, prob)| **prob > 0.) {
+                assert!((chain.to_transitions()[&id][id_next] - prob).abs() < 1e-12);
+            }
+        }
+
+        #[cfg(feature = "ndarray")]
+        assert_eq!(chain.to_array2()[[0, 1]], 0.55);
+        #[cfg(feature = "nalgebra")]
+        assert_eq!(chain.to_csr().get_entry(1, 2).unwrap().into_value(), 1.);
+    }
+
+}
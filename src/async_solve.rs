@@ -0,0 +1,145 @@
+// Solves awaited from async code, enabled with the "tokio" feature.
+//
+// The solve runs on tokio's blocking pool, so that it doesn't hold up the
+// workers of the runtime, the agent moving into it and coming back with the
+// result. Meanwhile the handle reports the last progress of the solve and
+// can cancel it. The solves must be started from within a tokio runtime.
+
+use std::future::Future;
+use std::panic;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::task::JoinHandle;
+
+use crate::error::Error;
+use crate::numeric::Real;
+use crate::progress::{CancellationToken, Progress, SharedProgress};
+use crate::solver::{SolveContext, SolverConfig};
+use crate::Agent;
+
+enum Job<F: Real> {
+    Solve(F),
+    Evaluate(F),
+}
+
+// Solve in progress, resolving to the agent and the result of the solve.
+// Dropping the handle cancels the solve.
+pub struct SolveHandle<F: Real = f64> {
+    progress: SharedProgress<F>,
+    cancellation: CancellationToken,
+    task: JoinHandle<(Agent<F>, Result<(), Error>)>,
+}
+
+impl<F: Real> SolveHandle<F> {
+
+    // The checks of the config apply as in the fallible solves, and its
+    // cancellation token, if any, also cancels the solve
+    fn spawn(mut agent: Agent<F>, config: &SolverConfig<F>, job: Job<F>) -> SolveHandle<F> {
+        let cancellation = config.cancellation.clone().unwrap_or_default();
+        let config = config.clone().with_cancellation(cancellation.clone());
+        let progress = SharedProgress::new();
+        let mut observer = progress.clone();
+
+        let task = tokio::task::spawn_blocking(move || {
+            let result = agent.check_keys(config.key_mismatch).and_then(|()| {
+                let mut context = SolveContext::new(&mut observer, Some(&config)).with_config(&config, agent.get_system_state().get_dense());
+                match job {
+                    Job::Solve(gamma) => agent.improve_observed(gamma, config.epsilon, config.policy_iters, config.eval_iters, &mut context),
+                    Job::Evaluate(gamma) => agent.evaluate_policy_observed(gamma, config.epsilon, config.eval_iters, &mut context),
+                }
+            });
+            return (agent, result)
+        });
+
+        return SolveHandle {progress, cancellation, task}
+    }
+
+    // Last report of the solve, None before its first sweep
+    pub fn progress(&self) -> Option<Progress<F>> {
+        return self.progress.latest()
+    }
+
+    // Stops the solve before its next sweep, the handle resolving with
+    // Error::Cancelled and the partial result
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.task.is_finished()
+    }
+
+}
+
+impl<F: Real> Future for SolveHandle<F> {
+    type Output = (Agent<F>, Result<(), Error>);
+
+    // A panic of the solve is raised again in the awaiting task
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        return Pin::new(&mut self.task).poll(cx).map(|joined| joined.unwrap_or_else(|error| panic::resume_unwind(error.into_panic())))
+    }
+}
+
+impl<F: Real> Drop for SolveHandle<F> {
+    fn drop(&mut self) {
+        self.cancellation.cancel();
+    }
+}
+
+impl<F: Real> Agent<F> {
+
+    // Agent::try_solve on the blocking pool
+    pub fn solve_async(self, gamma: F, config: &SolverConfig<F>) -> SolveHandle<F> {
+        return SolveHandle::spawn(self, config, Job::Solve(gamma))
+    }
+
+    // Agent::try_evaluate_policy on the blocking pool
+    pub fn evaluate_policy_async(self, gamma: F, config: &SolverConfig<F>) -> SolveHandle<F> {
+        return SolveHandle::spawn(self, config, Job::Evaluate(gamma))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::envs::GamblersProblem;
+    use crate::models;
+
+    #[test]
+    fn async_solve_test() {
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let model = GamblersProblem::new(20, 0.4).unwrap().build();
+        let config = SolverConfig::new().with_epsilon(1e-12).with_eval_iters(1000);
+
+        let mut expected: Agent = Agent::init_random(model.clone());
+        expected.try_solve(1., &config).unwrap();
+        let (agent, result) = runtime.block_on(async {Agent::init_random(model.clone()).solve_async(1., &config).await});
+        assert_eq!(result, Ok(()));
+        assert!(agent == expected);
+
+        let (agent, result) = runtime.block_on(async {agent.evaluate_policy_async(1., &config).await});
+        expected.try_evaluate_policy(1., &config).unwrap();
+        assert_eq!(result, Ok(()));
+        assert_eq!(agent.get_evaluation(), expected.get_evaluation());
+
+        // With an epsilon of zero, and no divergence window, the evaluation
+        // only stops when cancelled
+        let links = vec![
+            models::StateLink(0, 0, "Stay".to_string(), 1., 1.),
+        ];
+        let agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        let (_, result) = runtime.block_on(async {
+            let handle = agent.evaluate_policy_async(0.9, &SolverConfig::new().with_epsilon(0.).with_eval_iters(u32::MAX).with_divergence_window(u32::MAX));
+            while handle.progress().is_none_or(|progress| progress.sweep < 5) {
+                std::thread::yield_now();
+            }
+            handle.cancel();
+            handle.await
+        });
+        assert!(matches!(result, Err(Error::Cancelled {policy_iteration: 0, ..})));
+    }
+
+}
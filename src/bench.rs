@@ -0,0 +1,284 @@
+// Throughput measurements of the solvers, to compare machines, backends and
+// settings on standard models or on one's own.
+//
+// Throughput is counted in state backups per second: the number of states
+// times the number of evaluation sweeps, over the time taken. Models are
+// built before the clock starts, so only the solvers are measured.
+
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::envs::{CliffWalking, GamblersProblem};
+use crate::helper;
+use crate::models::{StateLink, SystemState};
+use crate::progress::{NoProgress, Progress};
+use crate::solver::SolveContext;
+use crate::{Agent, SolverConfig};
+
+#[cfg(feature = "rand")]
+use crate::envs::Garnet;
+
+// Models of a given size for the measurements
+#[derive(Debug, Clone, PartialEq)]
+pub enum BenchModel {
+    // States in a line, each moving to the next with a unit reward
+    Chain(usize),
+    // CliffWalking grid of n_rows x n_cols
+    Grid(usize, usize),
+    // GamblersProblem with this goal, many actions per state
+    Gambler(i64),
+    #[cfg(feature = "rand")]
+    Garnet(Garnet),
+}
+
+impl BenchModel {
+
+    pub fn name(&self) -> String {
+        return match self {
+            BenchModel::Chain(n_states) => format!("chain {}", n_states),
+            BenchModel::Grid(n_rows, n_cols) => format!("grid {}x{}", n_rows, n_cols),
+            BenchModel::Gambler(goal) => format!("gambler {}", goal),
+            #[cfg(feature = "rand")]
+            BenchModel::Garnet(garnet) => format!("garnet {}x{}x{}", garnet.n_states, garnet.n_actions, garnet.branching),
+        }
+    }
+
+    // Empty when the size is not valid for the model
+    pub fn build(&self) -> SystemState {
+        return match self {
+            BenchModel::Chain(n_states) => SystemState::create_and_build((0..*n_states as i64)
+                .map(|id| StateLink(id, id + 1, "Step".to_string(), 1., 1.))
+                .collect()),
+            BenchModel::Grid(n_rows, n_cols) => match CliffWalking::with_size(*n_rows, *n_cols) {
+                Some(grid) => grid.build(),
+                None => SystemState::create_and_build(Vec::new()),
+            },
+            BenchModel::Gambler(goal) => match GamblersProblem::new(*goal, 0.4) {
+                Some(gambler) => gambler.build(),
+                None => SystemState::create_and_build(Vec::new()),
+            },
+            #[cfg(feature = "rand")]
+            BenchModel::Garnet(garnet) => garnet.build(),
+        }
+    }
+
+}
+
+// One model of each kind with about n_states states
+pub fn standard_models(n_states: usize) -> Vec<BenchModel> {
+    let side = (n_states as f64).sqrt().round().max(3.) as usize;
+    let models = vec![
+        BenchModel::Chain(n_states),
+        BenchModel::Grid(side, side),
+        BenchModel::Gambler(n_states.max(2) as i64),
+    ];
+    #[cfg(feature = "rand")]
+    let models = [models, vec![BenchModel::Garnet(Garnet::new(n_states.max(10), 4, 10))]].concat();
+    return models
+}
+
+// Times of repeated runs of the same work
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timing {
+    pub runs: usize,
+    pub mean: Duration,
+    pub min: Duration,
+    pub max: Duration,
+}
+
+// Times run, runs times (at least once), calling setup before each run
+// outside of the clock. The result of each run is kept until the clock stops.
+pub fn time_runs<S, T>(runs: usize, mut setup: impl FnMut() -> S, mut run: impl FnMut(S) -> T) -> Timing {
+    let runs = runs.max(1);
+    let mut times: Vec<Duration> = Vec::with_capacity(runs);
+
+    for _ in 0..runs {
+        let input = setup();
+        let start = Instant::now();
+        let output = run(input);
+        times.push(start.elapsed());
+        drop(output);
+    }
+
+    let total: Duration = times.iter().sum();
+    return Timing {
+        runs,
+        mean: total/runs as u32,
+        min: *times.iter().min().unwrap(),
+        max: *times.iter().max().unwrap(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Throughput {
+    pub label: String,
+    pub n_states: usize,
+    pub n_transitions: usize,
+    // Evaluation sweeps of each run
+    pub sweeps: u32,
+    pub timing: Timing,
+}
+
+impl Throughput {
+
+    // Backups per second over the mean run
+    pub fn states_per_second(&self) -> f64 {
+        let seconds = self.timing.mean.as_secs_f64();
+        return if seconds > 0. {(self.n_states as f64)*(self.sweeps as f64)/seconds} else {f64::INFINITY}
+    }
+
+}
+
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}: {} states, {} transitions, {} sweeps in {:.3?} (min {:.3?}, max {:.3?}, {} runs), {:.3e} states/s",
+            self.label, self.n_states, self.n_transitions, self.sweeps, self.timing.mean, self.timing.min, self.timing.max, self.timing.runs, self.states_per_second())
+    }
+}
+
+fn count_transitions(system_state: &SystemState) -> usize {
+    let model = system_state.get_dense();
+    return (0..model.n_states())
+        .map(|pos| (0..model.get_actions(pos).len()).map(|action| model.get_successors(pos, action).len()).sum::<usize>())
+        .sum()
+}
+
+// Evaluation of the random policy for exactly sweeps sweeps, with the threads,
+// start states and partition of config
+pub fn bench_evaluation(model: &BenchModel, gamma: f64, sweeps: u32, config: &SolverConfig, runs: usize) -> Throughput {
+    let system_state = model.build();
+    let n_transitions = count_transitions(&system_state);
+    let mut agent: Agent = Agent::init_random(system_state);
+    let n_states = agent.get_evaluation().len();
+
+    // The rewards and transitions of the policy are cached after the first evaluation
+    agent.evaluate_policy(gamma, 0., 1);
+
+    let timing = time_runs(runs, || (), |_| {
+        let mut observer = NoProgress;
+        let mut context = SolveContext::new(&mut observer, None).with_config(config, agent.get_system_state().get_dense()).uninterrupted();
+        agent.evaluate_policy_observed(gamma, 0., sweeps, &mut context).unwrap();
+    });
+
+    return Throughput {label: format!("evaluate {}", model.name()), n_states, n_transitions, sweeps, timing}
+}
+
+// Full solve from the random policy with the settings of config. The sweeps
+// are those of the last run, which all runs share.
+pub fn bench_solve(model: &BenchModel, gamma: f64, config: &SolverConfig, runs: usize) -> Throughput {
+    let system_state = model.build();
+    let n_transitions = count_transitions(&system_state);
+    let n_states = system_state.get_dense().n_states();
+
+    let mut sweeps: u32 = 0;
+    let timing = time_runs(runs, || Agent::init_random(model.build()), |mut agent: Agent| {
+        sweeps = 0;
+        agent.solve_with_progress(gamma, config, &mut |_: &Progress| sweeps += 1);
+        agent
+    });
+
+    return Throughput {label: format!("solve {}", model.name()), n_states, n_transitions, sweeps, timing}
+}
+
+// Evaluation of the random policy on the GPU, for exactly sweeps sweeps
+#[cfg(feature = "gpu")]
+pub fn bench_gpu_evaluation(model: &BenchModel, gpu: &crate::gpu::GpuEvaluator, gamma: f64, sweeps: u32, runs: usize) -> Result<Throughput, crate::gpu::GpuError> {
+    let system_state = model.build();
+    let n_transitions = count_transitions(&system_state);
+    let mut agent: Agent = Agent::init_random(system_state);
+    let n_states = agent.get_evaluation().len();
+
+    agent.evaluate_policy_gpu(gpu, gamma, -1., 1)?;
+    let mut result = Ok(());
+    let timing = time_runs(runs, || (), |_| {
+        if result.is_ok() {
+            result = agent.evaluate_policy_gpu(gpu, gamma, -1., sweeps);
+        }
+    });
+    result?;
+
+    return Ok(Throughput {label: format!("gpu evaluate {}", model.name()), n_states, n_transitions, sweeps, timing})
+}
+
+// Compensated dot products of a sparse row with a dense vector, the inner
+// loop of the sweeps, with the scalar lanes and with the AVX2 kernel. The
+// AVX2 timing is None on CPUs without it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DotThroughput {
+    pub n_entries: usize,
+    // Dot products of each run
+    pub repeats: usize,
+    pub scalar: Timing,
+    pub avx2: Option<Timing>,
+}
+
+impl DotThroughput {
+
+    // Mean time of the scalar lanes over that of the AVX2 kernel
+    pub fn speedup(&self) -> Option<f64> {
+        return self.avx2.as_ref().map(|avx2| self.scalar.mean.as_secs_f64()/avx2.mean.as_secs_f64().max(f64::MIN_POSITIVE))
+    }
+
+}
+
+impl fmt::Display for DotThroughput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "sparse dot of {} entries, {} times: scalar {:.3?}", self.n_entries, self.repeats, self.scalar.mean)?;
+        return match (&self.avx2, self.speedup()) {
+            (Some(avx2), Some(speedup)) => write!(f, ", avx2 {:.3?} ({:.2}x)", avx2.mean, speedup),
+            _ => write!(f, ", avx2 not available"),
+        }
+    }
+}
+
+// Times repeats dot products of a row of n_entries with a dense vector ten
+// times longer, its positions spread out as in a transition matrix
+pub fn bench_sparse_dot(n_entries: usize, repeats: usize, runs: usize) -> DotThroughput {
+    let dense: Vec<f64> = (0..10*n_entries.max(1)).map(|pos| 1./(1. + pos as f64)).collect();
+    let row: Vec<(usize,f64)> = (0..n_entries).map(|entry| (10*entry + entry % 7, 1./n_entries as f64)).collect();
+
+    let scalar = time_runs(runs, || (), |_| (0..repeats).map(|_| black_box(helper::sparse_dot_dense_lanes(black_box(&row), &dense))).sum::<f64>());
+
+    #[cfg(target_arch = "x86_64")]
+    let avx2 = std::arch::is_x86_feature_detected!("avx2").then(|| time_runs(runs, || (), |_| {
+        // SAFETY: the CPU supports AVX2, as just checked
+        (0..repeats).map(|_| black_box(unsafe { helper::sparse_dot_dense_avx2(black_box(&row), &dense) })).sum::<f64>()
+    }));
+    #[cfg(not(target_arch = "x86_64"))]
+    let avx2 = None;
+
+    return DotThroughput {n_entries, repeats, scalar, avx2}
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn bench_test() {
+        let models = standard_models(100);
+        assert_eq!(models[0].name(), "chain 100");
+        assert_eq!(models[1].name(), "grid 10x10");
+        // The cells of the cliff are not states
+        assert_eq!(models[1].build().get_all_states().len(), 92);
+
+        let throughput = bench_evaluation(&models[0], 0.9, 20, &SolverConfig::new(), 3);
+        assert_eq!((throughput.n_states, throughput.n_transitions, throughput.sweeps), (101, 100, 20));
+        assert_eq!(throughput.timing.runs, 3);
+        assert!(throughput.timing.min <= throughput.timing.mean && throughput.timing.mean <= throughput.timing.max);
+        assert!(throughput.states_per_second() > 0.);
+        assert!(throughput.to_string().starts_with("evaluate chain 100: 101 states, 100 transitions, 20 sweeps"));
+
+        let throughput = bench_solve(&models[2], 1., &SolverConfig::new().with_eval_iters(1000), 1);
+        assert!(throughput.sweeps > 1);
+
+        let dot = bench_sparse_dot(64, 10, 2);
+        assert_eq!(dot.scalar.runs, 2);
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(dot.avx2.is_some(), std::arch::is_x86_feature_detected!("avx2"));
+        assert!(dot.to_string().starts_with("sparse dot of 64 entries, 10 times: scalar"));
+    }
+
+}
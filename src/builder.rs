@@ -0,0 +1,194 @@
+// Construction of agents with a chosen initial policy and values.
+//
+// Agent::init_random starts from the uniform policy and zero values. The
+// builder starts from any of the policies of PolicyInit, or from a saved one,
+// and seeds the values, so that a solve can start close to its solution.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(any(feature = "json", feature = "msgpack"))]
+use std::io::Read;
+
+use crate::collections::HashMap;
+use crate::models::{DenseModel, SystemState};
+use crate::numeric::Real;
+use crate::Agent;
+
+// Initial policy of an agent
+#[derive(Debug, Clone, PartialEq)]
+pub enum PolicyInit<F: Real = f64> {
+    // Every action of a state with the same probability
+    Uniform,
+    // One action per state drawn at random, the same for a given seed
+    #[cfg(feature = "rand")]
+    Random(u64),
+    // The action with the largest expected immediate reward, the first by
+    // name among ties
+    Greedy,
+    // The given policy, uniform for the states it misses. States missing
+    // from the model are ignored.
+    Policy(HashMap<i64,HashMap<String,F>>),
+}
+
+// Builder of an Agent, created with Agent::builder
+pub struct AgentBuilder<F: Real = f64> {
+    system_state: SystemState<F>,
+    policy: PolicyInit<F>,
+    initial_value: F,
+    values: HashMap<i64,F>,
+}
+
+impl<F: Real> AgentBuilder<F> {
+
+    pub fn new(system_state: SystemState<F>) -> AgentBuilder<F> {
+        return AgentBuilder {system_state, policy: PolicyInit::Uniform, initial_value: F::zero(), values: HashMap::new()}
+    }
+
+    pub fn with_policy(mut self, policy: PolicyInit<F>) -> AgentBuilder<F> {
+        self.policy = policy;
+        return self
+    }
+
+    // Policy read from a file written by io::write_policy_json
+    #[cfg(feature = "json")]
+    pub fn with_policy_json<R: Read>(self, reader: R) -> serde_json::Result<AgentBuilder<F>> where F: serde::de::DeserializeOwned {
+        let policy = crate::io::read_policy_json(reader)?;
+        return Ok(self.with_policy(PolicyInit::Policy(policy)))
+    }
+
+    // Policy read from a file written by io::write_policy_msgpack
+    #[cfg(feature = "msgpack")]
+    pub fn with_policy_msgpack<R: Read>(self, reader: R) -> Result<AgentBuilder<F>, rmp_serde::decode::Error> where F: serde::de::DeserializeOwned {
+        let policy = crate::io::read_policy_msgpack(reader)?;
+        return Ok(self.with_policy(PolicyInit::Policy(policy)))
+    }
+
+    // Value of every state the values given with with_values miss, zero by
+    // default
+    pub fn with_initial_value(mut self, value: F) -> AgentBuilder<F> {
+        self.initial_value = value;
+        return self
+    }
+
+    // Initial values of some states, such as those of a related solved model.
+    // States missing from the model are ignored.
+    pub fn with_values(mut self, values: HashMap<i64,F>) -> AgentBuilder<F> {
+        self.values = values;
+        return self
+    }
+
+    pub fn build(self) -> Agent<F> {
+
+        let mut agent = Agent::init_random(self.system_state);
+
+        #[cfg(feature = "rand")]
+        let mut rng: Option<rand::rngs::StdRng> = match &self.policy {
+            PolicyInit::Random(seed) => Some(rand::SeedableRng::seed_from_u64(*seed)),
+            _ => None,
+        };
+
+        let system_state = agent.get_shared_system_state();
+        let model = system_state.get_dense();
+        let mut policy = agent.policy.clone();
+
+        // States in id order, so random policies only depend on the seed
+        for (pos, id) in model.get_ids().iter().enumerate() {
+            let action: Option<String> = match &self.policy {
+                PolicyInit::Uniform => None,
+                #[cfg(feature = "rand")]
+                PolicyInit::Random(_) => {
+                    rand::seq::IndexedRandom::choose(model.get_actions(pos).as_slice(), rng.as_mut().unwrap()).cloned()
+                },
+                PolicyInit::Greedy => greedy_immediate_action(model, pos),
+                PolicyInit::Policy(given) => {
+                    if let Some(actions) = given.get(id) {
+                        let (row, unknown) = Agent::policy_row(model, pos, actions);
+                        policy[pos] = row;
+                        agent.unknown_keys.actions.extend(unknown.into_iter().map(|action| (*id, action)));
+                    }
+                    None
+                },
+            };
+            if let Some(action) = action {
+                policy[pos] = model.get_actions(pos).iter().map(|name| if *name == action {F::one()} else {F::zero()}).collect();
+            }
+        }
+        agent.unknown_keys.actions.sort();
+
+        let values: Vec<F> = model.get_ids().iter().map(|id| self.values.get(id).cloned().unwrap_or(self.initial_value)).collect();
+        agent.replace_policy(policy);
+        agent.replace_values(values);
+
+        return agent
+    }
+
+}
+
+fn greedy_immediate_action<F: Real>(model: &DenseModel<F>, pos: usize) -> Option<String> {
+    let mut best: Option<(&str, F)> = None;
+    for (action, name) in model.get_actions(pos).iter().enumerate() {
+        let reward = model.get_reward(pos, action);
+        if best.is_none_or(|(_, best_reward)| reward > best_reward) {
+            best = Some((name, reward));
+        }
+    }
+    return best.map(|(action, _)| action.to_string())
+}
+
+impl<F: Real> Agent<F> {
+
+    pub fn builder(system_state: SystemState<F>) -> AgentBuilder<F> {
+        return AgentBuilder::new(system_state)
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::models::StateLink;
+
+    fn two_state_model() -> SystemState {
+        return SystemState::create_and_build(vec![
+            StateLink::new(0, 1, "Small", 1., 1.),
+            StateLink::new(0, 1, "Large", 1., 5.),
+            StateLink::new(0, 0, "Wait", 1., 0.),
+            StateLink::new(1, 0, "Back", 1., 0.),
+        ])
+    }
+
+    #[test]
+    fn builder_test() {
+        let agent: Agent = Agent::builder(two_state_model()).build();
+        assert_eq!(agent.get_policy()[&0]["Wait"], 1./3.);
+        assert_eq!(agent[0], 0.);
+
+        let agent = Agent::builder(two_state_model())
+            .with_policy(PolicyInit::Greedy)
+            .with_initial_value(1.)
+            .with_values(HashMap::from([(1, 4.), (9, 2.)]))
+            .build();
+        assert_eq!(agent.get_best_action(0).unwrap(), (&"Large".to_string(), &1.));
+        assert_eq!(agent.get_best_action(1).unwrap().0, "Back");
+        assert_eq!(agent.values_sorted(), vec![(1, 4.), (0, 1.)]);
+
+        let policy = HashMap::from([(0, HashMap::from([("Small".to_string(), 1.)])), (5, HashMap::new())]);
+        let agent = Agent::builder(two_state_model()).with_policy(PolicyInit::Policy(policy)).build();
+        assert_eq!(agent.get_policy()[&0], HashMap::from([("Small".to_string(), 1.), ("Large".to_string(), 0.), ("Wait".to_string(), 0.)]));
+        assert_eq!(agent.get_policy()[&1], HashMap::from([("Back".to_string(), 1.)]));
+        assert_eq!(agent.get_policy().len(), 2);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn random_policy_test() {
+        let policy = |seed: u64| Agent::builder(two_state_model()).with_policy(PolicyInit::Random(seed)).build().get_policy().clone();
+        assert_eq!(policy(7), policy(7));
+        for actions in policy(7).values() {
+            assert_eq!(actions.values().filter(|prob| **prob == 1.).count(), 1);
+        }
+        assert!((0..20).any(|seed| policy(seed) != policy(7)));
+    }
+
+}
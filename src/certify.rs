@@ -0,0 +1,364 @@
+// Checks of a solved agent against the Bellman optimality equations
+//
+//     V(s) = max_a [ r(s,a) + gamma sum_s' P(s'|s,a) V(s') ]
+//
+// A policy passes if, in every state with actions, its action values are
+// within the tolerance of the best action value and the stored value is
+// within the tolerance of its action values. When it fails,
+// suboptimality_trace shows how the policy reaches a failing state.
+//
+// check_occupancy cross-checks the values of the policy itself, whether or
+// not it is optimal, with the occupancy identity
+//
+//     sum_s mu(s) V(s) = sum_s d(s) r(s)
+//
+// where d is the discounted occupancy of the policy from the start
+// distribution mu, and r the expected immediate rewards of the policy. The
+// occupancy is found by pushing mu through the chain of the policy, without
+// the values, so values that aren't those of the policy, from an evaluation
+// stopped early or solved on another model, break the identity.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::collections::HashMap;
+use crate::helper::{self, FastMap};
+use crate::numeric::Real;
+use crate::Agent;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimalityCertificate<F: Real = f64> {
+    pub gamma: F,
+    pub tolerance: F,
+    pub n_states: usize,
+    // Largest |V(s) - max_a Q(s,a)| over the states
+    pub bellman_residual: F,
+    // Bound on the distance to the optimal values, residual/(1 - gamma),
+    // only available with gamma < 1
+    pub value_error_bound: Option<F>,
+}
+
+// State failing the check
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimalityGap<F: Real = f64> {
+    pub state: i64,
+    pub best_action: String,
+    // max_a Q(s,a) minus the action value of the policy, positive when a
+    // better action exists
+    pub gap: F,
+    // V(s) minus the action value of the policy, non-zero when the values
+    // are not those of the policy
+    pub residual: F,
+}
+
+// Step of a trace, from a state with the action of the policy
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceStep<F: Real = f64> {
+    pub state: i64,
+    pub value: F,
+    pub action: String,
+    pub next_state: i64,
+    // Probability of the policy taking the action and reaching next_state
+    pub prob: F,
+}
+
+// Path followed by the policy from a start state to a state where a better
+// action exists
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuboptimalityTrace<F: Real = f64> {
+    pub steps: Vec<TraceStep<F>>,
+    pub gap: OptimalityGap<F>,
+    // Action of the policy in the failing state, the most likely one
+    pub policy_action: String,
+    // Probability of the whole path under the policy
+    pub path_prob: F,
+    // Value lost from the start state through this path: the gap, discounted
+    // by the length of the path and weighted by its probability
+    pub discounted_loss: F,
+}
+
+// Expected return from a start distribution, from the values and from the
+// occupancy of the policy
+#[derive(Debug, Clone, PartialEq)]
+pub struct OccupancyCheck<F: Real = f64> {
+    pub value_return: F,
+    pub occupancy_return: F,
+    pub tolerance: F,
+}
+
+impl<F: Real> OccupancyCheck<F> {
+
+    // value_return minus occupancy_return
+    pub fn get_difference(&self) -> F {
+        return self.value_return - self.occupancy_return
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        return self.get_difference().abs() <= self.tolerance
+    }
+
+}
+
+impl<F: Real> fmt::Display for SuboptimalityTrace<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "state {} (value {:.4}): {} -> state {} (p={:.4})", step.state, step.value, step.action, step.next_state, step.prob)?;
+        }
+        writeln!(f, "state {}: the policy plays {}, {} is better by {:.4}", self.gap.state, self.policy_action, self.gap.best_action, self.gap.gap)?;
+        return writeln!(f, "path probability {:.4}, value lost from the start {:.4}", self.path_prob, self.discounted_loss)
+    }
+}
+
+impl<F: Real> Agent<F> {
+
+    // Gaps are sorted by state id
+    pub fn certify_optimality(&self, gamma: F, tolerance: F) -> Result<OptimalityCertificate<F>, Vec<OptimalityGap<F>>> {
+
+        let q_values = self.calc_q_values(gamma);
+
+        let mut gaps: Vec<OptimalityGap<F>> = Vec::new();
+        let mut bellman_residual = F::zero();
+
+        let mut ids: Vec<&i64> = q_values.keys().collect();
+        ids.sort();
+
+        for id in ids {
+            let state_q_values = q_values.get(id).unwrap();
+
+            // Highest value, the first action by name among ties
+            let best = state_q_values.iter()
+                .max_by(|a, b| a.1.partial_cmp(b.1).unwrap().then(b.0.cmp(a.0)));
+            let (best_action, best_q) = match best {
+                Some((action, q_value)) => (action, *q_value),
+                None => continue,
+            };
+
+            let policy_q: F = match self.get_policy().get(id) {
+                Some(actions) => helper::match_mul_sum(actions, state_q_values),
+                None => F::zero(),
+            };
+            let value = *self.get_evaluation().get(id).unwrap_or(&F::zero());
+
+            bellman_residual = bellman_residual.max((value - best_q).abs());
+
+            let gap = best_q - policy_q;
+            let residual = value - policy_q;
+            if gap > tolerance || residual.abs() > tolerance {
+                gaps.push(OptimalityGap {state: *id, best_action: best_action.clone(), gap, residual});
+            }
+        }
+
+        if !gaps.is_empty() {
+            return Err(gaps)
+        }
+
+        let value_error_bound = if gamma < F::one() {Some(bellman_residual/(F::one() - gamma))} else {None};
+
+        return Ok(OptimalityCertificate {gamma, tolerance, n_states: q_values.len(), bellman_residual, value_error_bound})
+    }
+
+    // Expected return from start_dist computed from the values, as
+    // expected_return, and from the discounted occupancy of the policy, as
+    // visitation_frequencies, the two agreeing within the tolerance for
+    // values of the policy. Discrepancies are logged with the "tracing"
+    // feature.
+    pub fn check_occupancy(&self, start_dist: &HashMap<i64,F>, gamma: F, tolerance: F) -> OccupancyCheck<F> {
+        let value_return = self.expected_return(start_dist);
+        let occupancy = self.visitation_frequencies(start_dist, gamma);
+        let occupancy_return = helper::match_mul_sum_compensated(&occupancy, &self.calc_policy_rewards());
+
+        let check = OccupancyCheck {value_return, occupancy_return, tolerance};
+        #[cfg(feature = "tracing")]
+        if !check.is_consistent() {
+            tracing::warn!(value_return = value_return.as_f64(), occupancy_return = occupancy_return.as_f64(), "the values don't match the occupancy of the policy");
+        }
+        return check
+    }
+
+    // Shortest path the policy can follow from start to a state failing
+    // certify_optimality, None when no such state can be reached. Among
+    // paths of the same length the most likely one is kept.
+    pub fn suboptimality_trace(&self, start: i64, gamma: F, tolerance: F) -> Option<SuboptimalityTrace<F>> {
+
+        let gaps: FastMap<i64,OptimalityGap<F>> = match self.certify_optimality(gamma, tolerance) {
+            Ok(_) => return None,
+            Err(gaps) => gaps.into_iter().map(|gap| (gap.state, gap)).collect(),
+        };
+
+        // Breadth first over the transitions the policy can take, each state
+        // keeping the most likely step leading to it from the previous layer
+        let model = self.system_state.get_dense();
+        let mut reached: FastMap<i64,(F, Option<TraceStep<F>>)> = FastMap::default();
+        reached.insert(start, (F::one(), None));
+        let mut layer: Vec<i64> = vec![start];
+        let mut found: Option<i64> = if gaps.contains_key(&start) {Some(start)} else {None};
+
+        while found.is_none() && !layer.is_empty() {
+            let mut next_layer: FastMap<i64,(F, TraceStep<F>)> = FastMap::default();
+
+            for id in &layer {
+                let (path_prob, _) = reached.get(id).unwrap();
+                let path_prob = *path_prob;
+
+                let pos = match model.get_position(id) {
+                    Some(pos) => pos,
+                    None => continue,
+                };
+
+                for (action, action_prob) in self.policy[pos].iter().enumerate().filter(|(_, prob)| **prob > F::zero()) {
+                    for (pos_next, transition_prob) in model.get_successors(pos, action) {
+                        let id_next = &model.get_ids()[*pos_next];
+                        if *transition_prob <= F::zero() || reached.contains_key(id_next) {
+                            continue;
+                        }
+
+                        let prob = *action_prob*(*transition_prob);
+                        let step = TraceStep {
+                            state: *id,
+                            value: self.policy_evaluation[pos],
+                            action: model.get_actions(pos)[action].clone(),
+                            next_state: *id_next,
+                            prob,
+                        };
+
+                        let better = match next_layer.get(id_next) {
+                            Some((best_prob, best_step)) => path_prob*prob > *best_prob
+                                || (path_prob*prob == *best_prob && (step.state, &step.action) < (best_step.state, &best_step.action)),
+                            None => true,
+                        };
+                        if better {
+                            next_layer.insert(*id_next, (path_prob*prob, step));
+                        }
+                    }
+                }
+            }
+
+            let mut ids: Vec<i64> = next_layer.keys().cloned().collect();
+            ids.sort();
+
+            found = ids.iter().filter(|id| gaps.contains_key(*id))
+                .max_by(|a, b| next_layer.get(*a).unwrap().0.partial_cmp(&next_layer.get(*b).unwrap().0).unwrap().then(b.cmp(a)))
+                .cloned();
+
+            for (id, (prob, step)) in next_layer {
+                reached.insert(id, (prob, Some(step)));
+            }
+            layer = ids;
+        }
+
+        let end = found?;
+
+        let mut steps: Vec<TraceStep<F>> = Vec::new();
+        let mut current = end;
+        while let Some((_, Some(step))) = reached.get(&current) {
+            current = step.state;
+            steps.push(step.clone());
+        }
+        steps.reverse();
+
+        let path_prob = reached.get(&end).unwrap().0;
+        let gap = gaps.get(&end).unwrap().clone();
+        let policy_action = crate::render::greedy_action(self.get_policy().get(&end).unwrap()).cloned().unwrap_or_default();
+        let discounted_loss = path_prob*gamma.powi(steps.len() as i32)*gap.gap;
+
+        return Some(SuboptimalityTrace {steps, gap, policy_action, path_prob, discounted_loss})
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::envs::GamblersProblem;
+    use crate::models;
+
+    #[test]
+    fn certify_optimality_test() {
+        let links = vec![
+            models::StateLink(0, 2, "Now".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Later".to_string(), 1., 0.),
+            models::StateLink(1, 2, "Take".to_string(), 1., 3.),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+
+        // The random policy takes Now half of the time
+        agent.evaluate_policy(0.9, 1e-12, 100);
+        let gaps = agent.certify_optimality(0.9, 1e-6).unwrap_err();
+        assert_eq!(gaps.len(), 1);
+        assert_eq!(gaps[0].state, 0);
+        assert_eq!(gaps[0].best_action, "Later");
+        assert!((gaps[0].gap - (2.7 - 1.85)).abs() < 1e-9);
+        assert!(gaps[0].residual.abs() < 1e-9);
+
+        agent.deterministic_policy_improvement(0.9, 1e-12, 100, 100);
+        let certificate = agent.certify_optimality(0.9, 1e-6).unwrap();
+        assert_eq!(certificate.n_states, 3);
+        assert!(certificate.bellman_residual < 1e-9);
+        assert!(certificate.value_error_bound.unwrap() < 1e-8);
+
+        // Values that are not those of the policy
+        let mut agent: Agent = Agent::init_random(GamblersProblem::new(10, 0.4).unwrap().build());
+        agent.deterministic_policy_improvement(1., 1e-12, 100, 1000);
+        assert!(agent.certify_optimality(1., 1e-9).is_ok());
+        let mut values = agent.policy_evaluation.clone();
+        values[agent.system_state.get_dense().get_position(&5).unwrap()] = 1.;
+        agent.replace_values(values);
+        let gaps = agent.certify_optimality(1., 1e-9).unwrap_err();
+        assert!(gaps.iter().any(|gap| gap.state == 5 && gap.residual > 0.));
+    }
+
+    #[test]
+    fn check_occupancy_test() {
+        let mut agent: Agent = Agent::init_random(GamblersProblem::new(10, 0.4).unwrap().build());
+        let start = HashMap::from([(3, 0.5), (7, 0.5)]);
+        agent.deterministic_policy_improvement(0.9, 1e-12, 100, 1000);
+        let check = agent.check_occupancy(&start, 0.9, 1e-9);
+        assert!(check.is_consistent(), "{:?}", check);
+        assert!(check.value_return > 0.);
+
+        // An evaluation stopped after one sweep
+        let mut agent: Agent = Agent::init_random(GamblersProblem::new(10, 0.4).unwrap().build());
+        agent.evaluate_policy(0.9, 1e-12, 1);
+        let check = agent.check_occupancy(&start, 0.9, 1e-9);
+        assert!(!check.is_consistent());
+        assert!(check.get_difference() < 0.);
+    }
+
+    #[test]
+    fn suboptimality_trace_test() {
+        // A corridor 0 -> 1 -> 2 where only state 2 has a choice, the policy
+        // sticking to the worse one
+        let links = vec![
+            models::StateLink(0, 1, "Walk".to_string(), 1., 0.),
+            models::StateLink(1, 2, "Walk".to_string(), 0.5, 0.),
+            models::StateLink(1, 0, "Walk".to_string(), 0.5, 0.),
+            models::StateLink(2, 3, "Small".to_string(), 1., 1.),
+            models::StateLink(2, 3, "Large".to_string(), 1., 2.),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        let mut policy = agent.get_policy().clone();
+        policy.insert(2, HashMap::from([("Small".to_string(), 1.), ("Large".to_string(), 0.)]));
+        agent.set_polity(policy);
+        agent.evaluate_policy(0.9, 1e-12, 1000);
+
+        let trace = agent.suboptimality_trace(0, 0.9, 1e-6).unwrap();
+        assert_eq!(trace.steps.len(), 2);
+        assert_eq!((trace.steps[0].state, trace.steps[0].next_state), (0, 1));
+        assert_eq!((trace.steps[1].state, trace.steps[1].next_state), (1, 2));
+        assert_eq!(trace.gap.state, 2);
+        assert_eq!(trace.gap.best_action, "Large");
+        assert_eq!(trace.policy_action, "Small");
+        assert_eq!(trace.path_prob, 0.5);
+        assert!((trace.discounted_loss - 0.5*0.81).abs() < 1e-9);
+        assert!(trace.to_string().contains("state 2: the policy plays Small, Large is better by 1.0000"));
+
+        // Nothing to show from the end, or once solved
+        assert!(agent.suboptimality_trace(3, 0.9, 1e-6).is_none());
+        agent.deterministic_policy_improvement(0.9, 1e-12, 100, 1000);
+        assert!(agent.suboptimality_trace(0, 0.9, 1e-6).is_none());
+    }
+
+}
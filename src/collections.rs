@@ -0,0 +1,9 @@
+// Maps and sets of the models and agents: those of std, or of hashbrown
+// without the "std" feature. Both have the same API, so code using these
+// builds either way.
+
+#[cfg(feature = "std")]
+pub use std::collections::{HashMap, HashSet};
+
+#[cfg(not(feature = "std"))]
+pub use hashbrown::{HashMap, HashSet};
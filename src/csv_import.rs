@@ -0,0 +1,263 @@
+// CSV import of models, enabled with the "csv" feature.
+//
+// Each row of the file is a link. A CsvMapping tells which columns, by
+// header name, hold the parts of the link, and which other columns are kept
+// as metadata of the link rather than ignored. It also sets the delimiter
+// and how loosely cells are read, as exports from databases and data frame
+// libraries seldom write numbers exactly the same way.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+// How cells are turned into ids and numbers
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvCoercion {
+    // Whitespace around cells is removed
+    pub trim: bool,
+    // Ids written as floats with no fractional part, such as "3.0", are read
+    // as integers
+    pub float_ids: bool,
+    // Probabilities may be written as percentages, such as "25%"
+    pub percentages: bool,
+    // Reward of the links whose reward cell is empty, None for an error
+    pub default_reward: Option<f64>,
+}
+
+impl Default for CsvCoercion {
+    fn default() -> CsvCoercion {
+        return CsvCoercion {trim: true, float_ids: false, percentages: false, default_reward: None}
+    }
+}
+
+// Header names of the columns of a link, the metadata columns and the
+// format of the file. The default columns are those of the NDJSON links:
+// prev_state, new_state, action, probability and reward.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvMapping {
+    pub prev_state: String,
+    pub new_state: String,
+    pub action: String,
+    pub probability: String,
+    pub reward: String,
+    // Columns kept for each link, the other columns being ignored
+    pub metadata: Vec<String>,
+    pub delimiter: u8,
+    pub coercion: CsvCoercion,
+}
+
+impl Default for CsvMapping {
+    fn default() -> CsvMapping {
+        return CsvMapping {
+            prev_state: "prev_state".to_string(),
+            new_state: "new_state".to_string(),
+            action: "action".to_string(),
+            probability: "probability".to_string(),
+            reward: "reward".to_string(),
+            metadata: Vec::new(),
+            delimiter: b',',
+            coercion: CsvCoercion::default(),
+        }
+    }
+}
+
+impl CsvMapping {
+
+    pub fn new() -> CsvMapping {
+        return CsvMapping::default()
+    }
+
+    pub fn with_prev_state(mut self, column: &str) -> CsvMapping {
+        self.prev_state = column.to_string();
+        return self
+    }
+
+    pub fn with_new_state(mut self, column: &str) -> CsvMapping {
+        self.new_state = column.to_string();
+        return self
+    }
+
+    pub fn with_action(mut self, column: &str) -> CsvMapping {
+        self.action = column.to_string();
+        return self
+    }
+
+    pub fn with_probability(mut self, column: &str) -> CsvMapping {
+        self.probability = column.to_string();
+        return self
+    }
+
+    pub fn with_reward(mut self, column: &str) -> CsvMapping {
+        self.reward = column.to_string();
+        return self
+    }
+
+    pub fn with_metadata(mut self, columns: &[&str]) -> CsvMapping {
+        self.metadata = columns.iter().map(|column| column.to_string()).collect();
+        return self
+    }
+
+    pub fn with_delimiter(mut self, delimiter: u8) -> CsvMapping {
+        self.delimiter = delimiter;
+        return self
+    }
+
+    pub fn with_coercion(mut self, coercion: CsvCoercion) -> CsvMapping {
+        self.coercion = coercion;
+        return self
+    }
+
+}
+
+#[derive(Debug)]
+pub enum CsvError {
+    // Reading the file failed, or a row is malformed
+    Csv(csv::Error),
+    // A column of the mapping is not in the header
+    MissingColumn {column: String},
+    // A cell that can't be read as its part of a link, lines counted from 1
+    InvalidCell {line: u64, column: String, value: String},
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            CsvError::Csv(error) => write!(f, "{}", error),
+            CsvError::MissingColumn {column} => write!(f, "no column {} in the header", column),
+            CsvError::InvalidCell {line, column, value} => write!(f, "line {}: invalid {} \"{}\"", line, column, value),
+        }
+    }
+}
+
+impl std::error::Error for CsvError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            CsvError::Csv(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<csv::Error> for CsvError {
+    fn from(error: csv::Error) -> CsvError {
+        return CsvError::Csv(error)
+    }
+}
+
+// Links of a file, with the metadata columns of each
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvLinks<F: Real = f64> {
+    pub links: Vec<StateLink<F>>,
+    // Metadata of each link, in the order of links
+    pub metadata: Vec<HashMap<String,String>>,
+}
+
+impl<F: Real> CsvLinks<F> {
+
+    pub fn build(self) -> SystemState<F> {
+        return SystemState::create_and_build(self.links)
+    }
+
+}
+
+// Position of a column in the header
+fn column_position(headers: &csv::StringRecord, column: &str) -> Result<usize, CsvError> {
+    return headers.iter().position(|header| header == column)
+        .ok_or_else(|| CsvError::MissingColumn {column: column.to_string()})
+}
+
+fn parse_id(cell: &str, coercion: &CsvCoercion) -> Option<i64> {
+    if let Ok(id) = cell.parse::<i64>() {
+        return Some(id)
+    }
+    let value: f64 = cell.parse().ok().filter(|_| coercion.float_ids)?;
+    return if value.fract() == 0. && value.abs() < 2f64.powi(63) {Some(value as i64)} else {None}
+}
+
+fn parse_probability(cell: &str, coercion: &CsvCoercion) -> Option<f64> {
+    return match cell.strip_suffix('%') {
+        Some(percentage) if coercion.percentages => percentage.trim_end().parse::<f64>().ok().map(|value| value/100.),
+        _ => cell.parse().ok(),
+    }
+}
+
+pub fn read_links_csv<R: Read, F: Real>(reader: R, mapping: &CsvMapping) -> Result<CsvLinks<F>, CsvError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter)
+        .trim(if mapping.coercion.trim {csv::Trim::All} else {csv::Trim::None})
+        .from_reader(reader);
+
+    let headers = reader.headers()?.clone();
+    let prev_state = column_position(&headers, &mapping.prev_state)?;
+    let new_state = column_position(&headers, &mapping.new_state)?;
+    let action = column_position(&headers, &mapping.action)?;
+    let probability = column_position(&headers, &mapping.probability)?;
+    let reward = column_position(&headers, &mapping.reward)?;
+    let metadata: Vec<(&String, usize)> = mapping.metadata.iter()
+        .map(|column| column_position(&headers, column).map(|pos| (column, pos)))
+        .collect::<Result<_, _>>()?;
+
+    let mut links = CsvLinks {links: Vec::new(), metadata: Vec::new()};
+    let mut record = csv::StringRecord::new();
+    while reader.read_record(&mut record)? {
+        let line = record.position().map(|position| position.line()).unwrap_or(0);
+        let cell = |pos: usize| record.get(pos).unwrap_or("");
+        let invalid = |column: &String, pos: usize| CsvError::InvalidCell {line, column: column.clone(), value: cell(pos).to_string()};
+
+        let id_prev = parse_id(cell(prev_state), &mapping.coercion).ok_or_else(|| invalid(&mapping.prev_state, prev_state))?;
+        let id_next = parse_id(cell(new_state), &mapping.coercion).ok_or_else(|| invalid(&mapping.new_state, new_state))?;
+        let prob = parse_probability(cell(probability), &mapping.coercion).ok_or_else(|| invalid(&mapping.probability, probability))?;
+        let link_reward = match cell(reward) {
+            "" => mapping.coercion.default_reward,
+            value => value.parse::<f64>().ok(),
+        }.ok_or_else(|| invalid(&mapping.reward, reward))?;
+
+        links.links.push(StateLink(id_prev, id_next, cell(action).to_string(), F::from_f64(prob), F::from_f64(link_reward)));
+        links.metadata.push(metadata.iter().map(|(column, pos)| ((*column).clone(), cell(*pos).to_string())).collect());
+    }
+
+    return Ok(links)
+}
+
+pub fn read_model_csv<R: Read, F: Real>(reader: R, mapping: &CsvMapping) -> Result<SystemState<F>, CsvError> {
+    return Ok(read_links_csv(reader, mapping)?.build())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn csv_mapping_test() {
+        let text = "source;to;from;move;p;gain\n\
+                    sim;1.0;0;Go;25%;2\n\
+                    sim; 0 ;0;Go;75%;\n\
+                    log;1;1;Stay;1;0.5\n";
+        let mapping = CsvMapping::new()
+            .with_prev_state("from").with_new_state("to").with_action("move").with_probability("p").with_reward("gain")
+            .with_metadata(&["source"])
+            .with_delimiter(b';');
+
+        // Strict by default
+        let error = read_links_csv::<_, f64>(text.as_bytes(), &mapping).unwrap_err();
+        assert_eq!(error.to_string(), "line 2: invalid to \"1.0\"");
+
+        let coercion = CsvCoercion {float_ids: true, percentages: true, default_reward: Some(0.), ..CsvCoercion::default()};
+        let links: CsvLinks = read_links_csv(text.as_bytes(), &mapping.clone().with_coercion(coercion)).unwrap();
+        assert_eq!(links.links, vec![
+            StateLink(0, 1, "Go".to_string(), 0.25, 2.),
+            StateLink(0, 0, "Go".to_string(), 0.75, 0.),
+            StateLink(1, 1, "Stay".to_string(), 1., 0.5),
+        ]);
+        assert_eq!(links.metadata[2]["source"], "log");
+        assert_eq!(links.build().get_all_states().len(), 2);
+
+        let error = read_model_csv::<_, f64>(text.as_bytes(), &CsvMapping::new()).unwrap_err();
+        assert!(matches!(error, CsvError::MissingColumn {column} if column == "prev_state"));
+    }
+
+}
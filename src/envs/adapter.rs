@@ -0,0 +1,154 @@
+use std::marker::PhantomData;
+
+use crate::envs::Environment;
+
+// Environment of another crate seen as an Environment, through closures
+// calling its own methods, so that the simulator, learners and enumerators
+// of this crate run on it
+pub struct FnEnvironment<E, S, A, Reset, Step, Legal> {
+    inner: E,
+    reset: Reset,
+    step: Step,
+    legal_actions: Legal,
+    phantom: PhantomData<fn(A) -> S>,
+}
+
+impl<E, S, A, Reset, Step, Legal> FnEnvironment<E, S, A, Reset, Step, Legal>
+where Reset: FnMut(&mut E) -> S, Step: FnMut(&mut E, A) -> (S, f64, bool), Legal: Fn(&E, &S) -> Vec<A> {
+
+    pub fn new(inner: E, reset: Reset, step: Step, legal_actions: Legal) -> FnEnvironment<E, S, A, Reset, Step, Legal> {
+        return FnEnvironment {inner, reset, step, legal_actions, phantom: PhantomData}
+    }
+
+    pub fn get_inner(&self) -> &E {
+        return &self.inner
+    }
+
+    pub fn into_inner(self) -> E {
+        return self.inner
+    }
+
+}
+
+impl<E, S, A, Reset, Step, Legal> Environment for FnEnvironment<E, S, A, Reset, Step, Legal>
+where Reset: FnMut(&mut E) -> S, Step: FnMut(&mut E, A) -> (S, f64, bool), Legal: Fn(&E, &S) -> Vec<A> {
+
+    type State = S;
+    type Action = A;
+
+    fn reset(&mut self) -> S {
+        return (self.reset)(&mut self.inner)
+    }
+
+    fn step(&mut self, action: A) -> (S, f64, bool) {
+        return (self.step)(&mut self.inner, action)
+    }
+
+    fn legal_actions(&self, state: &S) -> Vec<A> {
+        return (self.legal_actions)(&self.inner, state)
+    }
+
+}
+
+// Environment that keeps its current state, the shape other crates' traits
+// usually take: the learner asks for the state and its actions, and taking an
+// action only returns the reward. Implementing such a trait for a session of
+// a ModelEnvironment, or any other environment, takes a line per method.
+pub struct EnvironmentSession<E: Environment> {
+    env: E,
+    state: E::State,
+    done: bool,
+}
+
+impl<E: Environment> EnvironmentSession<E> {
+
+    // Starts the first episode
+    pub fn new(mut env: E) -> EnvironmentSession<E> {
+        let state = env.reset();
+        return EnvironmentSession {env, state, done: false}
+    }
+
+    pub fn current_state(&self) -> &E::State {
+        return &self.state
+    }
+
+    pub fn legal_actions(&self) -> Vec<E::Action> {
+        return self.env.legal_actions(&self.state)
+    }
+
+    pub fn is_done(&self) -> bool {
+        return self.done
+    }
+
+    // Steps the environment and returns the reward, the state moving on.
+    // Once the episode is over, restart it.
+    pub fn take_action(&mut self, action: E::Action) -> f64 {
+        let (state, reward, done) = self.env.step(action);
+        self.state = state;
+        self.done = done;
+        return reward
+    }
+
+    pub fn restart(&mut self) -> &E::State {
+        self.state = self.env.reset();
+        self.done = false;
+        return &self.state
+    }
+
+    pub fn get_env(&self) -> &E {
+        return &self.env
+    }
+
+    pub fn into_env(self) -> E {
+        return self.env
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // Stands for an environment of another crate, with its own interface
+    struct Corridor {
+        position: u32,
+    }
+
+    impl Corridor {
+        fn start(&mut self) -> u32 {
+            self.position = 0;
+            return self.position
+        }
+
+        fn advance(&mut self, forward: bool) -> (u32, f64, bool) {
+            if forward {
+                self.position += 1;
+            }
+            return (self.position, if self.position == 3 {1.} else {0.}, self.position == 3)
+        }
+    }
+
+    #[test]
+    fn adapter_test() {
+        let env = FnEnvironment::new(
+            Corridor {position: 2},
+            |corridor: &mut Corridor| corridor.start(),
+            |corridor: &mut Corridor, forward: bool| corridor.advance(forward),
+            |_: &Corridor, position: &u32| if *position < 3 {vec![true, false]} else {Vec::new()},
+        );
+
+        let mut session = EnvironmentSession::new(env);
+        assert_eq!(*session.current_state(), 0);
+        assert_eq!(session.legal_actions(), vec![true, false]);
+        let rewards: Vec<f64> = [true, false, true, true].into_iter().map(|forward| session.take_action(forward)).collect();
+        assert_eq!(rewards, vec![0., 0., 0., 1.]);
+        assert!(session.is_done());
+        assert!(session.legal_actions().is_empty());
+
+        assert_eq!(*session.restart(), 0);
+        assert!(!session.is_done());
+        assert_eq!(session.into_env().into_inner().position, 0);
+    }
+
+}
@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+// Admission control of an M/M/1 queue. Customers arrive at the arrival rate
+// and are served one at a time at the service rate, with room for at most
+// capacity customers. Before each event the controller decides whether the
+// next arrival is let in, earning the admission reward, or turned away.
+// Every customer in the system costs the holding cost per unit of time.
+//
+// The continuous-time queue is uniformized: each step is one event of the
+// total rate arrival_rate + service_rate, an arrival with probability
+// arrival_rate/total and a service otherwise, which leaves an empty queue
+// unchanged. Holding costs are those of the mean time between events. States
+// are the numbers of customers, from 0 to capacity, and actions are "Accept"
+// and "Reject", only "Reject" when the queue is full. It's usually solved for
+// its average reward, or with a discount factor close to one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdmissionControl {
+    capacity: i64,
+    arrival_rate: f64,
+    service_rate: f64,
+    admission_reward: f64,
+    holding_cost: f64,
+}
+
+impl AdmissionControl {
+
+    // None when the capacity is below one or a rate is not positive
+    pub fn new(capacity: i64, arrival_rate: f64, service_rate: f64) -> Option<AdmissionControl> {
+        if capacity < 1 || !(arrival_rate > 0. && service_rate > 0.) {
+            return None
+        }
+        return Some(AdmissionControl {capacity, arrival_rate, service_rate, admission_reward: 10., holding_cost: 1.})
+    }
+
+    pub fn with_admission_reward(mut self, admission_reward: f64) -> AdmissionControl {
+        self.admission_reward = admission_reward;
+        return self
+    }
+
+    // Cost of each customer in the system per unit of time
+    pub fn with_holding_cost(mut self, holding_cost: f64) -> AdmissionControl {
+        self.holding_cost = holding_cost;
+        return self
+    }
+
+    pub fn get_capacity(&self) -> i64 {
+        return self.capacity
+    }
+
+    // Rate of the events of the uniformized chain, one per step
+    pub fn uniformization_rate(&self) -> f64 {
+        return self.arrival_rate + self.service_rate
+    }
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        let mut links: Vec<StateLink<F>> = Vec::new();
+        let rate = self.uniformization_rate();
+        let arrival_prob = self.arrival_rate/rate;
+
+        for queue in 0..=self.capacity {
+            let holding = -self.holding_cost*queue as f64/rate;
+            let actions: &[&str] = if queue < self.capacity {&["Accept", "Reject"]} else {&["Reject"]};
+
+            for action in actions {
+                // Outcomes by next state, the probability and the
+                // probability-weighted reward, an empty queue's service and a
+                // rejected arrival leading to the same state
+                let mut outcomes: BTreeMap<i64,(f64, f64)> = BTreeMap::new();
+                let arrival = if *action == "Accept" {(queue + 1, self.admission_reward)} else {(queue, 0.)};
+                for (next_state, prob, reward) in [(arrival.0, arrival_prob, arrival.1), ((queue - 1).max(0), 1. - arrival_prob, 0.)] {
+                    let outcome = outcomes.entry(next_state).or_insert((0., 0.));
+                    outcome.0 += prob;
+                    outcome.1 += prob*reward;
+                }
+
+                for (next_state, (prob, reward)) in outcomes {
+                    links.push(StateLink(queue, next_state, action.to_string(), F::from_f64(prob), F::from_f64(holding + reward/prob)));
+                }
+            }
+        }
+
+        return links
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn admission_test() {
+        assert!(AdmissionControl::new(0, 1., 1.).is_none());
+        assert!(AdmissionControl::new(5, 1., 0.).is_none());
+
+        let queue = AdmissionControl::new(10, 1., 1.).unwrap().with_holding_cost(2.);
+        let system_state: SystemState = queue.build();
+        assert_eq!(system_state.states().count(), 11);
+        assert_eq!(system_state.get_state(&10).unwrap().actions().collect::<Vec<&str>>(), vec!["Reject"]);
+
+        // An empty queue stays empty unless an arrival is accepted
+        assert_eq!(system_state.get_state(&0).unwrap().get_probs("Reject").unwrap()[&0], 1.);
+        let accept = system_state.get_state(&0).unwrap().get_eval_rewards()["Accept"];
+        assert!((accept - 5.).abs() < 1e-12);
+        for state in system_state.states() {
+            for action in state.actions() {
+                assert!((state.get_probs(action).unwrap().values().sum::<f64>() - 1.).abs() < 1e-12);
+            }
+        }
+
+        // The optimal policy admits customers up to a threshold
+        let mut agent: Agent = Agent::init_random(system_state);
+        agent.deterministic_policy_improvement(0.99, 1e-9, 100, 100_000);
+        let actions: Vec<String> = (0..10).map(|n| agent.get_best_action(n).unwrap().0.clone()).collect();
+        let threshold = actions.iter().position(|action| action == "Reject").unwrap();
+        assert!(threshold > 0);
+        assert!(actions[threshold..].iter().all(|action| action == "Reject"));
+    }
+
+}
@@ -0,0 +1,189 @@
+use rand::Rng;
+
+use crate::envs::Environment;
+
+// Blackjack as in Sutton and Barto (example 5.1), drawn from an infinite
+// deck. The player starts with two cards and draws ("Hit") until they stick
+// ("Stick") or go over 21, losing at once. The dealer then draws until
+// reaching 17 or more, and the larger sum wins, a dealer going over 21
+// losing. Rewards are 1, 0 and -1 for wins, draws and losses, given at the
+// end. Aces count 11 unless that takes the hand over 21, such an ace being
+// usable. Naturals are not treated apart.
+//
+// States are the player's sum from 12 to 21, the card the dealer shows and
+// whether the player has a usable ace, 200 states with ids from 0 to 199.
+// Sums below 12 are drawn to automatically, as hitting can't lose there.
+// Episodes end in BlackjackEnv::TERMINAL. The dealer's draws make the model
+// awkward to enumerate, which is why it comes as an environment, the usual
+// testbed for Monte Carlo control.
+pub struct BlackjackEnv<R: Rng> {
+    rng: R,
+    player_sum: u32,
+    usable_ace: bool,
+    dealer_card: u32,
+    done: bool,
+}
+
+// Card value, 1 for an ace and 10 for face cards
+fn draw_card<R: Rng>(rng: &mut R) -> u32 {
+    return rng.random_range(1..=13u32).min(10)
+}
+
+// Sum of a hand and whether an ace counts 11, given the sum with aces as 1
+fn hand_value(sum: u32, has_ace: bool) -> (u32, bool) {
+    return if has_ace && sum + 10 <= 21 {(sum + 10, true)} else {(sum, false)}
+}
+
+impl<R: Rng> BlackjackEnv<R> {
+
+    pub const TERMINAL: i64 = -1;
+
+    pub fn new(rng: R) -> BlackjackEnv<R> {
+        return BlackjackEnv {rng, player_sum: 0, usable_ace: false, dealer_card: 0, done: true}
+    }
+
+    // Id of a player sum from 12 to 21, a dealer card from 1 (ace) to 10 and
+    // a usable ace
+    pub fn encode(player_sum: u32, dealer_card: u32, usable_ace: bool) -> i64 {
+        return ((player_sum as i64 - 12)*10 + dealer_card as i64 - 1)*2 + usable_ace as i64
+    }
+
+    // (player sum, dealer card, usable ace) of a state id
+    pub fn decode(id: i64) -> (u32, u32, bool) {
+        return ((id/20 + 12) as u32, ((id/2) % 10 + 1) as u32, id % 2 == 1)
+    }
+
+    fn state(&self) -> i64 {
+        return if self.done {BlackjackEnv::<R>::TERMINAL} else {BlackjackEnv::<R>::encode(self.player_sum, self.dealer_card, self.usable_ace)}
+    }
+
+    // Adds a card to the player's hand
+    fn player_draws(&mut self) {
+        let card = draw_card(&mut self.rng);
+        let hard_sum = if self.usable_ace {self.player_sum - 10} else {self.player_sum} + card;
+        (self.player_sum, self.usable_ace) = hand_value(hard_sum, self.usable_ace || card == 1);
+    }
+
+    // Dealer's final sum, drawing from the card shown
+    fn dealer_plays(&mut self) -> u32 {
+        let (mut sum, mut has_ace) = (self.dealer_card, self.dealer_card == 1);
+        loop {
+            let (value, _) = hand_value(sum, has_ace);
+            if value >= 17 {
+                return value
+            }
+            let card = draw_card(&mut self.rng);
+            sum += card;
+            has_ace = has_ace || card == 1;
+        }
+    }
+
+}
+
+impl<R: Rng> Environment for BlackjackEnv<R> {
+
+    type State = i64;
+    type Action = String;
+
+    fn reset(&mut self) -> i64 {
+        (self.player_sum, self.usable_ace) = (0, false);
+        self.player_draws();
+        self.player_draws();
+        while self.player_sum < 12 {
+            self.player_draws();
+        }
+        self.dealer_card = draw_card(&mut self.rng);
+        self.done = false;
+        return self.state()
+    }
+
+    // Unknown actions, or any action once the episode is over, end it in
+    // place without reward
+    fn step(&mut self, action: String) -> (i64, f64, bool) {
+        if self.done {
+            return (self.state(), 0., true)
+        }
+
+        let reward = match action.as_str() {
+            "Hit" => {
+                self.player_draws();
+                if self.player_sum <= 21 {
+                    return (self.state(), 0., false)
+                }
+                -1.
+            },
+            "Stick" => {
+                let dealer_sum = self.dealer_plays();
+                if dealer_sum > 21 || self.player_sum > dealer_sum {1.} else if self.player_sum == dealer_sum {0.} else {-1.}
+            },
+            _ => 0.,
+        };
+
+        self.done = true;
+        return (self.state(), reward, true)
+    }
+
+    fn legal_actions(&self, state: &i64) -> Vec<String> {
+        if *state == BlackjackEnv::<R>::TERMINAL {
+            return Vec::new()
+        }
+        return vec!["Hit".to_string(), "Stick".to_string()]
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use rand::rngs::StdRng;
+    use rand::{RngCore, SeedableRng};
+
+    use super::*;
+    use crate::simulator::Simulator;
+
+    type Blackjack = BlackjackEnv<StdRng>;
+
+    #[test]
+    fn blackjack_test() {
+        assert_eq!(Blackjack::encode(12, 1, false), 0);
+        assert_eq!(Blackjack::encode(21, 10, true), 199);
+        assert_eq!(Blackjack::decode(Blackjack::encode(17, 6, true)), (17, 6, true));
+
+        let mut env = Blackjack::new(StdRng::seed_from_u64(3));
+        for _ in 0..1000 {
+            let state = env.reset();
+            assert!((0..200).contains(&state));
+            let (state, reward, done) = env.step("Stick".to_string());
+            assert_eq!((state, done), (Blackjack::TERMINAL, true));
+            assert!([-1., 0., 1.].contains(&reward));
+            assert!(env.legal_actions(&state).is_empty());
+        }
+
+        // Hitting until bust loses
+        env.reset();
+        loop {
+            let (_, reward, done) = env.step("Hit".to_string());
+            if done {
+                assert_eq!(reward, -1.);
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn blackjack_policy_test() {
+        // Sticking only on 20 or 21 loses on average, less than hitting to
+        // the end
+        let stick_on_20 = |state: &i64, _: &[String], _: &mut dyn RngCore| {
+            let (player_sum, _, _) = Blackjack::decode(*state);
+            Some(if player_sum >= 20 {"Stick".to_string()} else {"Hit".to_string()})
+        };
+        let mut simulator = Simulator::with_rng(Blackjack::new(StdRng::seed_from_u64(5)), StdRng::seed_from_u64(5));
+        let estimate = simulator.estimate_return(&stick_on_20, 20_000, 1.);
+        assert!(estimate.mean < 0. && estimate.mean > -0.4);
+
+        let always_hit = |_: &i64, _: &[String], _: &mut dyn RngCore| Some("Hit".to_string());
+        assert_eq!(simulator.estimate_return(&always_hit, 1000, 1.).mean, -1.);
+    }
+
+}
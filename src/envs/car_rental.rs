@@ -0,0 +1,173 @@
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+// Jack's car rental. Two locations rent cars during the day and get some of
+// them back, requests and returns being Poisson distributed. Every night cars
+// can be moved between the locations at a cost. Rented cars earn a reward,
+// requests for missing cars are lost, and cars beyond a location's capacity
+// are sent away.
+//
+// States are the numbers of cars (n_1, n_2) at the end of a day, with id
+// n_1*(max_cars + 1) + n_2. Actions are "Move_<k>", moving k cars from the
+// first location to the second, or -k cars the other way, from -max_move to
+// max_move and limited by the cars available. The default is the original
+// problem with 441 states, each action reaching every state, so around two
+// million links. Tail probabilities of the Poisson distributions are lumped
+// into the last reachable outcome, so the transitions of every action sum to
+// one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JacksCarRental {
+    pub max_cars: i64,
+    pub max_move: i64,
+    pub move_cost: f64,
+    pub rental_reward: f64,
+    pub request_rates: [f64; 2],
+    pub return_rates: [f64; 2],
+}
+
+impl Default for JacksCarRental {
+    fn default() -> JacksCarRental {
+        return JacksCarRental {
+            max_cars: 20,
+            max_move: 5,
+            move_cost: 2.,
+            rental_reward: 10.,
+            request_rates: [3., 4.],
+            return_rates: [3., 2.],
+        }
+    }
+}
+
+pub(super) fn poisson_pmf(rate: f64, n: i64) -> f64 {
+    let log_pmf = n as f64*rate.ln() - rate - (1..=n).map(|k| (k as f64).ln()).sum::<f64>();
+    return if rate == 0. {if n == 0 {1.} else {0.}} else {log_pmf.exp()}
+}
+
+impl JacksCarRental {
+
+    pub fn state_id(&self, cars_1: i64, cars_2: i64) -> i64 {
+        return cars_1*(self.max_cars + 1) + cars_2
+    }
+
+    pub fn move_action(cars: i64) -> String {
+        return format!("Move_{}", cars)
+    }
+
+    // Distribution of the cars at a location at the end of the day when it
+    // starts with the given cars, as probabilities of each count and the
+    // probability-weighted rental reward leading to it
+    fn day_outcomes(&self, location: usize, cars: i64) -> (Vec<f64>, Vec<f64>) {
+        let n_outcomes = (self.max_cars + 1) as usize;
+        let mut probs = vec![0.; n_outcomes];
+        let mut rewards = vec![0.; n_outcomes];
+
+        let mut request_tail = 1.;
+        for rented in 0..=cars {
+            // Requests beyond the available cars all rent every car
+            let request_prob = if rented < cars {poisson_pmf(self.request_rates[location], rented)} else {request_tail};
+            request_tail -= request_prob;
+
+            let left = cars - rented;
+            let mut return_tail = 1.;
+            for returned in 0..=(self.max_cars - left) {
+                // Returns beyond the capacity all end at max_cars
+                let return_prob = if left + returned < self.max_cars {poisson_pmf(self.return_rates[location], returned)} else {return_tail};
+                return_tail -= return_prob;
+
+                let prob = request_prob*return_prob;
+                probs[(left + returned) as usize] += prob;
+                rewards[(left + returned) as usize] += prob*self.rental_reward*rented as f64;
+            }
+        }
+
+        return (probs, rewards)
+    }
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        let mut links: Vec<StateLink<F>> = Vec::new();
+
+        // Day outcomes only depend on the cars in the morning, computed once per count
+        let outcomes: Vec<Vec<(Vec<f64>, Vec<f64>)>> = (0..2)
+            .map(|location| (0..=self.max_cars).map(|cars| self.day_outcomes(location, cars)).collect())
+            .collect();
+
+        for cars_1 in 0..=self.max_cars {
+            for cars_2 in 0..=self.max_cars {
+                for moved in -self.max_move.min(cars_2)..=self.max_move.min(cars_1) {
+                    let action = JacksCarRental::move_action(moved);
+                    let move_reward = -self.move_cost*moved.abs() as f64;
+                    let (probs_1, rewards_1) = &outcomes[0][(cars_1 - moved).min(self.max_cars) as usize];
+                    let (probs_2, rewards_2) = &outcomes[1][(cars_2 + moved).min(self.max_cars) as usize];
+
+                    for end_1 in 0..=self.max_cars as usize {
+                        for end_2 in 0..=self.max_cars as usize {
+                            let prob = probs_1[end_1]*probs_2[end_2];
+                            if prob <= 0. {
+                                continue;
+                            }
+
+                            // Expected reward given the outcome, as the reward of the link
+                            let reward = move_reward + rewards_1[end_1]/probs_1[end_1] + rewards_2[end_2]/probs_2[end_2];
+                            links.push(StateLink(
+                                self.state_id(cars_1, cars_2),
+                                self.state_id(end_1 as i64, end_2 as i64),
+                                action.clone(),
+                                F::from_f64(prob),
+                                F::from_f64(reward),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+
+        return links
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn car_rental_test() {
+        let rental = JacksCarRental {max_cars: 6, max_move: 2, ..JacksCarRental::default()};
+        let system_state: SystemState = rental.build();
+        assert_eq!(system_state.get_all_states().len(), 49);
+
+        // Moves are limited by the cars available
+        let empty = system_state.get_state(&rental.state_id(0, 0)).unwrap();
+        assert_eq!(empty.get_all_probs().len(), 1);
+        assert_eq!(system_state.get_state(&rental.state_id(1, 6)).unwrap().get_all_probs().len(), 4);
+
+        // Transitions of every action sum to one
+        for state in system_state.get_all_states().values() {
+            for probs in state.get_all_probs().values() {
+                assert!((probs.values().sum::<f64>() - 1.).abs() < 1e-12);
+            }
+        }
+
+        // Nothing to rent with no cars in the morning
+        assert!(empty.get_eval_rewards().get("Move_0").unwrap().abs() < 1e-12);
+
+        // With full locations and no move the expected reward is that of the truncated requests
+        let expected_rented: f64 = (0..2).map(|location| {
+            let rate = rental.request_rates[location];
+            (0..6).map(|n| n as f64*poisson_pmf(rate, n)).sum::<f64>()
+                + 6.*(1. - (0..6).map(|n| poisson_pmf(rate, n)).sum::<f64>())
+        }).sum();
+
+        let full = system_state.get_state(&rental.state_id(6, 6)).unwrap();
+        let rewards: &HashMap<String,f64> = full.get_eval_rewards();
+        assert!((rewards.get("Move_0").unwrap() - 10.*expected_rented).abs() < 1e-9);
+    }
+
+}
@@ -0,0 +1,180 @@
+use crate::envs::Environment;
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+// Moves as (action, row offset, column offset)
+const MOVES: [(&str, i64, i64); 4] = [("Up", -1, 0), ("Right", 0, 1), ("Down", 1, 0), ("Left", 0, -1)];
+
+const STEP_REWARD: f64 = -1.;
+const CLIFF_REWARD: f64 = -100.;
+
+// Deterministic gridworld with a cliff along the bottom row between the start
+// in the bottom left corner and the goal in the bottom right one. Every move
+// costs one, stepping into the cliff costs a hundred and sends the agent back
+// to the start, moves into the border leave it in place. The goal ends the
+// episode.
+//
+// States are numbered row by row from the top left corner, actions are
+// "Up", "Right", "Down" and "Left". The same dynamics are available as an
+// Environment, for learners, and as a SystemState, for exact solving.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CliffWalking {
+    n_rows: usize,
+    n_cols: usize,
+    position: i64,
+}
+
+impl Default for CliffWalking {
+    fn default() -> CliffWalking {
+        return CliffWalking::with_size(4, 12).unwrap()
+    }
+}
+
+impl CliffWalking {
+
+    // The classic 4x12 grid
+    pub fn new() -> CliffWalking {
+        return CliffWalking::default()
+    }
+
+    // None when the grid is too small to have a cliff between start and goal
+    pub fn with_size(n_rows: usize, n_cols: usize) -> Option<CliffWalking> {
+        if n_rows < 2 || n_cols < 3 {
+            return None
+        }
+
+        let mut cliff_walking = CliffWalking {n_rows, n_cols, position: 0};
+        cliff_walking.position = cliff_walking.get_start_state();
+
+        return Some(cliff_walking)
+    }
+
+    pub fn get_size(&self) -> (usize, usize) {
+        return (self.n_rows, self.n_cols)
+    }
+
+    pub fn state_id(&self, row: usize, col: usize) -> i64 {
+        return (row*self.n_cols + col) as i64
+    }
+
+    pub fn get_start_state(&self) -> i64 {
+        return self.state_id(self.n_rows - 1, 0)
+    }
+
+    pub fn get_goal_state(&self) -> i64 {
+        return self.state_id(self.n_rows - 1, self.n_cols - 1)
+    }
+
+    pub fn is_cliff(&self, state: i64) -> bool {
+        let (row, col) = (state as usize / self.n_cols, state as usize % self.n_cols);
+        return row == self.n_rows - 1 && col > 0 && col < self.n_cols - 1
+    }
+
+    // Next state and reward, None for unknown actions
+    pub fn transition(&self, state: i64, action: &str) -> Option<(i64, f64)> {
+        let (_, d_row, d_col) = MOVES.iter().find(|(name, _, _)| *name == action)?;
+        let row = (state / self.n_cols as i64 + d_row).clamp(0, self.n_rows as i64 - 1);
+        let col = (state % self.n_cols as i64 + d_col).clamp(0, self.n_cols as i64 - 1);
+        let next_state = row*self.n_cols as i64 + col;
+
+        if self.is_cliff(next_state) {
+            return Some((self.get_start_state(), CLIFF_REWARD))
+        }
+
+        return Some((next_state, STEP_REWARD))
+    }
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        let mut links: Vec<StateLink<F>> = Vec::new();
+
+        for state in 0..(self.n_rows*self.n_cols) as i64 {
+            if self.is_cliff(state) || state == self.get_goal_state() {
+                continue;
+            }
+
+            for (action, _, _) in MOVES {
+                let (next_state, reward) = self.transition(state, action).unwrap();
+                links.push(StateLink(state, next_state, action.to_string(), F::one(), F::from_f64(reward)));
+            }
+        }
+
+        return links
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+}
+
+impl Environment for CliffWalking {
+
+    type State = i64;
+    type Action = String;
+
+    fn reset(&mut self) -> i64 {
+        self.position = self.get_start_state();
+        return self.position
+    }
+
+    // Unknown actions end the episode in place without reward
+    fn step(&mut self, action: String) -> (i64, f64, bool) {
+        return match self.transition(self.position, &action) {
+            Some((next_state, reward)) => {
+                self.position = next_state;
+                (next_state, reward, next_state == self.get_goal_state())
+            },
+            None => (self.position, 0., true),
+        }
+    }
+
+    fn legal_actions(&self, state: &i64) -> Vec<String> {
+        if *state == self.get_goal_state() {
+            return Vec::new()
+        }
+        return MOVES.iter().map(|(action, _, _)| action.to_string()).collect()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn cliff_walking_test() {
+        assert!(CliffWalking::with_size(1, 12).is_none());
+
+        let mut env = CliffWalking::new();
+        assert_eq!(env.reset(), 36);
+        assert_eq!(env.step("Right".to_string()), (36, CLIFF_REWARD, false));
+        assert_eq!(env.step("Left".to_string()), (36, STEP_REWARD, false));
+        assert_eq!(env.step("Up".to_string()), (24, STEP_REWARD, false));
+
+        let system_state: SystemState = env.build();
+
+        // Cliff states are never reached
+        assert_eq!(system_state.get_all_states().len(), 38);
+
+        // The optimal path walks along the cliff edge, 13 steps
+        let mut agent: Agent = Agent::init_random(system_state);
+        agent.deterministic_policy_improvement(1., 1e-8, 1000, 1000);
+        assert_eq!(*agent.get_evaluation().get(&env.get_start_state()).unwrap(), -13.);
+
+        // Following the solved policy in the environment ends at the goal
+        let mut state = env.reset();
+        let mut total = 0.;
+        for _ in 0..13 {
+            let (action, _) = agent.get_best_action(state).unwrap();
+            let (next_state, reward, _) = env.step(action.clone());
+            state = next_state;
+            total += reward;
+        }
+        assert_eq!(state, env.get_goal_state());
+        assert!(env.legal_actions(&state).is_empty());
+        assert_eq!(total, -13.);
+    }
+
+}
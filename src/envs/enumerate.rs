@@ -0,0 +1,429 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::Hash;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::envs::{Environment, GenerativeModel};
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchOrder {
+    BreadthFirst,
+    DepthFirst,
+}
+
+// Expands the states reachable from a start state into links. States are
+// given ids in the order they are found, states found again are not expanded
+// twice, so cycles are fine.
+//
+// With a state limit the expansion stops before a state whose successors
+// would exceed it. States left unexpanded have no links and look terminal in
+// the resulting model, which is then marked as truncated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enumerator {
+    order: SearchOrder,
+    max_states: Option<usize>,
+    // Threads of the parallel enumerations, None for rayon's global pool
+    threads: Option<usize>,
+}
+
+impl Default for Enumerator {
+    fn default() -> Enumerator {
+        return Enumerator {order: SearchOrder::BreadthFirst, max_states: None, threads: None}
+    }
+}
+
+// Actions of an expanded state, each with its outcomes as (next state,
+// probability, reward, context to expand the next state)
+type Expansion<S, C> = Vec<(String, Vec<(S, f64, f64, Option<C>)>)>;
+
+// States found by an Enumerator and the links between them
+#[derive(Debug, Clone)]
+pub struct Enumeration<S> {
+    states: Vec<S>,
+    ids: HashMap<S,i64>,
+    links: Vec<StateLink>,
+    truncated: bool,
+}
+
+impl Enumerator {
+
+    pub fn new() -> Enumerator {
+        return Enumerator::default()
+    }
+
+    pub fn with_order(mut self, order: SearchOrder) -> Enumerator {
+        self.order = order;
+        return self
+    }
+
+    pub fn with_max_states(mut self, max_states: usize) -> Enumerator {
+        self.max_states = Some(max_states);
+        return self
+    }
+
+    // Threads of the parallel enumerations, with the "rayon" feature
+    pub fn with_threads(mut self, threads: usize) -> Enumerator {
+        self.threads = Some(threads);
+        return self
+    }
+
+    // Environments with deterministic dynamics, expanded by stepping clones
+    // of the environment left in each state. The start state is the one
+    // returned by reset.
+    pub fn enumerate_environment<E>(&self, env: &E) -> Enumeration<E::State>
+    where E: Environment + Clone, E::State: Eq + Hash + Clone, E::Action: ToString {
+
+        let mut start_env = env.clone();
+        let start = start_env.reset();
+
+        return self.expand(start, start_env, Enumerator::environment_successors)
+    }
+
+    pub fn enumerate_model<M>(&self, model: &M, start: M::State) -> Enumeration<M::State>
+    where M: GenerativeModel, M::State: Eq + Hash + Clone, M::Action: ToString {
+
+        return self.expand(start, (), |state, _| Enumerator::model_successors(model, state))
+    }
+
+    // Same as enumerate_environment, the states of each breadth-first level
+    // being expanded on several threads. The search order is ignored, the
+    // result is that of the breadth-first search.
+    #[cfg(feature = "rayon")]
+    pub fn enumerate_environment_parallel<E>(&self, env: &E) -> Enumeration<E::State>
+    where E: Environment + Clone + Send + Sync, E::State: Eq + Hash + Clone + Send + Sync, E::Action: ToString {
+
+        let mut start_env = env.clone();
+        let start = start_env.reset();
+
+        return self.expand_parallel(start, start_env, Enumerator::environment_successors)
+    }
+
+    // Same as enumerate_model, the states of each breadth-first level being
+    // expanded on several threads. The search order is ignored, the result is
+    // that of the breadth-first search.
+    #[cfg(feature = "rayon")]
+    pub fn enumerate_model_parallel<M>(&self, model: &M, start: M::State) -> Enumeration<M::State>
+    where M: GenerativeModel + Sync, M::State: Eq + Hash + Clone + Send + Sync, M::Action: ToString {
+
+        return self.expand_parallel(start, (), |state, _| Enumerator::model_successors(model, state))
+    }
+
+    fn environment_successors<E>(state: &E::State, snapshot: &E) -> Expansion<E::State, E>
+    where E: Environment + Clone, E::Action: ToString {
+
+        return snapshot.legal_actions(state).into_iter()
+            .map(|action| {
+                let action_name = action.to_string();
+                let mut next_env = snapshot.clone();
+                let (next_state, reward, done) = next_env.step(action);
+                // The episode is over in the next state, nothing left to expand
+                let next_env = if done {None} else {Some(next_env)};
+                (action_name, vec![(next_state, 1., reward, next_env)])
+            }).collect()
+    }
+
+    fn model_successors<M>(model: &M, state: &M::State) -> Expansion<M::State, ()>
+    where M: GenerativeModel, M::Action: ToString {
+
+        return model.actions(state).into_iter()
+            .map(|action| {
+                let successors = model.transitions(state, &action).into_iter()
+                    .filter(|(_, prob, _)| *prob > 0.)
+                    .map(|(next_state, prob, reward)| (next_state, prob, reward, Some(())))
+                    .collect();
+                (action.to_string(), successors)
+            }).collect()
+    }
+
+    // Search shared by environments and models. Each state is stored with a
+    // context needed to expand it, None for states that can't be expanded.
+    fn expand<S, C, X>(&self, start: S, start_context: C, successors: X) -> Enumeration<S>
+    where S: Eq + Hash + Clone, X: Fn(&S, &C) -> Expansion<S, C> {
+
+        let mut enumeration = Enumeration::start(&start);
+        let mut frontier: VecDeque<(S, C)> = VecDeque::from([(start, start_context)]);
+
+        loop {
+            let next = match self.order {
+                SearchOrder::BreadthFirst => frontier.pop_front(),
+                SearchOrder::DepthFirst => frontier.pop_back(),
+            };
+
+            let (state, context) = match next {
+                Some(next) => next,
+                None => break,
+            };
+
+            let expansion = successors(&state, &context);
+            match self.merge(&mut enumeration, &state, expansion) {
+                Some(new_frontier) => frontier.extend(new_frontier),
+                None => break,
+            }
+        }
+
+        return enumeration
+    }
+
+    // Breadth-first search expanding each level of states in parallel, the
+    // states of a level being shared between threads by work stealing. The
+    // expansions are then merged in order, so the ids and links are those of
+    // the sequential breadth-first search.
+    #[cfg(feature = "rayon")]
+    fn expand_parallel<S, C, X>(&self, start: S, start_context: C, successors: X) -> Enumeration<S>
+    where S: Eq + Hash + Clone + Send + Sync, C: Send, X: Fn(&S, &C) -> Expansion<S, C> + Sync {
+
+        let pool = self.threads.and_then(|n| rayon::ThreadPoolBuilder::new().num_threads(n).build().ok());
+        let mut enumeration = Enumeration::start(&start);
+        let mut level: Vec<(S, C)> = vec![(start, start_context)];
+
+        while !level.is_empty() {
+            let expand_level = |level: Vec<(S, C)>| -> Vec<(S, Expansion<S, C>)> {
+                level.into_par_iter()
+                    .map(|(state, context)| {
+                        let expansion = successors(&state, &context);
+                        (state, expansion)
+                    }).collect()
+            };
+            let expansions = match &pool {
+                Some(pool) => pool.install(|| expand_level(level)),
+                None => expand_level(level),
+            };
+
+            let mut next_level: Vec<(S, C)> = Vec::new();
+            for (state, expansion) in expansions {
+                match self.merge(&mut enumeration, &state, expansion) {
+                    Some(new_frontier) => next_level.extend(new_frontier),
+                    None => return enumeration,
+                }
+            }
+            level = next_level;
+        }
+
+        return enumeration
+    }
+
+    // Adds the links of an expanded state and ids for the states it leads to,
+    // returning the new states to expand. States found while expanding are
+    // only kept if all of them fit in the limit, otherwise the enumeration is
+    // marked as truncated and None returned.
+    fn merge<S, C>(&self, enumeration: &mut Enumeration<S>, state: &S, expansion: Expansion<S, C>) -> Option<Vec<(S, C)>>
+    where S: Eq + Hash + Clone {
+
+        let id_prev = *enumeration.ids.get(state).unwrap();
+
+        let mut new_states: Vec<S> = Vec::new();
+        let mut new_frontier: Vec<(S, C)> = Vec::new();
+        let mut links: Vec<StateLink> = Vec::new();
+
+        for (action, outcomes) in expansion {
+            let mut merged: BTreeMap<i64,(f64,f64)> = BTreeMap::new();
+
+            for (next_state, prob, reward, next_context) in outcomes {
+                let id_next = match enumeration.ids.get(&next_state) {
+                    Some(id) => *id,
+                    None => {
+                        let id = (enumeration.states.len() + new_states.len()) as i64;
+                        enumeration.ids.insert(next_state.clone(), id);
+                        new_states.push(next_state.clone());
+                        if let Some(next_context) = next_context {
+                            new_frontier.push((next_state, next_context));
+                        }
+                        id
+                    },
+                };
+
+                let entry = merged.entry(id_next).or_insert((0., 0.));
+                entry.0 += prob;
+                entry.1 += prob*reward;
+            }
+
+            // Rewards of merged outcomes are averaged by probability
+            for (id_next, (prob, weighted_reward)) in merged {
+                links.push(StateLink(id_prev, id_next, action.clone(), prob, weighted_reward/prob));
+            }
+        }
+
+        let n_states = enumeration.states.len() + new_states.len();
+        if self.max_states.is_some_and(|max_states| n_states > max_states) {
+            for new_state in &new_states {
+                enumeration.ids.remove(new_state);
+            }
+            enumeration.truncated = true;
+            return None
+        }
+
+        enumeration.states.extend(new_states);
+        enumeration.links.extend(links);
+        return Some(new_frontier)
+    }
+
+}
+
+impl<S: Eq + Hash + Clone> Enumeration<S> {
+
+    fn start(start: &S) -> Enumeration<S> {
+        return Enumeration {
+            states: vec![start.clone()],
+            ids: HashMap::from([(start.clone(), 0)]),
+            links: Vec::new(),
+            truncated: false,
+        }
+    }
+
+}
+
+impl<S> Enumeration<S> {
+
+    pub fn get_states(&self) -> &Vec<S> {
+        return &self.states
+    }
+
+    pub fn get_state(&self, id: i64) -> Option<&S> {
+        return usize::try_from(id).ok().and_then(|pos| self.states.get(pos))
+    }
+
+    pub fn len(&self) -> usize {
+        return self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.states.is_empty()
+    }
+
+    // Whether the state limit stopped the expansion
+    pub fn is_truncated(&self) -> bool {
+        return self.truncated
+    }
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        return self.links.iter()
+            .map(|link| StateLink(link.0, link.1, link.2.clone(), F::from_f64(link.3), F::from_f64(link.4)))
+            .collect()
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+    // Whether some state can be reached again from itself, in which case
+    // solving without discount relies on the policy leaving the cycle
+    pub fn has_cycles(&self) -> bool {
+        // Removes states without remaining successors until none is left,
+        // states on a cycle are never removed
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); self.states.len()];
+        let mut n_successors: Vec<usize> = vec![0; self.states.len()];
+        for link in &self.links {
+            predecessors[link.1 as usize].push(link.0 as usize);
+            n_successors[link.0 as usize] += 1;
+        }
+
+        let mut removable: Vec<usize> = (0..self.states.len()).filter(|pos| n_successors[*pos] == 0).collect();
+        let mut n_removed = 0;
+
+        while let Some(pos) = removable.pop() {
+            n_removed += 1;
+            for prev in &predecessors[pos] {
+                n_successors[*prev] -= 1;
+                if n_successors[*prev] == 0 {
+                    removable.push(*prev);
+                }
+            }
+        }
+
+        return n_removed < self.states.len()
+    }
+
+}
+
+impl<S: Eq + Hash> Enumeration<S> {
+
+    pub fn get_id(&self, state: &S) -> Option<i64> {
+        return self.ids.get(state).cloned()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::envs::{CliffWalking, GamblersProblem};
+
+    #[test]
+    fn environment_enumeration_test() {
+        let env = CliffWalking::new();
+
+        let breadth_first = Enumerator::new().enumerate_environment(&env);
+        let depth_first = Enumerator::new().with_order(SearchOrder::DepthFirst).enumerate_environment(&env);
+
+        // Cliff cells send back to the start and are never states
+        assert_eq!(breadth_first.len(), 38);
+        assert_eq!(depth_first.len(), 38);
+        assert!(!breadth_first.is_truncated());
+        assert!(breadth_first.has_cycles());
+        assert_eq!(breadth_first.get_state(0), Some(&36));
+
+        // Ids are in discovery order, the links match the generator's up to relabelling
+        let system_state: SystemState = breadth_first.build();
+        assert_eq!(system_state.get_all_states().len(), 38);
+        assert_eq!(breadth_first.get_links::<f64>().len(), env.get_links::<f64>().len());
+
+        let goal = breadth_first.get_id(&env.get_goal_state()).unwrap();
+        assert!(system_state.get_state(&goal).unwrap().get_all_probs().is_empty());
+
+        let truncated = Enumerator::new().with_max_states(10).enumerate_environment(&env);
+        assert!(truncated.is_truncated());
+        assert!(truncated.len() <= 10);
+    }
+
+    #[test]
+    fn model_enumeration_test() {
+        // Only capitals reachable from 25 with stakes of the gambler
+        let system_state: SystemState = GamblersProblem::new(100, 0.4).unwrap().build();
+        let enumeration = Enumerator::new().enumerate_model(&system_state, 25);
+
+        assert_eq!(enumeration.len(), 101);
+        assert!(enumeration.has_cycles());
+
+        let links: Vec<StateLink> = enumeration.get_links();
+        let start_links: Vec<&StateLink> = links.iter().filter(|link| link.0 == 0).collect();
+        assert_eq!(start_links.len(), 2*25);
+        assert!(start_links.iter().all(|link| link.3 == 0.4 || link.3 == 0.6));
+
+        // Without cycles
+        let system_state: SystemState = SystemState::create_and_build(vec![
+            StateLink(0, 1, "a".to_string(), 0.5, 1.),
+            StateLink(0, 2, "a".to_string(), 0.5, 0.),
+            StateLink(1, 2, "a".to_string(), 1., 1.),
+            StateLink(5, 0, "a".to_string(), 1., 1.),
+        ]);
+        let enumeration = Enumerator::new().enumerate_model(&system_state, 0);
+        assert_eq!(enumeration.len(), 3);
+        assert!(!enumeration.has_cycles());
+        assert_eq!(enumeration.get_id(&5), None);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_enumeration_test() {
+        // Same ids and links as the sequential breadth-first search
+        let env = CliffWalking::new();
+        let sequential = Enumerator::new().enumerate_environment(&env);
+        for enumerator in [Enumerator::new(), Enumerator::new().with_order(SearchOrder::DepthFirst).with_threads(3)] {
+            let parallel = enumerator.enumerate_environment_parallel(&env);
+            assert_eq!(parallel.get_states(), sequential.get_states());
+            assert_eq!(parallel.get_links::<f64>(), sequential.get_links::<f64>());
+        }
+
+        let system_state: SystemState = GamblersProblem::new(100, 0.4).unwrap().build();
+        let sequential = Enumerator::new().with_max_states(40).enumerate_model(&system_state, 25);
+        let parallel = Enumerator::new().with_max_states(40).enumerate_model_parallel(&system_state, 25);
+        assert!(parallel.is_truncated());
+        assert_eq!(parallel.get_states(), sequential.get_states());
+        assert_eq!(parallel.get_links::<f64>(), sequential.get_links::<f64>());
+    }
+
+}
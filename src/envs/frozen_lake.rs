@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LakeTile {
+    Start,
+    Frozen,
+    Hole,
+    Goal,
+}
+
+impl LakeTile {
+
+    fn from_char(tile: char) -> Option<LakeTile> {
+        return match tile {
+            'S' => Some(LakeTile::Start),
+            'F' => Some(LakeTile::Frozen),
+            'H' => Some(LakeTile::Hole),
+            'G' => Some(LakeTile::Goal),
+            _ => None,
+        }
+    }
+
+}
+
+// Moves in the order Left, Down, Right, Up, as (row, column) offsets
+const MOVES: [(&str, i64, i64); 4] = [("Left", 0, -1), ("Down", 1, 0), ("Right", 0, 1), ("Up", -1, 0)];
+
+// Slipperiness of the usual slippery lake, the three possible directions are equally likely
+pub const DEFAULT_SLIPPERINESS: f64 = 2./3.;
+
+// Gridworld on a frozen lake. The agent walks from the start to the goal,
+// moves slip with some probability to one of the two perpendicular
+// directions, and moves into the border leave it in place. Reaching the goal
+// gives a reward of one, holes and the goal end the episode.
+//
+// States are numbered row by row from the top left corner, actions are
+// "Left", "Down", "Right" and "Up".
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrozenLake {
+    tiles: Vec<Vec<LakeTile>>,
+    slipperiness: f64,
+}
+
+impl FrozenLake {
+
+    // Map rows made of 'S' (start), 'F' (frozen), 'H' (hole) and 'G' (goal).
+    // None when rows have different lengths, a tile is unknown, there isn't
+    // exactly one start or the slipperiness is not in [0, 1].
+    pub fn from_map(rows: &[&str], slipperiness: f64) -> Option<FrozenLake> {
+
+        let tiles: Vec<Vec<LakeTile>> = rows.iter()
+            .map(|row| row.chars().map(LakeTile::from_char).collect::<Option<Vec<LakeTile>>>())
+            .collect::<Option<Vec<Vec<LakeTile>>>>()?;
+
+        let n_cols = tiles.first().map(|row| row.len()).unwrap_or(0);
+        let n_starts = tiles.iter().flatten().filter(|tile| **tile == LakeTile::Start).count();
+
+        if n_cols == 0 || tiles.iter().any(|row| row.len() != n_cols) || n_starts != 1 || !(0. ..=1.).contains(&slipperiness) {
+            return None
+        }
+
+        return Some(FrozenLake {tiles, slipperiness})
+    }
+
+    pub fn four_by_four(slipperiness: f64) -> FrozenLake {
+        return FrozenLake::from_map(&[
+            "SFFF",
+            "FHFH",
+            "FFFH",
+            "HFFG",
+        ], slipperiness).unwrap()
+    }
+
+    pub fn eight_by_eight(slipperiness: f64) -> FrozenLake {
+        return FrozenLake::from_map(&[
+            "SFFFFFFF",
+            "FFFFFFFF",
+            "FFFHFFFF",
+            "FFFFFHFF",
+            "FFFHFFFF",
+            "FHHFFFHF",
+            "FHFFHFHF",
+            "FFFHFFFG",
+        ], slipperiness).unwrap()
+    }
+
+    pub fn get_size(&self) -> (usize, usize) {
+        return (self.tiles.len(), self.tiles[0].len())
+    }
+
+    pub fn get_tile(&self, row: usize, col: usize) -> LakeTile {
+        return self.tiles[row][col]
+    }
+
+    pub fn state_id(&self, row: usize, col: usize) -> i64 {
+        return (row*self.tiles[0].len() + col) as i64
+    }
+
+    pub fn get_start_state(&self) -> i64 {
+        let (n_rows, n_cols) = self.get_size();
+        let start = (0..n_rows*n_cols)
+            .find(|pos| self.tiles[pos/n_cols][pos % n_cols] == LakeTile::Start)
+            .unwrap();
+        return start as i64
+    }
+
+    fn move_from(&self, row: usize, col: usize, direction: usize) -> i64 {
+        let (n_rows, n_cols) = self.get_size();
+        let (_, d_row, d_col) = MOVES[direction];
+        let new_row = (row as i64 + d_row).clamp(0, n_rows as i64 - 1) as usize;
+        let new_col = (col as i64 + d_col).clamp(0, n_cols as i64 - 1) as usize;
+        return self.state_id(new_row, new_col)
+    }
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        let (n_rows, n_cols) = self.get_size();
+        let goal_ids: Vec<i64> = (0..n_rows*n_cols)
+            .filter(|pos| self.tiles[pos/n_cols][pos % n_cols] == LakeTile::Goal)
+            .map(|pos| pos as i64)
+            .collect();
+
+        let mut links: Vec<StateLink<F>> = Vec::new();
+
+        for row in 0..n_rows {
+            for col in 0..n_cols {
+                if matches!(self.tiles[row][col], LakeTile::Hole | LakeTile::Goal) {
+                    continue;
+                }
+
+                for (direction, (action, _, _)) in MOVES.iter().enumerate() {
+                    // Slipping sideways and moving into the border can lead to
+                    // the same state, probabilities are merged per successor
+                    let mut probs: BTreeMap<i64,f64> = BTreeMap::new();
+                    *probs.entry(self.move_from(row, col, direction)).or_default() += 1. - self.slipperiness;
+                    *probs.entry(self.move_from(row, col, (direction + 1) % 4)).or_default() += self.slipperiness/2.;
+                    *probs.entry(self.move_from(row, col, (direction + 3) % 4)).or_default() += self.slipperiness/2.;
+
+                    for (next_state, prob) in probs {
+                        if prob > 0. {
+                            let reward = if goal_ids.contains(&next_state) {1.} else {0.};
+                            links.push(StateLink(self.state_id(row, col), next_state, action.to_string(), F::from_f64(prob), F::from_f64(reward)));
+                        }
+                    }
+                }
+            }
+        }
+
+        return links
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn frozen_lake_test() {
+        assert!(FrozenLake::from_map(&["SF", "F"], 0.).is_none());
+        assert!(FrozenLake::from_map(&["SX"], 0.).is_none());
+        assert!(FrozenLake::from_map(&["FG"], 0.).is_none());
+
+        let lake = FrozenLake::four_by_four(DEFAULT_SLIPPERINESS);
+        let system_state: SystemState = lake.build();
+        assert_eq!(system_state.get_all_states().len(), 16);
+
+        // Left from the start stays in place with probability 2/3
+        let start = system_state.get_state(&0).unwrap();
+        let probs = start.get_probs("Left").unwrap();
+        assert!((probs.get(&0).unwrap() - 2./3.).abs() < 1e-12);
+        assert!((probs.get(&4).unwrap() - 1./3.).abs() < 1e-12);
+
+        // Holes and the goal are terminal
+        assert!(system_state.get_state(&5).unwrap().get_all_probs().is_empty());
+        assert!(system_state.get_state(&15).unwrap().get_all_probs().is_empty());
+
+        // Published optimal values of the slippery 4x4 lake with gamma = 0.9
+        let expected = [
+            0.069, 0.061, 0.074, 0.056,
+            0.092, 0., 0.112, 0.,
+            0.145, 0.247, 0.300, 0.,
+            0., 0.380, 0.639, 0.,
+        ];
+
+        let mut agent: Agent = Agent::init_random(system_state);
+        agent.deterministic_policy_improvement(0.9, 1e-8, 1000, 1000);
+
+        for (id, value) in expected.iter().enumerate() {
+            assert!((agent.get_evaluation().get(&(id as i64)).unwrap() - value).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn deterministic_lake_test() {
+        let lake = FrozenLake::eight_by_eight(0.);
+        assert_eq!(lake.get_size(), (8, 8));
+        assert_eq!(lake.get_tile(7, 7), LakeTile::Goal);
+
+        // Without slipping every move is deterministic
+        let system_state: SystemState = lake.build();
+        assert_eq!(system_state.get_all_states().len(), 64);
+
+        let start = system_state.get_state(&lake.get_start_state()).unwrap();
+        assert_eq!(start.get_all_probs().len(), 4);
+        for (action, next_state) in [("Left", 0), ("Down", 8), ("Right", 1), ("Up", 0)] {
+            let probs = start.get_probs(action).unwrap();
+            assert_eq!(probs.len(), 1);
+            assert_eq!(probs.get(&next_state), Some(&1.));
+        }
+
+        // Only moves into the goal are rewarded
+        let rewarded: Vec<StateLink> = lake.get_links().into_iter().filter(|link| link.4 > 0.).collect();
+        assert_eq!(rewarded.len(), 2);
+        assert!(rewarded.iter().all(|link| link.1 == 63));
+    }
+
+}
@@ -0,0 +1,97 @@
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+// Gambler's problem. A gambler with a capital between 1 and goal - 1 stakes
+// part of it on a coin flip, winning the stake with the head probability and
+// losing it otherwise. Reaching the goal gives a reward of one and ends the
+// game, as does going broke.
+//
+// States are the capital from 0 to goal, actions are "Stake_<amount>" with
+// amounts from 1 to the smaller of the capital and what is missing to reach
+// the goal. Solved without discount, the value of a state is the probability
+// of reaching the goal from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GamblersProblem {
+    goal: i64,
+    head_prob: f64,
+}
+
+impl GamblersProblem {
+
+    // None when the goal is below 2 or the head probability is not in [0, 1]
+    pub fn new(goal: i64, head_prob: f64) -> Option<GamblersProblem> {
+        if goal < 2 || !(0. ..=1.).contains(&head_prob) {
+            return None
+        }
+        return Some(GamblersProblem {goal, head_prob})
+    }
+
+    pub fn get_goal(&self) -> i64 {
+        return self.goal
+    }
+
+    pub fn get_head_prob(&self) -> f64 {
+        return self.head_prob
+    }
+
+    pub fn stake_action(stake: i64) -> String {
+        return format!("Stake_{}", stake)
+    }
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        let mut links: Vec<StateLink<F>> = Vec::new();
+
+        for capital in 1..self.goal {
+            for stake in 1..=capital.min(self.goal - capital) {
+                let action = GamblersProblem::stake_action(stake);
+                let win_reward = if capital + stake == self.goal {F::one()} else {F::zero()};
+
+                if self.head_prob > 0. {
+                    links.push(StateLink(capital, capital + stake, action.clone(), F::from_f64(self.head_prob), win_reward));
+                }
+                if self.head_prob < 1. {
+                    links.push(StateLink(capital, capital - stake, action, F::from_f64(1. - self.head_prob), F::zero()));
+                }
+            }
+        }
+
+        return links
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn gambler_test() {
+        assert!(GamblersProblem::new(1, 0.4).is_none());
+        assert!(GamblersProblem::new(100, 1.5).is_none());
+
+        let gambler = GamblersProblem::new(100, 0.4).unwrap();
+        let system_state: SystemState = gambler.build();
+        assert_eq!(system_state.get_all_states().len(), 101);
+        assert_eq!(system_state.get_state(&50).unwrap().get_all_probs().len(), 50);
+        assert_eq!(system_state.get_state(&99).unwrap().get_all_probs().len(), 1);
+
+        // With p < 0.5 bold play is optimal, V(50) = p, V(25) = p^2 and
+        // V(75) = p + (1 - p)p
+        let mut agent: Agent = Agent::init_random(system_state);
+        agent.deterministic_policy_improvement(1., 1e-10, 1000, 10_000);
+
+        let evaluation = agent.get_evaluation();
+        assert!((evaluation.get(&50).unwrap() - 0.4).abs() < 1e-6);
+        assert!((evaluation.get(&25).unwrap() - 0.16).abs() < 1e-6);
+        assert!((evaluation.get(&75).unwrap() - 0.64).abs() < 1e-6);
+        assert_eq!(*evaluation.get(&0).unwrap(), 0.);
+        assert_eq!(*evaluation.get(&100).unwrap(), 0.);
+    }
+
+}
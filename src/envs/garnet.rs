@@ -0,0 +1,114 @@
+use rand::rngs::StdRng;
+use rand::seq::index;
+use rand::{Rng, SeedableRng};
+
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+// Random MDPs (Garnets). Every state has n_actions actions named
+// "Action_<a>", each leading to branching distinct successors drawn
+// uniformly, with probabilities given by cutting [0, 1] at branching - 1
+// uniform points. Rewards are uniform in [0, 1), except for a reward_sparsity
+// fraction of the links which have no reward.
+//
+// Generation only depends on the parameters and the seed, or the rng given
+// to get_links_with_rng. There are no
+// terminal states, so the models are meant to be solved with gamma < 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Garnet {
+    pub n_states: usize,
+    pub n_actions: usize,
+    pub branching: usize,
+    pub reward_sparsity: f64,
+    pub seed: u64,
+}
+
+impl Garnet {
+
+    pub fn new(n_states: usize, n_actions: usize, branching: usize) -> Garnet {
+        return Garnet {n_states, n_actions, branching, reward_sparsity: 0., seed: 0}
+    }
+
+    pub fn is_valid(&self) -> bool {
+        return self.n_states > 0 && self.n_actions > 0
+            && self.branching > 0 && self.branching <= self.n_states
+            && (0. ..=1.).contains(&self.reward_sparsity)
+    }
+
+    // Empty when the parameters are not valid
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        return self.get_links_with_rng(&mut StdRng::seed_from_u64(self.seed))
+    }
+
+    // Links drawn from rng, the seed being ignored
+    pub fn get_links_with_rng<F: Real, R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<StateLink<F>> {
+        if !self.is_valid() {
+            return Vec::new()
+        }
+
+        let mut links: Vec<StateLink<F>> = Vec::with_capacity(self.n_states*self.n_actions*self.branching);
+
+        for state in 0..self.n_states {
+            for action in 0..self.n_actions {
+                let action = format!("Action_{}", action);
+                let successors = index::sample(rng, self.n_states, self.branching);
+
+                let mut cuts: Vec<f64> = (1..self.branching).map(|_| rng.random::<f64>()).collect();
+                cuts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                cuts.push(1.);
+
+                let mut previous = 0.;
+                for (next_state, cut) in successors.iter().zip(cuts) {
+                    let reward = if rng.random::<f64>() < self.reward_sparsity {0.} else {rng.random::<f64>()};
+                    links.push(StateLink(state as i64, next_state as i64, action.clone(), F::from_f64(cut - previous), F::from_f64(reward)));
+                    previous = cut;
+                }
+            }
+        }
+
+        return links
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn garnet_test() {
+        assert!(Garnet::new(10, 2, 0).get_links::<f64>().is_empty());
+        assert!(Garnet::new(10, 2, 11).get_links::<f64>().is_empty());
+
+        let garnet = Garnet {reward_sparsity: 0.8, seed: 3, ..Garnet::new(50, 3, 4)};
+        let links: Vec<StateLink> = garnet.get_links();
+        assert_eq!(links.len(), 50*3*4);
+        assert_eq!(links, garnet.get_links());
+        assert_ne!(links, Garnet {seed: 4, ..garnet.clone()}.get_links());
+        assert_eq!(links, garnet.get_links_with_rng(&mut StdRng::seed_from_u64(3)));
+
+        let n_rewarded = links.iter().filter(|link| link.4 > 0.).count();
+        assert!(n_rewarded > 60 && n_rewarded < 180);
+
+        let system_state: SystemState = garnet.build();
+        assert_eq!(system_state.get_all_states().len(), 50);
+        for state in system_state.get_all_states().values() {
+            assert_eq!(state.get_all_probs().len(), 3);
+            for probs in state.get_all_probs().values() {
+                assert_eq!(probs.len(), 4);
+                assert!((probs.values().sum::<f64>() - 1.).abs() < 1e-12);
+            }
+        }
+
+        let mut agent: Agent = Agent::init_random(system_state);
+        agent.evaluate_policy(0.9, 1e-8, 1000);
+        assert!(agent.get_evaluation().values().all(|value| *value >= 0. && *value < 10.));
+    }
+
+}
@@ -0,0 +1,171 @@
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+use super::car_rental::poisson_pmf;
+
+// Inventory control. Every period a store with a stock between 0 and its
+// capacity orders more units, delivered at once, then faces a random demand.
+// Units sold earn the price, unmet demand is lost at the shortage cost, and
+// the units left over pay the holding cost. Orders cost a fixed amount plus
+// a cost per unit.
+//
+// States are the stock at the start of a period, from 0 to capacity. Actions
+// are "Order_<q>" for q from 0 to what fits in the store. The demand is given
+// as its probabilities from 0 up to some largest demand. Outcomes leading to
+// the same stock are merged into one link with their expected reward, as
+// demands beyond the stock all empty it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InventoryControl {
+    capacity: i64,
+    demand: Vec<f64>,
+    fixed_order_cost: f64,
+    unit_order_cost: f64,
+    holding_cost: f64,
+    price: f64,
+    shortage_cost: f64,
+}
+
+impl InventoryControl {
+
+    // None when the capacity is negative or the demand probabilities are not
+    // in [0, 1] or don't sum to one
+    pub fn new(capacity: i64, demand: Vec<f64>) -> Option<InventoryControl> {
+        if capacity < 0 || demand.iter().any(|prob| !(0. ..=1.).contains(prob)) || (demand.iter().sum::<f64>() - 1.).abs() > 1e-9 {
+            return None
+        }
+        return Some(InventoryControl {capacity, demand, fixed_order_cost: 4., unit_order_cost: 2., holding_cost: 1., price: 8., shortage_cost: 0.})
+    }
+
+    // Demand following a Poisson distribution, the demands beyond
+    // max_demand lumped into it
+    pub fn poisson(capacity: i64, rate: f64, max_demand: i64) -> Option<InventoryControl> {
+        if rate.is_nan() || rate < 0. || max_demand < 0 {
+            return None
+        }
+        let mut demand: Vec<f64> = (0..max_demand).map(|n| poisson_pmf(rate, n)).collect();
+        demand.push(1. - demand.iter().sum::<f64>());
+        return InventoryControl::new(capacity, demand)
+    }
+
+    pub fn with_order_cost(mut self, fixed: f64, per_unit: f64) -> InventoryControl {
+        self.fixed_order_cost = fixed;
+        self.unit_order_cost = per_unit;
+        return self
+    }
+
+    // Cost of each unit left at the end of a period
+    pub fn with_holding_cost(mut self, holding_cost: f64) -> InventoryControl {
+        self.holding_cost = holding_cost;
+        return self
+    }
+
+    pub fn with_price(mut self, price: f64) -> InventoryControl {
+        self.price = price;
+        return self
+    }
+
+    // Cost of each unit of demand that couldn't be met
+    pub fn with_shortage_cost(mut self, shortage_cost: f64) -> InventoryControl {
+        self.shortage_cost = shortage_cost;
+        return self
+    }
+
+    pub fn get_capacity(&self) -> i64 {
+        return self.capacity
+    }
+
+    pub fn get_demand(&self) -> &Vec<f64> {
+        return &self.demand
+    }
+
+    pub fn order_action(quantity: i64) -> String {
+        return format!("Order_{}", quantity)
+    }
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        let mut links: Vec<StateLink<F>> = Vec::new();
+
+        for stock in 0..=self.capacity {
+            for quantity in 0..=(self.capacity - stock) {
+                let action = InventoryControl::order_action(quantity);
+                let order_cost = if quantity > 0 {self.fixed_order_cost + self.unit_order_cost*quantity as f64} else {0.};
+                let available = stock + quantity;
+
+                // Probability and probability-weighted reward of each stock left
+                let mut probs = vec![0.; (available + 1) as usize];
+                let mut rewards = vec![0.; (available + 1) as usize];
+                for (demand, prob) in self.demand.iter().enumerate() {
+                    let sold = available.min(demand as i64);
+                    let left = available - sold;
+                    let reward = self.price*sold as f64 - self.holding_cost*left as f64
+                        - self.shortage_cost*(demand as i64 - sold) as f64 - order_cost;
+                    probs[left as usize] += prob;
+                    rewards[left as usize] += prob*reward;
+                }
+
+                for (left, prob) in probs.iter().enumerate() {
+                    if *prob > 0. {
+                        links.push(StateLink(stock, left as i64, action.clone(), F::from_f64(*prob), F::from_f64(rewards[left]/prob)));
+                    }
+                }
+            }
+        }
+
+        return links
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn inventory_test() {
+        assert!(InventoryControl::new(3, vec![0.5, 0.6]).is_none());
+        assert!(InventoryControl::new(3, vec![-0.5, 1.5]).is_none());
+
+        let inventory = InventoryControl::new(2, vec![0.25, 0.5, 0.25]).unwrap();
+        let system_state: SystemState = inventory.build();
+        assert_eq!(system_state.states().count(), 3);
+        assert_eq!(system_state.get_state(&0).unwrap().actions().collect::<Vec<&str>>(), vec!["Order_0", "Order_1", "Order_2"]);
+        assert_eq!(system_state.get_state(&2).unwrap().actions().count(), 1);
+
+        // Stock never grows beyond what was available
+        for transition in system_state.transitions() {
+            assert!(transition.id_next <= transition.id + transition.action[6..].parse::<i64>().unwrap());
+        }
+        // Ordering one unit from an empty store: sold with probability 3/4,
+        // held otherwise
+        let rewards = system_state.get_state(&0).unwrap().get_eval_rewards();
+        assert!((rewards["Order_1"] - (0.75*8. - 0.25*1. - 6.)).abs() < 1e-12);
+        assert_eq!(rewards["Order_0"], 0.);
+
+        // Without fixed cost and with a high price, the store refills
+        let mut agent: Agent = Agent::init_random(inventory.with_order_cost(0., 2.).with_price(20.).build());
+        agent.deterministic_policy_improvement(0.9, 1e-6, 100, 1000);
+        assert_eq!(agent.get_best_action(0).unwrap().0, "Order_2");
+        assert_eq!(agent.get_best_action(1).unwrap().0, "Order_1");
+    }
+
+    #[test]
+    fn poisson_demand_test() {
+        let inventory = InventoryControl::poisson(5, 2., 8).unwrap();
+        assert_eq!(inventory.get_demand().len(), 9);
+        assert!((inventory.get_demand().iter().sum::<f64>() - 1.).abs() < 1e-12);
+
+        let system_state: SystemState = inventory.build();
+        for state in system_state.states() {
+            for action in state.actions() {
+                assert!((state.get_probs(action).unwrap().values().sum::<f64>() - 1.).abs() < 1e-12);
+            }
+        }
+    }
+
+}
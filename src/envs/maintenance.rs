@@ -0,0 +1,160 @@
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+// Machine maintenance. A machine wears down through condition levels, from 0
+// (new) to n_conditions - 1 (worst). Operating it costs more the worse its
+// condition is, wears it down one level with the deterioration probability
+// and makes it fail with the failure probability of its condition. Repairing
+// it brings it back one level and replacing it makes it new, each at a cost.
+// A failure costs the failure cost and ends the machine's life.
+//
+// Rewards are the opposite of the costs, so solving the model minimizes the
+// expected cost. States are the conditions, with ids 0 to n_conditions - 1,
+// and the failed state, with id n_conditions and no action. Actions are
+// "Operate" in every condition, "Repair" and "Replace" in worn conditions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineMaintenance {
+    failure_probs: Vec<f64>,
+    deterioration_prob: f64,
+    operating_cost: f64,
+    wear_cost: f64,
+    repair_cost: f64,
+    replace_cost: f64,
+    failure_cost: f64,
+}
+
+impl MachineMaintenance {
+
+    // One failure probability per condition, the number of conditions being
+    // their number. None without conditions or with probabilities outside
+    // of [0, 1].
+    pub fn new(failure_probs: Vec<f64>) -> Option<MachineMaintenance> {
+        if failure_probs.is_empty() || failure_probs.iter().any(|prob| !(0. ..=1.).contains(prob)) {
+            return None
+        }
+        return Some(MachineMaintenance {
+            failure_probs,
+            deterioration_prob: 0.3,
+            operating_cost: 1.,
+            wear_cost: 1.,
+            repair_cost: 5.,
+            replace_cost: 15.,
+            failure_cost: 100.,
+        })
+    }
+
+    // None when the probability is not in [0, 1]
+    pub fn with_deterioration_prob(mut self, deterioration_prob: f64) -> Option<MachineMaintenance> {
+        if !(0. ..=1.).contains(&deterioration_prob) {
+            return None
+        }
+        self.deterioration_prob = deterioration_prob;
+        return Some(self)
+    }
+
+    // Cost of operating a new machine, and extra cost per level of wear
+    pub fn with_operating_cost(mut self, operating_cost: f64, wear_cost: f64) -> MachineMaintenance {
+        self.operating_cost = operating_cost;
+        self.wear_cost = wear_cost;
+        return self
+    }
+
+    pub fn with_repair_cost(mut self, repair_cost: f64) -> MachineMaintenance {
+        self.repair_cost = repair_cost;
+        return self
+    }
+
+    pub fn with_replace_cost(mut self, replace_cost: f64) -> MachineMaintenance {
+        self.replace_cost = replace_cost;
+        return self
+    }
+
+    pub fn with_failure_cost(mut self, failure_cost: f64) -> MachineMaintenance {
+        self.failure_cost = failure_cost;
+        return self
+    }
+
+    pub fn n_conditions(&self) -> i64 {
+        return self.failure_probs.len() as i64
+    }
+
+    pub fn failed_state(&self) -> i64 {
+        return self.n_conditions()
+    }
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        let mut links: Vec<StateLink<F>> = Vec::new();
+        let worst = self.n_conditions() - 1;
+
+        for (condition, failure_prob) in self.failure_probs.iter().enumerate() {
+            let condition = condition as i64;
+            let cost = self.operating_cost + self.wear_cost*condition as f64;
+            let deterioration_prob = if condition < worst {self.deterioration_prob} else {0.};
+
+            let outcomes = [
+                (self.failed_state(), *failure_prob, -cost - self.failure_cost),
+                (condition + 1, (1. - failure_prob)*deterioration_prob, -cost),
+                (condition, (1. - failure_prob)*(1. - deterioration_prob), -cost),
+            ];
+            for (next_state, prob, reward) in outcomes {
+                if prob > 0. {
+                    links.push(StateLink(condition, next_state, "Operate".to_string(), F::from_f64(prob), F::from_f64(reward)));
+                }
+            }
+
+            if condition > 0 {
+                links.push(StateLink(condition, condition - 1, "Repair".to_string(), F::one(), F::from_f64(-self.repair_cost)));
+                links.push(StateLink(condition, 0, "Replace".to_string(), F::one(), F::from_f64(-self.replace_cost)));
+            }
+        }
+
+        return links
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn maintenance_test() {
+        assert!(MachineMaintenance::new(Vec::new()).is_none());
+        assert!(MachineMaintenance::new(vec![0., 1.5]).is_none());
+
+        let machine = MachineMaintenance::new(vec![0., 0.01, 0.05, 0.2]).unwrap();
+        let system_state: SystemState = machine.build();
+        assert_eq!(system_state.states().count(), 5);
+        assert_eq!(system_state.get_state(&0).unwrap().actions().collect::<Vec<&str>>(), vec!["Operate"]);
+        assert_eq!(system_state.get_state(&2).unwrap().actions().collect::<Vec<&str>>(), vec!["Operate", "Repair", "Replace"]);
+        assert_eq!(system_state.get_state(&machine.failed_state()).unwrap().actions().count(), 0);
+        for state in system_state.states() {
+            for action in state.actions() {
+                assert!((state.get_probs(action).unwrap().values().sum::<f64>() - 1.).abs() < 1e-12);
+            }
+        }
+
+        // The cheapest policy keeps the machine running, maintaining it once
+        // worn rather than letting it fail
+        let mut agent: Agent = Agent::init_random(system_state);
+        agent.deterministic_policy_improvement(0.95, 1e-9, 100, 10_000);
+        assert_eq!(agent.get_best_action(0).unwrap().0, "Operate");
+        assert_ne!(agent.get_best_action(3).unwrap().0, "Operate");
+        assert_eq!(agent[machine.failed_state()], 0.);
+        assert!(agent.reach_probability(&[machine.failed_state()])[&0] < 1.);
+
+        // Operating until failure is certain to end there
+        let mut runner: Agent = Agent::init_random(machine.build());
+        for condition in 1..4 {
+            runner.set_state_action(condition, "Operate").unwrap();
+        }
+        assert!((runner.reach_probability(&[machine.failed_state()])[&0] - 1.).abs() < 1e-9);
+    }
+
+}
@@ -0,0 +1,126 @@
+// Environments an agent interacts with step by step.
+//
+// An environment starts an episode with reset, then every step applies an
+// action and returns the next state, the reward and whether the episode is
+// over. Simulators, model-free learners and state-space enumerators are all
+// written against this trait, so any game or simulation implementing it can
+// be used with them.
+//
+// The module also has generators of classic models, built as StateLinks and
+// SystemStates ready to be solved.
+
+mod adapter;
+mod admission;
+mod car_rental;
+mod cliff_walking;
+mod enumerate;
+mod frozen_lake;
+mod gambler;
+mod inventory;
+mod maintenance;
+mod recycling;
+mod student;
+mod taxi;
+
+#[cfg(feature = "rand")]
+mod blackjack;
+
+#[cfg(feature = "rand")]
+mod garnet;
+
+#[cfg(feature = "rand")]
+mod model;
+
+#[cfg(feature = "rurel")]
+mod rurel;
+
+pub use adapter::{EnvironmentSession, FnEnvironment};
+pub use admission::AdmissionControl;
+pub use car_rental::JacksCarRental;
+pub use cliff_walking::CliffWalking;
+pub use enumerate::{Enumeration, Enumerator, SearchOrder};
+pub use frozen_lake::{FrozenLake, LakeTile, DEFAULT_SLIPPERINESS};
+pub use gambler::GamblersProblem;
+pub use inventory::InventoryControl;
+pub use maintenance::MachineMaintenance;
+pub use recycling::RecyclingRobot;
+pub use student::StudentMdp;
+pub use taxi::{Taxi, IN_TAXI};
+
+#[cfg(feature = "rand")]
+pub use blackjack::BlackjackEnv;
+
+#[cfg(feature = "rand")]
+pub use garnet::Garnet;
+
+#[cfg(feature = "rand")]
+pub use model::ModelEnvironment;
+
+#[cfg(feature = "rand")]
+pub use taxi::TaxiEnv;
+
+#[cfg(feature = "rurel")]
+pub use self::rurel::{RurelAgent, RurelEnvironment, RurelState};
+
+use crate::models::SystemState;
+use crate::numeric::Real;
+
+pub trait Environment {
+
+    type State;
+    type Action;
+
+    // Starts a new episode and returns its initial state
+    fn reset(&mut self) -> Self::State;
+
+    // Applies an action, returns (next state, reward, episode is over)
+    fn step(&mut self, action: Self::Action) -> (Self::State, f64, bool);
+
+    // Actions available in a state, empty for terminal states
+    fn legal_actions(&self, state: &Self::State) -> Vec<Self::Action>;
+
+}
+
+// Models giving the outcomes of any action from any state, as needed to
+// enumerate a state space without playing episodes
+pub trait GenerativeModel {
+
+    type State;
+    type Action;
+
+    // Actions available in a state, empty for terminal states
+    fn actions(&self, state: &Self::State) -> Vec<Self::Action>;
+
+    // Successors of a state and action as (next state, probability, reward)
+    fn transitions(&self, state: &Self::State, action: &Self::Action) -> Vec<(Self::State, f64, f64)>;
+
+}
+
+impl<F: Real> GenerativeModel for SystemState<F> {
+
+    type State = i64;
+    type Action = String;
+
+    fn actions(&self, state: &i64) -> Vec<String> {
+        let model = self.get_dense();
+        return match model.get_position(state) {
+            Some(pos) => model.get_actions(pos).clone(),
+            None => Vec::new(),
+        }
+    }
+
+    fn transitions(&self, state: &i64, action: &String) -> Vec<(i64, f64, f64)> {
+        let model = self.get_dense();
+        let (pos, index) = match model.get_position(state).and_then(|pos| Some((pos, model.get_action_index(pos, action)?))) {
+            Some(found) => found,
+            None => return Vec::new(),
+        };
+
+        // Successors are sorted by position, and positions follow the ids
+        return model.get_successors(pos, index).iter()
+            .zip(model.get_successor_rewards(pos, index))
+            .map(|((pos_next, prob), reward)| (model.get_ids()[*pos_next], prob.as_f64(), reward.as_f64()))
+            .collect()
+    }
+
+}
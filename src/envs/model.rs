@@ -0,0 +1,78 @@
+use rand::Rng;
+
+use crate::envs::Environment;
+use crate::helper;
+use crate::models::SystemState;
+use crate::numeric::Real;
+
+// A SystemState used as a generative model: next states are drawn from the
+// transition probabilities of the chosen action. Episodes end in states
+// without actions, and taking an action a state doesn't have ends the episode
+// in place without reward.
+pub struct ModelEnvironment<'a, R: Rng, F: Real = f64> {
+    system_state: &'a SystemState<F>,
+    start_state: i64,
+    current_state: i64,
+    rng: R,
+}
+
+impl<'a, R: Rng, F: Real> ModelEnvironment<'a, R, F> {
+
+    pub fn new(system_state: &'a SystemState<F>, start_state: i64, rng: R) -> ModelEnvironment<'a, R, F> {
+        return ModelEnvironment {system_state, start_state, current_state: start_state, rng}
+    }
+
+    pub fn get_current_state(&self) -> i64 {
+        return self.current_state
+    }
+
+}
+
+impl<'a, R: Rng, F: Real> Environment for ModelEnvironment<'a, R, F> {
+
+    type State = i64;
+    type Action = String;
+
+    fn reset(&mut self) -> i64 {
+        self.current_state = self.start_state;
+        return self.current_state
+    }
+
+    fn step(&mut self, action: String) -> (i64, f64, bool) {
+        let model = self.system_state.get_dense();
+        let pos = match model.get_position(&self.current_state) {
+            Some(pos) => pos,
+            None => return (self.current_state, 0., true),
+        };
+
+        let index = match model.get_action_index(pos, &action) {
+            Some(index) => index,
+            None => return (self.current_state, 0., true),
+        };
+
+        // Drawn over the successors sorted by position, so seeded runs repeat
+        let successors = model.get_successors(pos, index);
+        let successor = match helper::sample_index(successors.iter().map(|(_, prob)| *prob), &mut self.rng) {
+            Some(successor) => successor,
+            None => return (self.current_state, 0., true),
+        };
+
+        let pos_next = successors[successor].0;
+        let next_state = model.get_ids()[pos_next];
+        let reward = model.get_successor_rewards(pos, index)[successor].as_f64();
+
+        self.current_state = next_state;
+        let done = model.get_actions(pos_next).is_empty();
+
+        return (next_state, reward, done)
+    }
+
+    fn legal_actions(&self, state: &i64) -> Vec<String> {
+        let model = self.system_state.get_dense();
+        return match model.get_position(state) {
+            Some(pos) => model.get_actions(pos).clone(),
+            None => Vec::new(),
+        }
+    }
+
+}
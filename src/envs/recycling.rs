@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+// Recycling robot of Sutton and Barto (example 3.3). A robot collecting cans
+// has a high or low battery. Searching for cans earns r_search, and from a
+// high battery ends with a low one with probability 1 - alpha. From a low
+// battery it runs flat with probability 1 - beta, and the robot is carried
+// back to its charger with a high battery and the rescue reward. Waiting
+// for someone to bring a can earns r_wait without using the battery, and a
+// low battery can be recharged, earning nothing.
+//
+// States are RecyclingRobot::HIGH and LOW, actions "Search", "Wait" and,
+// with a low battery, "Recharge". The model is small enough for
+// optimal_solution to find its solution in closed form, a reference for the
+// solvers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecyclingRobot {
+    pub alpha: f64,
+    pub beta: f64,
+    pub r_search: f64,
+    pub r_wait: f64,
+    pub rescue_reward: f64,
+}
+
+impl Default for RecyclingRobot {
+    fn default() -> RecyclingRobot {
+        return RecyclingRobot {alpha: 0.8, beta: 0.6, r_search: 2., r_wait: 1., rescue_reward: -3.}
+    }
+}
+
+impl RecyclingRobot {
+
+    pub const HIGH: i64 = 0;
+    pub const LOW: i64 = 1;
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        let link = |prev: i64, next: i64, action: &str, prob: f64, reward: f64| StateLink(prev, next, action.to_string(), F::from_f64(prob), F::from_f64(reward));
+        let (high, low) = (RecyclingRobot::HIGH, RecyclingRobot::LOW);
+        let links = vec![
+            link(high, high, "Search", self.alpha, self.r_search),
+            link(high, low, "Search", 1. - self.alpha, self.r_search),
+            link(low, low, "Search", self.beta, self.r_search),
+            link(low, high, "Search", 1. - self.beta, self.rescue_reward),
+            link(high, high, "Wait", 1., self.r_wait),
+            link(low, low, "Wait", 1., self.r_wait),
+            link(low, high, "Recharge", 1., 0.),
+        ];
+
+        // Certain outcomes leave links of probability zero out
+        return links.into_iter().filter(|link| link.3 > F::zero()).collect()
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+    // Optimal policy and values for a discount factor below one, found by
+    // solving the Bellman equations of each of the six deterministic
+    // policies and keeping the best. The first by name wins among ties.
+    pub fn optimal_solution(&self, gamma: f64) -> (HashMap<i64,String>, HashMap<i64,f64>) {
+        let links: Vec<StateLink<f64>> = self.get_links();
+        // Expected reward and probability of moving to HIGH of a state and action
+        let outcome = |state: i64, action: &str| -> (f64, f64) {
+            let links: Vec<&StateLink<f64>> = links.iter().filter(|link| link.0 == state && link.2 == action).collect();
+            return (
+                links.iter().map(|link| link.3*link.4).sum(),
+                links.iter().filter(|link| link.1 == RecyclingRobot::HIGH).map(|link| link.3).sum(),
+            )
+        };
+
+        let mut best: Option<(&str, &str, f64, f64)> = None;
+        for high_action in ["Search", "Wait"] {
+            for low_action in ["Recharge", "Search", "Wait"] {
+                let (r_high, p_high) = outcome(RecyclingRobot::HIGH, high_action);
+                let (r_low, p_low) = outcome(RecyclingRobot::LOW, low_action);
+                // v_h = r_h + gamma*(p_h*v_h + (1 - p_h)*v_l)
+                // v_l = r_l + gamma*(p_l*v_h + (1 - p_l)*v_l)
+                let (a, b, c, d) = (1. - gamma*p_high, -gamma*(1. - p_high), -gamma*p_low, 1. - gamma*(1. - p_low));
+                let determinant = a*d - b*c;
+                let v_high = (r_high*d - b*r_low)/determinant;
+                let v_low = (a*r_low - c*r_high)/determinant;
+
+                // The optimal policy is the best in every state at once
+                if best.is_none_or(|(_, _, high, low)| v_high + v_low > high + low + 1e-12) {
+                    best = Some((high_action, low_action, v_high, v_low));
+                }
+            }
+        }
+
+        let (high_action, low_action, v_high, v_low) = best.unwrap();
+        return (
+            HashMap::from([(RecyclingRobot::HIGH, high_action.to_string()), (RecyclingRobot::LOW, low_action.to_string())]),
+            HashMap::from([(RecyclingRobot::HIGH, v_high), (RecyclingRobot::LOW, v_low)]),
+        )
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn recycling_robot_test() {
+        let (high, low) = (RecyclingRobot::HIGH, RecyclingRobot::LOW);
+        let robot = RecyclingRobot::default();
+        let system_state: SystemState = robot.build();
+        assert_eq!(system_state.get_state(&low).unwrap().actions().collect::<Vec<&str>>(), vec!["Recharge", "Search", "Wait"]);
+
+        // Looking far enough ahead, the robot searches and recharges when low,
+        // with values 1000/59 and 900/59. Short-sighted, it waits when low.
+        let (policy, values) = robot.optimal_solution(0.9);
+        assert_eq!(policy, HashMap::from([(high, "Search".to_string()), (low, "Recharge".to_string())]));
+        assert!((values[&high] - 1000./59.).abs() < 1e-12);
+        assert!((values[&low] - 900./59.).abs() < 1e-12);
+        assert_eq!(robot.optimal_solution(0.5).0[&low], "Wait");
+
+        // The solvers agree with the closed form
+        let mut agent: Agent = Agent::init_random(system_state);
+        agent.deterministic_policy_improvement(0.9, 1e-12, 100, 10_000);
+        for state in [high, low] {
+            assert_eq!(agent.get_best_action(state).unwrap().0, &policy[&state]);
+            assert!((agent[state] - values[&state]).abs() < 1e-9);
+        }
+    }
+
+}
@@ -0,0 +1,242 @@
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+use rurel::mdp::{Agent, State};
+
+use crate::envs::Environment;
+
+// Adapters between the environments of this crate and the traits of rurel,
+// the Q-learning crate. rurel rewards the states arrived in rather than the
+// steps, and its agents keep their current state and have no reset.
+
+// A state of an Environment as a rurel State, with the reward of the step
+// that led to it and its legal actions, none once the episode is over.
+// States compare and hash by the environment's state alone, so the Q table
+// of rurel has one entry per state however it was reached.
+#[derive(Debug, Clone)]
+pub struct RurelState<S, A> {
+    state: S,
+    reward: f64,
+    actions: Vec<A>,
+}
+
+impl<S, A> RurelState<S, A> {
+
+    pub fn get_state(&self) -> &S {
+        return &self.state
+    }
+
+}
+
+impl<S: PartialEq, A> PartialEq for RurelState<S, A> {
+    fn eq(&self, other: &RurelState<S, A>) -> bool {
+        return self.state == other.state
+    }
+}
+
+impl<S: Eq, A> Eq for RurelState<S, A> {}
+
+impl<S: Hash, A> Hash for RurelState<S, A> {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.state.hash(hasher);
+    }
+}
+
+impl<S: Eq + Hash + Clone, A: Eq + Hash + Clone> State for RurelState<S, A> {
+
+    type A = A;
+
+    fn reward(&self) -> f64 {
+        return self.reward
+    }
+
+    fn actions(&self) -> Vec<A> {
+        return self.actions.clone()
+    }
+
+}
+
+// An Environment played as a rurel Agent, such as a ModelEnvironment over a
+// built model, one episode at a time: AgentTrainer::train with rurel's
+// SinkStates runs to the end of the episode, and reset starts the next one.
+pub struct RurelAgent<E: Environment> {
+    env: E,
+    state: RurelState<E::State, E::Action>,
+}
+
+impl<E: Environment> RurelAgent<E> {
+
+    // Starts the first episode
+    pub fn new(mut env: E) -> RurelAgent<E> {
+        let state = RurelAgent::start(&mut env);
+        return RurelAgent {env, state}
+    }
+
+    pub fn reset(&mut self) {
+        self.state = RurelAgent::start(&mut self.env);
+    }
+
+    fn start(env: &mut E) -> RurelState<E::State, E::Action> {
+        let state = env.reset();
+        let actions = env.legal_actions(&state);
+        return RurelState {state, reward: 0., actions}
+    }
+
+    pub fn get_env(&self) -> &E {
+        return &self.env
+    }
+
+    pub fn into_env(self) -> E {
+        return self.env
+    }
+
+}
+
+impl<E: Environment> Agent<RurelState<E::State, E::Action>> for RurelAgent<E>
+where E::State: Eq + Hash + Clone, E::Action: Eq + Hash + Clone {
+
+    fn current_state(&self) -> &RurelState<E::State, E::Action> {
+        return &self.state
+    }
+
+    fn take_action(&mut self, action: &E::Action) {
+        let (state, reward, done) = self.env.step(action.clone());
+        let actions = if done {Vec::new()} else {self.env.legal_actions(&state)};
+        self.state = RurelState {state, reward, actions};
+    }
+
+}
+
+// A rurel Agent played as an Environment, so that the simulator, learners
+// and enumerators of this crate run on it. reset builds a new agent with
+// new_agent, the reward of a step is that of the state arrived in, and the
+// episode ends in states without actions.
+pub struct RurelEnvironment<S, G, New> {
+    agent: G,
+    new_agent: New,
+    phantom: PhantomData<fn() -> S>,
+}
+
+impl<S: State, G: Agent<S>, New: FnMut() -> G> RurelEnvironment<S, G, New> {
+
+    pub fn new(mut new_agent: New) -> RurelEnvironment<S, G, New> {
+        return RurelEnvironment {agent: new_agent(), new_agent, phantom: PhantomData}
+    }
+
+    pub fn get_agent(&self) -> &G {
+        return &self.agent
+    }
+
+}
+
+impl<S: State, G: Agent<S>, New: FnMut() -> G> Environment for RurelEnvironment<S, G, New> {
+
+    type State = S;
+    type Action = S::A;
+
+    fn reset(&mut self) -> S {
+        self.agent = (self.new_agent)();
+        return self.agent.current_state().clone()
+    }
+
+    fn step(&mut self, action: S::A) -> (S, f64, bool) {
+        self.agent.take_action(&action);
+        let state = self.agent.current_state().clone();
+        let (reward, done) = (state.reward(), state.actions().is_empty());
+        return (state, reward, done)
+    }
+
+    fn legal_actions(&self, state: &S) -> Vec<S::A> {
+        return state.actions()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn rurel_agent_test() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+        use rurel::AgentTrainer;
+        use rurel::strategy::explore::RandomExploration;
+        use rurel::strategy::learn::QLearning;
+        use rurel::strategy::terminate::SinkStates;
+
+        use crate::envs::ModelEnvironment;
+        use crate::models::{StateLink, SystemState};
+
+
+        // A sure reward of 1 to the right, a coin flip for 3 or nothing to the left
+        let system_state = SystemState::create_and_build(vec![
+            StateLink(0, 1, "Right".to_string(), 1., 1.),
+            StateLink(0, 2, "Left".to_string(), 0.5, 3.),
+            StateLink(0, 3, "Left".to_string(), 0.5, 0.),
+        ]);
+        let mut agent = RurelAgent::new(ModelEnvironment::new(&system_state, 0, StdRng::seed_from_u64(1)));
+        let start = agent.current_state().clone();
+        assert_eq!(*start.get_state(), 0);
+        assert_eq!(start.actions(), vec!["Left".to_string(), "Right".to_string()]);
+
+        let mut trainer = AgentTrainer::new();
+        for _ in 0..5000 {
+            agent.reset();
+            trainer.train(&mut agent, &QLearning::new(0.01, 1., 0.), &mut SinkStates {}, &RandomExploration::new());
+        }
+
+        // Close to the values of the model, the coin flip being worth 1.5
+        assert_eq!(trainer.best_action(&start), Some("Left".to_string()));
+        assert!((trainer.expected_value(&start, &"Right".to_string()).unwrap() - 1.).abs() < 1e-6);
+        assert!((trainer.expected_value(&start, &"Left".to_string()).unwrap() - 1.5).abs() < 0.45);
+    }
+
+    // A rurel agent walking to 3, the state being rewarded once reached
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Position(u32);
+
+    impl State for Position {
+        type A = bool;
+
+        fn reward(&self) -> f64 {
+            return if self.0 == 3 {1.} else {0.}
+        }
+
+        fn actions(&self) -> Vec<bool> {
+            return if self.0 < 3 {vec![true, false]} else {Vec::new()}
+        }
+    }
+
+    struct Walker {
+        position: Position,
+    }
+
+    impl Agent<Position> for Walker {
+        fn current_state(&self) -> &Position {
+            return &self.position
+        }
+
+        fn take_action(&mut self, forward: &bool) {
+            if *forward {
+                self.position.0 += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn rurel_environment_test() {
+        let mut env = RurelEnvironment::new(|| Walker {position: Position(0)});
+        env.step(true);
+        assert_eq!(env.get_agent().current_state().0, 1);
+
+        assert_eq!(env.reset(), Position(0));
+        assert_eq!(env.legal_actions(&Position(0)), vec![true, false]);
+        let steps: Vec<(Position, f64, bool)> = [true, false, true, true].into_iter().map(|forward| env.step(forward)).collect();
+        assert_eq!(steps.last(), Some(&(Position(3), 1., true)));
+        assert!(steps[..3].iter().all(|(_, reward, done)| *reward == 0. && !done));
+    }
+
+}
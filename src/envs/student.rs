@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+// Student MDP of David Silver's reinforcement learning course. A student
+// goes through three classes, studying (reward -2) to move on, and may drift
+// to Facebook (reward -1 per visit) instead of the first class. From the
+// second class they can go to sleep, and from the third one studying passes
+// the exam (reward 10) and ends at sleep, while going to the pub (reward 1)
+// sends them back to one of the classes at random. Sleep has no action.
+//
+// The model mixes deterministic moves, a random one and an absorbing state,
+// and its values are known exactly: optimal_values for a few discount
+// factors, and random_policy_values for the uniform policy without discount.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StudentMdp;
+
+impl StudentMdp {
+
+    pub const CLASS_1: i64 = 0;
+    pub const CLASS_2: i64 = 1;
+    pub const CLASS_3: i64 = 2;
+    pub const FACEBOOK: i64 = 3;
+    pub const SLEEP: i64 = 4;
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        let link = |prev: i64, next: i64, action: &str, prob: f64, reward: f64| StateLink(prev, next, action.to_string(), F::from_f64(prob), F::from_f64(reward));
+        return vec![
+            link(StudentMdp::CLASS_1, StudentMdp::CLASS_2, "Study", 1., -2.),
+            link(StudentMdp::CLASS_1, StudentMdp::FACEBOOK, "Facebook", 1., -1.),
+            link(StudentMdp::CLASS_2, StudentMdp::CLASS_3, "Study", 1., -2.),
+            link(StudentMdp::CLASS_2, StudentMdp::SLEEP, "Sleep", 1., 0.),
+            link(StudentMdp::CLASS_3, StudentMdp::SLEEP, "Study", 1., 10.),
+            link(StudentMdp::CLASS_3, StudentMdp::CLASS_1, "Pub", 0.2, 1.),
+            link(StudentMdp::CLASS_3, StudentMdp::CLASS_2, "Pub", 0.4, 1.),
+            link(StudentMdp::CLASS_3, StudentMdp::CLASS_3, "Pub", 0.4, 1.),
+            link(StudentMdp::FACEBOOK, StudentMdp::FACEBOOK, "Facebook", 1., -1.),
+            link(StudentMdp::FACEBOOK, StudentMdp::CLASS_1, "Quit", 1., 0.),
+        ]
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+    // Optimal values for gamma 1, 0.9 and 0.5, None for other discount
+    // factors. For all three the optimal policy studies in every class and
+    // quits Facebook.
+    pub fn optimal_values(gamma: f64) -> Option<HashMap<i64,f64>> {
+        let values: [f64; 5] = if gamma == 1. {
+            [6., 8., 10., 6., 0.]
+        } else if gamma == 0.9 {
+            [4.3, 7., 10., 3.87, 0.]
+        } else if gamma == 0.5 {
+            [-0.5, 3., 10., -0.25, 0.]
+        } else {
+            return None
+        };
+        return Some(values.iter().enumerate().map(|(id, value)| (id as i64, *value)).collect())
+    }
+
+    // Values of the uniform random policy without discount, -17/13, 35/13,
+    // 96/13 and -30/13 in the classes and on Facebook
+    pub fn random_policy_values() -> HashMap<i64,f64> {
+        return HashMap::from([
+            (StudentMdp::CLASS_1, -17./13.),
+            (StudentMdp::CLASS_2, 35./13.),
+            (StudentMdp::CLASS_3, 96./13.),
+            (StudentMdp::FACEBOOK, -30./13.),
+            (StudentMdp::SLEEP, 0.),
+        ])
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn student_mdp_test() {
+        let mut agent: Agent = Agent::init_random(StudentMdp.build());
+        agent.evaluate_policy(1., 1e-12, 10_000);
+        for (id, value) in StudentMdp::random_policy_values() {
+            assert!((agent[id] - value).abs() < 1e-9);
+        }
+
+        assert!(StudentMdp::optimal_values(0.7).is_none());
+        for gamma in [1., 0.9, 0.5] {
+            let mut agent: Agent = Agent::init_random(StudentMdp.build());
+            agent.deterministic_policy_improvement(gamma, 1e-12, 100, 10_000);
+            for (id, value) in StudentMdp::optimal_values(gamma).unwrap() {
+                assert!((agent[id] - value).abs() < 1e-9);
+            }
+            for id in [StudentMdp::CLASS_1, StudentMdp::CLASS_2, StudentMdp::CLASS_3] {
+                assert_eq!(agent.get_best_action(id).unwrap().0, "Study");
+            }
+            assert_eq!(agent.get_best_action(StudentMdp::FACEBOOK).unwrap().0, "Quit");
+        }
+    }
+
+}
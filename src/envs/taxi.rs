@@ -0,0 +1,211 @@
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+
+#[cfg(feature = "rand")]
+use rand::Rng;
+
+#[cfg(feature = "rand")]
+use crate::envs::Environment;
+
+// Walls are the '|' between cells, the taxi can't cross them moving east or west
+const MAP: [&str; 5] = [
+    "|R: | : :G|",
+    "| : | : : |",
+    "| : : : : |",
+    "| | : | : |",
+    "|Y| : |B: |",
+];
+
+// Pickup and dropoff locations R, G, Y and B as (row, column)
+const LOCATIONS: [(i64, i64); 4] = [(0, 0), (0, 4), (4, 0), (4, 3)];
+
+const ACTIONS: [&str; 6] = ["South", "North", "East", "West", "Pickup", "Dropoff"];
+
+// Passenger location meaning it is in the taxi
+pub const IN_TAXI: i64 = 4;
+
+// Pickup and dropoff on a 5x5 grid with walls. The passenger waits at one of
+// four locations and wants to go to another. Every step costs one, a pickup
+// or dropoff at the wrong place costs ten and delivering the passenger gives
+// twenty and ends the episode. Dropping the passenger at one of the other
+// locations is allowed and leaves it waiting there.
+//
+// States encode (taxi row, taxi column, passenger location, destination) as
+// ((row*5 + column)*5 + passenger)*4 + destination, where the passenger
+// location is the index of a location or IN_TAXI, giving 500 states. Actions
+// are "South", "North", "East", "West", "Pickup" and "Dropoff".
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Taxi;
+
+impl Taxi {
+
+    pub fn new() -> Taxi {
+        return Taxi
+    }
+
+    pub fn encode(&self, row: i64, col: i64, passenger: i64, destination: i64) -> i64 {
+        return ((row*5 + col)*5 + passenger)*4 + destination
+    }
+
+    // (taxi row, taxi column, passenger location, destination)
+    pub fn decode(&self, state: i64) -> (i64, i64, i64, i64) {
+        return (state/100, state/20 % 5, state/4 % 5, state % 4)
+    }
+
+    pub fn is_terminal(&self, state: i64) -> bool {
+        let (_, _, passenger, destination) = self.decode(state);
+        return passenger == destination
+    }
+
+    // States an episode can start from, the passenger waiting away from its destination
+    pub fn get_start_states(&self) -> Vec<i64> {
+        return (0..500).filter(|state| {
+            let (_, _, passenger, destination) = self.decode(*state);
+            passenger != IN_TAXI && passenger != destination
+        }).collect()
+    }
+
+    // Next state and reward, None for unknown actions
+    pub fn transition(&self, state: i64, action: &str) -> Option<(i64, f64)> {
+        let (row, col, passenger, destination) = self.decode(state);
+        let walls = MAP[row as usize].as_bytes();
+        let location = LOCATIONS.iter().position(|location| *location == (row, col)).map(|pos| pos as i64);
+
+        let (row, col, passenger, reward) = match action {
+            "South" => ((row + 1).min(4), col, passenger, -1.),
+            "North" => ((row - 1).max(0), col, passenger, -1.),
+            "East" if walls[2*col as usize + 2] != b'|' => (row, col + 1, passenger, -1.),
+            "West" if walls[2*col as usize] != b'|' => (row, col - 1, passenger, -1.),
+            "East" | "West" => (row, col, passenger, -1.),
+            "Pickup" => match location {
+                Some(location) if location == passenger => (row, col, IN_TAXI, -1.),
+                _ => (row, col, passenger, -10.),
+            },
+            "Dropoff" => match location {
+                Some(location) if passenger == IN_TAXI && location == destination => (row, col, destination, 20.),
+                Some(location) if passenger == IN_TAXI => (row, col, location, -1.),
+                _ => (row, col, passenger, -10.),
+            },
+            _ => return None,
+        };
+
+        return Some((self.encode(row, col, passenger, destination), reward))
+    }
+
+    pub fn get_links<F: Real>(&self) -> Vec<StateLink<F>> {
+        let mut links: Vec<StateLink<F>> = Vec::new();
+
+        for state in (0..500).filter(|state| !self.is_terminal(*state)) {
+            for action in ACTIONS {
+                let (next_state, reward) = self.transition(state, action).unwrap();
+                links.push(StateLink(state, next_state, action.to_string(), F::one(), F::from_f64(reward)));
+            }
+        }
+
+        return links
+    }
+
+    pub fn build<F: Real>(&self) -> SystemState<F> {
+        return SystemState::create_and_build(self.get_links())
+    }
+
+}
+
+// Taxi episodes starting from a random start state
+#[cfg(feature = "rand")]
+pub struct TaxiEnv<R: Rng> {
+    taxi: Taxi,
+    state: i64,
+    rng: R,
+}
+
+#[cfg(feature = "rand")]
+impl<R: Rng> TaxiEnv<R> {
+
+    pub fn new(rng: R) -> TaxiEnv<R> {
+        return TaxiEnv {taxi: Taxi::new(), state: 0, rng}
+    }
+
+    pub fn get_taxi(&self) -> &Taxi {
+        return &self.taxi
+    }
+
+}
+
+#[cfg(feature = "rand")]
+impl<R: Rng> Environment for TaxiEnv<R> {
+
+    type State = i64;
+    type Action = String;
+
+    fn reset(&mut self) -> i64 {
+        let start_states = self.taxi.get_start_states();
+        self.state = start_states[self.rng.random_range(0..start_states.len())];
+        return self.state
+    }
+
+    // Unknown actions end the episode in place without reward
+    fn step(&mut self, action: String) -> (i64, f64, bool) {
+        return match self.taxi.transition(self.state, &action) {
+            Some((next_state, reward)) => {
+                self.state = next_state;
+                (next_state, reward, self.taxi.is_terminal(next_state))
+            },
+            None => (self.state, 0., true),
+        }
+    }
+
+    fn legal_actions(&self, state: &i64) -> Vec<String> {
+        if self.taxi.is_terminal(*state) {
+            return Vec::new()
+        }
+        return ACTIONS.iter().map(|action| action.to_string()).collect()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Agent;
+
+    #[test]
+    fn taxi_test() {
+        let taxi = Taxi::new();
+        assert_eq!(taxi.decode(taxi.encode(3, 1, 2, 0)), (3, 1, 2, 0));
+        assert_eq!(taxi.get_start_states().len(), 300);
+
+        // Wall east of the start, wrong pickup, then pickup and dropoff at the wrong location
+        let start = taxi.encode(0, 1, 0, 1);
+        assert_eq!(taxi.transition(start, "East"), Some((start, -1.)));
+        assert_eq!(taxi.transition(start, "Pickup"), Some((start, -10.)));
+        assert_eq!(taxi.transition(taxi.encode(0, 0, 0, 1), "Pickup"), Some((taxi.encode(0, 0, IN_TAXI, 1), -1.)));
+        assert_eq!(taxi.transition(taxi.encode(4, 3, IN_TAXI, 1), "Dropoff"), Some((taxi.encode(4, 3, 3, 1), -1.)));
+        assert_eq!(taxi.transition(taxi.encode(0, 4, IN_TAXI, 1), "Dropoff"), Some((taxi.encode(0, 4, 1, 1), 20.)));
+
+        let system_state: SystemState = taxi.build();
+        // Delivered passengers are only reachable with the taxi at the destination
+        assert_eq!(system_state.get_all_states().len(), 404);
+
+        // Pickup at R, around the wall to G in 8 moves and dropoff
+        let mut agent: Agent = Agent::init_random(system_state);
+        agent.deterministic_policy_improvement(1., 1e-8, 1000, 10_000);
+        let value = agent.get_evaluation().get(&taxi.encode(0, 0, 0, 1)).unwrap();
+        assert!((value - 11.).abs() < 1e-6);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn taxi_env_test() {
+        use rand::SeedableRng;
+
+        let mut env = TaxiEnv::new(rand::rngs::StdRng::seed_from_u64(1));
+        for _ in 0..20 {
+            let state = env.reset();
+            assert!(env.get_taxi().get_start_states().contains(&state));
+            assert_eq!(env.legal_actions(&state).len(), 6);
+        }
+    }
+
+}
@@ -0,0 +1,367 @@
+// Recorded rollouts.
+//
+// A Transition is one step of an environment, an Episode the transitions of
+// one rollout in order, and an EpisodeBatch a set of episodes with summary
+// statistics, of their returns and of how much of the state space they
+// explored. Rewards and returns are f64 whatever the model precision, as
+// they come from Environment::step.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition<S, A> {
+    pub state: S,
+    pub action: A,
+    pub reward: f64,
+    pub next_state: S,
+    // Whether the environment ended the episode on this step
+    pub done: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Episode<S, A> {
+    start_state: S,
+    transitions: Vec<Transition<S,A>>,
+}
+
+impl<S, A> Episode<S, A> {
+
+    pub fn new(start_state: S) -> Episode<S, A> {
+        return Episode {start_state, transitions: Vec::new()}
+    }
+
+    // The transition must start where the previous one ended
+    pub fn push(&mut self, transition: Transition<S,A>) {
+        self.transitions.push(transition);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.transitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.transitions.is_empty()
+    }
+
+    pub fn get_start_state(&self) -> &S {
+        return &self.start_state
+    }
+
+    pub fn get_transitions(&self) -> &Vec<Transition<S,A>> {
+        return &self.transitions
+    }
+
+    // State where the episode stopped
+    pub fn get_last_state(&self) -> &S {
+        return match self.transitions.last() {
+            Some(transition) => &transition.next_state,
+            None => &self.start_state,
+        }
+    }
+
+    // Whether the environment ended the episode, rather than a step limit or the policy
+    pub fn is_terminated(&self) -> bool {
+        return self.transitions.last().map(|transition| transition.done).unwrap_or(false)
+    }
+
+    // Visited states in order, including the start and the last state
+    pub fn states(&self) -> impl Iterator<Item = &S> {
+        return std::iter::once(&self.start_state)
+            .chain(self.transitions.iter().map(|transition| &transition.next_state))
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &A> {
+        return self.transitions.iter().map(|transition| &transition.action)
+    }
+
+    pub fn rewards(&self) -> impl Iterator<Item = f64> + '_ {
+        return self.transitions.iter().map(|transition| transition.reward)
+    }
+
+    // Discounted return from each step to the end of the episode
+    pub fn returns(&self, gamma: f64) -> Vec<f64> {
+        let mut returns = vec![0.; self.transitions.len()];
+        let mut future = 0.;
+        for (step, transition) in self.transitions.iter().enumerate().rev() {
+            future = transition.reward + gamma*future;
+            returns[step] = future;
+        }
+        return returns
+    }
+
+    // Discounted return from the start state
+    pub fn total_return(&self, gamma: f64) -> f64 {
+        return self.returns(gamma).first().cloned().unwrap_or(0.)
+    }
+
+}
+
+impl<S: Eq + Hash + Clone, A> Episode<S, A> {
+
+    // Number of times each state was left, the last state is not counted
+    pub fn visit_counts(&self) -> HashMap<S,usize> {
+        let mut counts: HashMap<S,usize> = HashMap::new();
+        for transition in &self.transitions {
+            *counts.entry(transition.state.clone()).or_default() += 1;
+        }
+        return counts
+    }
+
+}
+
+// Summary of the returns and lengths of a batch of episodes
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpisodeStats {
+    pub n_episodes: usize,
+    pub mean_return: f64,
+    // Sample standard deviation, zero with less than two episodes
+    pub std_return: f64,
+    pub min_return: f64,
+    pub max_return: f64,
+    pub mean_length: f64,
+    pub terminated_fraction: f64,
+}
+
+// How much of the state space a batch of episodes explored, as the rollouts
+// of the behaviour policy of a learner
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExplorationStats<S: Eq + Hash, A: Eq + Hash> {
+    // Times each action was taken in each state
+    pub state_action_counts: HashMap<S,HashMap<A,usize>>,
+    // States visited, the start and last states of the episodes included
+    pub n_visited: usize,
+    // Of the states of the state space
+    pub visited_fraction: f64,
+    // Steps of the last episodes reaching a state no earlier step or
+    // episode had, out of their steps. Zero without steps.
+    pub novelty_rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpisodeBatch<S, A> {
+    episodes: Vec<Episode<S,A>>,
+}
+
+impl<S, A> Default for EpisodeBatch<S, A> {
+    fn default() -> EpisodeBatch<S, A> {
+        return EpisodeBatch {episodes: Vec::new()}
+    }
+}
+
+impl<S, A> EpisodeBatch<S, A> {
+
+    pub fn new() -> EpisodeBatch<S, A> {
+        return EpisodeBatch::default()
+    }
+
+    pub fn push(&mut self, episode: Episode<S,A>) {
+        self.episodes.push(episode);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.episodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.episodes.is_empty()
+    }
+
+    pub fn get_episodes(&self) -> &Vec<Episode<S,A>> {
+        return &self.episodes
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Episode<S,A>> {
+        return self.episodes.iter()
+    }
+
+    // Discounted return of each episode from its start state
+    pub fn total_returns(&self, gamma: f64) -> Vec<f64> {
+        return self.episodes.iter().map(|episode| episode.total_return(gamma)).collect()
+    }
+
+    pub fn mean_return(&self, gamma: f64) -> f64 {
+        return self.summary(gamma).mean_return
+    }
+
+    pub fn summary(&self, gamma: f64) -> EpisodeStats {
+        let returns = self.total_returns(gamma);
+        let n_episodes = returns.len();
+        let n = (n_episodes as f64).max(1.);
+
+        let mean_return = returns.iter().sum::<f64>()/n;
+        let std_return = match n_episodes {
+            0 | 1 => 0.,
+            _ => (returns.iter().map(|ret| (ret - mean_return).powi(2)).sum::<f64>()/(n - 1.)).sqrt(),
+        };
+
+        return EpisodeStats {
+            n_episodes,
+            mean_return,
+            std_return,
+            min_return: returns.iter().cloned().fold(f64::INFINITY, f64::min),
+            max_return: returns.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+            mean_length: self.episodes.iter().map(|episode| episode.len()).sum::<usize>() as f64/n,
+            terminated_fraction: self.episodes.iter().filter(|episode| episode.is_terminated()).count() as f64/n,
+        }
+    }
+
+}
+
+impl<S: Eq + Hash + Clone, A> EpisodeBatch<S, A> {
+
+    // Visit counts summed over the episodes
+    pub fn visit_counts(&self) -> HashMap<S,usize> {
+        let mut counts: HashMap<S,usize> = HashMap::new();
+        for episode in &self.episodes {
+            for (state, count) in episode.visit_counts() {
+                *counts.entry(state).or_default() += count;
+            }
+        }
+        return counts
+    }
+
+}
+
+impl<S: Eq + Hash + Clone, A: Eq + Hash + Clone> EpisodeBatch<S, A> {
+
+    // Exploration of the episodes in a state space of n_states states, the
+    // novelty rate over the last window episodes
+    pub fn exploration(&self, n_states: usize, window: usize) -> ExplorationStats<S, A> {
+        let mut state_action_counts: HashMap<S,HashMap<A,usize>> = HashMap::new();
+        let mut visited: HashSet<S> = HashSet::new();
+        let (mut n_steps, mut n_novel) = (0_usize, 0_usize);
+        let first_recent = self.episodes.len().saturating_sub(window);
+
+        for (index, episode) in self.episodes.iter().enumerate() {
+            visited.insert(episode.start_state.clone());
+            for transition in &episode.transitions {
+                *state_action_counts.entry(transition.state.clone()).or_default().entry(transition.action.clone()).or_default() += 1;
+                let novel = visited.insert(transition.next_state.clone());
+                if index >= first_recent {
+                    n_steps += 1;
+                    n_novel += novel as usize;
+                }
+            }
+        }
+
+        return ExplorationStats {
+            state_action_counts,
+            n_visited: visited.len(),
+            visited_fraction: if n_states > 0 {visited.len() as f64/n_states as f64} else {0.},
+            novelty_rate: if n_steps > 0 {n_novel as f64/n_steps as f64} else {0.},
+        }
+    }
+
+}
+
+impl<S, A> FromIterator<Episode<S,A>> for EpisodeBatch<S, A> {
+    fn from_iter<I: IntoIterator<Item = Episode<S,A>>>(iter: I) -> EpisodeBatch<S, A> {
+        return EpisodeBatch {episodes: iter.into_iter().collect()}
+    }
+}
+
+impl<S, A> IntoIterator for EpisodeBatch<S, A> {
+    type Item = Episode<S,A>;
+    type IntoIter = std::vec::IntoIter<Episode<S,A>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return self.episodes.into_iter()
+    }
+}
+
+impl<'a, S, A> IntoIterator for &'a EpisodeBatch<S, A> {
+    type Item = &'a Episode<S,A>;
+    type IntoIter = std::slice::Iter<'a, Episode<S,A>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return self.episodes.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn episode(states: &[i64], rewards: &[f64], done: bool) -> Episode<i64, &'static str> {
+        let mut episode = Episode::new(states[0]);
+        for (step, reward) in rewards.iter().enumerate() {
+            episode.push(Transition {
+                state: states[step],
+                action: "a",
+                reward: *reward,
+                next_state: states[step + 1],
+                done: done && step + 1 == rewards.len(),
+            });
+        }
+        return episode
+    }
+
+    #[test]
+    fn episode_test() {
+        let episode = episode(&[0, 0, 2, 1], &[1., 0., 2.], true);
+
+        assert_eq!(episode.len(), 3);
+        assert!(episode.is_terminated());
+        assert_eq!(*episode.get_last_state(), 1);
+        assert_eq!(episode.states().cloned().collect::<Vec<i64>>(), vec![0, 0, 2, 1]);
+        assert_eq!(episode.returns(0.5), vec![1.5, 1., 2.]);
+        assert_eq!(episode.total_return(1.), 3.);
+
+        let counts = episode.visit_counts();
+        assert_eq!(counts.get(&0), Some(&2));
+        assert_eq!(counts.get(&2), Some(&1));
+        assert_eq!(counts.get(&1), None);
+
+        let empty: Episode<i64, &str> = Episode::new(5);
+        assert!(!empty.is_terminated());
+        assert_eq!(*empty.get_last_state(), 5);
+        assert_eq!(empty.total_return(0.9), 0.);
+    }
+
+    #[test]
+    fn batch_test() {
+        let batch: EpisodeBatch<i64, &str> = vec![
+            episode(&[0, 1], &[1.], true),
+            episode(&[0, 0, 1], &[0., 3.], true),
+            episode(&[0, 0, 0], &[0., 0.], false),
+        ].into_iter().collect();
+
+        let stats = batch.summary(1.);
+        assert_eq!(stats.n_episodes, 3);
+        assert_eq!(stats.mean_return, 4./3.);
+        assert!((stats.std_return - (7_f64/3.).sqrt()).abs() < 1e-12);
+        assert_eq!(stats.min_return, 0.);
+        assert_eq!(stats.max_return, 3.);
+        assert_eq!(stats.mean_length, 5./3.);
+        assert_eq!(stats.terminated_fraction, 2./3.);
+
+        assert_eq!(batch.visit_counts().get(&0), Some(&5));
+
+        let empty: EpisodeBatch<i64, &str> = EpisodeBatch::new();
+        assert_eq!(empty.summary(1.).mean_return, 0.);
+    }
+
+    #[test]
+    fn exploration_test() {
+        let batch: EpisodeBatch<i64, &str> = vec![
+            episode(&[0, 1, 2], &[0., 0.], true),
+            episode(&[0, 1, 3], &[0., 0.], true),
+            episode(&[0, 1, 1], &[0., 0.], false),
+        ].into_iter().collect();
+
+        let stats = batch.exploration(8, 2);
+        assert_eq!(stats.state_action_counts[&1][&"a"], 3);
+        assert_eq!(stats.state_action_counts[&0][&"a"], 3);
+        assert_eq!(stats.n_visited, 4);
+        assert_eq!(stats.visited_fraction, 0.5);
+        // Only state 3 was new in the last two episodes
+        assert_eq!(stats.novelty_rate, 0.25);
+        assert_eq!(batch.exploration(8, 3).novelty_rate, 3./6.);
+
+        let empty: EpisodeBatch<i64, &str> = EpisodeBatch::new();
+        assert_eq!(empty.exploration(0, 10).novelty_rate, 0.);
+    }
+
+}
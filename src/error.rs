@@ -0,0 +1,99 @@
+// Errors of the solvers.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::models::LinkIssue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DivergenceCause {
+    // Some value went past SolverConfig::value_bound
+    ValueBound,
+    // Values kept moving the same way without delta shrinking for
+    // SolverConfig::divergence_window sweeps
+    Growth,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+    // Policy evaluation doesn't converge, typically with gamma = 1 and a
+    // policy stuck in a cycle with non-zero reward. states holds the sorted
+    // ids of the states whose values diverge.
+    EvaluationDiverged {sweep: u32, cause: DivergenceCause, states: Vec<i64>},
+    // The id of a state missing from the model
+    UnknownState {id: i64},
+    // An action the state doesn't have
+    UnknownAction {state: i64, action: String},
+    // A link the model doesn't have
+    UnknownLink {prev_state: i64, new_state: i64, action: String},
+    // Action probabilities that are negative, not finite or don't sum to one
+    InvalidDistribution {state: i64},
+    // A policy mixing weight outside of [0, 1]
+    InvalidMixingWeight,
+    // A state covered by only one of the policies being combined
+    PolicyMismatch {state: i64},
+    // A policy whose states don't match the model's: the sorted ids of its
+    // states missing from the model, and of the states of the model with
+    // actions it has no probabilities for
+    PolicyKeyMismatch {unknown: Vec<i64>, missing: Vec<i64>},
+    // Links rejected by a strict build, with all their issues
+    InvalidLinks {issues: Vec<LinkIssue>},
+    // A rollback further back than the rounds recorded
+    NoPolicyHistory {rounds: usize, recorded: usize},
+    // A sweep checkpoint that couldn't be written, or doesn't match the model
+    CheckpointFailed {message: String},
+    // A solve stopped through its CancellationToken, before the given sweep
+    // of the round of policy iteration was done
+    Cancelled {policy_iteration: u32, sweep: u32},
+    // Regions solved in turn for the given number of rounds, the last one
+    // still changing a value by max_diff or an action
+    RegionsNotConverged {rounds: u32, max_diff: f64},
+    // The thread pool of SolverConfig::with_threads couldn't be built
+    ThreadPoolFailed {message: String},
+}
+
+// First ids of a list, for messages
+fn format_ids(ids: &[i64]) -> String {
+    let shown: Vec<String> = ids.iter().take(10).map(|id| id.to_string()).collect();
+    let more = if ids.len() > shown.len() {", ..."} else {""};
+    return format!("{}{}", shown.join(", "), more)
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            Error::EvaluationDiverged {sweep, cause, states} => {
+                let cause = match cause {
+                    DivergenceCause::ValueBound => "values beyond the bound",
+                    DivergenceCause::Growth => "values growing without converging",
+                };
+                write!(f, "policy evaluation diverged at sweep {} ({}) in {} states: {}", sweep, cause, states.len(), format_ids(states))
+            },
+            Error::UnknownState {id} => write!(f, "state {} is not in the model", id),
+            Error::UnknownAction {state, action} => write!(f, "state {} has no action {}", state, action),
+            Error::UnknownLink {prev_state, new_state, action} => write!(f, "state {} has no link to {} with action {}", prev_state, new_state, action),
+            Error::InvalidDistribution {state} => write!(f, "the action probabilities of state {} are not a distribution", state),
+            Error::InvalidMixingWeight => write!(f, "the mixing weight is not in [0, 1]"),
+            Error::PolicyMismatch {state} => write!(f, "state {} is only in one of the policies", state),
+            Error::PolicyKeyMismatch {unknown, missing} => write!(
+                f, "the policy doesn't match the model: {} states not in the model ({}), {} states without a policy ({})",
+                unknown.len(), format_ids(unknown), missing.len(), format_ids(missing),
+            ),
+            Error::NoPolicyHistory {rounds, recorded} => write!(f, "can't roll back {} rounds with {} policies recorded", rounds, recorded),
+            Error::CheckpointFailed {message} => write!(f, "sweep checkpoint failed: {}", message),
+            Error::Cancelled {policy_iteration, sweep} => write!(f, "solve cancelled in sweep {} of round {}", sweep, policy_iteration),
+            Error::RegionsNotConverged {rounds, max_diff} => write!(f, "regions not converged after {} rounds, the last one changing values by up to {}", rounds, max_diff),
+            Error::ThreadPoolFailed {message} => write!(f, "can't build the thread pool: {}", message),
+            Error::InvalidLinks {issues} => {
+                let shown: Vec<String> = issues.iter().take(10).map(|issue| issue.to_string()).collect();
+                let more = if issues.len() > shown.len() {", ..."} else {""};
+                write!(f, "{} issues in the links: {}{}", issues.len(), shown.join(", "), more)
+            },
+        }
+    }
+}
+
+impl core::error::Error for Error {}
@@ -0,0 +1,325 @@
+// Exact solving in rational arithmetic, enabled with the "exact" feature.
+//
+// Probabilities and rewards are arbitrary precision fractions, policies are
+// evaluated by solving (I - gamma P) v = r with Gaussian elimination, and
+// policy iteration only switches actions that are strictly better. The
+// optimal values and policy are then exact, with no tolerance involved. Every
+// evaluation is a dense solve, so this is meant for small models: teaching
+// examples and fixtures to check the floating point solvers against.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use num_rational::BigRational;
+use num_traits::{One, ToPrimitive, Zero};
+
+use crate::models::SystemState;
+use crate::numeric::Real;
+
+// Transition between states given an action, with exact probability and reward
+// (prev_state, new_state, action, probability, reward)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExactLink(pub i64, pub i64, pub String, pub BigRational, pub BigRational);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExactError {
+    // A number of the model is NaN or infinite
+    NotFinite {state: i64},
+    // The policy has no action, or an unknown one, for a state with actions
+    UnknownAction {state: i64},
+    // The evaluation has no unique solution, typically with gamma = 1 and a
+    // policy never leaving some states. states holds the sorted ids of the
+    // states left without a pivot.
+    Singular {states: Vec<i64>},
+}
+
+impl fmt::Display for ExactError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            ExactError::NotFinite {state} => write!(f, "state {} has a probability or reward that is not finite", state),
+            ExactError::UnknownAction {state} => write!(f, "the policy has no action of state {}", state),
+            ExactError::Singular {states} => write!(f, "the evaluation has no unique solution, in {} states", states.len()),
+        }
+    }
+}
+
+impl std::error::Error for ExactError {}
+
+// Outcomes of an action, as (position of the next state, probability, reward)
+type Outcomes = Vec<(usize, BigRational, BigRational)>;
+
+// Model in state id order, the actions of each state sorted by name
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExactModel {
+    ids: Vec<i64>,
+    actions: Vec<Vec<(String, Outcomes)>>,
+}
+
+// Optimal deterministic policy and its values
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExactSolution {
+    // Action of each state with actions
+    pub policy: HashMap<i64,String>,
+    pub values: HashMap<i64,BigRational>,
+    // Rounds of policy improvement
+    pub iterations: u32,
+}
+
+impl ExactSolution {
+
+    // Values rounded to a floating point type, to compare with other solvers
+    pub fn values_as<F: Real>(&self) -> HashMap<i64,F> {
+        return self.values.iter()
+            .map(|(id, value)| (*id, F::from_f64(value.to_f64().unwrap_or(f64::NAN))))
+            .collect()
+    }
+
+}
+
+impl ExactModel {
+
+    // Links between the same states with the same action add up their
+    // probabilities, the last reward given being kept
+    pub fn new(links: Vec<ExactLink>) -> ExactModel {
+        let mut ids: Vec<i64> = links.iter().flat_map(|link| [link.0, link.1]).collect();
+        ids.sort();
+        ids.dedup();
+        let position = |id: &i64| ids.binary_search(id).unwrap();
+
+        let mut actions: Vec<Vec<(String, Outcomes)>> = vec![Vec::new(); ids.len()];
+        for ExactLink(prev, next, action, prob, reward) in links {
+            let (pos_prev, pos_next) = (position(&prev), position(&next));
+            let state_actions = &mut actions[pos_prev];
+            let index = match state_actions.iter().position(|(name, _)| *name == action) {
+                Some(index) => index,
+                None => {
+                    state_actions.push((action, Vec::new()));
+                    state_actions.len() - 1
+                },
+            };
+            let outcomes = &mut state_actions[index].1;
+            match outcomes.iter_mut().find(|outcome| outcome.0 == pos_next) {
+                Some(outcome) => {
+                    outcome.1 += prob;
+                    outcome.2 = reward;
+                },
+                None => outcomes.push((pos_next, prob, reward)),
+            }
+        }
+        for state_actions in actions.iter_mut() {
+            state_actions.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+
+        return ExactModel {ids, actions}
+    }
+
+    // The exact values of the floating point numbers of a system, e.g. 0.1
+    // becomes 3602879701896397/36028797018963968. Models meant to be exact
+    // are better written with ExactLinks.
+    pub fn from_system_state<F: Real>(system_state: &SystemState<F>) -> Result<ExactModel, ExactError> {
+        let to_exact = |value: F, state: i64| BigRational::from_float(value.as_f64()).ok_or(ExactError::NotFinite {state});
+
+        let mut links: Vec<ExactLink> = Vec::new();
+        for link in system_state.get_specification() {
+            links.push(ExactLink(link.0, link.1, link.2.clone(), to_exact(link.3, link.0)?, to_exact(link.4, link.0)?));
+        }
+        return Ok(ExactModel::new(links))
+    }
+
+    pub fn get_ids(&self) -> &Vec<i64> {
+        return &self.ids
+    }
+
+    fn expected_reward(&self, pos: usize, action: usize) -> BigRational {
+        return self.actions[pos][action].1.iter()
+            .fold(BigRational::zero(), |sum, (_, prob, reward)| sum + prob*reward)
+    }
+
+    fn q_value(&self, pos: usize, action: usize, values: &[BigRational], gamma: &BigRational) -> BigRational {
+        let future = self.actions[pos][action].1.iter()
+            .fold(BigRational::zero(), |sum, (pos_next, prob, _)| sum + prob*&values[*pos_next]);
+        return self.expected_reward(pos, action) + gamma*future
+    }
+
+    // Values of a deterministic policy, given by action index (None for
+    // states without actions)
+    fn evaluate_indices(&self, policy: &[Option<usize>], gamma: &BigRational) -> Result<Vec<BigRational>, ExactError> {
+        let n = self.ids.len();
+
+        // Rows of I - gamma P, with r appended
+        let mut rows: Vec<Vec<BigRational>> = (0..n).map(|pos| {
+            let mut row = vec![BigRational::zero(); n + 1];
+            row[pos] = BigRational::one();
+            if let Some(action) = policy[pos] {
+                for (pos_next, prob, _) in &self.actions[pos][action].1 {
+                    row[*pos_next] -= gamma*prob;
+                }
+                row[n] = self.expected_reward(pos, action);
+            }
+            row
+        }).collect();
+
+        // Gauss-Jordan elimination, columns without a pivot left singular
+        let mut pivots: Vec<Option<usize>> = vec![None; n];
+        let mut next_row = 0;
+        for col in 0..n {
+            let pivot = match (next_row..n).find(|row| !rows[*row][col].is_zero()) {
+                Some(pivot) => pivot,
+                None => continue,
+            };
+            rows.swap(next_row, pivot);
+
+            let scale = rows[next_row][col].recip();
+            for value in rows[next_row].iter_mut() {
+                *value *= &scale;
+            }
+            let pivot_row = rows[next_row].clone();
+            for (row, values) in rows.iter_mut().enumerate() {
+                if row != next_row && !values[col].is_zero() {
+                    let factor = values[col].clone();
+                    for (value, pivot_value) in values.iter_mut().zip(&pivot_row).skip(col) {
+                        *value -= &factor*pivot_value;
+                    }
+                }
+            }
+            pivots[col] = Some(next_row);
+            next_row += 1;
+        }
+
+        let singular: Vec<i64> = (0..n).filter(|col| pivots[*col].is_none()).map(|col| self.ids[col]).collect();
+        if !singular.is_empty() {
+            return Err(ExactError::Singular {states: singular})
+        }
+
+        return Ok(pivots.iter().map(|row| rows[row.unwrap()][n].clone()).collect())
+    }
+
+    // Values of a deterministic policy, giving the action of each state with actions
+    pub fn evaluate(&self, policy: &HashMap<i64,String>, gamma: &BigRational) -> Result<HashMap<i64,BigRational>, ExactError> {
+        let mut indices: Vec<Option<usize>> = Vec::with_capacity(self.ids.len());
+        for (pos, id) in self.ids.iter().enumerate() {
+            if self.actions[pos].is_empty() {
+                indices.push(None);
+                continue;
+            }
+            let index = policy.get(id).and_then(|action| self.actions[pos].iter().position(|(name, _)| name == action));
+            indices.push(Some(index.ok_or(ExactError::UnknownAction {state: *id})?));
+        }
+
+        let values = self.evaluate_indices(&indices, gamma)?;
+        return Ok(self.ids.iter().cloned().zip(values).collect())
+    }
+
+    // Policy iteration from the first action by name in every state. An
+    // action replaces the current one only when strictly better, the first by
+    // name among the best, so the iteration ends in finitely many rounds.
+    // With gamma = 1 every policy met has to reach states without actions.
+    pub fn solve(&self, gamma: &BigRational) -> Result<ExactSolution, ExactError> {
+        let mut policy: Vec<Option<usize>> = self.actions.iter()
+            .map(|actions| if actions.is_empty() {None} else {Some(0)})
+            .collect();
+        let mut values = self.evaluate_indices(&policy, gamma)?;
+        let mut iterations: u32 = 0;
+
+        loop {
+            let mut changed = false;
+            for (pos, action) in policy.iter_mut().enumerate() {
+                let current = match action {
+                    Some(current) => *current,
+                    None => continue,
+                };
+                let mut best = (current, self.q_value(pos, current, &values, gamma));
+                for action in 0..self.actions[pos].len() {
+                    let q_value = self.q_value(pos, action, &values, gamma);
+                    if q_value > best.1 {
+                        best = (action, q_value);
+                    }
+                }
+                if best.0 != current {
+                    *action = Some(best.0);
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break
+            }
+            iterations += 1;
+            values = self.evaluate_indices(&policy, gamma)?;
+        }
+
+        return Ok(ExactSolution {
+            policy: self.ids.iter().zip(&policy).zip(&self.actions)
+                .filter_map(|((id, action), actions)| action.map(|action| (*id, actions[action].0.clone())))
+                .collect(),
+            values: self.ids.iter().cloned().zip(values).collect(),
+            iterations,
+        })
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::envs::GamblersProblem;
+    use crate::{Agent, SolverConfig};
+
+    fn ratio(numer: i64, denom: i64) -> BigRational {
+        return BigRational::new(numer.into(), denom.into())
+    }
+
+    #[test]
+    fn exact_solve_test() {
+        // Taking 1 now or waiting a step for 3, with a coin flip on the way
+        let links = vec![
+            ExactLink(0, 3, "Now".to_string(), ratio(1, 1), ratio(1, 1)),
+            ExactLink(0, 1, "Later".to_string(), ratio(1, 2), ratio(0, 1)),
+            ExactLink(0, 2, "Later".to_string(), ratio(1, 2), ratio(0, 1)),
+            ExactLink(1, 3, "Take".to_string(), ratio(1, 1), ratio(3, 1)),
+            ExactLink(2, 3, "Take".to_string(), ratio(1, 1), ratio(1, 1)),
+        ];
+        let model = ExactModel::new(links);
+        let solution = model.solve(&ratio(9, 10)).unwrap();
+
+        // (3 + 1)/2 * 9/10 = 9/5 beats 1
+        assert_eq!(solution.policy.get(&0).unwrap(), "Later");
+        assert_eq!(*solution.values.get(&0).unwrap(), ratio(9, 5));
+        assert_eq!(*solution.values.get(&3).unwrap(), ratio(0, 1));
+        assert!(!solution.policy.contains_key(&3));
+        // Later comes first by name, nothing to improve
+        assert_eq!(solution.iterations, 0);
+
+        // With a heavier discount waiting is no longer worth it
+        let solution = model.solve(&ratio(2, 5)).unwrap();
+        assert_eq!(solution.policy.get(&0).unwrap(), "Now");
+        assert_eq!(*solution.values.get(&0).unwrap(), ratio(1, 1));
+        assert_eq!(solution.iterations, 1);
+
+        let policy: HashMap<i64,String> = HashMap::from([(0, "Later".to_string()), (1, "Take".to_string()), (2, "Take".to_string())]);
+        assert_eq!(*model.evaluate(&policy, &ratio(2, 5)).unwrap().get(&0).unwrap(), ratio(4, 5));
+        assert_eq!(model.evaluate(&HashMap::new(), &ratio(1, 2)), Err(ExactError::UnknownAction {state: 0}));
+
+        // A loop that is never left has no solution without discount
+        let model = ExactModel::new(vec![
+            ExactLink(0, 1, "Go".to_string(), ratio(1, 1), ratio(1, 1)),
+            ExactLink(1, 0, "Back".to_string(), ratio(1, 1), ratio(1, 1)),
+        ]);
+        assert!(matches!(model.solve(&ratio(1, 1)), Err(ExactError::Singular {..})));
+    }
+
+    #[test]
+    fn exact_system_state_test() {
+        // Same optimal values as the floating point solver
+        let system_state: SystemState = GamblersProblem::new(10, 0.4).unwrap().build();
+        let solution = ExactModel::from_system_state(&system_state).unwrap().solve(&ratio(1, 1)).unwrap();
+
+        let mut agent: Agent = Agent::init_random(GamblersProblem::new(10, 0.4).unwrap().build());
+        agent.solve(1., &SolverConfig::new().with_epsilon(1e-12).with_eval_iters(10_000));
+        for (id, value) in solution.values_as::<f64>() {
+            assert!((agent.get_evaluation().get(&id).unwrap() - value).abs() < 1e-9);
+        }
+    }
+
+}
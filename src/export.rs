@@ -0,0 +1,133 @@
+// Arrow and Parquet export of value and Q tables, enabled with the "arrow" feature.
+//
+// Values are exported with columns (state_id, value) and Q tables with
+// columns (state_id, action, q_value), sorted by state and action, so they
+// load directly as data frames in pandas or Polars. Values are always written
+// as 64 bit floats, whatever the precision the agent was solved with.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+
+use crate::{Agent, Real};
+
+pub fn values_schema() -> Schema {
+    return Schema::new(vec![
+        Field::new("state_id", DataType::Int64, false),
+        Field::new("value", DataType::Float64, false),
+    ])
+}
+
+pub fn q_values_schema() -> Schema {
+    return Schema::new(vec![
+        Field::new("state_id", DataType::Int64, false),
+        Field::new("action", DataType::Utf8, false),
+        Field::new("q_value", DataType::Float64, false),
+    ])
+}
+
+// Record batch with one row per state
+pub fn values_to_record_batch<F: Real>(values: &HashMap<i64,F>) -> Result<RecordBatch, ArrowError> {
+    let mut rows: Vec<(&i64,&F)> = values.iter().collect();
+    rows.sort_by_key(|(id, _)| **id);
+
+    let ids: ArrayRef = Arc::new(Int64Array::from_iter_values(rows.iter().map(|(id, _)| **id)));
+    let values: ArrayRef = Arc::new(Float64Array::from_iter_values(rows.iter().map(|(_, value)| value.as_f64())));
+
+    return RecordBatch::try_new(Arc::new(values_schema()), vec![ids, values])
+}
+
+// Record batch with one row per state/action pair
+pub fn q_values_to_record_batch<F: Real>(q_values: &HashMap<i64,HashMap<String,F>>) -> Result<RecordBatch, ArrowError> {
+    let mut rows: Vec<(i64,&String,f64)> = q_values.iter()
+        .flat_map(|(id, actions)| actions.iter().map(|(action, q)| (*id, action, q.as_f64())))
+        .collect();
+    rows.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(b.1)));
+
+    let ids: ArrayRef = Arc::new(Int64Array::from_iter_values(rows.iter().map(|row| row.0)));
+    let actions: ArrayRef = Arc::new(StringArray::from_iter_values(rows.iter().map(|row| row.1)));
+    let q_values: ArrayRef = Arc::new(Float64Array::from_iter_values(rows.iter().map(|row| row.2)));
+
+    return RecordBatch::try_new(Arc::new(q_values_schema()), vec![ids, actions, q_values])
+}
+
+// Writes a record batch as a single Parquet file
+pub fn write_parquet<P: AsRef<Path>>(batch: &RecordBatch, path: P) -> Result<(), ParquetError> {
+    let file = File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+    writer.write(batch)?;
+    writer.close()?;
+    return Ok(())
+}
+
+// Writes the agent's current evaluation to a Parquet file
+pub fn write_values_parquet<F: Real, P: AsRef<Path>>(agent: &Agent<F>, path: P) -> Result<(), ParquetError> {
+    let batch = values_to_record_batch(agent.get_evaluation())?;
+    return write_parquet(&batch, path)
+}
+
+// Writes the agent's Q table to a Parquet file
+pub fn write_q_values_parquet<F: Real, P: AsRef<Path>>(agent: &Agent<F>, gamma: F, path: P) -> Result<(), ParquetError> {
+    let batch = q_values_to_record_batch(&agent.calc_q_values(gamma))?;
+    return write_parquet(&batch, path)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::models;
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn solved_arms() -> Agent {
+        let links = vec![
+            models::StateLink(0, 1, "Arm_1".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Arm_2".to_string(), 1., 2.),
+            models::StateLink(0, 1, "Arm_3".to_string(), 1., 3.),
+        ];
+        let mut agent = Agent::init_random(models::SystemState::create_and_build(links));
+        agent.deterministic_policy_improvement(1., 0.01, 100, 100);
+        return agent
+    }
+
+    #[test]
+    fn q_values_batch_test() {
+        let agent = solved_arms();
+        let batch = q_values_to_record_batch(&agent.calc_q_values(1.)).unwrap();
+
+        assert_eq!(batch.num_rows(), 3);
+
+        let actions = batch.column(1).as_any().downcast_ref::<StringArray>().unwrap();
+        let q_values = batch.column(2).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(actions.value(0), "Arm_1");
+        assert_eq!(q_values.values().to_vec(), vec![1., 2., 3.]);
+    }
+
+    #[test]
+    fn parquet_round_trip_test() {
+        let agent = solved_arms();
+        let path = std::env::temp_dir().join(format!("complete_iter_values_{}.parquet", std::process::id()));
+
+        write_values_parquet(&agent, &path).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap()
+            .build().unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|batch| batch.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(batches.len(), 1);
+        let ids = batches[0].column(0).as_any().downcast_ref::<Int64Array>().unwrap();
+        let values = batches[0].column(1).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(ids.values().to_vec(), vec![0, 1]);
+        assert!((values.value(0) - 3.).abs() < 0.02);
+        assert_eq!(values.len(), 2);
+    }
+
+}
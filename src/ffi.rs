@@ -0,0 +1,303 @@
+// C interface to the solver, enabled with the "ffi" feature.
+//
+// Models and agents are handed to C as opaque handles that are allocated and
+// freed on the Rust side only. Strings coming back from the solver are copied
+// into caller-provided buffers, so no allocation ever crosses the boundary.
+// The matching header lives in include/complete_iter.h (see cbindgen.toml).
+
+use std::ffi::{c_char, c_int, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crate::{models, Agent};
+
+pub const CI_OK: c_int = 0;
+pub const CI_ERR_NULL: c_int = -1;
+pub const CI_ERR_INVALID: c_int = -2;
+pub const CI_ERR_NOT_FOUND: c_int = -3;
+pub const CI_ERR_PANIC: c_int = -4;
+
+/// Opaque handle to a built model
+pub struct CiModel(models::SystemState);
+
+/// Opaque handle to an agent, owns its model
+pub struct CiAgent(Agent);
+
+/// Builds a model from parallel link arrays of length n_links.
+/// Returns NULL if a required pointer is NULL or an action is not valid UTF-8.
+///
+/// # Safety
+/// Every non-NULL pointer must reference n_links readable elements and every
+/// action must be a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ci_model_new(
+    prev_states: *const i64,
+    next_states: *const i64,
+    actions: *const *const c_char,
+    probs: *const f64,
+    rewards: *const f64,
+    n_links: usize,
+) -> *mut CiModel {
+
+    if n_links > 0 && (prev_states.is_null() || next_states.is_null()
+        || actions.is_null() || probs.is_null() || rewards.is_null()) {
+        return ptr::null_mut()
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut links: Vec<models::StateLink> = Vec::with_capacity(n_links);
+
+        if n_links > 0 {
+            let (prev_states, next_states, actions, probs, rewards) = unsafe {(
+                slice::from_raw_parts(prev_states, n_links),
+                slice::from_raw_parts(next_states, n_links),
+                slice::from_raw_parts(actions, n_links),
+                slice::from_raw_parts(probs, n_links),
+                slice::from_raw_parts(rewards, n_links),
+            )};
+
+            for i in 0..n_links {
+                if actions[i].is_null() {
+                    return None
+                }
+                let action = match unsafe { CStr::from_ptr(actions[i]) }.to_str() {
+                    Ok(action) => action.to_string(),
+                    Err(_) => return None,
+                };
+                links.push(models::StateLink(prev_states[i], next_states[i], action, probs[i], rewards[i]));
+            }
+        }
+
+        Some(models::SystemState::create_and_build(links))
+    }));
+
+    match result {
+        Ok(Some(system_state)) => Box::into_raw(Box::new(CiModel(system_state))),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Number of states in the model, 0 for a NULL handle
+///
+/// # Safety
+/// model must be NULL or a live handle returned by ci_model_new.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ci_model_n_states(model: *const CiModel) -> usize {
+    match unsafe { model.as_ref() } {
+        Some(model) => model.0.get_dense().n_states(),
+        None => 0,
+    }
+}
+
+/// Releases a model that was not handed over to ci_agent_new
+///
+/// # Safety
+/// model must be NULL or a live handle returned by ci_model_new.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ci_model_free(model: *mut CiModel) {
+    if !model.is_null() {
+        drop(unsafe { Box::from_raw(model) });
+    }
+}
+
+/// Creates an agent with a uniform random policy.
+/// Takes ownership of the model, whose handle must not be used afterwards.
+///
+/// # Safety
+/// model must be NULL or a live handle returned by ci_model_new.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ci_agent_new(model: *mut CiModel) -> *mut CiAgent {
+    if model.is_null() {
+        return ptr::null_mut()
+    }
+
+    let model = unsafe { Box::from_raw(model) };
+
+    match panic::catch_unwind(AssertUnwindSafe(|| Agent::init_random(model.0))) {
+        Ok(agent) => Box::into_raw(Box::new(CiAgent(agent))),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Releases an agent and its model
+///
+/// # Safety
+/// agent must be NULL or a live handle returned by ci_agent_new.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ci_agent_free(agent: *mut CiAgent) {
+    if !agent.is_null() {
+        drop(unsafe { Box::from_raw(agent) });
+    }
+}
+
+/// Runs deterministic policy improvement on the agent
+///
+/// # Safety
+/// agent must be NULL or a live handle returned by ci_agent_new.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ci_agent_solve(
+    agent: *mut CiAgent,
+    gamma: f64,
+    epsilon: f64,
+    policy_iters: u32,
+    eval_iters: u32,
+) -> c_int {
+
+    let agent = match unsafe { agent.as_mut() } {
+        Some(agent) => agent,
+        None => return CI_ERR_NULL,
+    };
+
+    if !(0. ..=1.).contains(&gamma) || epsilon.is_nan() || epsilon <= 0. {
+        return CI_ERR_INVALID
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        agent.0.deterministic_policy_improvement(gamma, epsilon, policy_iters, eval_iters)
+    }));
+
+    match result {
+        Ok(_) => CI_OK,
+        Err(_) => CI_ERR_PANIC,
+    }
+}
+
+/// Writes the current evaluation of a state into value
+///
+/// # Safety
+/// agent must be NULL or a live handle returned by ci_agent_new and value must
+/// be NULL or point to writable memory.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ci_agent_value(agent: *const CiAgent, state_id: i64, value: *mut f64) -> c_int {
+    let agent = match unsafe { agent.as_ref() } {
+        Some(agent) => agent,
+        None => return CI_ERR_NULL,
+    };
+
+    if value.is_null() {
+        return CI_ERR_NULL
+    }
+
+    match agent.0.get_evaluation().get(&state_id) {
+        Some(state_value) => {
+            unsafe { *value = *state_value };
+            CI_OK
+        },
+        None => CI_ERR_NOT_FOUND,
+    }
+}
+
+/// Copies the best action of a state, NUL-terminated, into buffer.
+/// Returns the length of the action name (without the NUL) like snprintf does;
+/// nothing is written when buffer_len is not larger than that length.
+/// Returns CI_ERR_NOT_FOUND for unknown states and states without actions.
+///
+/// # Safety
+/// agent must be NULL or a live handle returned by ci_agent_new and buffer must
+/// be NULL or point to buffer_len writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ci_agent_best_action(
+    agent: *const CiAgent,
+    state_id: i64,
+    buffer: *mut c_char,
+    buffer_len: usize,
+) -> c_int {
+
+    let agent = match unsafe { agent.as_ref() } {
+        Some(agent) => agent,
+        None => return CI_ERR_NULL,
+    };
+
+    if agent.0.get_policy().get(&state_id).is_none() {
+        return CI_ERR_NOT_FOUND
+    }
+
+    let action = match agent.0.get_best_action(state_id) {
+        Some((action, _)) => action.as_bytes(),
+        None => return CI_ERR_NOT_FOUND,
+    };
+
+    let length = match c_int::try_from(action.len()) {
+        Ok(length) => length,
+        Err(_) => return CI_ERR_INVALID,
+    };
+
+    if !buffer.is_null() && buffer_len > action.len() {
+        unsafe {
+            ptr::copy_nonoverlapping(action.as_ptr(), buffer as *mut u8, action.len());
+            *buffer.add(action.len()) = 0;
+        }
+    }
+
+    return length
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::ffi::CString;
+
+    fn build_arms() -> *mut CiModel {
+        let arms: Vec<CString> = ["Arm_1", "Arm_2", "Arm_3"].iter()
+            .map(|arm| CString::new(*arm).unwrap()).collect();
+        let actions: Vec<*const c_char> = arms.iter().map(|arm| arm.as_ptr()).collect();
+        let prev_states = [0, 0, 0];
+        let next_states = [1, 1, 1];
+        let probs = [1., 1., 1.];
+        let rewards = [1., 2., 3.];
+
+        unsafe {
+            ci_model_new(prev_states.as_ptr(), next_states.as_ptr(), actions.as_ptr(),
+                probs.as_ptr(), rewards.as_ptr(), 3)
+        }
+    }
+
+    #[test]
+    fn ffi_solve_test() {
+        let model = build_arms();
+        assert!(!model.is_null());
+        assert_eq!(unsafe { ci_model_n_states(model) }, 2);
+
+        let agent = unsafe { ci_agent_new(model) };
+        assert!(!agent.is_null());
+        assert_eq!(unsafe { ci_agent_solve(agent, 1., 0.01, 100, 100) }, CI_OK);
+
+        let mut value = 0.;
+        assert_eq!(unsafe { ci_agent_value(agent, 0, &mut value) }, CI_OK);
+        assert!((value - 3.).abs() < 0.02);
+
+        let mut buffer = [1 as c_char; 16];
+        let length = unsafe { ci_agent_best_action(agent, 0, buffer.as_mut_ptr(), buffer.len()) };
+        assert_eq!(length, 5);
+        assert_eq!(unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_str().unwrap(), "Arm_3");
+
+        unsafe { ci_agent_free(agent) };
+    }
+
+    #[test]
+    fn ffi_error_test() {
+        let model = unsafe { ci_model_new(ptr::null(), ptr::null(), ptr::null(), ptr::null(), ptr::null(), 1) };
+        assert!(model.is_null());
+        assert!(unsafe { ci_agent_new(ptr::null_mut()) }.is_null());
+        assert_eq!(unsafe { ci_agent_solve(ptr::null_mut(), 1., 0.01, 10, 10) }, CI_ERR_NULL);
+
+        let agent = unsafe { ci_agent_new(build_arms()) };
+        let mut value = 0.;
+        assert_eq!(unsafe { ci_agent_value(agent, 42, &mut value) }, CI_ERR_NOT_FOUND);
+        assert_eq!(unsafe { ci_agent_solve(agent, 2., 0.01, 10, 10) }, CI_ERR_INVALID);
+
+        // Terminal state has no actions
+        let mut buffer = [0 as c_char; 16];
+        assert_eq!(unsafe { ci_agent_best_action(agent, 1, buffer.as_mut_ptr(), buffer.len()) }, CI_ERR_NOT_FOUND);
+
+        // Too small buffers are left untouched but the length is reported
+        let mut small = [7 as c_char; 2];
+        assert_eq!(unsafe { ci_agent_best_action(agent, 0, small.as_mut_ptr(), small.len()) }, 5);
+        assert_eq!(small, [7, 7]);
+
+        unsafe { ci_agent_free(agent) };
+    }
+
+}
@@ -0,0 +1,181 @@
+use crate::games::{Outcome, Player, TurnBasedGame};
+
+// Connect three. Players take turns dropping a piece in a column of the
+// board, where it falls to the lowest empty cell, and the first to line up
+// three pieces horizontally, vertically or diagonally wins. A full board
+// with no line is a draw. Moves are column indices.
+//
+// The default 4 by 4 board has about 42 thousand positions, a 4 by 5 board
+// (4 columns of 5 cells) about 200 thousand, 5 by 4 about 600 thousand and
+// 5 by 5 about 4 million, sizes that show the cost of enumeration and
+// solving while still being solved exactly.
+// Positions are identified by their cells written in base 3, the player to
+// move following from the number of pieces. Boards are limited to 39 cells
+// for the ids to fit in an i64.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectThree {
+    n_cols: usize,
+    n_rows: usize,
+    // Pieces of each column from the bottom, 1 for the first player and 2
+    // for the second
+    columns: Vec<Vec<u8>>,
+}
+
+impl Default for ConnectThree {
+    fn default() -> ConnectThree {
+        return ConnectThree::new(4, 4).unwrap()
+    }
+}
+
+impl ConnectThree {
+
+    // Empty board, None when it has no cell or more than 39
+    pub fn new(n_cols: usize, n_rows: usize) -> Option<ConnectThree> {
+        if n_cols*n_rows == 0 || n_cols*n_rows > 39 {
+            return None
+        }
+        return Some(ConnectThree {n_cols, n_rows, columns: vec![Vec::new(); n_cols]})
+    }
+
+    // 0 for an empty cell, 1 or 2 for the pieces of the first or second player
+    pub fn get_cell(&self, col: usize, row: usize) -> u8 {
+        return self.columns[col].get(row).cloned().unwrap_or(0)
+    }
+
+    // The same position seen in a mirror, column i becoming n_cols - 1 - i
+    pub fn mirrored(&self) -> ConnectThree {
+        let columns: Vec<Vec<u8>> = self.columns.iter().rev().cloned().collect();
+        return ConnectThree {columns, ..self.clone()}
+    }
+
+    // Smallest id of the position and its mirror image, the same for both.
+    // Moves of mirrored positions are mirrored too, so models using it as
+    // state id must mirror their actions.
+    pub fn canonical_state_id(&self) -> i64 {
+        return self.state_id().min(self.mirrored().state_id())
+    }
+
+    fn n_pieces(&self) -> usize {
+        return self.columns.iter().map(|column| column.len()).sum()
+    }
+
+    // Player with three in a row, if any
+    fn winner(&self) -> Option<u8> {
+        let (n_cols, n_rows) = (self.n_cols as i64, self.n_rows as i64);
+        for col in 0..n_cols {
+            for row in 0..n_rows {
+                let piece = self.get_cell(col as usize, row as usize);
+                if piece == 0 {
+                    continue;
+                }
+                for (d_col, d_row) in [(1, 0), (0, 1), (1, 1), (1, -1)] {
+                    let in_line = (1..3).all(|step| {
+                        let (c, r) = (col + step*d_col, row + step*d_row);
+                        c < n_cols && (0..n_rows).contains(&r) && self.get_cell(c as usize, r as usize) == piece
+                    });
+                    if in_line {
+                        return Some(piece)
+                    }
+                }
+            }
+        }
+        return None
+    }
+
+}
+
+impl TurnBasedGame for ConnectThree {
+
+    type Move = usize;
+
+    fn current_player(&self) -> Player {
+        return if self.n_pieces().is_multiple_of(2) {Player::First} else {Player::Second}
+    }
+
+    fn legal_moves(&self) -> Vec<usize> {
+        if self.winner().is_some() {
+            return Vec::new()
+        }
+        return (0..self.n_cols).filter(|col| self.columns[*col].len() < self.n_rows).collect()
+    }
+
+    fn apply(&mut self, game_move: &usize) {
+        let piece = if self.current_player() == Player::First {1} else {2};
+        self.columns[*game_move].push(piece);
+    }
+
+    fn undo(&mut self, game_move: &usize) {
+        self.columns[*game_move].pop();
+    }
+
+    fn is_terminal(&self) -> bool {
+        return self.outcome().is_some()
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        return match self.winner() {
+            Some(1) => Some(Outcome::Win(Player::First)),
+            Some(_) => Some(Outcome::Win(Player::Second)),
+            None if self.n_pieces() == self.n_cols*self.n_rows => Some(Outcome::Draw),
+            None => None,
+        }
+    }
+
+    fn state_id(&self) -> i64 {
+        let mut id: i64 = 0;
+        for col in 0..self.n_cols {
+            for row in 0..self.n_rows {
+                id = 3*id + self.get_cell(col, row) as i64;
+            }
+        }
+        return id
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::games::{build_model, UniformOpponent};
+    use crate::Agent;
+
+    fn play(moves: &[usize]) -> ConnectThree {
+        let mut game = ConnectThree::default();
+        for game_move in moves {
+            game.apply(game_move);
+        }
+        return game
+    }
+
+    #[test]
+    fn connect_three_test() {
+        assert!(ConnectThree::new(7, 6).is_none());
+
+        // Vertical and diagonal lines
+        assert_eq!(play(&[0, 1, 0, 1, 0]).outcome(), Some(Outcome::Win(Player::First)));
+        assert_eq!(play(&[0, 1, 1, 2, 2, 3, 2]).outcome(), Some(Outcome::Win(Player::First)));
+        assert_eq!(play(&[0, 1, 0]).outcome(), None);
+        assert!(play(&[0, 1, 0, 1, 0]).legal_moves().is_empty());
+
+        let game = play(&[0, 0, 0, 0]);
+        assert_eq!(game.legal_moves(), vec![1, 2, 3]);
+        assert_eq!(game.current_player(), Player::First);
+        assert_eq!(game.mirrored().get_cell(3, 3), 2);
+        assert_eq!(game.canonical_state_id(), game.mirrored().canonical_state_id());
+        assert_ne!(game.state_id(), game.mirrored().state_id());
+    }
+
+    #[test]
+    fn connect_three_model_test() {
+        // The first player wins, completing the bottom row before the second
+        // one completes the first column
+        let game = play(&[1, 0, 2, 0]);
+        assert_eq!(game.current_player(), Player::First);
+        let mut agent: Agent = Agent::init_random(build_model(&game, Player::First, &UniformOpponent));
+        agent.deterministic_policy_improvement(1., 1e-9, 100, 1000);
+        assert_eq!(agent[game.state_id()], 1.);
+        assert_eq!(play(&[1, 0, 2, 0, 3]).outcome(), Some(Outcome::Win(Player::First)));
+    }
+
+}
@@ -0,0 +1,135 @@
+// Two-player turn-based games.
+//
+// A game implementing TurnBasedGame can be turned into a single-agent model:
+// the agent plays one side and the opponent's replies, drawn from an
+// opponent policy, become the stochastic part of the transitions. Wins are
+// worth 1, losses -1 and draws 0, given when the game ends.
+
+use std::collections::HashMap;
+
+use crate::numeric::Real;
+use crate::Agent;
+
+mod connect;
+mod model;
+mod nim;
+mod repl;
+#[cfg(feature = "rand")]
+mod tournament;
+
+pub use connect::ConnectThree;
+pub use model::{build_links, build_model, GameModel};
+pub use nim::{Nim, NimMove, XorStrategy};
+pub use repl::play_interactive;
+#[cfg(feature = "rand")]
+pub use tournament::{play_game, play_match, round_robin, MatchStats, RateEstimate};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Player {
+    First,
+    Second,
+}
+
+impl Player {
+
+    pub fn other(&self) -> Player {
+        return match self {
+            Player::First => Player::Second,
+            Player::Second => Player::First,
+        }
+    }
+
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Win(Player),
+    Draw,
+}
+
+impl Outcome {
+
+    // Reward of the outcome for a player
+    pub fn reward(&self, player: Player) -> f64 {
+        return match self {
+            Outcome::Win(winner) if *winner == player => 1.,
+            Outcome::Win(_) => -1.,
+            Outcome::Draw => 0.,
+        }
+    }
+
+}
+
+pub trait TurnBasedGame {
+
+    // Moves become the actions of the model through to_string
+    type Move: Clone + ToString;
+
+    fn current_player(&self) -> Player;
+
+    // Moves of the current player, empty once the game is over
+    fn legal_moves(&self) -> Vec<Self::Move>;
+
+    fn apply(&mut self, game_move: &Self::Move);
+
+    // Reverts a move, which must be the last one applied
+    fn undo(&mut self, game_move: &Self::Move);
+
+    fn is_terminal(&self) -> bool;
+
+    // None while the game is not over
+    fn outcome(&self) -> Option<Outcome>;
+
+    // Id of the position, used as state id in the model. Positions with the
+    // same id must be the same for the rest of the game.
+    fn state_id(&self) -> i64;
+
+}
+
+// Probabilities of the opponent's moves in a position
+pub trait OpponentPolicy<G: TurnBasedGame> {
+    fn move_probs(&self, game: &G, moves: &[G::Move]) -> Vec<f64>;
+}
+
+// Closures receiving the position and its legal moves
+impl<G: TurnBasedGame, P: Fn(&G, &[G::Move]) -> Vec<f64>> OpponentPolicy<G> for P {
+    fn move_probs(&self, game: &G, moves: &[G::Move]) -> Vec<f64> {
+        return self(game, moves)
+    }
+}
+
+// Opponent playing every legal move with the same probability
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UniformOpponent;
+
+impl<G: TurnBasedGame> OpponentPolicy<G> for UniformOpponent {
+    fn move_probs(&self, _game: &G, moves: &[G::Move]) -> Vec<f64> {
+        return vec![1./moves.len() as f64; moves.len()]
+    }
+}
+
+// Policy tables as stored by Agent, for instance an agent solved for the
+// other player. Positions missing from the table, or without probability on
+// any legal move, are played uniformly.
+impl<G: TurnBasedGame, F: Real> OpponentPolicy<G> for HashMap<i64,HashMap<String,F>> {
+    fn move_probs(&self, game: &G, moves: &[G::Move]) -> Vec<f64> {
+        let probs: Vec<f64> = match self.get(&game.state_id()) {
+            Some(policy) => moves.iter()
+                .map(|game_move| policy.get(&game_move.to_string()).map(|prob| prob.as_f64()).unwrap_or(0.))
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let total: f64 = probs.iter().sum();
+        if total <= 0. {
+            return UniformOpponent.move_probs(game, moves)
+        }
+        return probs.iter().map(|prob| prob/total).collect()
+    }
+}
+
+impl<G: TurnBasedGame, F: Real> OpponentPolicy<G> for Agent<F> {
+    fn move_probs(&self, game: &G, moves: &[G::Move]) -> Vec<f64> {
+        return self.get_policy().move_probs(game, moves)
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::games::{OpponentPolicy, Player, TurnBasedGame};
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+use crate::Agent;
+
+// Positions reached from the current one once the opponent has replied, with
+// their probabilities. Replies are expanded until it is the player's turn
+// again or the game is over.
+fn opponent_replies<G, O>(game: &mut G, player: Player, opponent: &O, prob: f64, replies: &mut Vec<(G,f64)>)
+where G: TurnBasedGame + Clone, O: OpponentPolicy<G> + ?Sized {
+
+    if game.is_terminal() || game.current_player() == player {
+        replies.push((game.clone(), prob));
+        return
+    }
+
+    let moves = game.legal_moves();
+    let probs = opponent.move_probs(game, &moves);
+
+    for (game_move, move_prob) in moves.iter().zip(probs) {
+        if move_prob > 0. {
+            game.apply(game_move);
+            opponent_replies(game, player, opponent, prob*move_prob, replies);
+            game.undo(game_move);
+        }
+    }
+}
+
+// Links of the game as seen by one player, from the given position. States
+// are the positions where the player is to move, and the positions where the
+// game ended. When the opponent moves first, every position after its
+// opening moves is a starting state of the model.
+pub fn build_links<G, O, F>(game: &G, player: Player, opponent: &O) -> Vec<StateLink<F>>
+where G: TurnBasedGame + Clone, O: OpponentPolicy<G> + ?Sized, F: Real {
+
+    let mut links: Vec<StateLink<F>> = Vec::new();
+    let mut ids_done: HashSet<i64> = HashSet::new();
+
+    let mut start_positions: Vec<(G,f64)> = Vec::new();
+    opponent_replies(&mut game.clone(), player, opponent, 1., &mut start_positions);
+
+    let mut positions: Vec<G> = start_positions.into_iter()
+        .map(|(position, _)| position)
+        .filter(|position| !position.is_terminal())
+        .collect();
+
+    while let Some(mut position) = positions.pop() {
+        let id_prev = position.state_id();
+        if !ids_done.insert(id_prev) {
+            continue;
+        }
+
+        for game_move in position.legal_moves() {
+            let action = game_move.to_string();
+
+            let mut replies: Vec<(G,f64)> = Vec::new();
+            position.apply(&game_move);
+            opponent_replies(&mut position, player, opponent, 1., &mut replies);
+            position.undo(&game_move);
+
+            // Different replies can reach the same position, their probabilities are merged
+            let mut successors: BTreeMap<i64,(f64,f64)> = BTreeMap::new();
+
+            for (next_position, prob) in replies {
+                let id_next = next_position.state_id();
+                let reward = next_position.outcome().map(|outcome| outcome.reward(player)).unwrap_or(0.);
+                successors.entry(id_next).or_insert((0., reward)).0 += prob;
+
+                if !next_position.is_terminal() && !ids_done.contains(&id_next) {
+                    positions.push(next_position);
+                }
+            }
+
+            for (id_next, (prob, reward)) in successors {
+                links.push(StateLink(id_prev, id_next, action.clone(), F::from_f64(prob), F::from_f64(reward)));
+            }
+        }
+    }
+
+    return links
+}
+
+pub fn build_model<G, O, F>(game: &G, player: Player, opponent: &O) -> SystemState<F>
+where G: TurnBasedGame + Clone, O: OpponentPolicy<G> + ?Sized, F: Real {
+    return SystemState::create_and_build(build_links(game, player, opponent))
+}
+
+// A game seen by one player from a starting position, rebuilt whenever the
+// opponent's policy changes. Alternating rederive and solving for both
+// players gives best-response dynamics.
+#[derive(Debug, Clone)]
+pub struct GameModel<G> {
+    game: G,
+    player: Player,
+}
+
+impl<G: TurnBasedGame + Clone> GameModel<G> {
+
+    pub fn new(game: G, player: Player) -> GameModel<G> {
+        return GameModel {game, player}
+    }
+
+    pub fn get_game(&self) -> &G {
+        return &self.game
+    }
+
+    pub fn get_player(&self) -> Player {
+        return self.player
+    }
+
+    pub fn build<O, F>(&self, opponent: &O) -> SystemState<F>
+    where O: OpponentPolicy<G> + ?Sized, F: Real {
+        return build_model(&self.game, self.player, opponent)
+    }
+
+    // Agent on the model against a new opponent policy, starting from the
+    // policy and values of an agent solved against the previous one. States
+    // that are new to the model start with a random policy and a zero value.
+    pub fn rederive<O, F>(&self, agent: &Agent<F>, opponent: &O) -> Agent<F>
+    where O: OpponentPolicy<G> + ?Sized, F: Real {
+
+        let mut new_agent = Agent::init_random(self.build(opponent));
+        let system_state = new_agent.get_shared_system_state();
+        let model = system_state.get_dense();
+
+        let mut policy = new_agent.policy.clone();
+        for (pos, id) in model.get_ids().iter().enumerate() {
+            if let Some(old_policy) = agent.get_policy().get(id) {
+                // Only kept when the state still has the same actions
+                let actions = model.get_actions(pos);
+                if old_policy.len() == actions.len() && actions.iter().all(|action| old_policy.contains_key(action)) {
+                    policy[pos] = actions.iter().map(|action| old_policy[action]).collect();
+                }
+            }
+        }
+
+        let values: Vec<F> = model.get_ids().iter().zip(&new_agent.policy_evaluation)
+            .map(|(id, value)| *agent.get_evaluation().get(id).unwrap_or(value))
+            .collect();
+        new_agent.replace_policy(policy);
+        new_agent.replace_values(values);
+
+        return new_agent
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::games::{Outcome, UniformOpponent};
+
+    // Players take one or two stones in turn, whoever takes the last one wins
+    #[derive(Clone)]
+    struct Stones {
+        stones: i64,
+        first_to_move: bool,
+    }
+
+    impl TurnBasedGame for Stones {
+
+        type Move = i64;
+
+        fn current_player(&self) -> Player {
+            return if self.first_to_move {Player::First} else {Player::Second}
+        }
+
+        fn legal_moves(&self) -> Vec<i64> {
+            return (1..=self.stones.min(2)).collect()
+        }
+
+        fn apply(&mut self, game_move: &i64) {
+            self.stones -= game_move;
+            self.first_to_move = !self.first_to_move;
+        }
+
+        fn undo(&mut self, game_move: &i64) {
+            self.stones += game_move;
+            self.first_to_move = !self.first_to_move;
+        }
+
+        fn is_terminal(&self) -> bool {
+            return self.stones == 0
+        }
+
+        fn outcome(&self) -> Option<Outcome> {
+            // The player who took the last stone is not the one to move
+            return if self.is_terminal() {Some(Outcome::Win(self.current_player().other()))} else {None}
+        }
+
+        fn state_id(&self) -> i64 {
+            return 2*self.stones + self.first_to_move as i64
+        }
+
+    }
+
+    #[test]
+    fn stones_model_test() {
+        let game = Stones {stones: 4, first_to_move: true};
+
+        // Second player, the first one takes one or two stones to start
+        let links: Vec<StateLink> = build_links(&game, Player::Second, &UniformOpponent);
+        let mut starts: Vec<i64> = links.iter().map(|link| link.0).filter(|id| *id >= 4).collect();
+        starts.sort();
+        starts.dedup();
+        assert_eq!(starts, vec![4, 6]);
+
+        // Taking the last stones wins, otherwise the opponent replies
+        let mut agent: Agent = Agent::init_random(build_model(&game, Player::First, &UniformOpponent));
+        agent.deterministic_policy_improvement(1., 1e-8, 100, 100);
+
+        // With 4 stones taking one leaves 3, a lost position for the opponent
+        assert_eq!(agent.get_best_action(9).unwrap().0, "1");
+        assert_eq!(*agent.get_evaluation().get(&9).unwrap(), 1.);
+
+        // An opponent taking the last stones whenever it can wins from 3 stones
+        let greedy = |game: &Stones, moves: &[i64]| moves.iter().map(|game_move| if *game_move == game.stones.min(2) {1.} else {0.}).collect();
+        let mut agent: Agent = Agent::init_random(build_model(&Stones {stones: 3, first_to_move: true}, Player::First, &greedy));
+        agent.deterministic_policy_improvement(1., 1e-8, 100, 100);
+        assert_eq!(*agent.get_evaluation().get(&7).unwrap(), -1.);
+    }
+
+    #[test]
+    fn best_response_test() {
+        let first = GameModel::new(Stones {stones: 7, first_to_move: true}, Player::First);
+        let second = GameModel::new(Stones {stones: 7, first_to_move: true}, Player::Second);
+
+        // Best responses to each other, starting from a uniform first player
+        let mut first_agent: Agent = Agent::init_random(first.build(&UniformOpponent));
+        let mut second_agent: Agent = Agent::init_random(second.build(&first_agent));
+
+        for _ in 0..4 {
+            second_agent = second.rederive(&second_agent, &first_agent);
+            second_agent.deterministic_policy_improvement(1., 1e-8, 100, 100);
+            first_agent = first.rederive(&first_agent, &second_agent);
+            first_agent.deterministic_policy_improvement(1., 1e-8, 100, 100);
+        }
+
+        // 7 stones is a won position for the first player, taking one
+        // stone to leave a multiple of 3, whatever the second player does
+        assert_eq!(first_agent.get_best_action(15).unwrap().0, "1");
+        assert_eq!(*first_agent.get_evaluation().get(&15).unwrap(), 1.);
+
+        // The second player's policy is followed as a table
+        let table = second_agent.get_policy();
+        let game = Stones {stones: 5, first_to_move: false};
+        let probs = table.move_probs(&game, &game.legal_moves());
+        assert_eq!(probs.iter().sum::<f64>(), 1.);
+
+        // Unknown positions are played uniformly
+        let unknown = Stones {stones: 50, first_to_move: false};
+        assert_eq!(table.move_probs(&unknown, &[1, 2]), vec![0.5, 0.5]);
+    }
+
+}
@@ -0,0 +1,171 @@
+use std::fmt;
+
+use crate::games::{OpponentPolicy, Outcome, Player, TurnBasedGame};
+
+// Taking objects from one heap, written heap:count in the model's actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NimMove {
+    pub heap: usize,
+    pub count: u32,
+}
+
+impl fmt::Display for NimMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{}:{}", self.heap, self.count)
+    }
+}
+
+// Nim. Players take turns removing any number of objects from one heap, and
+// whoever takes the last object wins. A position is lost for the player to
+// move exactly when the xor of the heap sizes, its nim-sum, is zero, and the
+// winning moves are those bringing it back to zero.
+//
+// Positions are identified by the heap sizes written in mixed radix, each
+// heap's base being one more than its starting size, times two plus one when
+// the first player is to move. Heaps must be small enough for the id to fit
+// in an i64.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nim {
+    heaps: Vec<u32>,
+    sizes: Vec<u32>,
+    first_to_move: bool,
+}
+
+impl Nim {
+
+    // Starting position, the first player to move
+    pub fn new(heaps: Vec<u32>) -> Nim {
+        return Nim {sizes: heaps.clone(), heaps, first_to_move: true}
+    }
+
+    pub fn get_heaps(&self) -> &Vec<u32> {
+        return &self.heaps
+    }
+
+    pub fn nim_sum(&self) -> u32 {
+        return self.heaps.iter().fold(0, |sum, heap| sum ^ heap)
+    }
+
+    // Moves leaving a nim-sum of zero, none in lost positions
+    pub fn winning_moves(&self) -> Vec<NimMove> {
+        let nim_sum = self.nim_sum();
+        return self.heaps.iter().enumerate()
+            .filter(|(_, heap)| (**heap ^ nim_sum) < **heap)
+            .map(|(heap, size)| NimMove {heap, count: size - (size ^ nim_sum)})
+            .collect()
+    }
+
+}
+
+impl TurnBasedGame for Nim {
+
+    type Move = NimMove;
+
+    fn current_player(&self) -> Player {
+        return if self.first_to_move {Player::First} else {Player::Second}
+    }
+
+    fn legal_moves(&self) -> Vec<NimMove> {
+        return self.heaps.iter().enumerate()
+            .flat_map(|(heap, size)| (1..=*size).map(move |count| NimMove {heap, count}))
+            .collect()
+    }
+
+    fn apply(&mut self, game_move: &NimMove) {
+        self.heaps[game_move.heap] -= game_move.count;
+        self.first_to_move = !self.first_to_move;
+    }
+
+    fn undo(&mut self, game_move: &NimMove) {
+        self.heaps[game_move.heap] += game_move.count;
+        self.first_to_move = !self.first_to_move;
+    }
+
+    fn is_terminal(&self) -> bool {
+        return self.heaps.iter().all(|heap| *heap == 0)
+    }
+
+    fn outcome(&self) -> Option<Outcome> {
+        // The player who took the last object is not the one to move
+        return if self.is_terminal() {Some(Outcome::Win(self.current_player().other()))} else {None}
+    }
+
+    fn state_id(&self) -> i64 {
+        let heaps_id = self.heaps.iter().zip(&self.sizes)
+            .fold(0, |id, (heap, size)| id*(*size as i64 + 1) + *heap as i64);
+        return 2*heaps_id + self.first_to_move as i64
+    }
+
+}
+
+// Opponent following the xor rule, playing its winning moves uniformly, or
+// any move uniformly from a lost position
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct XorStrategy;
+
+impl OpponentPolicy<Nim> for XorStrategy {
+    fn move_probs(&self, game: &Nim, moves: &[NimMove]) -> Vec<f64> {
+        let winning = game.winning_moves();
+        let n_winning = moves.iter().filter(|game_move| winning.contains(game_move)).count();
+        if n_winning == 0 {
+            return vec![1./moves.len() as f64; moves.len()]
+        }
+        return moves.iter().map(|game_move| if winning.contains(game_move) {1./n_winning as f64} else {0.}).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::games::build_model;
+    use crate::Agent;
+
+    #[test]
+    fn nim_test() {
+        let mut game = Nim::new(vec![1, 2, 4]);
+        assert_eq!(game.nim_sum(), 7);
+        assert_eq!(game.winning_moves(), vec![NimMove {heap: 2, count: 1}]);
+        assert_eq!(game.legal_moves().len(), 7);
+
+        let game_move = NimMove {heap: 1, count: 2};
+        assert_eq!(game_move.to_string(), "1:2");
+        let id = game.state_id();
+        game.apply(&game_move);
+        assert_eq!(game.current_player(), Player::Second);
+        game.undo(&game_move);
+        assert_eq!(game.state_id(), id);
+    }
+
+    #[test]
+    fn xor_rule_test() {
+        // Against an opponent playing the xor rule, every position the agent
+        // moves from is won exactly when its nim-sum is not zero
+        let sizes = vec![2, 3, 4];
+        let mut agent: Agent = Agent::init_random(build_model(&Nim::new(sizes.clone()), Player::First, &XorStrategy));
+        agent.deterministic_policy_improvement(1., 1e-9, 100, 1000);
+
+        let mut n_checked = 0;
+        for a in 0..=2 {
+            for b in 0..=3 {
+                for c in 0..=4 {
+                    let position = Nim {heaps: vec![a, b, c], sizes: sizes.clone(), first_to_move: true};
+                    let value = match agent.value_of(position.state_id()) {
+                        Ok(value) if !position.is_terminal() => value,
+                        _ => continue,
+                    };
+                    n_checked += 1;
+                    if position.nim_sum() != 0 {
+                        assert_eq!(value, 1.);
+                        let (action, _) = agent.get_best_action(position.state_id()).unwrap();
+                        assert!(position.winning_moves().iter().any(|game_move| game_move.to_string() == *action));
+                    } else {
+                        assert_eq!(value, -1.);
+                    }
+                }
+            }
+        }
+        assert!(n_checked > 20);
+    }
+
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+
+use crate::games::{Outcome, Player, TurnBasedGame};
+use crate::numeric::Real;
+use crate::Agent;
+
+const HELP: &str = "Commands: <move> plays a move, values shows the agent's value after each move, undo takes back your last move, quit ends the game";
+
+// Interactive game between a person, reading moves from input, and an agent
+// playing agent_player with its current policy. Every agent move is printed
+// with the agent's Q-values, and the person can ask for the agent's value of
+// the positions each legal move leads to, or undo moves.
+//
+// Returns the outcome, or None when the person quits or the input ends.
+pub fn play_interactive<G, F, R, W>(agent: &Agent<F>, game: &mut G, agent_player: Player, gamma: F, input: &mut R, output: &mut W) -> io::Result<Option<Outcome>>
+where G: TurnBasedGame + Display, F: Real, R: BufRead, W: Write {
+
+    let q_values = agent.calc_q_values(gamma);
+    let mut history: Vec<(Player, G::Move)> = Vec::new();
+
+    writeln!(output, "{}", HELP)?;
+
+    loop {
+        writeln!(output, "\n{}\n", game)?;
+
+        if let Some(outcome) = game.outcome() {
+            match outcome {
+                Outcome::Win(player) if player == agent_player => writeln!(output, "The agent won!")?,
+                Outcome::Win(_) => writeln!(output, "You won!")?,
+                Outcome::Draw => writeln!(output, "Draw.")?,
+            }
+            return Ok(Some(outcome))
+        }
+
+        let moves = game.legal_moves();
+
+        if game.current_player() == agent_player {
+            let state_id = game.state_id();
+            let chosen = agent.get_policy().get(&state_id)
+                .and_then(|policy| moves.iter().filter(|game_move| policy.contains_key(&game_move.to_string()))
+                    .max_by(|a, b| policy.get(&a.to_string()).partial_cmp(&policy.get(&b.to_string())).unwrap()))
+                .cloned();
+
+            let game_move = match chosen {
+                Some(game_move) => game_move,
+                None => {
+                    writeln!(output, "The agent doesn't know this position and plays the first legal move.")?;
+                    moves[0].clone()
+                },
+            };
+
+            writeln!(output, "The agent plays {}", game_move.to_string())?;
+            if let Some(state_q_values) = q_values.get(&state_id) {
+                writeln!(output, "  q-values: {}", format_sorted(state_q_values))?;
+            }
+
+            game.apply(&game_move);
+            history.push((agent_player, game_move));
+            continue;
+        }
+
+        let move_names: Vec<String> = moves.iter().map(|game_move| game_move.to_string()).collect();
+        writeln!(output, "Your turn, moves: {}", move_names.join(" "))?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None)
+        }
+
+        match line.trim() {
+            "quit" => return Ok(None),
+            "help" => writeln!(output, "{}", HELP)?,
+            "values" => {
+                for game_move in &moves {
+                    game.apply(game_move);
+                    let value = match game.outcome() {
+                        Some(outcome) => Some(outcome.reward(agent_player)),
+                        None => agent.get_evaluation().get(&game.state_id()).map(|value| value.as_f64()),
+                    };
+                    game.undo(game_move);
+
+                    match value {
+                        Some(value) => writeln!(output, "  {}: {:.4}", game_move.to_string(), value)?,
+                        None => writeln!(output, "  {}: unknown", game_move.to_string())?,
+                    }
+                }
+            },
+            "undo" => {
+                // Takes back the agent's replies and the last move of the person
+                while let Some((player, game_move)) = history.pop() {
+                    game.undo(&game_move);
+                    if player != agent_player {
+                        break;
+                    }
+                }
+            },
+            play => match moves.iter().position(|game_move| game_move.to_string() == play) {
+                Some(pos) => {
+                    game.apply(&moves[pos]);
+                    history.push((agent_player.other(), moves[pos].clone()));
+                },
+                None => writeln!(output, "Unknown command or illegal move, type help for the commands.")?,
+            },
+        }
+    }
+}
+
+fn format_sorted<F: Real>(values: &HashMap<String,F>) -> String {
+    let mut sorted: Vec<(&String,&F)> = values.iter().collect();
+    sorted.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap().then(a.0.cmp(b.0)));
+    return sorted.iter()
+        .map(|(action, value)| format!("{}={:.4}", action, value))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+
+    use std::fmt;
+
+    use super::*;
+    use crate::games::{build_model, UniformOpponent};
+
+    // Players take one or two stones in turn, whoever takes the last one wins
+    #[derive(Clone)]
+    struct Stones {
+        stones: i64,
+        first_to_move: bool,
+    }
+
+    impl fmt::Display for Stones {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            return write!(f, "{} stones", self.stones)
+        }
+    }
+
+    impl TurnBasedGame for Stones {
+
+        type Move = i64;
+
+        fn current_player(&self) -> Player {
+            return if self.first_to_move {Player::First} else {Player::Second}
+        }
+
+        fn legal_moves(&self) -> Vec<i64> {
+            return (1..=self.stones.min(2)).collect()
+        }
+
+        fn apply(&mut self, game_move: &i64) {
+            self.stones -= game_move;
+            self.first_to_move = !self.first_to_move;
+        }
+
+        fn undo(&mut self, game_move: &i64) {
+            self.stones += game_move;
+            self.first_to_move = !self.first_to_move;
+        }
+
+        fn is_terminal(&self) -> bool {
+            return self.stones == 0
+        }
+
+        fn outcome(&self) -> Option<Outcome> {
+            return if self.is_terminal() {Some(Outcome::Win(self.current_player().other()))} else {None}
+        }
+
+        fn state_id(&self) -> i64 {
+            return 2*self.stones + self.first_to_move as i64
+        }
+
+    }
+
+    #[test]
+    fn scripted_game_test() {
+        let start = Stones {stones: 5, first_to_move: false};
+        let mut agent: Agent = Agent::init_random(build_model(&start, Player::First, &UniformOpponent));
+        agent.deterministic_policy_improvement(1., 1e-8, 100, 100);
+
+        // The person takes 1 leaving 4, the agent takes 1 leaving 3, the
+        // person takes it back, asks for values, plays 2, then loses
+        let mut game = start.clone();
+        let mut input = "1\nundo\nvalues\nfoo\n2\n1\n".as_bytes();
+        let mut output: Vec<u8> = Vec::new();
+        let outcome = play_interactive(&agent, &mut game, Player::First, 1., &mut input, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert_eq!(outcome, Some(Outcome::Win(Player::First)));
+        assert!(output.contains("The agent plays 1\n  q-values: 1=1.0000"));
+        assert!(output.contains("  1: 1.0000\n  2: 0.0000"));
+        assert!(output.contains("Unknown command"));
+        assert!(output.contains("The agent won!"));
+        assert_eq!(game.stones, 0);
+
+        // Ending the input stops the game
+        let mut game = start.clone();
+        let outcome = play_interactive(&agent, &mut game, Player::First, 1., &mut "".as_bytes(), &mut Vec::new()).unwrap();
+        assert_eq!(outcome, None);
+    }
+
+}
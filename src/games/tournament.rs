@@ -0,0 +1,239 @@
+use rand::Rng;
+
+use crate::games::{OpponentPolicy, Outcome, Player, TurnBasedGame};
+use crate::simulator::Z_95;
+
+// Proportion of games with some result, with its 95% Wilson score interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateEstimate {
+    pub rate: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl RateEstimate {
+
+    pub fn new(count: usize, n_games: usize) -> RateEstimate {
+        if n_games == 0 {
+            return RateEstimate {rate: 0., lower: 0., upper: 1.}
+        }
+
+        let n = n_games as f64;
+        let rate = count as f64/n;
+        let z2 = Z_95*Z_95;
+        let center = (rate + z2/(2.*n))/(1. + z2/n);
+        let half_width = Z_95/(1. + z2/n)*(rate*(1. - rate)/n + z2/(4.*n*n)).sqrt();
+
+        return RateEstimate {rate, lower: (center - half_width).max(0.), upper: (center + half_width).min(1.)}
+    }
+
+}
+
+// Results of the games between two policies, the first one always moving first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MatchStats {
+    pub n_games: usize,
+    pub first_wins: usize,
+    pub second_wins: usize,
+    pub draws: usize,
+}
+
+impl MatchStats {
+
+    pub fn add(&mut self, outcome: Outcome) {
+        self.n_games += 1;
+        match outcome {
+            Outcome::Win(Player::First) => self.first_wins += 1,
+            Outcome::Win(Player::Second) => self.second_wins += 1,
+            Outcome::Draw => self.draws += 1,
+        }
+    }
+
+    pub fn win_rate(&self, player: Player) -> RateEstimate {
+        return match player {
+            Player::First => RateEstimate::new(self.first_wins, self.n_games),
+            Player::Second => RateEstimate::new(self.second_wins, self.n_games),
+        }
+    }
+
+    pub fn loss_rate(&self, player: Player) -> RateEstimate {
+        return self.win_rate(player.other())
+    }
+
+    pub fn draw_rate(&self) -> RateEstimate {
+        return RateEstimate::new(self.draws, self.n_games)
+    }
+
+    // Mean reward of a player, wins counting 1 and losses -1
+    pub fn mean_score(&self, player: Player) -> f64 {
+        let (wins, losses) = match player {
+            Player::First => (self.first_wins, self.second_wins),
+            Player::Second => (self.second_wins, self.first_wins),
+        };
+        return (wins as f64 - losses as f64)/(self.n_games.max(1) as f64)
+    }
+
+}
+
+fn sample_move<G, P, R>(game: &G, policy: &P, rng: &mut R) -> G::Move
+where G: TurnBasedGame, P: OpponentPolicy<G> + ?Sized, R: Rng + ?Sized {
+
+    let moves = game.legal_moves();
+    let probs = policy.move_probs(game, &moves);
+    let total: f64 = probs.iter().filter(|prob| **prob > 0.).sum();
+
+    let mut threshold = rng.random::<f64>()*total;
+    for (game_move, prob) in moves.iter().zip(&probs) {
+        if *prob > 0. {
+            threshold -= prob;
+            if threshold < 0. {
+                return game_move.clone()
+            }
+        }
+    }
+
+    // Rounding left the threshold at zero, or no move has a positive probability
+    return match moves.iter().zip(&probs).rev().find(|(_, prob)| **prob > 0.) {
+        Some((game_move, _)) => game_move.clone(),
+        None => moves[rng.random_range(0..moves.len())].clone(),
+    }
+}
+
+// Plays one game from the given position, moves are drawn from the policies
+pub fn play_game<G, P1, P2, R>(game: &G, first: &P1, second: &P2, rng: &mut R) -> Outcome
+where G: TurnBasedGame + Clone, P1: OpponentPolicy<G> + ?Sized, P2: OpponentPolicy<G> + ?Sized, R: Rng + ?Sized {
+
+    let mut game = game.clone();
+
+    loop {
+        if let Some(outcome) = game.outcome() {
+            return outcome
+        }
+
+        let game_move = match game.current_player() {
+            Player::First => sample_move(&game, first, rng),
+            Player::Second => sample_move(&game, second, rng),
+        };
+        game.apply(&game_move);
+    }
+}
+
+pub fn play_match<G, P1, P2, R>(game: &G, first: &P1, second: &P2, n_games: usize, rng: &mut R) -> MatchStats
+where G: TurnBasedGame + Clone, P1: OpponentPolicy<G> + ?Sized, P2: OpponentPolicy<G> + ?Sized, R: Rng + ?Sized {
+
+    let mut stats = MatchStats::default();
+    for _ in 0..n_games {
+        stats.add(play_game(game, first, second, rng));
+    }
+    return stats
+}
+
+// Every policy against every other one, both as first and second player.
+// The result at [i][j] has policy i moving first against policy j, the
+// diagonal is left empty.
+pub fn round_robin<G, R>(game: &G, policies: &[&dyn OpponentPolicy<G>], n_games: usize, rng: &mut R) -> Vec<Vec<MatchStats>>
+where G: TurnBasedGame + Clone, R: Rng + ?Sized {
+
+    return (0..policies.len()).map(|i| {
+        (0..policies.len()).map(|j| {
+            if i == j {
+                MatchStats::default()
+            } else {
+                play_match(game, policies[i], policies[j], n_games, rng)
+            }
+        }).collect()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::games::{build_model, UniformOpponent};
+    use crate::Agent;
+
+    // Players take one or two stones in turn, whoever takes the last one wins
+    #[derive(Clone)]
+    struct Stones {
+        stones: i64,
+        first_to_move: bool,
+    }
+
+    impl TurnBasedGame for Stones {
+
+        type Move = i64;
+
+        fn current_player(&self) -> Player {
+            return if self.first_to_move {Player::First} else {Player::Second}
+        }
+
+        fn legal_moves(&self) -> Vec<i64> {
+            return (1..=self.stones.min(2)).collect()
+        }
+
+        fn apply(&mut self, game_move: &i64) {
+            self.stones -= game_move;
+            self.first_to_move = !self.first_to_move;
+        }
+
+        fn undo(&mut self, game_move: &i64) {
+            self.stones += game_move;
+            self.first_to_move = !self.first_to_move;
+        }
+
+        fn is_terminal(&self) -> bool {
+            return self.stones == 0
+        }
+
+        fn outcome(&self) -> Option<Outcome> {
+            return if self.is_terminal() {Some(Outcome::Win(self.current_player().other()))} else {None}
+        }
+
+        fn state_id(&self) -> i64 {
+            return 2*self.stones + self.first_to_move as i64
+        }
+
+    }
+
+    #[test]
+    fn rate_estimate_test() {
+        let estimate = RateEstimate::new(50, 100);
+        assert_eq!(estimate.rate, 0.5);
+        assert!((estimate.lower - 0.4038).abs() < 1e-4);
+        assert!((estimate.upper - 0.5962).abs() < 1e-4);
+
+        let estimate = RateEstimate::new(0, 10);
+        assert_eq!(estimate.lower, 0.);
+        assert!(estimate.upper > 0.2);
+    }
+
+    #[test]
+    fn tournament_test() {
+        let game = Stones {stones: 7, first_to_move: true};
+        let mut agent: Agent = Agent::init_random(build_model(&game, Player::First, &UniformOpponent));
+        agent.deterministic_policy_improvement(1., 1e-8, 100, 100);
+
+        let mut rng = StdRng::seed_from_u64(1);
+
+        // The solved first player always wins from 7 stones against a random player
+        let stats = play_match(&game, &agent, &UniformOpponent, 200, &mut rng);
+        assert_eq!(stats.n_games, 200);
+        assert_eq!(stats.first_wins, 200);
+        assert_eq!(stats.win_rate(Player::First).rate, 1.);
+        assert_eq!(stats.mean_score(Player::Second), -1.);
+
+        let results = round_robin(&game, &[&agent, &UniformOpponent], 200, &mut rng);
+        assert_eq!(results[0][0].n_games, 0);
+        assert_eq!(results[0][1].first_wins, 200);
+
+        // The agent only knows the first player's positions and plays the second one at random
+        let random_match = &results[1][0];
+        assert_eq!(random_match.n_games, 200);
+        let rate = random_match.win_rate(Player::First);
+        assert!(rate.lower < rate.rate && rate.rate < rate.upper);
+    }
+
+}
@@ -0,0 +1,334 @@
+// Policy evaluation on the GPU, enabled with the "gpu" feature.
+//
+// The rewards and transitions of the policy are uploaded once, the
+// transitions as a CSR matrix, and every sweep runs as a compute shader with
+// one invocation per state. wgpu picks the backend (Vulkan, Metal, DX12), and
+// the shader works in single precision whatever the numeric type of the
+// agent. The values only come back to the host once the evaluation ends,
+// the largest change of a sweep every few sweeps.
+//
+// Only the evaluation is offloaded: the improvement steps, a max over the
+// actions of each state, stay on the CPU, between evaluate_policy_gpu calls.
+
+use std::fmt;
+use std::sync::mpsc;
+
+use wgpu::util::DeviceExt;
+
+use crate::numeric::Real;
+use crate::progress::NoProgress;
+use crate::solver::SolveContext;
+use crate::Agent;
+
+const WORKGROUP_SIZE: u32 = 256;
+// Workgroups along one dimension of a dispatch
+const MAX_GROUPS: u32 = 65535;
+// Sweeps between two readbacks of delta
+const DELTA_INTERVAL: u32 = 8;
+
+const SWEEP_SHADER: &str = r#"
+struct Params {
+    n_states: u32,
+    stride: u32,
+    gamma: f32,
+}
+
+@group(0) @binding(0) var<storage, read> row_starts: array<u32>;
+@group(0) @binding(1) var<storage, read> columns: array<u32>;
+@group(0) @binding(2) var<storage, read> probs: array<f32>;
+@group(0) @binding(3) var<storage, read> rewards: array<f32>;
+@group(0) @binding(4) var<storage, read> values: array<f32>;
+@group(0) @binding(5) var<storage, read_write> new_values: array<f32>;
+@group(0) @binding(6) var<storage, read_write> delta: atomic<u32>;
+@group(0) @binding(7) var<uniform> params: Params;
+
+@compute @workgroup_size(256)
+fn sweep(@builtin(global_invocation_id) id: vec3<u32>) {
+    let row = id.x + id.y*params.stride;
+    if (row >= params.n_states) {
+        return;
+    }
+
+    // Compensated sum of the products, as on the CPU
+    var sum = 0.0;
+    var compensation = 0.0;
+    for (var k = row_starts[row]; k < row_starts[row + 1u]; k++) {
+        let product = probs[k]*values[columns[k]];
+        let total = sum + product;
+        if (abs(sum) >= abs(product)) {
+            compensation += (sum - total) + product;
+        } else {
+            compensation += (product - total) + sum;
+        }
+        sum = total;
+    }
+
+    let value = rewards[row] + params.gamma*(sum + compensation);
+    new_values[row] = value;
+    // Non-negative floats compare like their bits read as unsigned integers
+    atomicMax(&delta, bitcast<u32>(abs(value - values[row])));
+}
+"#;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GpuError {
+    // No adapter, or none able to create a device
+    Unavailable(String),
+    // More states or transitions than u32 indices, or the adapter's buffers, can hold
+    TooLarge(String),
+    Readback(String),
+}
+
+impl fmt::Display for GpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            GpuError::Unavailable(message) => write!(f, "no GPU available: {}", message),
+            GpuError::TooLarge(message) => write!(f, "model too large for the GPU: {}", message),
+            GpuError::Readback(message) => write!(f, "reading back from the GPU failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for GpuError {}
+
+// Device and compiled sweep, reusable across evaluations
+pub struct GpuEvaluator {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    limits: wgpu::Limits,
+    delta_interval: u32,
+}
+
+impl GpuEvaluator {
+
+    // Uses the high performance adapter, with the largest buffers it supports
+    pub fn new() -> Result<GpuEvaluator, GpuError> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        })).map_err(|error| GpuError::Unavailable(error.to_string()))?;
+
+        let limits = adapter.limits();
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("complete-iter"),
+            required_limits: limits.clone(),
+            ..Default::default()
+        })).map_err(|error| GpuError::Unavailable(error.to_string()))?;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sweep"),
+            source: wgpu::ShaderSource::Wgsl(SWEEP_SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("sweep"),
+            layout: None,
+            module: &module,
+            entry_point: Some("sweep"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        return Ok(GpuEvaluator {device, queue, pipeline, limits, delta_interval: DELTA_INTERVAL})
+    }
+
+    // Sweeps queued between two readbacks of delta, 8 by default. Each
+    // readback waits for the device, while the evaluation may run up to
+    // sweeps - 1 sweeps past convergence.
+    pub fn with_delta_interval(mut self, sweeps: u32) -> GpuEvaluator {
+        self.delta_interval = sweeps.max(1);
+        return self
+    }
+
+    fn storage_buffer(&self, label: &str, contents: &[u8], usage: wgpu::BufferUsages) -> Result<wgpu::Buffer, GpuError> {
+        if contents.len() as u64 > self.limits.max_storage_buffer_binding_size as u64 {
+            return Err(GpuError::TooLarge(format!("{} takes {} bytes", label, contents.len())))
+        }
+        return Ok(self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents,
+            usage: wgpu::BufferUsages::STORAGE | usage,
+        }))
+    }
+
+    // Copies a buffer to the host once the queued work is done
+    fn read_buffer(&self, buffer: &wgpu::Buffer, size: u64) -> Result<Vec<u8>, GpuError> {
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = mpsc::channel();
+        staging.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::PollType::Wait).map_err(|error| GpuError::Readback(error.to_string()))?;
+        receiver.recv()
+            .map_err(|error| GpuError::Readback(error.to_string()))?
+            .map_err(|error| GpuError::Readback(error.to_string()))?;
+
+        let bytes = staging.slice(..).get_mapped_range().to_vec();
+        staging.unmap();
+        return Ok(bytes)
+    }
+
+}
+
+impl<F: Real> Agent<F> {
+
+    // Same as evaluate_policy, with the sweeps on the GPU. The values are
+    // rounded to single precision, so epsilon should stay well above the
+    // precision of f32 relative to the values.
+    pub fn evaluate_policy_gpu(&mut self, gpu: &GpuEvaluator, gamma: F, epsilon: F, n_iter: u32) -> Result<(), GpuError> {
+
+        let matrices = self.take_policy_matrices(&SolveContext::new(&mut NoProgress, None));
+        let ids = self.system_state.get_dense().get_ids();
+        let n_states = ids.len();
+
+        let n_transitions: usize = matrices.successors.iter().map(|successors| successors.len()).sum();
+        if n_states >= u32::MAX as usize || n_transitions >= u32::MAX as usize {
+            self.policy_cache = Some((self.policy_version, matrices));
+            return Err(GpuError::TooLarge(format!("{} states and {} transitions", n_states, n_transitions)))
+        }
+
+        let mut row_starts: Vec<u32> = Vec::with_capacity(n_states + 1);
+        let mut columns: Vec<u32> = Vec::with_capacity(n_transitions);
+        let mut probs: Vec<f32> = Vec::with_capacity(n_transitions);
+        row_starts.push(0);
+        for successors in &matrices.successors {
+            for (pos_next, prob) in successors {
+                columns.push(*pos_next as u32);
+                probs.push(prob.as_f64() as f32);
+            }
+            row_starts.push(columns.len() as u32);
+        }
+        let rewards: Vec<f32> = matrices.rewards.iter().map(|reward| reward.as_f64() as f32).collect();
+        let values: Vec<f32> = self.policy_evaluation.iter().map(|value| value.as_f64() as f32).collect();
+        self.policy_cache = Some((self.policy_version, matrices));
+
+        // Empty buffers can't be bound
+        if n_states == 0 {
+            return Ok(())
+        }
+        if columns.is_empty() {
+            columns.push(0);
+            probs.push(0.);
+        }
+
+        let copy = wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+        let buffers = [
+            gpu.storage_buffer("row_starts", bytemuck::cast_slice(&row_starts), wgpu::BufferUsages::empty())?,
+            gpu.storage_buffer("columns", bytemuck::cast_slice(&columns), wgpu::BufferUsages::empty())?,
+            gpu.storage_buffer("probs", bytemuck::cast_slice(&probs), wgpu::BufferUsages::empty())?,
+            gpu.storage_buffer("rewards", bytemuck::cast_slice(&rewards), wgpu::BufferUsages::empty())?,
+        ];
+        // Each sweep reads one buffer of values and writes the other
+        let value_buffers = [
+            gpu.storage_buffer("values", bytemuck::cast_slice(&values), copy)?,
+            gpu.storage_buffer("new_values", bytemuck::cast_slice(&values), copy)?,
+        ];
+        let delta_buffer = gpu.storage_buffer("delta", bytemuck::cast_slice(&[0u32]), copy)?;
+
+        let n_groups = (n_states as u32).div_ceil(WORKGROUP_SIZE);
+        let groups_x = n_groups.min(MAX_GROUPS);
+        let groups_y = n_groups.div_ceil(groups_x);
+        let params = [n_states as u32, groups_x*WORKGROUP_SIZE, (gamma.as_f64() as f32).to_bits(), 0];
+        let params_buffer = gpu.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: bytemuck::cast_slice(&params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let layout = gpu.pipeline.get_bind_group_layout(0);
+        let bind_groups: Vec<wgpu::BindGroup> = [(0, 1), (1, 0)].iter()
+            .map(|(read, write)| {
+                let bound = [&buffers[0], &buffers[1], &buffers[2], &buffers[3], &value_buffers[*read], &value_buffers[*write], &delta_buffer, &params_buffer];
+                let entries: Vec<wgpu::BindGroupEntry> = bound.iter().enumerate()
+                    .map(|(binding, buffer)| wgpu::BindGroupEntry {binding: binding as u32, resource: buffer.as_entire_binding()})
+                    .collect();
+                gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {label: Some("sweep"), layout: &layout, entries: &entries})
+            }).collect();
+
+        // Iterative policy evaluation, the sweeps queued in batches and delta
+        // read back for the last sweep of each
+        let epsilon = epsilon.as_f64() as f32;
+        let mut counter: u32 = 0;
+
+        while counter < n_iter {
+            let batch = gpu.delta_interval.min(n_iter - counter);
+            let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            for sweep in counter..counter + batch {
+                if sweep == counter + batch - 1 {
+                    encoder.clear_buffer(&delta_buffer, 0, None);
+                }
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+                pass.set_pipeline(&gpu.pipeline);
+                pass.set_bind_group(0, &bind_groups[sweep as usize % 2], &[]);
+                pass.dispatch_workgroups(groups_x, groups_y, 1);
+            }
+            gpu.queue.submit(Some(encoder.finish()));
+            counter += batch;
+
+            let delta_bytes = gpu.read_buffer(&delta_buffer, 4)?;
+            let delta = f32::from_bits(bytemuck::pod_read_unaligned::<u32>(&delta_bytes));
+            if delta < epsilon {
+                break
+            }
+        }
+
+        // Written by the last sweep
+        let value_bytes = gpu.read_buffer(&value_buffers[counter as usize % 2], (n_states*4) as u64)?;
+        let values: Vec<F> = bytemuck::cast_slice::<u8, f32>(&value_bytes).iter().map(|value| F::from_f64(*value as f64)).collect();
+        self.replace_values(values);
+
+        return Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::envs::GamblersProblem;
+
+    // Machines without a GPU (or a software adapter) can't run the shader, so
+    // the test only runs when asked for
+    #[test]
+    #[ignore = "needs a GPU adapter, run with --features gpu -- --ignored"]
+    fn gpu_evaluation_test() {
+        let gpu = GpuEvaluator::new().expect("no GPU adapter");
+
+        let mut agent: Agent = Agent::init_random(GamblersProblem::new(50, 0.4).unwrap().build());
+        agent.deterministic_policy_improvement(0.9, 1e-9, 100, 1000);
+        let expected = agent.get_evaluation().clone();
+
+        let mut agent_gpu: Agent = Agent::init_random(GamblersProblem::new(50, 0.4).unwrap().build());
+        agent_gpu.set_polity(agent.get_policy().clone());
+        agent_gpu.evaluate_policy_gpu(&gpu, 0.9, 1e-6, 1000).unwrap();
+
+        for (id, value) in &expected {
+            assert!((agent_gpu.get_evaluation().get(id).unwrap() - value).abs() < 1e-4);
+        }
+
+        // Delta read back after every sweep, or with an odd number of sweeps in the last batch
+        for (interval, n_iter) in [(1, 1000), (3, 1000), (8, 5)] {
+            let gpu = GpuEvaluator::new().unwrap().with_delta_interval(interval);
+            let mut agent_gpu: Agent = Agent::init_random(GamblersProblem::new(50, 0.4).unwrap().build());
+            agent_gpu.set_polity(agent.get_policy().clone());
+            let mut agent_cpu = agent_gpu.clone();
+            agent_gpu.evaluate_policy_gpu(&gpu, 0.9, 1e-6, n_iter).unwrap();
+            agent_cpu.evaluate_policy(0.9, 1e-6, n_iter);
+            for (id, value) in agent_cpu.get_evaluation() {
+                assert!((agent_gpu.get_evaluation().get(id).unwrap() - value).abs() < 1e-4);
+            }
+        }
+    }
+
+}
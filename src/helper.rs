@@ -1,20 +1,537 @@
-use std::{collections::HashMap, hash::Hash};
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+#[cfg(feature = "rand")]
+use rand::Rng;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::collections::HashMap;
+use crate::numeric::Real;
+
+// Maps larger than this are reduced in parallel when the "rayon" feature is
+// enabled, unless the "deterministic" feature is too
+pub const PARALLEL_THRESHOLD: usize = 8192;
+
+// Hasher of the maps kept inside the crate, such as the positions of the
+// states, FxHash with the "fxhash" feature. The maps of the public API keep
+// the standard hasher, so the feature changes no signature.
+#[cfg(feature = "fxhash")]
+pub type FastHasher = rustc_hash::FxBuildHasher;
+
+#[cfg(all(feature = "std", not(feature = "fxhash")))]
+pub type FastHasher = std::collections::hash_map::RandomState;
+
+// Without std, the hasher of the hashbrown maps
+#[cfg(not(feature = "std"))]
+pub type FastHasher = hashbrown::DefaultHashBuilder;
+
+pub type FastMap<K,V> = HashMap<K,V,FastHasher>;
+
+// Bound on map keys, which must also be Sync for the parallel reductions
+#[cfg(feature = "rayon")]
+pub trait MapKey: Eq + Hash + KeyOrder + Sync {}
+
+#[cfg(feature = "rayon")]
+impl<T: Eq + Hash + KeyOrder + Sync> MapKey for T {}
+
+#[cfg(not(feature = "rayon"))]
+pub trait MapKey: Eq + Hash + KeyOrder {}
+
+#[cfg(not(feature = "rayon"))]
+impl<T: Eq + Hash + KeyOrder> MapKey for T {}
+
+// Keys are only ordered for the reductions of the "deterministic" feature
+#[cfg(feature = "deterministic")]
+pub trait KeyOrder: Ord {}
+
+#[cfg(feature = "deterministic")]
+impl<T: Ord> KeyOrder for T {}
+
+#[cfg(not(feature = "deterministic"))]
+pub trait KeyOrder {}
+
+#[cfg(not(feature = "deterministic"))]
+impl<T> KeyOrder for T {}
+
+// Products of the values with matching keys, in the iteration order of
+// map_1
+#[cfg(not(feature = "deterministic"))]
+fn matched_products<'a,T: MapKey,F: Real>(map_1: &'a HashMap<T,F>, map_2: &'a HashMap<T,F>) -> impl Iterator<Item = F> + 'a {
+    return map_1.iter()
+        .map(|(key, value)| *map_2.get(key).unwrap_or(&F::zero())*(*value))
+}
+
+// With the "deterministic" feature, in increasing key order, so that their
+// sums come out the same bits whatever the hash order of the maps
+#[cfg(feature = "deterministic")]
+fn matched_products<'a,T: MapKey,F: Real>(map_1: &'a HashMap<T,F>, map_2: &'a HashMap<T,F>) -> impl Iterator<Item = F> + 'a {
+    let mut entries: Vec<(&T, &F)> = map_1.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    return entries.into_iter()
+        .map(|(key, value)| *map_2.get(key).unwrap_or(&F::zero())*(*value))
+}
 
 // A function that computes the product of items with matching keys
-pub fn match_mul<'a,T: Eq + Hash>(map_1: &'a HashMap<T,f64>, map_2: &'a HashMap<T,f64>) -> HashMap<&'a T,f64> {
+pub fn match_mul<'a,T: MapKey,F: Real>(map_1: &'a HashMap<T,F>, map_2: &'a HashMap<T,F>) -> HashMap<&'a T,F> {
+    #[cfg(feature = "rayon")]
+    if map_1.len() >= PARALLEL_THRESHOLD {
+        return par_match_mul(map_1, map_2)
+    }
+
     return map_1.iter()
-        .map(|(key, value)| (key, map_2.get(key).unwrap_or(&0.)*value))
+        .map(|(key, value)| (key, *map_2.get(key).unwrap_or(&F::zero())*(*value)))
         .collect()
 }
 
 // Computes the sum of the product of items with matching keys
-pub fn match_mul_sum<T: Eq + Hash>(map_1: &HashMap<T,f64>, map_2: &HashMap<T,f64>) -> f64 {
-    return map_1.iter()
-        .map(|(key, value)| map_2.get(key).unwrap_or(&0.)*value)
+pub fn match_mul_sum<T: MapKey,F: Real>(map_1: &HashMap<T,F>, map_2: &HashMap<T,F>) -> F {
+    #[cfg(all(feature = "rayon", not(feature = "deterministic")))]
+    if map_1.len() >= PARALLEL_THRESHOLD {
+        return par_match_mul_sum(map_1, map_2)
+    }
+
+    return matched_products(map_1, map_2).sum()
+}
+
+// Neumaier's compensated summation: the rounding error of every addition is
+// carried in a separate term, so long sums of small values stay accurate
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CompensatedSum<F: Real> {
+    sum: F,
+    compensation: F,
+}
+
+impl<F: Real> CompensatedSum<F> {
+
+    pub fn new() -> CompensatedSum<F> {
+        return CompensatedSum {sum: F::zero(), compensation: F::zero()}
+    }
+
+    pub fn add(&mut self, value: F) {
+        let total = self.sum + value;
+        if self.sum.abs() >= value.abs() {
+            self.compensation += (self.sum - total) + value;
+        } else {
+            self.compensation += (value - total) + self.sum;
+        }
+        self.sum = total;
+    }
+
+    // Combines two partial sums, used by the parallel reductions
+    pub fn merge(mut self, other: CompensatedSum<F>) -> CompensatedSum<F> {
+        self.add(other.sum);
+        self.add(other.compensation);
+        return self
+    }
+
+    pub fn total(&self) -> F {
+        return self.sum + self.compensation
+    }
+
+}
+
+pub fn compensated_sum<F: Real, I: IntoIterator<Item = F>>(values: I) -> F {
+    let mut sum = CompensatedSum::new();
+    for value in values {
+        sum.add(value);
+    }
+    return sum.total()
+}
+
+// Same as match_mul_sum, with compensated summation of the products
+pub fn match_mul_sum_compensated<T: MapKey,F: Real>(map_1: &HashMap<T,F>, map_2: &HashMap<T,F>) -> F {
+    #[cfg(all(feature = "rayon", not(feature = "deterministic")))]
+    if map_1.len() >= PARALLEL_THRESHOLD {
+        return par_match_mul_sum_compensated(map_1, map_2)
+    }
+
+    return compensated_sum(matched_products(map_1, map_2))
+}
+
+// Parallel versions of the reductions above, used automatically for large maps
+
+#[cfg(feature = "rayon")]
+pub fn par_match_mul<'a,T: MapKey,F: Real>(map_1: &'a HashMap<T,F>, map_2: &'a HashMap<T,F>) -> HashMap<&'a T,F> {
+    return map_1.par_iter()
+        .map(|(key, value)| (key, *map_2.get(key).unwrap_or(&F::zero())*(*value)))
+        .collect()
+}
+
+#[cfg(feature = "rayon")]
+pub fn par_match_mul_sum<T: MapKey,F: Real>(map_1: &HashMap<T,F>, map_2: &HashMap<T,F>) -> F {
+    return map_1.par_iter()
+        .map(|(key, value)| *map_2.get(key).unwrap_or(&F::zero())*(*value))
         .sum()
 }
 
-#[cfg(test)]
+#[cfg(feature = "rayon")]
+pub fn par_match_mul_sum_compensated<T: MapKey,F: Real>(map_1: &HashMap<T,F>, map_2: &HashMap<T,F>) -> F {
+    return map_1.par_iter()
+        .fold(CompensatedSum::new, |mut sum, (key, value)| {
+            sum.add(*map_2.get(key).unwrap_or(&F::zero())*(*value));
+            sum
+        })
+        .reduce(CompensatedSum::new, CompensatedSum::merge)
+        .total()
+}
+
+// Sparse vectors are stored as (key, value) pairs sorted by key, which avoids
+// hashing in inner loops. Keys are usually positions in a dense vector.
+
+// Builds a sparse vector, summing the values of repeated keys
+pub fn to_sparse<K: Ord,F: Real,I: IntoIterator<Item = (K,F)>>(entries: I) -> Vec<(K,F)> {
+    let mut entries: Vec<(K,F)> = entries.into_iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut sparse: Vec<(K,F)> = Vec::with_capacity(entries.len());
+    for (key, value) in entries {
+        match sparse.last_mut() {
+            Some(last) if last.0 == key => last.1 += value,
+            _ => sparse.push((key, value)),
+        }
+    }
+
+    return sparse
+}
+
+// Dot product of two sparse vectors
+pub fn sparse_dot<K: Ord,F: Real>(x: &[(K,F)], y: &[(K,F)]) -> F {
+    let mut sum = F::zero();
+    let (mut i, mut j) = (0, 0);
+
+    while i < x.len() && j < y.len() {
+        match x[i].0.cmp(&y[j].0) {
+            core::cmp::Ordering::Less => i += 1,
+            core::cmp::Ordering::Greater => j += 1,
+            core::cmp::Ordering::Equal => {
+                sum += x[i].1*y[j].1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+
+    return sum
+}
+
+// Dot product of a sparse vector of positions with a dense vector
+pub fn sparse_dot_dense<F: Real>(x: &[(usize,F)], dense: &[F]) -> F {
+    return x.iter().map(|(pos, value)| *value*dense[*pos]).sum()
+}
+
+// Same as sparse_dot_dense, with compensated summation of the products
+pub fn sparse_dot_dense_compensated<F: Real>(x: &[(usize,F)], dense: &[F]) -> F {
+    #[cfg(all(feature = "rayon", not(feature = "deterministic")))]
+    if x.len() >= PARALLEL_THRESHOLD {
+        return x.par_iter()
+            .fold(CompensatedSum::new, |mut sum, (pos, value)| {
+                sum.add(*value*dense[*pos]);
+                sum
+            })
+            .reduce(CompensatedSum::new, CompensatedSum::merge)
+            .total()
+    }
+
+    #[cfg(all(target_arch = "x86_64", feature = "std"))]
+    if x.len() >= LANES && std::arch::is_x86_feature_detected!("avx2") {
+        // SAFETY: the CPU supports AVX2, as just checked
+        return unsafe { sparse_dot_dense_avx2(x, dense) }
+    }
+
+    if x.len() >= LANES {
+        return sparse_dot_dense_lanes(x, dense)
+    }
+
+    return compensated_sum(x.iter().map(|(pos, value)| *value*dense[*pos]))
+}
+
+// Accumulators of the chunked dot product, two AVX2 vectors of f64 or one
+// of f32
+const LANES: usize = 8;
+
+// Compensated dot product over LANES interleaved accumulators, merged at the
+// end. The lanes are independent, so the additions of a chunk don't wait on
+// a single running sum, and the error of each addition comes from a TwoSum,
+// without the branch of CompensatedSum::add. Both give the exact error of
+// the addition, so the results are the same.
+#[inline(always)]
+pub(crate) fn sparse_dot_dense_lanes<F: Real>(x: &[(usize,F)], dense: &[F]) -> F {
+    let mut sums = [F::zero(); LANES];
+    let mut compensations = [F::zero(); LANES];
+
+    let chunks = x.chunks_exact(LANES);
+    let rest = chunks.remainder();
+    for chunk in chunks {
+        for (lane, (pos, value)) in chunk.iter().enumerate() {
+            let product = *value*dense[*pos];
+            let total = sums[lane] + product;
+            let virtual_product = total - sums[lane];
+            compensations[lane] += (sums[lane] - (total - virtual_product)) + (product - virtual_product);
+            sums[lane] = total;
+        }
+    }
+
+    return merge_lanes(sums, compensations, rest, dense)
+}
+
+// Total of the lanes, plus the products of the entries left after the chunks
+#[inline(always)]
+fn merge_lanes<F: Real>(sums: [F; LANES], compensations: [F; LANES], rest: &[(usize,F)], dense: &[F]) -> F {
+    let mut sum = sums.iter().zip(compensations)
+        .fold(CompensatedSum::new(), |sum, (lane_sum, compensation)| sum.merge(CompensatedSum {sum: *lane_sum, compensation}));
+    for (pos, value) in rest {
+        sum.add(*value*dense[*pos]);
+    }
+    return sum.total()
+}
+
+// The chunked kernel in AVX2 intrinsics, selected at runtime. The values at
+// the positions of a chunk are loaded into vectors, and the TwoSums of the
+// lanes run as vector additions, giving the bits of sparse_dot_dense_lanes.
+// Real is only implemented for f64 and f32.
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+#[target_feature(enable = "avx2")]
+pub(crate) fn sparse_dot_dense_avx2<F: Real>(x: &[(usize,F)], dense: &[F]) -> F {
+    use core::any::TypeId;
+
+    if TypeId::of::<F>() == TypeId::of::<f64>() {
+        // SAFETY: F is f64, the casts don't change the types
+        let (x, dense) = unsafe { (&*(x as *const [(usize,F)] as *const [(usize,f64)]), &*(dense as *const [F] as *const [f64])) };
+        return F::from_f64(avx2::dot_f64(x, dense))
+    }
+
+    // SAFETY: F is f32, the only other Real
+    let (x, dense) = unsafe { (&*(x as *const [(usize,F)] as *const [(usize,f32)]), &*(dense as *const [F] as *const [f32])) };
+    return F::from_f64(avx2::dot_f32(x, dense) as f64)
+}
+
+#[cfg(all(target_arch = "x86_64", feature = "std"))]
+mod avx2 {
+
+    use core::arch::x86_64::*;
+
+    use super::{merge_lanes, LANES};
+
+    // Adds products to the lanes of sums, the exact errors of the additions
+    // going to compensations (TwoSum)
+    #[target_feature(enable = "avx2")]
+    fn two_sum_pd(sums: &mut __m256d, compensations: &mut __m256d, products: __m256d) {
+        let total = _mm256_add_pd(*sums, products);
+        let virtual_product = _mm256_sub_pd(total, *sums);
+        let error = _mm256_add_pd(
+            _mm256_sub_pd(*sums, _mm256_sub_pd(total, virtual_product)),
+            _mm256_sub_pd(products, virtual_product),
+        );
+        *compensations = _mm256_add_pd(*compensations, error);
+        *sums = total;
+    }
+
+    // Products of four entries of x with the values of dense at their
+    // positions. Loads beat the gather instructions, which the microcode
+    // mitigations of many CPUs make slow, and keep the bounds checks.
+    #[target_feature(enable = "avx2")]
+    fn products_pd(entries: &[(usize,f64)], dense: &[f64]) -> __m256d {
+        let values = _mm256_set_pd(entries[3].1, entries[2].1, entries[1].1, entries[0].1);
+        let gathered = _mm256_set_pd(dense[entries[3].0], dense[entries[2].0], dense[entries[1].0], dense[entries[0].0]);
+        return _mm256_mul_pd(values, gathered)
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) fn dot_f64(x: &[(usize,f64)], dense: &[f64]) -> f64 {
+        // The eight lanes as two vectors of four
+        let (mut low_sums, mut high_sums) = (_mm256_setzero_pd(), _mm256_setzero_pd());
+        let (mut low_compensations, mut high_compensations) = (_mm256_setzero_pd(), _mm256_setzero_pd());
+
+        let chunks = x.chunks_exact(LANES);
+        let rest = chunks.remainder();
+        for chunk in chunks {
+            two_sum_pd(&mut low_sums, &mut low_compensations, products_pd(&chunk[..4], dense));
+            two_sum_pd(&mut high_sums, &mut high_compensations, products_pd(&chunk[4..], dense));
+        }
+
+        let mut sums = [0.; LANES];
+        let mut compensations = [0.; LANES];
+        // SAFETY: the arrays hold LANES doubles
+        unsafe {
+            _mm256_storeu_pd(sums.as_mut_ptr(), low_sums);
+            _mm256_storeu_pd(sums.as_mut_ptr().add(4), high_sums);
+            _mm256_storeu_pd(compensations.as_mut_ptr(), low_compensations);
+            _mm256_storeu_pd(compensations.as_mut_ptr().add(4), high_compensations);
+        }
+        return merge_lanes(sums, compensations, rest, dense)
+    }
+
+    // Same as dot_f64, the eight lanes of f32 in a single vector
+    #[target_feature(enable = "avx2")]
+    pub(super) fn dot_f32(x: &[(usize,f32)], dense: &[f32]) -> f32 {
+        let mut lane_sums = _mm256_setzero_ps();
+        let mut lane_compensations = _mm256_setzero_ps();
+
+        let chunks = x.chunks_exact(LANES);
+        let rest = chunks.remainder();
+        for chunk in chunks {
+            let values = _mm256_set_ps(chunk[7].1, chunk[6].1, chunk[5].1, chunk[4].1, chunk[3].1, chunk[2].1, chunk[1].1, chunk[0].1);
+            let gathered = _mm256_set_ps(
+                dense[chunk[7].0], dense[chunk[6].0], dense[chunk[5].0], dense[chunk[4].0],
+                dense[chunk[3].0], dense[chunk[2].0], dense[chunk[1].0], dense[chunk[0].0],
+            );
+            let products = _mm256_mul_ps(values, gathered);
+
+            let total = _mm256_add_ps(lane_sums, products);
+            let virtual_product = _mm256_sub_ps(total, lane_sums);
+            let error = _mm256_add_ps(
+                _mm256_sub_ps(lane_sums, _mm256_sub_ps(total, virtual_product)),
+                _mm256_sub_ps(products, virtual_product),
+            );
+            lane_compensations = _mm256_add_ps(lane_compensations, error);
+            lane_sums = total;
+        }
+
+        let mut sums = [0.; LANES];
+        let mut compensations = [0.; LANES];
+        // SAFETY: the arrays hold LANES floats
+        unsafe {
+            _mm256_storeu_ps(sums.as_mut_ptr(), lane_sums);
+            _mm256_storeu_ps(compensations.as_mut_ptr(), lane_compensations);
+        }
+        return merge_lanes(sums, compensations, rest, dense)
+    }
+
+}
+
+// Computes alpha*x + y
+pub fn sparse_axpy<K: Ord + Clone,F: Real>(alpha: F, x: &[(K,F)], y: &[(K,F)]) -> Vec<(K,F)> {
+    let mut result: Vec<(K,F)> = Vec::with_capacity(x.len() + y.len());
+    let (mut i, mut j) = (0, 0);
+
+    while i < x.len() || j < y.len() {
+        if j == y.len() || (i < x.len() && x[i].0 < y[j].0) {
+            result.push((x[i].0.clone(), alpha*x[i].1));
+            i += 1;
+        } else if i == x.len() || y[j].0 < x[i].0 {
+            result.push(y[j].clone());
+            j += 1;
+        } else {
+            result.push((x[i].0.clone(), alpha*x[i].1 + y[j].1));
+            i += 1;
+            j += 1;
+        }
+    }
+
+    return result
+}
+
+// Scales the values so they sum to one and returns the previous sum.
+// Vectors summing to zero are left untouched.
+pub fn sparse_normalize<K,F: Real>(x: &mut [(K,F)]) -> F {
+    let total = compensated_sum(x.iter().map(|(_, value)| *value));
+
+    if total != F::zero() {
+        for (_, value) in x.iter_mut() {
+            *value /= total;
+        }
+    }
+
+    return total
+}
+
+// Distribution utilities, a distribution maps keys to probabilities and
+// missing keys have probability zero
+
+// Scales the probabilities so they sum to one and returns the previous sum.
+// Distributions summing to zero are left untouched.
+pub fn normalize<T: MapKey,F: Real>(dist: &mut HashMap<T,F>) -> F {
+    let total = compensated_sum(dist.values().cloned());
+
+    if total != F::zero() {
+        for value in dist.values_mut() {
+            *value /= total;
+        }
+    }
+
+    return total
+}
+
+// Shannon entropy in nats
+pub fn entropy<T: MapKey,F: Real>(dist: &HashMap<T,F>) -> F {
+    return -compensated_sum(dist.values()
+        .filter(|prob| **prob > F::zero())
+        .map(|prob| *prob*prob.ln()))
+}
+
+// Kullback-Leibler divergence KL(p || q) in nats, infinite when q gives
+// probability zero to a key that p can draw
+pub fn kl_divergence<T: MapKey,F: Real>(p: &HashMap<T,F>, q: &HashMap<T,F>) -> F {
+    let mut sum = CompensatedSum::new();
+
+    for (key, prob) in p.iter().filter(|(_, prob)| **prob > F::zero()) {
+        let other = *q.get(key).unwrap_or(&F::zero());
+        if other <= F::zero() {
+            return F::infinity()
+        }
+        sum.add(*prob*(*prob/other).ln());
+    }
+
+    return sum.total()
+}
+
+// Total variation distance, half the L1 distance between the distributions
+pub fn total_variation<T: MapKey,F: Real>(p: &HashMap<T,F>, q: &HashMap<T,F>) -> F {
+    let only_q = q.iter()
+        .filter(|(key, _)| !p.contains_key(*key))
+        .map(|(_, prob)| prob.abs());
+    let shared = p.iter()
+        .map(|(key, prob)| (*prob - *q.get(key).unwrap_or(&F::zero())).abs());
+
+    return compensated_sum(shared.chain(only_q))/F::from_f64(2.)
+}
+
+// Draws one of the entries with probability proportional to its weight,
+// without collecting or sorting them: the entries are walked once for the
+// total and once for the draw, so they must come in the same order both times.
+// Zero, negative and NaN weights are never drawn, and None is returned when no
+// entry has a positive weight.
+#[cfg(feature = "rand")]
+fn sample_entries<T,F: Real,R: Rng + ?Sized>(entries: impl Iterator<Item = (T,F)> + Clone, rng: &mut R) -> Option<T> {
+    let positive = entries.filter(|(_, weight)| *weight > F::zero());
+
+    let total: f64 = positive.clone().map(|(_, weight)| weight.as_f64()).sum();
+    if total <= 0. || !total.is_finite() {
+        return None
+    }
+
+    let mut remaining = rng.random::<f64>()*total;
+    let mut last = None;
+    for (entry, weight) in positive {
+        remaining -= weight.as_f64();
+        if remaining < 0. {
+            return Some(entry)
+        }
+        last = Some(entry);
+    }
+
+    // Rounding can leave a tiny remainder after the last entry
+    return last
+}
+
+// Draws a key of the map with probability proportional to its weight. Maps
+// are walked in their own order, which changes between runs, so seeded draws
+// only repeat with sample_index over an ordered slice.
+#[cfg(feature = "rand")]
+pub fn sample_weighted<'a,K,F: Real,R: Rng + ?Sized>(weights: &'a HashMap<K,F>, rng: &mut R) -> Option<&'a K> {
+    return sample_entries(weights.iter().map(|(key, weight)| (key, *weight)), rng)
+}
+
+// Draws the index of a weight, e.g. of an action in the position-indexed
+// policy of an Agent or of a successor in a DenseModel. These are sorted, so
+// a seeded rng gives the same draws on every run.
+#[cfg(feature = "rand")]
+pub fn sample_index<F: Real,R: Rng + ?Sized>(weights: impl Iterator<Item = F> + Clone, rng: &mut R) -> Option<usize> {
+    return sample_entries(weights.enumerate(), rng)
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
 
     use super::*;
@@ -61,4 +578,217 @@ mod tests {
 
     }
 
-}
\ No newline at end of file
+    // Test compensated sums of many small terms
+    #[test]
+    fn compensated_sum_test() {
+        let values: Vec<f64> = std::iter::once(1.).chain(std::iter::repeat_n(1e-16, 10_000)).collect();
+
+        assert_eq!(values.iter().sum::<f64>(), 1.);
+        assert!((compensated_sum(values.iter().cloned()) - (1. + 1e-12)).abs() < 1e-15);
+
+        let mut map_1: HashMap<i64,f32> = HashMap::new();
+        let mut map_2: HashMap<i64,f32> = HashMap::new();
+        map_1.insert(-1, 1.);
+        map_2.insert(-1, 1.);
+        for key in 0..50_000 {
+            map_1.insert(key, 1e-4);
+            map_2.insert(key, 1e-4);
+        }
+
+        let exact = 1. + 50_000. * 1e-8;
+        assert!((match_mul_sum_compensated(&map_1, &map_2) as f64 - exact).abs() < 1e-6);
+    }
+
+    // Test that the reductions don't depend on the hash order of the maps
+    #[cfg(feature = "deterministic")]
+    #[test]
+    fn deterministic_reduction_test() {
+        // Terms whose rounding depends on the order they are added in
+        let terms: Vec<(i64,f64)> = (0..1000).map(|key| (key, if key % 2 == 0 {1e16} else {1.}*(1. + key as f64/7.))).collect();
+        let signs: HashMap<i64,f64> = terms.iter().map(|(key, _)| (*key, if key % 3 == 0 {-1.} else {1.})).collect();
+
+        let sorted = terms.iter().map(|(key, value)| signs[key]*value).sum::<f64>();
+        // Each map has its own random hash order
+        for _ in 0..20 {
+            let map: HashMap<i64,f64> = terms.iter().cloned().collect();
+            assert_eq!(match_mul_sum(&map, &signs).to_bits(), sorted.to_bits());
+            assert_eq!(match_mul_sum_compensated(&map, &signs).to_bits(), match_mul_sum_compensated(&map.clone(), &signs).to_bits());
+        }
+    }
+
+    // Test that long rows are summed in the same order on every run
+    #[cfg(all(feature = "deterministic", feature = "rayon"))]
+    #[test]
+    fn deterministic_long_row_test() {
+        let n = 4*PARALLEL_THRESHOLD;
+        let dense: Vec<f64> = (0..n).map(|pos| if pos % 2 == 0 {1e16} else {1.}*(1. + pos as f64/7.)).collect();
+        let x: Vec<(usize,f64)> = (0..n).map(|pos| (pos, if pos % 3 == 0 {-1.} else {1.})).collect();
+
+        let sequential = sparse_dot_dense_lanes(&x, &dense);
+        for _ in 0..20 {
+            assert_eq!(sparse_dot_dense_compensated(&x, &dense).to_bits(), sequential.to_bits());
+        }
+    }
+
+    // Test parallel reductions against the sequential ones
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_reduction_test() {
+        let map_1: HashMap<i64,f64> = (0..3*PARALLEL_THRESHOLD as i64).map(|key| (key, 1./(1 + key) as f64)).collect();
+        let map_2: HashMap<i64,f64> = (0..3*PARALLEL_THRESHOLD as i64).step_by(2).map(|key| (key, 2.)).collect();
+
+        let expected: f64 = map_1.iter()
+            .map(|(key, value)| map_2.get(key).unwrap_or(&0.)*value)
+            .sum();
+
+        assert!((par_match_mul_sum(&map_1, &map_2) - expected).abs() < 1e-9);
+        assert!((par_match_mul_sum_compensated(&map_1, &map_2) - expected).abs() < 1e-9);
+        assert!((match_mul_sum(&map_1, &map_2) - expected).abs() < 1e-9);
+        assert_eq!(par_match_mul(&map_1, &map_2), map_1.iter()
+            .map(|(key, value)| (key, map_2.get(key).unwrap_or(&0.)*value))
+            .collect::<HashMap<&i64,f64>>());
+    }
+
+    // Test sparse vector operations
+    #[test]
+    fn sparse_vector_test() {
+        let x = to_sparse(vec![(3, 1.), (0, 2.), (5, 0.5), (3, 1.)]);
+        let y = to_sparse(vec![(5, 4.), (1, 3.), (3, 1.)]);
+
+        assert_eq!(x, vec![(0, 2.), (3, 2.), (5, 0.5)]);
+        assert_eq!(sparse_dot(&x, &y), 4.);
+        assert_eq!(sparse_axpy(2., &x, &y), vec![(0, 4.), (1, 3.), (3, 5.), (5, 5.)]);
+        assert_eq!(sparse_dot_dense(&x, &[1., 1., 1., 2., 1., 2.]), 7.);
+        assert_eq!(sparse_dot_dense_compensated(&x, &[1., 1., 1., 2., 1., 2.]), 7.);
+
+        let mut z = x.clone();
+        assert_eq!(sparse_normalize(&mut z), 4.5);
+        assert!((z.iter().map(|(_, value)| value).sum::<f64>() - 1.).abs() < 1e-12);
+
+        let mut empty: Vec<(usize,f64)> = vec![(1, 0.)];
+        assert_eq!(sparse_normalize(&mut empty), 0.);
+        assert_eq!(empty, vec![(1, 0.)]);
+    }
+
+    // Test the chunked dot product against a single compensated sum
+    #[test]
+    fn chunked_dot_test() {
+        // Long rows take the chunked kernel, with a remainder
+        let dense: Vec<f64> = (0..103).map(|pos| 1./(1. + pos as f64)).collect();
+        let x: Vec<(usize,f64)> = (0..103).step_by(2).map(|pos| (pos, 0.5 + pos as f64)).collect();
+        let expected = compensated_sum(x.iter().map(|(pos, value)| *value*dense[*pos]));
+        assert!((sparse_dot_dense_compensated(&x, &dense) - expected).abs() < 1e-12);
+        assert!((sparse_dot_dense_lanes(&x, &dense) - expected).abs() < 1e-12);
+
+        // Cancellations spread over the lanes are still compensated
+        let dense = vec![1.; 9];
+        let x: Vec<(usize,f64)> = vec![(0, 1e16), (1, 1.), (2, 1e-3), (3, 3.), (4, -1e16), (5, 1.), (6, -1e-3), (7, 2.), (8, 1.)];
+        assert_eq!(sparse_dot_dense_compensated(&x, &dense), 8.);
+        assert_eq!(sparse_dot_dense_lanes(&x, &dense), 8.);
+    }
+
+    // Test the AVX2 kernel against a sequential compensated sum, and against
+    // the scalar lanes bit for bit, on CPUs that have it
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn avx2_dot_test() {
+        if !std::arch::is_x86_feature_detected!("avx2") {
+            return
+        }
+        let sequential = |x: &[(usize,f64)], dense: &[f64]| -> f64 {
+            let mut sum = CompensatedSum::new();
+            for (pos, value) in x {
+                sum.add(*value*dense[*pos]);
+            }
+            sum.total()
+        };
+
+        let dense: Vec<f64> = (0..1000).map(|pos| (pos as f64).sin()).collect();
+        let x: Vec<(usize,f64)> = (0..1000).step_by(3).map(|pos| (pos, 1e-3*pos as f64 - 0.4)).collect();
+        // SAFETY: the CPU supports AVX2, as just checked
+        let avx2 = unsafe { sparse_dot_dense_avx2(&x, &dense) };
+        assert!((avx2 - sequential(&x, &dense)).abs() < 1e-13);
+        assert_eq!(avx2.to_bits(), sparse_dot_dense_lanes(&x, &dense).to_bits());
+
+        // Cancellations within and between the lanes
+        let dense = vec![1.; 9];
+        let x: Vec<(usize,f64)> = vec![(0, 1e16), (1, 1.), (2, 1e-3), (3, 3.), (4, -1e16), (5, 1.), (6, -1e-3), (7, 2.), (8, 1.)];
+        // SAFETY: as above
+        assert_eq!(unsafe { sparse_dot_dense_avx2(&x, &dense) }, sequential(&x, &dense));
+
+        let dense: Vec<f32> = (0..1000).map(|pos| (pos as f32).sin()).collect();
+        let x: Vec<(usize,f32)> = (0..1000).rev().step_by(7).map(|pos| (pos, 1e-3*pos as f32 - 0.4)).collect();
+        let expected: f64 = x.iter().map(|(pos, value)| (*value*dense[*pos]) as f64).sum();
+        // SAFETY: as above
+        let avx2 = unsafe { sparse_dot_dense_avx2(&x, &dense) };
+        assert!((avx2 as f64 - expected).abs() < 1e-5);
+        assert_eq!(avx2.to_bits(), sparse_dot_dense_lanes(&x, &dense).to_bits());
+    }
+
+    // Test weighted sampling frequencies and degenerate weights
+    #[cfg(feature = "rand")]
+    #[test]
+    fn sample_weighted_test() {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut weights: HashMap<&str,f64> = HashMap::new();
+        weights.insert("rare", 1.);
+        weights.insert("common", 3.);
+        weights.insert("never", 0.);
+        weights.insert("negative", -5.);
+        weights.insert("nan", f64::NAN);
+
+        let mut counts: HashMap<&str,u32> = HashMap::new();
+        for _ in 0..10_000 {
+            *counts.entry(*sample_weighted(&weights, &mut rng).unwrap()).or_default() += 1;
+        }
+
+        assert_eq!(counts.len(), 2);
+        assert!((*counts.get("common").unwrap() as f64/10_000. - 0.75).abs() < 0.02);
+
+        let empty: HashMap<i64,f64> = HashMap::new();
+        assert_eq!(sample_weighted(&empty, &mut rng), None);
+
+        let zeros: HashMap<i64,f32> = [(1, 0.), (2, -1.)].into_iter().collect();
+        assert_eq!(sample_weighted(&zeros, &mut rng), None);
+
+        // Over a slice the draws repeat with the seed
+        let row = [0., 1., f64::NAN, 3.];
+        let draws = |seed: u64| {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            (0..100).map(|_| sample_index(row.iter().copied(), &mut rng).unwrap()).collect::<Vec<usize>>()
+        };
+        assert_eq!(draws(3), draws(3));
+        assert!(draws(3).iter().all(|index| *index == 1 || *index == 3));
+        assert_eq!(sample_index([0_f32, -1.].into_iter(), &mut rng), None);
+    }
+
+    // Test distribution utilities
+    #[test]
+    fn distribution_test() {
+        let mut p: HashMap<String,f64> = HashMap::new();
+        p.insert("a".to_string(), 2.);
+        p.insert("b".to_string(), 2.);
+
+        assert_eq!(normalize(&mut p), 4.);
+        assert_eq!(*p.get("a").unwrap(), 0.5);
+        assert!((entropy(&p) - 2_f64.ln()).abs() < 1e-12);
+
+        let mut q: HashMap<String,f64> = HashMap::new();
+        q.insert("a".to_string(), 0.25);
+        q.insert("b".to_string(), 0.25);
+        q.insert("c".to_string(), 0.5);
+
+        assert!((kl_divergence(&p, &q) - 2_f64.ln()).abs() < 1e-12);
+        assert_eq!(kl_divergence(&q, &p), f64::INFINITY);
+        assert_eq!(kl_divergence(&p, &p), 0.);
+        assert!((total_variation(&p, &q) - 0.5).abs() < 1e-12);
+        assert_eq!(total_variation(&q, &p), total_variation(&p, &q));
+
+        let mut zeros: HashMap<i64,f64> = [(0, 0.)].into_iter().collect();
+        assert_eq!(normalize(&mut zeros), 0.);
+        assert_eq!(entropy(&zeros), 0.);
+    }
+
+}
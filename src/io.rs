@@ -0,0 +1,298 @@
+// Reading and writing models and policies.
+//
+// Models are stored as their list of links, each link being an array
+// [prev_state, new_state, action, probability, reward], policies as a map
+// state_id -> {action -> probability}, and checkpoints as an object with the
+// policy and a map state_id -> value. The same schema is used by every
+// encoding: JSON with the "json" feature and MessagePack with the "msgpack"
+// feature, the latter being much more compact for large models.
+//
+// Models can also be streamed as newline-delimited JSON, one link per line,
+// either as the array above or as an object with the fields prev_state,
+// new_state, action, probability and reward. Lines are read one at a time,
+// so only the model being built is held in memory, and errors report the
+// line they come from.
+
+use std::collections::HashMap;
+#[cfg(feature = "json")]
+use std::fmt;
+#[cfg(feature = "json")]
+use std::io::BufRead;
+use std::io::{Read, Write};
+#[cfg(feature = "json")]
+use std::marker::PhantomData;
+
+use crate::models;
+use crate::numeric::Real;
+use crate::solver::Checkpoint;
+
+#[cfg(feature = "json")]
+pub fn write_model_json<W: Write, F: Real + serde::Serialize>(writer: W, system_state: &models::SystemState<F>) -> serde_json::Result<()> {
+    return serde_json::to_writer(writer, system_state.get_specification())
+}
+
+#[cfg(feature = "json")]
+pub fn read_model_json<R: Read, F: Real + serde::de::DeserializeOwned>(reader: R) -> serde_json::Result<models::SystemState<F>> {
+    let links: Vec<models::StateLink<F>> = serde_json::from_reader(reader)?;
+    return Ok(models::SystemState::create_and_build(links))
+}
+
+#[cfg(feature = "json")]
+pub fn write_policy_json<W: Write, F: Real + serde::Serialize>(writer: W, policy: &HashMap<i64,HashMap<String,F>>) -> serde_json::Result<()> {
+    return serde_json::to_writer(writer, policy)
+}
+
+#[cfg(feature = "json")]
+pub fn read_policy_json<R: Read, F: Real + serde::de::DeserializeOwned>(reader: R) -> serde_json::Result<HashMap<i64,HashMap<String,F>>> {
+    return serde_json::from_reader(reader)
+}
+
+#[cfg(feature = "json")]
+pub fn write_checkpoint_json<W: Write, F: Real + serde::Serialize>(writer: W, checkpoint: &Checkpoint<F>) -> serde_json::Result<()> {
+    return serde_json::to_writer(writer, checkpoint)
+}
+
+#[cfg(feature = "json")]
+pub fn read_checkpoint_json<R: Read, F: Real + serde::de::DeserializeOwned>(reader: R) -> serde_json::Result<Checkpoint<F>> {
+    return serde_json::from_reader(reader)
+}
+
+// Error of an NDJSON stream, with its line counted from 1
+#[cfg(feature = "json")]
+#[derive(Debug)]
+pub enum NdjsonError {
+    // Reading the line failed
+    Io {line: usize, error: std::io::Error},
+    // The line is not a link
+    Parse {line: usize, error: serde_json::Error},
+}
+
+#[cfg(feature = "json")]
+impl NdjsonError {
+
+    pub fn get_line(&self) -> usize {
+        return match self {
+            NdjsonError::Io {line, ..} | NdjsonError::Parse {line, ..} => *line,
+        }
+    }
+
+}
+
+#[cfg(feature = "json")]
+impl fmt::Display for NdjsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            NdjsonError::Io {line, error} => write!(f, "line {}: {}", line, error),
+            NdjsonError::Parse {line, error} => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl std::error::Error for NdjsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        return match self {
+            NdjsonError::Io {error, ..} => Some(error),
+            NdjsonError::Parse {error, ..} => Some(error),
+        }
+    }
+}
+
+// A link line in either form
+#[cfg(feature = "json")]
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum LinkLine<F: Real> {
+    Array(models::StateLink<F>),
+    Object {prev_state: i64, new_state: i64, action: String, probability: F, reward: F},
+}
+
+// Links of an NDJSON stream, read a line at a time. Blank lines are skipped.
+// Errors are yielded in place of the links of their lines, and reading
+// carries on with the next line, so bad lines can be counted or skipped.
+#[cfg(feature = "json")]
+pub struct NdjsonLinks<R: BufRead, F: Real = f64> {
+    reader: R,
+    buffer: String,
+    line: usize,
+    phantom: PhantomData<F>,
+}
+
+#[cfg(feature = "json")]
+impl<R: BufRead, F: Real + serde::de::DeserializeOwned> Iterator for NdjsonLinks<R, F> {
+    type Item = Result<models::StateLink<F>, NdjsonError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buffer.clear();
+            self.line += 1;
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => return None,
+                Ok(_) => {},
+                Err(error) => return Some(Err(NdjsonError::Io {line: self.line, error})),
+            }
+            if self.buffer.trim().is_empty() {
+                continue;
+            }
+
+            return Some(match serde_json::from_str::<LinkLine<F>>(&self.buffer) {
+                Ok(LinkLine::Array(link)) => Ok(link),
+                Ok(LinkLine::Object {prev_state, new_state, action, probability, reward}) => Ok(models::StateLink(prev_state, new_state, action, probability, reward)),
+                Err(error) => Err(NdjsonError::Parse {line: self.line, error}),
+            })
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+pub fn read_links_ndjson<R: BufRead, F: Real + serde::de::DeserializeOwned>(reader: R) -> NdjsonLinks<R, F> {
+    return NdjsonLinks {reader, buffer: String::new(), line: 0, phantom: PhantomData}
+}
+
+// Model of an NDJSON stream, stopping at the first bad line. Shards can be
+// chained, e.g. with Read::chain, or their links collected together.
+#[cfg(feature = "json")]
+pub fn read_model_ndjson<R: BufRead, F: Real + serde::de::DeserializeOwned>(reader: R) -> Result<models::SystemState<F>, NdjsonError> {
+    return read_links_ndjson(reader).collect()
+}
+
+// One link per line, as arrays
+#[cfg(feature = "json")]
+pub fn write_model_ndjson<W: Write, F: Real + serde::Serialize>(mut writer: W, system_state: &models::SystemState<F>) -> std::io::Result<()> {
+    for link in system_state.get_specification() {
+        serde_json::to_writer(&mut writer, link)?;
+        writer.write_all(b"\n")?;
+    }
+    return Ok(())
+}
+
+#[cfg(feature = "msgpack")]
+pub fn write_model_msgpack<W: Write, F: Real + serde::Serialize>(mut writer: W, system_state: &models::SystemState<F>) -> Result<(), rmp_serde::encode::Error> {
+    return rmp_serde::encode::write(&mut writer, system_state.get_specification())
+}
+
+#[cfg(feature = "msgpack")]
+pub fn read_model_msgpack<R: Read, F: Real + serde::de::DeserializeOwned>(reader: R) -> Result<models::SystemState<F>, rmp_serde::decode::Error> {
+    let links: Vec<models::StateLink<F>> = rmp_serde::from_read(reader)?;
+    return Ok(models::SystemState::create_and_build(links))
+}
+
+#[cfg(feature = "msgpack")]
+pub fn write_policy_msgpack<W: Write, F: Real + serde::Serialize>(mut writer: W, policy: &HashMap<i64,HashMap<String,F>>) -> Result<(), rmp_serde::encode::Error> {
+    return rmp_serde::encode::write(&mut writer, policy)
+}
+
+#[cfg(feature = "msgpack")]
+pub fn read_policy_msgpack<R: Read, F: Real + serde::de::DeserializeOwned>(reader: R) -> Result<HashMap<i64,HashMap<String,F>>, rmp_serde::decode::Error> {
+    return rmp_serde::from_read(reader)
+}
+
+#[cfg(feature = "msgpack")]
+pub fn write_checkpoint_msgpack<W: Write, F: Real + serde::Serialize>(mut writer: W, checkpoint: &Checkpoint<F>) -> Result<(), rmp_serde::encode::Error> {
+    return rmp_serde::encode::write(&mut writer, checkpoint)
+}
+
+#[cfg(feature = "msgpack")]
+pub fn read_checkpoint_msgpack<R: Read, F: Real + serde::de::DeserializeOwned>(reader: R) -> Result<Checkpoint<F>, rmp_serde::decode::Error> {
+    return rmp_serde::from_read(reader)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn test_links() -> Vec<models::StateLink> {
+        return vec![
+            models::StateLink(0, 1, "First_Action".to_string(), 1., 0.),
+            models::StateLink(0, 0, "Second_Action".to_string(), 0.9, 0.),
+            models::StateLink(0, 1, "Second_Action".to_string(), 0.1, 10.),
+        ]
+    }
+
+    fn test_policy() -> HashMap<i64,HashMap<String,f64>> {
+        let mut policy: HashMap<i64,HashMap<String,f64>> = HashMap::new();
+        policy.entry(0).or_default().insert("First_Action".to_string(), 0.25);
+        policy.entry(0).or_default().insert("Second_Action".to_string(), 0.75);
+        policy.insert(1, HashMap::new());
+        return policy
+    }
+
+    fn test_checkpoint() -> Checkpoint {
+        return Checkpoint {policy: test_policy(), values: HashMap::from([(0, 2.5), (1, 0.)])}
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trip_test() {
+        let system_state = models::SystemState::create_and_build(test_links());
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_model_json(&mut buffer, &system_state).unwrap();
+        assert_eq!(read_model_json(buffer.as_slice()).unwrap(), system_state);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_policy_json(&mut buffer, &test_policy()).unwrap();
+        assert_eq!(read_policy_json(buffer.as_slice()).unwrap(), test_policy());
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_checkpoint_json(&mut buffer, &test_checkpoint()).unwrap();
+        assert_eq!(read_checkpoint_json(buffer.as_slice()).unwrap(), test_checkpoint());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn ndjson_test() {
+        let system_state = models::SystemState::create_and_build(test_links());
+        let mut buffer: Vec<u8> = Vec::new();
+        write_model_ndjson(&mut buffer, &system_state).unwrap();
+        assert_eq!(buffer.iter().filter(|byte| **byte == b'\n').count(), 3);
+        assert_eq!(read_model_ndjson(buffer.as_slice()).unwrap(), system_state);
+
+        // Both forms, blank lines and a bad line reported by number
+        let text = "[0, 1, \"Go\", 1.0, 2.0]\n\n{\"prev_state\": 1, \"new_state\": 1, \"action\": \"Stay\", \"probability\": 1.0, \"reward\": 0.0}\n[1, 0]\n";
+        let links: Vec<Result<models::StateLink, NdjsonError>> = read_links_ndjson(text.as_bytes()).collect();
+        assert_eq!(links.len(), 3);
+        assert_eq!(*links[1].as_ref().unwrap(), models::StateLink(1, 1, "Stay".to_string(), 1., 0.));
+        assert_eq!(links[2].as_ref().unwrap_err().get_line(), 4);
+
+        let error = read_model_ndjson::<_, f64>(text.as_bytes()).unwrap_err();
+        assert!(error.to_string().starts_with("line 4: "));
+        let valid: models::SystemState = read_links_ndjson(text.as_bytes()).filter_map(Result::ok).collect();
+        assert_eq!(valid.get_all_states().len(), 2);
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_round_trip_test() {
+        let system_state = models::SystemState::create_and_build(test_links());
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_model_msgpack(&mut buffer, &system_state).unwrap();
+        assert_eq!(read_model_msgpack(buffer.as_slice()).unwrap(), system_state);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_policy_msgpack(&mut buffer, &test_policy()).unwrap();
+        assert_eq!(read_policy_msgpack(buffer.as_slice()).unwrap(), test_policy());
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_checkpoint_msgpack(&mut buffer, &test_checkpoint()).unwrap();
+        assert_eq!(read_checkpoint_msgpack(buffer.as_slice()).unwrap(), test_checkpoint());
+    }
+
+    #[cfg(all(feature = "json", feature = "msgpack"))]
+    #[test]
+    fn msgpack_matches_json_schema_test() {
+        let system_state = models::SystemState::create_and_build(test_links());
+
+        let mut json_buffer: Vec<u8> = Vec::new();
+        write_model_json(&mut json_buffer, &system_state).unwrap();
+        let mut msgpack_buffer: Vec<u8> = Vec::new();
+        write_model_msgpack(&mut msgpack_buffer, &system_state).unwrap();
+
+        let from_json: serde_json::Value = serde_json::from_slice(&json_buffer).unwrap();
+        let from_msgpack: serde_json::Value = rmp_serde::from_slice(&msgpack_buffer).unwrap();
+        assert_eq!(from_json, from_msgpack);
+    }
+
+}
@@ -0,0 +1,118 @@
+// State keys that don't fit in an i64, such as 128-bit hashes or byte
+// strings.
+//
+// Models identify states by i64 ids. StateKeys gives each key a dense id, in
+// the order the keys are first seen, and keeps the key of every id, so keys
+// of any size map to ids without the collisions of a truncation. Results
+// keyed by id, like the values or policy of an agent, are mapped back to the
+// keys with by_key.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use crate::collections::HashMap;
+use crate::models::StateLink;
+use crate::numeric::Real;
+
+#[derive(Debug, Clone)]
+pub struct StateKeys<K> {
+    keys: Vec<K>,
+    ids: HashMap<K,i64>,
+}
+
+impl<K: Eq + Hash + Clone> Default for StateKeys<K> {
+    fn default() -> StateKeys<K> {
+        return StateKeys {keys: Vec::new(), ids: HashMap::new()}
+    }
+}
+
+impl<K: Eq + Hash + Clone> StateKeys<K> {
+
+    pub fn new() -> StateKeys<K> {
+        return StateKeys::default()
+    }
+
+    // Id of the key, given the next free id if it is new
+    pub fn id(&mut self, key: &K) -> i64 {
+        if let Some(id) = self.ids.get(key) {
+            return *id
+        }
+        let id = self.keys.len() as i64;
+        self.keys.push(key.clone());
+        self.ids.insert(key.clone(), id);
+        return id
+    }
+
+    // Id of a key already seen
+    pub fn get_id(&self, key: &K) -> Option<i64> {
+        return self.ids.get(key).cloned()
+    }
+
+    pub fn get_key(&self, id: i64) -> Option<&K> {
+        return usize::try_from(id).ok().and_then(|pos| self.keys.get(pos))
+    }
+
+    // Keys by id
+    pub fn get_keys(&self) -> &Vec<K> {
+        return &self.keys
+    }
+
+    pub fn len(&self) -> usize {
+        return self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.keys.is_empty()
+    }
+
+    pub fn link<F: Real>(&mut self, prev_state: &K, new_state: &K, action: impl Into<String>, prob: F, reward: F) -> StateLink<F> {
+        return StateLink::new(self.id(prev_state), self.id(new_state), action, prob, reward)
+    }
+
+    // Links of (prev_state, new_state, action, probability, reward) tuples
+    pub fn links<F: Real, A: Into<String>, I: IntoIterator<Item = (K, K, A, F, F)>>(&mut self, links: I) -> Vec<StateLink<F>> {
+        return links.into_iter()
+            .map(|(prev_state, new_state, action, prob, reward)| self.link(&prev_state, &new_state, action, prob, reward))
+            .collect()
+    }
+
+    // Map keyed by id, such as Agent::get_evaluation or get_policy, keyed by
+    // state key instead. Ids without a key are left out.
+    pub fn by_key<V: Clone>(&self, by_id: &HashMap<i64,V>) -> HashMap<K,V> {
+        return by_id.iter()
+            .filter_map(|(id, value)| self.get_key(*id).map(|key| (key.clone(), value.clone())))
+            .collect()
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::models::SystemState;
+    use crate::Agent;
+
+    #[test]
+    fn state_keys_test() {
+        // Hashes agreeing on their low 64 bits
+        let (start, end) = (u128::MAX, u64::MAX as u128);
+        let mut keys: StateKeys<u128> = StateKeys::new();
+        let links = keys.links([(start, end, "Go", 1., 2.), (end, end, "Stay", 1., 0.)]);
+        assert_eq!(links, vec![StateLink::new(0, 1, "Go", 1., 2.), StateLink::new(1, 1, "Stay", 1., 0.)]);
+        assert_eq!((keys.get_id(&end), keys.get_key(0), keys.get_key(2)), (Some(1), Some(&start), None));
+
+        let mut agent: Agent = Agent::init_random(SystemState::create_and_build(links));
+        agent.evaluate_policy(0.5, 1e-12, 1000);
+        let values = keys.by_key(agent.get_evaluation());
+        assert_eq!(values, HashMap::from([(start, 2.), (end, 0.)]));
+
+        let mut byte_keys: StateKeys<Vec<u8>> = StateKeys::new();
+        assert_eq!(byte_keys.id(&b"board:x.o".to_vec()), 0);
+        assert_eq!(byte_keys.id(&b"board:xoo".to_vec()), 1);
+        assert_eq!(byte_keys.id(&b"board:x.o".to_vec()), 0);
+        assert_eq!(byte_keys.len(), 2);
+    }
+
+}
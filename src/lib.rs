@@ -1,159 +1,665 @@
-use std::collections::HashMap;
+// Without the default "std" feature the crate is no_std, with alloc: the
+// models, the agents and their solvers build with the maps of hashbrown
+// (see collections), and the modules reading files, printing, timing or
+// running threads are left out.
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use once_cell::race::OnceBox;
+
+use collections::HashMap;
+
+pub mod numeric;
+pub mod collections;
 pub mod models;
 pub mod helper;
+pub mod linalg;
+pub mod solver;
+pub mod error;
+pub mod progress;
+pub mod builder;
+pub mod policy;
+pub mod keys;
+pub mod memory;
+pub mod certify;
+pub mod render;
+pub mod analysis;
+pub mod reachability;
+pub mod priority;
+pub mod transform;
+pub mod regions;
+
+#[cfg(feature = "std")]
+pub mod envs;
+#[cfg(feature = "std")]
+pub mod episode;
+#[cfg(feature = "std")]
+pub mod games;
+#[cfg(feature = "std")]
+pub mod bench;
+#[cfg(feature = "std")]
+pub mod npy;
+#[cfg(feature = "std")]
+pub mod sweep_checkpoint;
+
+#[cfg(feature = "rand")]
+pub mod simulator;
+
+#[cfg(feature = "rand")]
+pub mod seed;
+
+pub use numeric::Real;
+pub use error::Error;
+pub use solver::{Checkpoint, SolverConfig};
+use solver::SolveContext;
+pub use progress::{CancellationToken, Progress, ProgressObserver};
+pub use builder::{AgentBuilder, PolicyInit};
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "http")]
+pub mod service;
+
+#[cfg(feature = "arrow")]
+pub mod export;
+
+#[cfg(feature = "plotters")]
+pub mod plot;
+
+#[cfg(feature = "gpu")]
+pub mod gpu;
+
+#[cfg(feature = "tokio")]
+pub mod async_solve;
+
+#[cfg(feature = "exact")]
+pub mod exact;
+
+#[cfg(any(feature = "json", feature = "msgpack"))]
+pub mod io;
+
+#[cfg(feature = "csv")]
+pub mod csv_import;
+
+#[cfg(feature = "sqlite")]
+pub mod repository;
+
+// Largest gap between one and the sum of the probabilities of a policy
+const PROBABILITY_TOLERANCE: f64 = 1e-6;
+
+// Clones share the model and copy the policy and values. To branch them,
+// e.g. to try an improvement and roll it back, take a checkpoint and
+// restore it.
+//
+// Agents and models are Send and Sync (checked below), and every query
+// takes &self, so a solved agent in an Arc answers best-action and value
+// queries from any number of threads. Solving takes &mut self: an agent
+// solved again while it is queried goes behind an RwLock, or is solved as a
+// clone that then replaces the Arc.
+#[derive(Clone)]
+pub struct Agent<F: Real = f64> {
+    // Shared with the other agents of the model, see init_random_shared
+    system_state: Arc<models::SystemState<F>>,
+    // Probabilities by position and action index of the dense model, a row
+    // of zeros for the states with actions the policy leaves out
+    policy: Vec<Vec<F>>,
+    // Values by position of the dense model
+    policy_evaluation: Vec<F>,
+    // The policy and values by state id, built for get_policy and
+    // get_evaluation after each change
+    policy_map: OnceBox<HashMap<i64,HashMap<String,F>>>,
+    evaluation_map: OnceBox<HashMap<i64,F>>,
+    // States and actions of the policy given to set_polity that the model
+    // doesn't have, for validate_policy
+    unknown_keys: UnknownKeys,
+    // Bumped whenever the policy is replaced, invalidating the cache
+    policy_version: u64,
+    // Rewards and transitions of the policy, with the version they were computed for
+    policy_cache: Option<(u64, solver::PolicyMatrices<F>)>,
+    // Policies and values of the last rounds of the latest improvement,
+    // oldest first, see SolverConfig::with_policy_history
+    policy_history: Vec<Checkpoint<F>>,
+    // States with a link whose reward changed since the last solve of all
+    // the states, re-solved by resolve
+    changed_states: Vec<i64>,
+}
 
-pub struct Agent {
-    system_state: models::SystemState,
-    policy: HashMap<i64,HashMap<String,f64>>,
-    policy_evaluation: HashMap<i64,f64>,
+// States and actions, with a positive probability, dropped from a policy
+// translated to the positions of the model, sorted
+#[derive(Clone, Default)]
+struct UnknownKeys {
+    states: Vec<i64>,
+    actions: Vec<(i64,String)>,
 }
 
-impl Agent {
+impl<F: Real> Agent<F> {
 
-    pub fn init_random(system_state: models::SystemState) -> Agent {
+    pub fn init_random(system_state: models::SystemState<F>) -> Agent<F> {
+        return Agent::init_random_shared(Arc::new(system_state))
+    }
 
-        let policy: HashMap<i64,HashMap<String,f64>> = system_state
-            .get_all_states()
-            .iter()
-            .map(|(id, state)| (*id, state.get_random_policy()))
-            .collect();
+    // Agent on a model shared with other agents, e.g. solving it for several
+    // discount factors, without a copy of the model each
+    pub fn init_random_shared(system_state: Arc<models::SystemState<F>>) -> Agent<F> {
+
+        let model = system_state.get_dense();
+        let policy: Vec<Vec<F>> = (0..model.n_states())
+            .map(|pos| {
+                let n_actions = model.get_actions(pos).len();
+                vec![F::one()/F::from_f64(n_actions as f64); n_actions]
+            }).collect();
+        let policy_evaluation: Vec<F> = vec![F::zero(); model.n_states()];
+
+        return Agent {
+            system_state, policy, policy_evaluation, policy_map: OnceBox::new(), evaluation_map: OnceBox::new(), unknown_keys: UnknownKeys::default(),
+            policy_version: 0, policy_cache: None, policy_history: Vec::new(), changed_states: Vec::new(),
+        }
+    }
+
+    // The states and actions of the policy that the model doesn't have are
+    // dropped, and reported by validate_policy
+    pub fn set_polity(&mut self, policy: HashMap<i64,HashMap<String,F>>) {
+        let model = self.system_state.get_dense();
+        let mut dense: Vec<Vec<F>> = (0..model.n_states()).map(|pos| vec![F::zero(); model.get_actions(pos).len()]).collect();
+        let mut unknown_keys = UnknownKeys::default();
+        for (id, actions) in &policy {
+            match model.get_position(id) {
+                Some(pos) => {
+                    let (row, unknown) = Agent::policy_row(model, pos, actions);
+                    dense[pos] = row;
+                    unknown_keys.actions.extend(unknown.into_iter().map(|action| (*id, action)));
+                },
+                None => unknown_keys.states.push(*id),
+            }
+        }
+        unknown_keys.states.sort();
+        unknown_keys.actions.sort();
 
-        let policy_evaluation: HashMap<i64,f64> = system_state.get_all_states()
-            .iter().map(|(id, _)| (*id, 0.)).collect();
+        self.replace_policy(dense);
+        self.unknown_keys = unknown_keys;
+    }
 
-        return Agent {system_state, policy, policy_evaluation}
+    // Probabilities of the actions of a state by action index, with the
+    // actions it doesn't have given a positive probability
+    fn policy_row(model: &models::DenseModel<F>, pos: usize, actions: &HashMap<String,F>) -> (Vec<F>, Vec<String>) {
+        let mut row = vec![F::zero(); model.get_actions(pos).len()];
+        let mut unknown: Vec<String> = Vec::new();
+        for (action, prob) in actions {
+            match model.get_action_index(pos, action) {
+                Some(index) => row[index] = *prob,
+                None if *prob > F::zero() => unknown.push(action.clone()),
+                None => (),
+            }
+        }
+        return (row, unknown)
     }
 
-    pub fn set_polity(&mut self, policy: HashMap<i64,HashMap<String,f64>>) {
+    // Replaces the policy, by position and action index
+    pub(crate) fn replace_policy(&mut self, policy: Vec<Vec<F>>) {
         self.policy = policy;
+        self.policy_map = OnceBox::new();
+        self.policy_version += 1;
+    }
+
+    // Replaces the values, by position
+    pub(crate) fn replace_values(&mut self, values: Vec<F>) {
+        self.policy_evaluation = values;
+        self.evaluation_map = OnceBox::new();
+    }
+
+    // Policy of the states with one, by state id and action name, every
+    // action of a state listed
+    pub fn get_policy(&self) -> &HashMap<i64,HashMap<String,F>> {
+        return self.policy_map.get_or_init(|| Box::new(Agent::policy_from_dense(self.system_state.get_dense(), &self.policy)))
+    }
+
+    // Replaces the policy of one state. The actions must be those of the
+    // state, with probabilities summing to one; actions left out get zero.
+    pub fn set_state_policy(&mut self, state_id: i64, actions: HashMap<String,F>) -> Result<(), Error> {
+        let model = self.system_state.get_dense();
+        let pos = model.get_position(&state_id).ok_or(Error::UnknownState {id: state_id})?;
+        if let Some(action) = actions.keys().find(|action| model.get_action_index(pos, action).is_none()) {
+            return Err(Error::UnknownAction {state: state_id, action: action.clone()})
+        }
+
+        let total: F = actions.values().cloned().sum();
+        let valid = actions.values().all(|prob| prob.is_finite() && *prob >= F::zero())
+            && (model.get_actions(pos).is_empty() || (total - F::one()).abs() <= F::from_f64(PROBABILITY_TOLERANCE));
+        if !valid {
+            return Err(Error::InvalidDistribution {state: state_id})
+        }
+
+        let mut policy = core::mem::take(&mut self.policy);
+        policy[pos] = Agent::policy_row(model, pos, &actions).0;
+        self.replace_policy(policy);
+        self.unknown_keys.actions.retain(|(id, _)| *id != state_id);
+        return Ok(())
     }
 
-    pub fn get_policy(&self) -> &HashMap<i64,HashMap<String,f64>> {
-        return &self.policy
+    // Pins one state to a single action
+    pub fn set_state_action(&mut self, state_id: i64, action: &str) -> Result<(), Error> {
+        return self.set_state_policy(state_id, HashMap::from([(action.to_string(), F::one())]))
     }
 
-    pub fn get_best_action(&self, state_id: i64) -> Option<(&String,&f64)> {
-        self.policy.get(&state_id).unwrap().iter()
-            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+    // Most probable action of the policy, ties going to the first by name
+    pub fn get_best_action(&self, state_id: i64) -> Option<(&String,&F)> {
+        let model = self.system_state.get_dense();
+        let pos = model.get_position(&state_id)?;
+        return model.get_actions(pos).iter().zip(&self.policy[pos])
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap().then(b.0.cmp(a.0)))
     }
 
-    pub fn get_evaluation(&self) -> &HashMap<i64,f64> {
-        return &self.policy_evaluation
+    // Values by state id
+    pub fn get_evaluation(&self) -> &HashMap<i64,F> {
+        return self.evaluation_map.get_or_init(|| Box::new(linalg::from_dense_vector(&self.policy_evaluation, self.system_state.get_dense().get_ids())))
     }
 
-    pub fn get_system_state(&self) -> &models::SystemState {
+    pub fn value_of(&self, state_id: i64) -> Result<F, Error> {
+        return self.system_state.get_dense().get_position(&state_id)
+            .map(|pos| self.policy_evaluation[pos])
+            .ok_or(Error::UnknownState {id: state_id})
+    }
+
+    // States with their values, the largest value first, then by id
+    pub fn values_sorted(&self) -> Vec<(i64, F)> {
+        let ids = self.system_state.get_dense().get_ids();
+        let mut values: Vec<(i64, F)> = ids.iter().cloned().zip(self.policy_evaluation.iter().cloned()).collect();
+        values.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(core::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+        return values
+    }
+
+    // Value of the current evaluation averaged over a distribution of first
+    // states, states missing from the model count as zero
+    pub fn expected_return(&self, start_dist: &HashMap<i64,F>) -> F {
+        return helper::match_mul_sum_compensated(start_dist, self.get_evaluation())
+    }
+
+    pub fn get_system_state(&self) -> &models::SystemState<F> {
         return &self.system_state
     }
 
-    pub fn evaluate_policy(&mut self, gamma: f64, epsilon: f64, n_iter: u32) {
+    // The model, to start other agents on it with init_random_shared
+    pub fn get_shared_system_state(&self) -> Arc<models::SystemState<F>> {
+        return Arc::clone(&self.system_state)
+    }
+
+    // Inner products use compensated summation, states with many successors
+    // would otherwise accumulate rounding errors larger than epsilon
+    pub fn evaluate_policy(&mut self, gamma: F, epsilon: F, n_iter: u32) {
+        self.evaluate_policy_with_progress(gamma, epsilon, n_iter, &mut progress::NoProgress);
+    }
 
-        // rewards
-        // policy: HashMap<i64,HashMap<String,f64>>
-        let static_rewards: HashMap<i64,f64> = self.policy
-            .iter().map(|(id, actions_prob)| {
-                let actions_reward = self.system_state.get_state(id).unwrap().get_eval_rewards();
-                (*id, helper::match_mul_sum(actions_prob, actions_reward))
-            }).collect();
+    // Same as evaluate_policy, reporting every sweep to the observer
+    pub fn evaluate_policy_with_progress(&mut self, gamma: F, epsilon: F, n_iter: u32, observer: &mut dyn ProgressObserver<F>) {
+        // Without a config there are no divergence checks, cancellation or checkpoints, the evaluation can't fail
+        self.evaluate_policy_observed(gamma, epsilon, n_iter, &mut SolveContext::new(observer, None)).unwrap();
+    }
 
-        // transition_probs: HashMap<String,HashMap<i64,f64>>
-        let state_probs: HashMap<i64,HashMap<i64,f64>> = self.policy
-            .iter().map(|(id_prev, action_prob)| {
-                let transition_probs: HashMap<i64,f64> = self.system_state.get_state(id_prev)
-                    .unwrap().get_eval_probs()
-                    .iter().map(|(id_next, transition_prob)| {
-                        (*id_next, helper::match_mul_sum(action_prob, transition_prob))
-                    }).collect();
-                (*id_prev, transition_probs)
-            }).collect();
+    // Evaluation shared by all the variants
+    pub(crate) fn evaluate_policy_observed(&mut self, gamma: F, epsilon: F, n_iter: u32, context: &mut SolveContext<F>) -> Result<(), Error> {
+
+        let matrices = self.take_policy_matrices(context);
+        let mut values = self.policy_evaluation.clone();
+        context.checkpoint_policy(false, &self.policy);
+
+        let result = Agent::evaluate_dense(self.system_state.get_dense().get_ids(), &matrices, &mut values, gamma, epsilon, n_iter, context);
+        self.replace_values(values);
+        self.policy_cache = Some((self.policy_version, matrices));
+
+        return result
+    }
+
+    // Rewards and transitions of the current policy, from the cache when the
+    // policy hasn't changed since they were computed. They go back to the
+    // cache once used.
+    pub(crate) fn take_policy_matrices(&mut self, context: &SolveContext<F>) -> solver::PolicyMatrices<F> {
+        return match self.policy_cache.take() {
+            Some((version, matrices)) if version == self.policy_version => matrices,
+            _ => solver::PolicyMatrices::build(self.system_state.get_dense(), &self.policy, context),
+        }
+    }
+
+    // Whether a row of the policy is listed by state id: the states without
+    // actions are, those with actions but a row of zeros aren't
+    fn listed(probs: &[F]) -> bool {
+        return probs.is_empty() || probs.iter().any(|prob| *prob != F::zero())
+    }
+
+    // Policy by state id and action name, of the listed states
+    fn policy_from_dense(model: &models::DenseModel<F>, policy: &[Vec<F>]) -> HashMap<i64,HashMap<String,F>> {
+        return model.get_ids().iter().enumerate()
+            .filter(|(pos, _)| Agent::listed(&policy[*pos]))
+            .map(|(pos, id)| (*id, model.get_actions(pos).iter().cloned().zip(policy[pos].iter().cloned()).collect()))
+            .collect()
+    }
+
+    // Iterative evaluation of a dense policy, values holding the initial
+    // values and left with those of the last sweep
+    fn evaluate_dense(ids: &[i64], matrices: &solver::PolicyMatrices<F>, values: &mut Vec<F>, gamma: F, epsilon: F, n_iter: u32, context: &mut SolveContext<F>) -> Result<(), Error> {
+
+        // Each sweep writes into the buffer of the previous values, swapped afterwards
+        let mut new_values: Vec<F> = vec![F::zero(); ids.len()];
+        let mut monitor = context.divergence.map(|config| solver::DivergenceMonitor::new(config, ids.len()));
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("evaluate_policy", gamma = gamma.as_f64(), n_states = ids.len()).entered();
 
         // Iterative policy evaluation
         let mut counter: u32 = 0;
+        let mut previous_delta: Option<F> = None;
+
+        // Sweep stopped at a checkpoint, values holding those it started from
+        let mut cursor: usize = 0;
+        if let Some((sweep, sweep_cursor, written)) = context.take_resumed_sweep() {
+            counter = sweep - 1;
+            cursor = sweep_cursor;
+            new_values = written;
+        }
 
         loop {
-            let mut delta = 0.;
-
-            self.policy_evaluation = self.policy_evaluation.iter()
-            .map(|(id, value)| {
-                let future_reward = gamma*helper::match_mul_sum(state_probs.get(id).unwrap(), &self.policy_evaluation);
-                let new_reward = static_rewards.get(id).unwrap() + future_reward;
-                delta = f64::max(delta, (new_reward - value).abs());
-                (*id, new_reward)
-            }).collect();
+            context.sweep(values, matrices, gamma, &mut new_values, counter + 1, cursor)?;
+            cursor = 0;
+
+            let delta = values.iter().zip(&new_values)
+                .fold(F::zero(), |delta, (value, new_value)| delta.max((*new_value - *value).abs()));
+            #[cfg(feature = "tracing")]
+            let n_updated = values.iter().zip(&new_values).filter(|(value, new_value)| value != new_value).count();
 
             counter += 1;
 
-            if (delta < epsilon) || (counter == n_iter) {
+            let checked = monitor.as_mut().map(|monitor| monitor.check(counter, values, &new_values, delta, ids));
+            core::mem::swap(values, &mut new_values);
+
+            if let Some(Err(error)) = checked {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(sweep = counter, delta = delta.as_f64(), "policy evaluation diverged");
+                return Err(error)
+            }
+
+            context.observer.on_progress(&Progress {
+                policy_iteration: context.policy_iteration,
+                sweep: counter,
+                delta,
+                epsilon,
+                estimated_remaining: Progress::extrapolate(delta, previous_delta, epsilon, n_iter.saturating_sub(counter)),
+                elapsed: context.elapsed(),
+            });
+            previous_delta = Some(delta);
+
+            #[cfg(feature = "tracing")]
+            tracing::trace!(sweep = counter, delta = delta.as_f64(), states_updated = n_updated, elapsed_us = context.elapsed().as_micros() as u64, "evaluation sweep");
+
+            if (delta < epsilon) || (counter >= n_iter) {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(sweeps = counter, delta = delta.as_f64(), converged = delta < epsilon, elapsed_us = context.elapsed().as_micros() as u64, "policy evaluated");
                 break
             }
         }
-        
+
+        return Ok(())
     }
 
-    pub fn deterministic_policy_improvement(&mut self, gamma: f64, epsilon: f64, policy_iters: u32, eval_iters: u32) {
-        
-        // Default string for states with no actions
-        let default_str = "_No_Actions_".to_string();
-        self.evaluate_policy(gamma, epsilon, eval_iters);
+    pub fn deterministic_policy_improvement(&mut self, gamma: F, epsilon: F, policy_iters: u32, eval_iters: u32) {
+        self.deterministic_policy_improvement_with_progress(gamma, epsilon, policy_iters, eval_iters, &mut progress::NoProgress);
+    }
 
-        let mut policy_counter: u32 = 0;
+    // Same as deterministic_policy_improvement, reporting every evaluation sweep to the observer
+    pub fn deterministic_policy_improvement_with_progress(&mut self, gamma: F, epsilon: F, policy_iters: u32, eval_iters: u32, observer: &mut dyn ProgressObserver<F>) {
+        // Without a config there are no divergence checks, cancellation or checkpoints, the evaluations can't fail
+        self.improve_observed(gamma, epsilon, policy_iters, eval_iters, &mut SolveContext::new(observer, None)).unwrap();
+    }
+
+    // Policy iteration runs on the dense model, the policy and values being
+    // translated back once done (or once an evaluation diverges)
+    pub(crate) fn improve_observed(&mut self, gamma: F, epsilon: F, policy_iters: u32, eval_iters: u32, context: &mut SolveContext<F>) -> Result<(), Error> {
+
+        let system_state = Arc::clone(&self.system_state);
+        let model = system_state.get_dense();
+        let mut policy = self.policy.clone();
+        let mut values = self.policy_evaluation.clone();
+
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("policy_improvement", gamma = gamma.as_f64(), n_states = model.n_states()).entered();
+        // Discount factor of each round, annealed towards gamma with a schedule
+        let schedule = context.gamma_schedule;
+        let round_gamma = |round: u32| schedule.map_or(gamma, |schedule| schedule.gamma(round, gamma));
+
+        // Policy and values of each round evaluated, the first included
+        let capacity = context.history.unwrap_or(0);
+        let mut history: VecDeque<(Vec<Vec<F>>, Vec<F>)> = VecDeque::new();
+        let mut record = |policy: &[Vec<F>], values: &[F]| {
+            if capacity > 0 {
+                if history.len() == capacity {
+                    history.pop_front();
+                }
+                history.push_back((policy.to_vec(), values.to_vec()));
+            }
+        };
+
+        context.checkpoint_policy(true, &policy);
+        // Round whose evaluation stopped at a sweep checkpoint, carried on
+        // with the policy it had already improved
+        let mut interrupted = context.resumed_round();
+
+        let mut matrices = solver::PolicyMatrices::build(model, &policy, context);
+        let mut result = match context.resume || interrupted.is_some() {
+            true => Ok(()),
+            false => Agent::evaluate_dense(model.get_ids(), &matrices, &mut values, round_gamma(0), epsilon, eval_iters, context),
+        };
+        if result.is_ok() && interrupted.is_none() {
+            record(&policy, &values);
+        }
+
+        let mut policy_counter: u32 = interrupted.map_or(0, |round| round - 1);
+
+        while result.is_ok() {
+            let old_values = values.clone();
+            let improve_gamma = round_gamma(policy_counter);
+
+            // The greedy action of each state only depends on the frozen
+            // evaluation, so the states can be improved in parallel. Ties go
+            // to the first action by name. Inactive states keep their policy.
+            let active = context.active.as_deref();
+            let new_policy: Vec<Vec<F>> = match interrupted.is_some() {
+                true => policy.clone(),
+                false => context.map_positions(model.n_states(), |pos| {
+                    if active.is_some_and(|active| !active[pos]) {
+                        return policy[pos].clone()
+                    }
+                    let n_actions = model.get_actions(pos).len();
+                    let best = (0..n_actions)
+                        .map(|action| (action, model.get_reward(pos, action) + improve_gamma*helper::sparse_dot_dense_compensated(model.get_successors(pos, action), &values)))
+                        .fold(None, |best: Option<(usize, F)>, (action, q_value)| match best {
+                            Some((_, best_q)) if best_q >= q_value => best,
+                            _ => Some((action, q_value)),
+                        });
+                    (0..n_actions).map(|action| if Some(action) == best.map(|best| best.0) {F::one()} else {F::zero()}).collect()
+                }),
+            };
+
+            // Compared with the policy being replaced
+            #[cfg(feature = "tracing")]
+            let n_changed = new_policy.iter().zip(&policy)
+                .filter(|(new_probs, old_probs)| new_probs.iter().zip(old_probs.iter()).any(|(new_prob, old_prob)| *new_prob == F::one() && *old_prob != F::one()))
+                .count();
+
+            policy = new_policy;
+            matrices = solver::PolicyMatrices::build(model, &policy, context);
+            context.checkpoint_policy(true, &policy);
+
+            context.policy_iteration = policy_counter + 1;
+            result = Agent::evaluate_dense(model.get_ids(), &matrices, &mut values, round_gamma(policy_counter + 1), epsilon, eval_iters, context);
+            if result.is_err() {
+                break
+            }
+            record(&policy, &values);
+
+            // The values before an interrupted round are lost, so it can't
+            // count as converged
+            let max_diff: F = match interrupted.take() {
+                Some(_) => F::infinity(),
+                None => old_values.iter().zip(&values)
+                    .map(|(old_val, new_val)| (*old_val - *new_val).abs())
+                    .fold(F::zero(), |a, b| a.max(b)),
+            };
 
-        loop {
-            let old_eval = self.policy_evaluation.clone();
-
-            self.policy = self.system_state.get_all_states().iter()
-                .map(|(id, state)| {
-                    let best_action = self.calc_best_action(state, &default_str);
-                    (*id, self.calc_best_policy(state, best_action))
-                }).collect();
-
-            self.evaluate_policy(gamma, epsilon, 100);
-
-            let max_diff: f64 = old_eval.iter()
-            .map(|(id, old_val)| {
-                let new_val = self.policy_evaluation.get(id).unwrap();
-                (old_val - new_val).abs()
-            }).max_by(|a,b| a.partial_cmp(b).unwrap())
-            .unwrap();
-            
             policy_counter += 1;
-            if (max_diff < epsilon) || (policy_counter == policy_iters) {
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(iteration = policy_counter, max_diff = max_diff.as_f64(), states_updated = n_changed, elapsed_us = context.elapsed().as_micros() as u64, "policy improved");
+
+            if (max_diff < epsilon && round_gamma(policy_counter) == gamma) || (policy_counter == policy_iters) {
                 break;
             }
+        }
 
+        if context.active.is_none() {
+            self.changed_states.clear();
         }
+        self.policy_history = history.iter()
+            .map(|(policy, values)| Checkpoint {policy: Agent::policy_from_dense(model, policy), values: linalg::from_dense_vector(values, model.get_ids())})
+            .collect();
+        self.replace_policy(policy);
+        self.replace_values(values);
+        self.unknown_keys = UnknownKeys::default();
+        self.policy_cache = Some((self.policy_version, matrices));
+
+        return result
+    }
 
+    // Best action without discount
+    pub fn calc_best_action<'a>(&'a self, state: &'a models::ModelState<F>, default_str: &'a str) -> &'a str {
+        return self.calc_greedy_action(state, F::one(), default_str)
     }
 
-    pub fn calc_best_action<'a>(&'a self, state: &'a models::ModelState, default_str: &'a String) -> &'a String {
+    // Action maximizing the discounted action value under the current
+    // evaluation, ties going to the first by name
+    pub fn calc_greedy_action<'a>(&'a self, state: &'a models::ModelState<F>, gamma: F, default_str: &'a str) -> &'a str {
 
-        let max_action_reward: &String = state.get_all_probs().iter()
+        let max_action_reward: &str = state.get_all_probs().iter()
             .map(|(action, probs)| {
                 let action_reward = state.get_eval_rewards().get(action).unwrap();
-                let future_reward = helper::match_mul_sum(probs, &self.policy_evaluation);
-                (action, action_reward + future_reward)
+                let future_reward = helper::match_mul_sum(probs, self.get_evaluation());
+                (action.as_str(), *action_reward + gamma*future_reward)
             })
-            .max_by(|a,b| a.1.partial_cmp(&b.1).unwrap())
-            .unwrap_or((default_str, 0.)).0;
+            .max_by(|a,b| a.1.partial_cmp(&b.1).unwrap().then(b.0.cmp(a.0)))
+            .unwrap_or((default_str, F::zero())).0;
 
         return max_action_reward
 
     }
 
-    pub fn calc_best_policy(&self, state: &models::ModelState, best_action: &String) -> HashMap<String,f64> {
-        return state.get_eval_rewards().iter()
-            .map(|(action, _)| {
-                if *action == *best_action {
-                    (action.clone(), 1.)
+    pub fn calc_best_policy(&self, state: &models::ModelState<F>, best_action: &str) -> HashMap<String,F> {
+        return state.get_eval_rewards().keys()
+            .map(|action| {
+                if action == best_action {
+                    (action.clone(), F::one())
                 } else {
-                    (action.clone(), 0.)
+                    (action.clone(), F::zero())
                 }
             }).collect()
     }
 
+    // Expected immediate reward of each state under the current policy
+    pub fn calc_policy_rewards(&self) -> HashMap<i64,F> {
+        // The states of get_policy, from the dense model
+        let model = self.system_state.get_dense();
+        return self.policy_positions()
+            .map(|(pos, id)| {
+                let reward = helper::compensated_sum(self.policy[pos].iter().enumerate()
+                    .map(|(action, prob)| *prob*model.get_reward(pos, action)));
+                (id, reward)
+            }).collect()
+    }
+
+    // Transition probabilities between states under the current policy,
+    // i.e. the Markov chain induced by the policy. Next states only reached
+    // by actions the policy never takes are left out.
+    pub fn calc_policy_transitions(&self) -> HashMap<i64,HashMap<i64,F>> {
+        let model = self.system_state.get_dense();
+        return self.policy_positions()
+            .map(|(pos, id)| {
+                let successors = helper::to_sparse(self.policy[pos].iter().enumerate()
+                    .filter(|(_, prob)| **prob != F::zero())
+                    .flat_map(|(action, prob)| model.get_successors(pos, action).iter().map(move |(pos_next, prob_next)| (*pos_next, *prob*(*prob_next)))));
+                (id, successors.into_iter().map(|(pos_next, prob)| (model.get_ids()[pos_next], prob)).collect())
+            }).collect()
+    }
+
+    // Positions and ids of the states listed by get_policy
+    fn policy_positions(&self) -> impl Iterator<Item = (usize, i64)> + '_ {
+        return self.system_state.get_dense().get_ids().iter().enumerate()
+            .filter(|(pos, _)| Agent::listed(&self.policy[*pos]))
+            .map(|(pos, id)| (pos, *id))
+    }
+
+    // Action values Q(s,a) under the current evaluation
+    pub fn calc_q_values(&self, gamma: F) -> HashMap<i64,HashMap<String,F>> {
+        let model = self.system_state.get_dense();
+        return model.get_ids().iter().enumerate()
+            .map(|(pos, id)| {
+                let q_values: HashMap<String,F> = model.get_actions(pos).iter().enumerate()
+                    .map(|(action, name)| {
+                        let future_reward = helper::sparse_dot_dense_compensated(model.get_successors(pos, action), &self.policy_evaluation);
+                        (name.clone(), model.get_reward(pos, action) + gamma*future_reward)
+                    }).collect();
+                (*id, q_values)
+            }).collect()
+    }
+
+    // Advantages A(s,a) = Q(s,a) - V(s) under the current evaluation, how
+    // much better than the policy each action does. Zero for the greedy
+    // action of a solved agent, negative for the others.
+    pub fn advantages(&self, gamma: F) -> HashMap<i64,HashMap<String,F>> {
+        return self.calc_q_values(gamma).into_iter()
+            .map(|(id, q_values)| {
+                let value = *self.get_evaluation().get(&id).unwrap_or(&F::zero());
+                (id, q_values.into_iter().map(|(action, q_value)| (action, q_value - value)).collect())
+            }).collect()
+    }
+
+}
+
+// Fails to compile if a model or an agent stops being Send or Sync
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<models::SystemState<f64>>();
+    assert_send_sync::<models::SystemState<f32>>();
+    assert_send_sync::<Agent<f64>>();
+    assert_send_sync::<Agent<f32>>();
+    assert_send_sync::<Checkpoint<f64>>();
+};
+
+// Agents are equal with the same model, policy and values, whatever their
+// cached matrices
+impl<F: Real> PartialEq for Agent<F> {
+    fn eq(&self, other: &Agent<F>) -> bool {
+        return self.policy == other.policy && self.policy_evaluation == other.policy_evaluation && self.system_state == other.system_state
+    }
 }
 
-#[cfg(test)]
+// Value of a state, panicking if it is not in the model. Agent::value_of
+// returns an error instead.
+impl<F: Real> core::ops::Index<i64> for Agent<F> {
+    type Output = F;
+
+    fn index(&self, state_id: i64) -> &F {
+        return self.system_state.get_dense().get_position(&state_id)
+            .map(|pos| &self.policy_evaluation[pos])
+            .unwrap_or_else(|| panic!("state {} is not in the model", state_id))
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
 
     use super::*;
@@ -209,7 +715,7 @@ mod tests {
         ];
 
         let system_state = models::SystemState::create_and_build(links);
-        let mut test_agent = Agent::init_random(system_state);
+        let mut test_agent: Agent = Agent::init_random(system_state);
 
         let epsilon = 0.01;
         test_agent.evaluate_policy(1., epsilon, 10);
@@ -252,7 +758,7 @@ mod tests {
         ];
 
         let system_state = models::SystemState::create_and_build(links);
-        let mut test_agent = Agent::init_random(system_state);
+        let mut test_agent: Agent = Agent::init_random(system_state);
 
         let epsilon = 0.01;
         test_agent.evaluate_policy(1., epsilon, 10);
@@ -298,6 +804,52 @@ mod tests {
 
     }
 
+    #[test]
+    fn expected_return_test() {
+        let links = vec![
+            models::StateLink(0, 1, "Arm_1".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Arm_2".to_string(), 1., 3.),
+            models::StateLink(1, 2, "Arm_1".to_string(), 1., 2.),
+        ];
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+
+        let epsilon = 0.01;
+        test_agent.evaluate_policy(1., epsilon, 10);
+
+        // Starting in either state with the same probability, unknown states are ignored
+        let start_dist: HashMap<i64,f64> = HashMap::from([(0, 0.5), (1, 0.5), (5, 1.)]);
+        let diff = (test_agent.expected_return(&start_dist) - 3.).abs();
+
+        assert!(diff < 2.*epsilon);
+    }
+
+    #[test]
+    fn policy_cache_test() {
+        let links = vec![
+            models::StateLink(0, 1, "Small".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Large".to_string(), 1., 3.),
+        ];
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+
+        // Evaluating again reuses the rewards of the random policy
+        test_agent.evaluate_policy(1., 1e-9, 10);
+        test_agent.evaluate_policy(1., 1e-9, 10);
+        assert!(matches!(&test_agent.policy_cache, Some((0, matrices)) if matrices.rewards == vec![2., 0.]));
+
+        // Replacing the policy invalidates them
+        let mut policy = test_agent.get_policy().clone();
+        policy.insert(0, HashMap::from([("Small".to_string(), 1.), ("Large".to_string(), 0.)]));
+        test_agent.set_polity(policy);
+        test_agent.evaluate_policy(1., 1e-9, 10);
+        assert_eq!(*test_agent.get_evaluation().get(&0).unwrap(), 1.);
+
+        // Improvement leaves those of the new policy
+        test_agent.deterministic_policy_improvement(1., 1e-9, 10, 10);
+        assert!(matches!(&test_agent.policy_cache, Some((2, matrices)) if matrices.rewards == vec![3., 0.]));
+        test_agent.evaluate_policy(1., 1e-9, 10);
+        assert_eq!(*test_agent.get_evaluation().get(&0).unwrap(), 3.);
+    }
+
     #[test]
     pub fn policy_improv_test_1() {
         // Simple n-armed model with a single attempt
@@ -309,7 +861,7 @@ mod tests {
         ];
 
         let system_state = models::SystemState::create_and_build(links);
-        let mut test_agent = Agent::init_random(system_state);
+        let mut test_agent: Agent = Agent::init_random(system_state);
 
         let epsilon = 0.01;
         test_agent.deterministic_policy_improvement(1., epsilon, 100, 100);
@@ -338,7 +890,7 @@ mod tests {
         ];
 
         let system_state = models::SystemState::create_and_build(links);
-        let mut test_agent = Agent::init_random(system_state);
+        let mut test_agent: Agent = Agent::init_random(system_state);
 
         let epsilon = 0.01;
         test_agent.deterministic_policy_improvement(1., epsilon, 100, 100);
@@ -359,4 +911,292 @@ mod tests {
         assert!(diff < 2.*epsilon);
     }
 
+    #[test]
+    pub fn discounted_improvement_test() {
+        // Reward 1 now, or 2 one step later that gamma = 0.1 makes worth 0.2
+        let links = vec![
+            models::StateLink(0, 2, "Now".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Later".to_string(), 1., 0.),
+            models::StateLink(1, 2, "Collect".to_string(), 1., 2.),
+        ];
+
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        test_agent.evaluate_policy(0.1, 1e-12, 100);
+
+        // The undiscounted greedy step, used by the improvement before the
+        // gamma sweep, takes the later reward at face value
+        let state = test_agent.get_system_state().get_state(&0).unwrap();
+        assert_eq!(test_agent.calc_best_action(state, ""), "Later");
+        assert_eq!(test_agent.calc_greedy_action(state, 0.1, ""), "Now");
+
+        test_agent.deterministic_policy_improvement(0.1, 1e-12, 100, 100);
+        assert_eq!(test_agent.get_best_action(0).unwrap().0, "Now");
+        assert!((test_agent.get_evaluation()[&0] - 1.).abs() < 1e-9);
+        assert!(test_agent.certify_optimality(0.1, 1e-9).is_ok());
+    }
+
+    #[test]
+    pub fn improvement_eval_iters_test() {
+        // A chain whose values take one sweep per step to propagate
+        let links: Vec<models::StateLink> = (0..5).map(|id| models::StateLink(id, id + 1, "Step".to_string(), 1., 1.)).collect();
+
+        // The evaluation after the improvement runs eval_iters sweeps, not a
+        // fixed 100, on top of the first one's: 2 + 2 sweeps rather than 2 + 100
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links.clone()));
+        test_agent.deterministic_policy_improvement(1., 0., 1, 2);
+        assert_eq!(test_agent.get_evaluation()[&0], 4.);
+
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        test_agent.deterministic_policy_improvement(1., 0., 1, 100);
+        assert_eq!(test_agent.get_evaluation()[&0], 5.);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    pub fn tracing_test() {
+        use std::sync::Mutex;
+        use tracing::span;
+
+        // Keeps the messages of the events
+        struct Recorder(Mutex<Vec<String>>);
+
+        struct MessageVisitor<'a>(&'a mut String);
+
+        impl tracing::field::Visit for MessageVisitor<'_> {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    *self.0 = format!("{:?}", value);
+                }
+            }
+        }
+
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _metadata: &tracing::Metadata<'_>) -> bool { true }
+            fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id { span::Id::from_u64(1) }
+            fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+            fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+            fn event(&self, event: &tracing::Event<'_>) {
+                let mut message = String::new();
+                event.record(&mut MessageVisitor(&mut message));
+                self.0.lock().unwrap().push(message);
+            }
+            fn enter(&self, _span: &span::Id) {}
+            fn exit(&self, _span: &span::Id) {}
+        }
+
+        let links = vec![
+            models::StateLink(0, 1, "Arm_1".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Arm_2".to_string(), 1., 2.),
+        ];
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+
+        let recorder = std::sync::Arc::new(Recorder(Mutex::new(Vec::new())));
+        tracing::subscriber::with_default(recorder.clone(), || test_agent.deterministic_policy_improvement(1., 0.01, 100, 100));
+
+        let messages = recorder.0.lock().unwrap();
+        assert!(messages.iter().any(|message| message == "evaluation sweep"));
+        assert!(messages.iter().any(|message| message == "policy evaluated"));
+        assert_eq!(messages.last().unwrap(), "policy improved");
+    }
+
+    #[test]
+    pub fn single_precision_test() {
+        // Two n-armed model solved with f32 values
+        let arms = ["Arm_1".to_string(), "Arm_2".to_string(), "Arm_3".to_string()];
+        let links: Vec<models::StateLink<f32>> = vec![
+            models::StateLink(0, 1, arms[0].clone(), 1., 1.),
+            models::StateLink(0, 1, arms[1].clone(), 1., 2.),
+            models::StateLink(0, 1, arms[2].clone(), 1., 3.),
+            models::StateLink(1, 2, arms[0].clone(), 1., 3.),
+            models::StateLink(1, 2, arms[1].clone(), 1., 2.),
+            models::StateLink(1, 2, arms[2].clone(), 1., 1.),
+        ];
+
+        let system_state = models::SystemState::create_and_build(links);
+        let mut test_agent = Agent::init_random(system_state);
+
+        let epsilon: f32 = 0.01;
+        test_agent.deterministic_policy_improvement(1., epsilon, 100, 100);
+
+        let diff = (test_agent.get_evaluation().get(&0).unwrap() - 6.).abs();
+        assert!(diff < 2.*epsilon);
+
+        let best_action = test_agent.get_best_action(1).unwrap().0;
+        assert_eq!(*best_action, arms[0]);
+    }
+
+    #[test]
+    pub fn value_accessors_test() {
+        let links = vec![
+            models::StateLink(0, 2, "Arm_1".to_string(), 1., 1.),
+            models::StateLink(1, 2, "Arm_1".to_string(), 1., 3.),
+            models::StateLink(3, 2, "Arm_1".to_string(), 1., 1.),
+        ];
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        test_agent.evaluate_policy(0.9, 1e-6, 100);
+
+        assert_eq!(test_agent[1], 3.);
+        assert_eq!(test_agent.value_of(0), Ok(1.));
+        assert_eq!(test_agent.value_of(7), Err(Error::UnknownState {id: 7}));
+        assert_eq!(test_agent.values_sorted(), vec![(1, 3.), (0, 1.), (3, 1.), (2, 0.)]);
+    }
+
+    #[test]
+    pub fn tie_breaking_test() {
+        let links = vec![
+            models::StateLink(0, 1, "B".to_string(), 1., 1.),
+            models::StateLink(0, 1, "C".to_string(), 1., 1.),
+            models::StateLink(0, 1, "A".to_string(), 1., 1.),
+            models::StateLink(1, 1, "Stay".to_string(), 1., 0.),
+        ];
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        test_agent.evaluate_policy(0.5, 1e-12, 100);
+
+        // Equal probabilities and equal action values alike
+        assert_eq!(test_agent.get_best_action(0).unwrap().0, "A");
+        let state = test_agent.get_system_state().get_state(&0).unwrap();
+        assert_eq!(test_agent.calc_greedy_action(state, 0.5, "None"), "A");
+    }
+
+    #[test]
+    pub fn state_policy_test() {
+        let links = vec![
+            models::StateLink(0, 1, "Arm_1".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Arm_2".to_string(), 1., 2.),
+            models::StateLink(0, 1, "Arm_3".to_string(), 1., 3.),
+        ];
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+
+        test_agent.set_state_action(0, "Arm_1").unwrap();
+        test_agent.evaluate_policy(1., 0.01, 100);
+        assert_eq!(test_agent[0], 1.);
+        assert_eq!(test_agent.get_policy()[&0]["Arm_3"], 0.);
+
+        test_agent.set_state_policy(0, HashMap::from([("Arm_2".to_string(), 0.5), ("Arm_3".to_string(), 0.5)])).unwrap();
+        test_agent.evaluate_policy(1., 0.01, 100);
+        assert_eq!(test_agent[0], 2.5);
+
+        // Terminal states only take an empty policy
+        test_agent.set_state_policy(1, HashMap::new()).unwrap();
+
+        assert_eq!(test_agent.set_state_action(2, "Arm_1"), Err(Error::UnknownState {id: 2}));
+        assert_eq!(test_agent.set_state_action(0, "Arm_4"), Err(Error::UnknownAction {state: 0, action: "Arm_4".to_string()}));
+        assert_eq!(test_agent.set_state_policy(0, HashMap::from([("Arm_1".to_string(), 0.5)])), Err(Error::InvalidDistribution {state: 0}));
+        assert_eq!(test_agent.set_state_policy(0, HashMap::from([("Arm_1".to_string(), 1.5), ("Arm_2".to_string(), -0.5)])), Err(Error::InvalidDistribution {state: 0}));
+        assert_eq!(test_agent.get_policy()[&0]["Arm_2"], 0.5);
+    }
+
+    #[test]
+    pub fn clone_test() {
+        let links = vec![
+            models::StateLink(0, 1, "Arm_1".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Arm_2".to_string(), 1., 2.),
+        ];
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        test_agent.evaluate_policy(1., 0.01, 100);
+
+        // A branch improves on its own
+        let mut branch = test_agent.clone();
+        assert!(branch == test_agent);
+        branch.deterministic_policy_improvement(1., 0.01, 100, 100);
+        assert!(branch != test_agent);
+        assert_eq!(test_agent[0], 1.5);
+        assert_eq!(branch[0], 2.);
+
+        // Rolled back from a checkpoint, the cached matrices follow the policy
+        let snapshot = test_agent.checkpoint();
+        test_agent.deterministic_policy_improvement(1., 0.01, 100, 100);
+        assert!(branch == test_agent);
+        test_agent.restore(&snapshot);
+        test_agent.evaluate_policy(1., 0.01, 100);
+        assert_eq!(test_agent[0], 1.5);
+    }
+
+    #[test]
+    pub fn advantages_test() {
+        let links = vec![
+            models::StateLink(0, 1, "Arm_1".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Arm_2".to_string(), 1., 3.),
+            models::StateLink(1, 1, "Stay".to_string(), 1., 0.),
+        ];
+        let mut test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        test_agent.evaluate_policy(0.5, 1e-12, 100);
+
+        // The uniform policy is worth 2 in state 0
+        let advantages = test_agent.advantages(0.5);
+        assert_eq!((advantages[&0]["Arm_1"], advantages[&0]["Arm_2"]), (-1., 1.));
+        assert_eq!(advantages[&1]["Stay"], 0.);
+
+        test_agent.deterministic_policy_improvement(0.5, 1e-12, 100, 100);
+        let advantages = test_agent.advantages(0.5);
+        assert_eq!((advantages[&0]["Arm_1"], advantages[&0]["Arm_2"]), (-2., 0.));
+    }
+
+    #[test]
+    pub fn shared_agent_test() {
+        let links = vec![
+            models::StateLink(0, 1, "Arm_1".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Arm_2".to_string(), 1., 2.),
+            models::StateLink(1, 1, "Stay".to_string(), 1., 0.),
+        ];
+        let model = Arc::new(models::SystemState::create_and_build(links));
+        let mut test_agent: Agent = Agent::init_random_shared(Arc::clone(&model));
+        test_agent.deterministic_policy_improvement(0.5, 1e-9, 100, 100);
+
+        // Agents and their clones keep the one model
+        let other: Agent = Agent::init_random_shared(test_agent.get_shared_system_state());
+        assert!(Arc::ptr_eq(&model, &other.get_shared_system_state()));
+        assert!(Arc::ptr_eq(&model, &test_agent.clone().get_shared_system_state()));
+
+        let shared = Arc::new(test_agent);
+        let answers: Vec<(String, f64)> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4).map(|_| {
+                let agent = Arc::clone(&shared);
+                scope.spawn(move || (agent.get_best_action(0).unwrap().0.clone(), agent.value_of(0).unwrap()))
+            }).collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+        assert!(answers.iter().all(|answer| *answer == ("Arm_2".to_string(), 2.)));
+    }
+
+    #[test]
+    #[should_panic(expected = "state 7 is not in the model")]
+    pub fn index_unknown_state_test() {
+        let links = vec![models::StateLink(0, 1, "Arm_1".to_string(), 1., 1.)];
+        let test_agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        let _ = test_agent[7];
+    }
+
+}
+
+// Run with --no-default-features, the crate then being no_std
+#[cfg(all(test, not(feature = "std")))]
+mod no_std_tests {
+
+    use super::*;
+
+    #[test]
+    fn pre_solved_policy_test() {
+        let links = vec![
+            models::StateLink(0, 1, "Go".to_string(), 1., 1.),
+            models::StateLink(0, 0, "Wait".to_string(), 1., 0.),
+            models::StateLink(1, 1, "Stay".to_string(), 1., 0.5),
+        ];
+        let policy = HashMap::from([
+            (0, HashMap::from([("Wait".to_string(), 1.)])),
+            (1, HashMap::from([("Stay".to_string(), 1.)])),
+        ]);
+        let mut agent: Agent = Agent::builder(models::SystemState::create_and_build(links))
+            .with_policy(PolicyInit::Policy(policy))
+            .build();
+        assert_eq!(agent.validate_policy(), Ok(()));
+
+        // Occasional re-evaluation, then improvement
+        agent.evaluate_policy(0.5, 1e-12, 1000);
+        assert!(agent[0] == 0. && (agent[1] - 1.).abs() < 1e-9);
+        agent.try_solve(0.5, &SolverConfig::new().with_epsilon(1e-12).with_eval_iters(1000)).unwrap();
+        assert_eq!(agent.get_best_action(0).unwrap().0, "Go");
+        assert!((agent[0] - 1.5).abs() < 1e-9);
+    }
+
 }
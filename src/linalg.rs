@@ -0,0 +1,179 @@
+// Conversions between policy-weighted transition structures and standard
+// linear algebra types, enabled with the "nalgebra" and "ndarray" features.
+//
+// A transition structure maps each state to its successors with their
+// probabilities, as returned by Agent::calc_policy_transitions. Matrices are
+// square, rows and columns follow the order of an index of state ids, and the
+// conversions back to maps skip zero entries.
+
+use alloc::vec::Vec;
+
+use crate::collections::HashMap;
+use crate::numeric::Real;
+
+// Sorted ids of every state appearing in the transitions, as rows or columns
+pub fn state_index<F: Real>(transitions: &HashMap<i64,HashMap<i64,F>>) -> Vec<i64> {
+    let mut ids: Vec<i64> = transitions.iter()
+        .flat_map(|(id, successors)| core::iter::once(id).chain(successors.keys()))
+        .cloned().collect();
+    ids.sort();
+    ids.dedup();
+    return ids
+}
+
+// Position of each id in an index
+pub fn index_positions(ids: &[i64]) -> HashMap<i64,usize> {
+    return ids.iter().enumerate().map(|(pos, id)| (*id, pos)).collect()
+}
+
+// Dense vector of state values (or rewards) in index order, missing states are zero
+pub fn to_dense_vector<F: Real>(values: &HashMap<i64,F>, ids: &[i64]) -> Vec<F> {
+    return ids.iter().map(|id| *values.get(id).unwrap_or(&F::zero())).collect()
+}
+
+pub fn from_dense_vector<F: Real>(values: &[F], ids: &[i64]) -> HashMap<i64,F> {
+    return ids.iter().cloned().zip(values.iter().cloned()).collect()
+}
+
+#[cfg(feature = "nalgebra")]
+pub fn to_csr<F: Real + nalgebra::Scalar>(transitions: &HashMap<i64,HashMap<i64,F>>) -> (nalgebra_sparse::CsrMatrix<F>, Vec<i64>) {
+    let ids = state_index(transitions);
+    let positions = index_positions(&ids);
+
+    let mut offsets: Vec<usize> = Vec::with_capacity(ids.len() + 1);
+    let mut columns: Vec<usize> = Vec::new();
+    let mut values: Vec<F> = Vec::new();
+    offsets.push(0);
+
+    for id in &ids {
+        if let Some(successors) = transitions.get(id) {
+            let row = crate::helper::to_sparse(successors.iter()
+                .filter(|(_, prob)| **prob != F::zero())
+                .map(|(id_next, prob)| (*positions.get(id_next).unwrap(), *prob)));
+            for (col, prob) in row {
+                columns.push(col);
+                values.push(prob);
+            }
+        }
+        offsets.push(columns.len());
+    }
+
+    let matrix = nalgebra_sparse::CsrMatrix::try_from_csr_data(ids.len(), ids.len(), offsets, columns, values)
+        .expect("rows are sorted and in bounds");
+
+    return (matrix, ids)
+}
+
+#[cfg(feature = "nalgebra")]
+pub fn from_csr<F: Real + nalgebra::Scalar>(matrix: &nalgebra_sparse::CsrMatrix<F>, ids: &[i64]) -> HashMap<i64,HashMap<i64,F>> {
+    return matrix.row_iter().enumerate()
+        .map(|(row, entries)| {
+            let successors: HashMap<i64,F> = entries.col_indices().iter().zip(entries.values())
+                .filter(|(_, prob)| **prob != F::zero())
+                .map(|(col, prob)| (ids[*col], *prob))
+                .collect();
+            (ids[row], successors)
+        }).collect()
+}
+
+#[cfg(feature = "ndarray")]
+pub fn to_array2<F: Real>(transitions: &HashMap<i64,HashMap<i64,F>>) -> (ndarray::Array2<F>, Vec<i64>) {
+    let ids = state_index(transitions);
+    let positions = index_positions(&ids);
+    let mut matrix = ndarray::Array2::zeros((ids.len(), ids.len()));
+
+    for (id, successors) in transitions {
+        let row = *positions.get(id).unwrap();
+        for (id_next, prob) in successors {
+            matrix[[row, *positions.get(id_next).unwrap()]] += *prob;
+        }
+    }
+
+    return (matrix, ids)
+}
+
+#[cfg(feature = "ndarray")]
+pub fn from_array2<F: Real>(matrix: &ndarray::Array2<F>, ids: &[i64]) -> HashMap<i64,HashMap<i64,F>> {
+    return matrix.outer_iter().enumerate()
+        .map(|(row, probs)| {
+            let successors: HashMap<i64,F> = probs.iter().enumerate()
+                .filter(|(_, prob)| **prob != F::zero())
+                .map(|(col, prob)| (ids[col], *prob))
+                .collect();
+            (ids[row], successors)
+        }).collect()
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::{models, Agent};
+
+    fn random_agent() -> Agent {
+        // Two actions, one leads to end without reward
+        // Other leads to either same state or end with a reward
+        let links = vec![
+            models::StateLink(0, 1, "First_Action".to_string(), 1., 0.),
+            models::StateLink(0, 0, "Second_Action".to_string(), 0.9, 0.),
+            models::StateLink(0, 1, "Second_Action".to_string(), 0.1, 10.),
+            models::StateLink(1, 2, "First_Action".to_string(), 1., 1.),
+        ];
+        return Agent::init_random(models::SystemState::create_and_build(links))
+    }
+
+    #[test]
+    fn dense_vector_test() {
+        let agent = random_agent();
+        let ids = state_index(&agent.calc_policy_transitions());
+        let rewards = to_dense_vector(&agent.calc_policy_rewards(), &ids);
+
+        assert_eq!(ids, vec![0, 1, 2]);
+        assert_eq!(rewards, vec![0.5, 1., 0.]);
+        assert_eq!(from_dense_vector(&rewards, &ids), agent.calc_policy_rewards());
+    }
+
+    #[cfg(feature = "nalgebra")]
+    #[test]
+    fn csr_round_trip_test() {
+        let mut agent = random_agent();
+        let transitions = agent.calc_policy_transitions();
+        let (matrix, ids) = to_csr(&transitions);
+
+        assert_eq!(matrix.nnz(), 3);
+
+        // Terminal states have no successors and no entry in the round trip
+        let mut expected = transitions.clone();
+        expected.retain(|_, successors| { successors.retain(|_, prob| *prob != 0.); true });
+        assert_eq!(from_csr(&matrix, &ids), expected);
+
+        // Direct solve of (I - gamma*P) v = r matches iterative evaluation
+        let gamma = 0.9;
+        let rewards = nalgebra::DVector::from_vec(to_dense_vector(&agent.calc_policy_rewards(), &ids));
+        let system = nalgebra::DMatrix::identity(ids.len(), ids.len()) - nalgebra::DMatrix::from(&matrix)*gamma;
+        let values = system.lu().solve(&rewards).unwrap();
+
+        agent.evaluate_policy(gamma, 1e-10, 10_000);
+        for (pos, id) in ids.iter().enumerate() {
+            assert!((agent.get_evaluation().get(id).unwrap() - values[pos]).abs() < 1e-8);
+        }
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn array2_round_trip_test() {
+        let agent = random_agent();
+        let transitions = agent.calc_policy_transitions();
+        let (matrix, ids) = to_array2(&transitions);
+
+        assert_eq!(matrix.shape(), &[3, 3]);
+        assert_eq!(matrix[[0, 0]], 0.45);
+        assert_eq!(matrix[[0, 1]], 0.55);
+        assert_eq!(matrix.row(2).sum(), 0.);
+
+        let round_trip = from_array2(&matrix, &ids);
+        assert_eq!(round_trip.get(&0), transitions.get(&0));
+        assert_eq!(round_trip.get(&2).unwrap().len(), 0);
+    }
+
+}
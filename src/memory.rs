@@ -0,0 +1,210 @@
+// Estimates of the memory held by systems and agents, to see how close a
+// model is to the limits of the machine before building or solving it.
+//
+// The estimates count the heap allocations of the containers from their
+// capacities, plus the inline size of the containers themselves. Hash maps are
+// counted as one entry and one control byte per slot of their capacity, which
+// is close to what std allocates but leaves out the allocator's own overhead.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::size_of;
+
+use crate::collections::HashMap;
+use crate::models::{ModelState, SystemState};
+use crate::numeric::Real;
+use crate::solver::PolicyMatrices;
+use crate::Agent;
+
+// Estimated bytes by component
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryFootprint {
+    // Transition probabilities, in the maps, the dense model and the links
+    pub transitions: usize,
+    // Rewards of the states and actions, in the maps and the dense model
+    pub rewards: usize,
+    // Rewards and transitions of the policy computed for evaluation
+    pub eval_caches: usize,
+    // Ids and action names of the dense model
+    pub index: usize,
+    pub policy: usize,
+    pub values: usize,
+}
+
+impl MemoryFootprint {
+
+    pub fn total(&self) -> usize {
+        return self.transitions + self.rewards + self.eval_caches + self.index + self.policy + self.values
+    }
+
+}
+
+impl core::ops::Add for MemoryFootprint {
+    type Output = MemoryFootprint;
+
+    fn add(self, other: MemoryFootprint) -> MemoryFootprint {
+        return MemoryFootprint {
+            transitions: self.transitions + other.transitions,
+            rewards: self.rewards + other.rewards,
+            eval_caches: self.eval_caches + other.eval_caches,
+            index: self.index + other.index,
+            policy: self.policy + other.policy,
+            values: self.values + other.values,
+        }
+    }
+}
+
+impl fmt::Display for MemoryFootprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "{} total (transitions {}, rewards {}, eval caches {}, index {}, policy {}, values {})",
+            human_bytes(self.total()), human_bytes(self.transitions), human_bytes(self.rewards),
+            human_bytes(self.eval_caches), human_bytes(self.index), human_bytes(self.policy), human_bytes(self.values))
+    }
+}
+
+// Bytes in binary units, e.g. "1.50 MiB"
+pub fn human_bytes(bytes: usize) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < units.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+    return if unit == 0 {format!("{} B", bytes)} else {format!("{:.2} {}", size, units[unit])}
+}
+
+// Slots of a map, without what its keys and values own
+pub(crate) fn map_bytes<K, V, S>(map: &HashMap<K,V,S>) -> usize {
+    return size_of::<HashMap<K,V,S>>() + map.capacity()*(size_of::<(K,V)>() + 1)
+}
+
+pub(crate) fn vec_bytes<T>(vec: &Vec<T>) -> usize {
+    return size_of::<Vec<T>>() + vec.capacity()*size_of::<T>()
+}
+
+// Map of maps, as held by each state, without what the keys own
+fn nested_map_bytes<K, K2, V>(map: &HashMap<K,HashMap<K2,V>>) -> usize {
+    return map_bytes(map) + map.values()
+        .map(|inner| map_bytes(inner) - size_of::<HashMap<K2,V>>())
+        .sum::<usize>()
+}
+
+fn names_bytes<'a>(names: impl Iterator<Item = &'a String>) -> usize {
+    return names.map(|name| name.capacity()).sum()
+}
+
+pub(crate) fn sparse_rows_bytes<F>(rows: &Vec<Vec<(usize,F)>>) -> usize {
+    return vec_bytes(rows) + rows.iter().map(|row| vec_bytes(row) - size_of::<Vec<(usize,F)>>()).sum::<usize>()
+}
+
+impl<F: Real> ModelState<F> {
+
+    fn memory_footprint(&self) -> MemoryFootprint {
+        return MemoryFootprint {
+            transitions: nested_map_bytes(self.get_all_probs()) + names_bytes(self.get_all_probs().keys()),
+            rewards: nested_map_bytes(self.get_all_action_rewards()) + names_bytes(self.get_all_action_rewards().keys()) + size_of::<F>(),
+            eval_caches: map_bytes(self.get_eval_rewards()) + names_bytes(self.get_eval_rewards().keys())
+                + nested_map_bytes(self.get_eval_probs()) + names_bytes(self.get_eval_probs().values().flat_map(|probs| probs.keys())),
+            ..MemoryFootprint::default()
+        }
+    }
+
+}
+
+impl<F: Real> PolicyMatrices<F> {
+
+    fn memory_footprint(&self) -> usize {
+        return vec_bytes(&self.rewards) + sparse_rows_bytes(&self.successors)
+    }
+
+}
+
+impl<F: Real> SystemState<F> {
+
+    // Estimated bytes held by the links, the dense model and the states if
+    // they have been built
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        let links = self.get_specification();
+
+        let mut footprint = self.get_dense().memory_footprint();
+        footprint.transitions += vec_bytes(links) + names_bytes(links.iter().map(|link| &link.2));
+
+        return match self.built_states() {
+            Some(states) => {
+                footprint.index += map_bytes(states);
+                states.values().fold(footprint, |footprint, state| footprint + state.memory_footprint())
+            },
+            None => footprint,
+        }
+    }
+
+}
+
+impl<F: Real> Agent<F> {
+
+    // Estimated bytes held by the agent: its system, policy, values and the
+    // rewards and transitions of the policy cached for evaluation
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        let mut footprint = self.system_state.memory_footprint();
+
+        footprint.policy = vec_bytes(&self.policy) + self.policy.iter().map(|probs| vec_bytes(probs) - size_of::<Vec<F>>()).sum::<usize>();
+        footprint.values = vec_bytes(&self.policy_evaluation);
+        // Maps built for get_policy and get_evaluation
+        if let Some(policy) = self.policy_map.get() {
+            footprint.policy += nested_map_bytes(policy) + names_bytes(policy.values().flat_map(|probs| probs.keys()));
+        }
+        if let Some(values) = self.evaluation_map.get() {
+            footprint.values += map_bytes(values);
+        }
+        if let Some((_, matrices)) = &self.policy_cache {
+            footprint.eval_caches += matrices.memory_footprint();
+        }
+
+        return footprint
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::models;
+
+    #[test]
+    fn memory_footprint_test() {
+        assert_eq!(human_bytes(512), "512 B");
+        assert_eq!(human_bytes(3*1024*1024/2), "1.50 MiB");
+
+        let small: SystemState = SystemState::create_and_build((0..10)
+            .map(|id| models::StateLink(id, id + 1, "Step".to_string(), 1., 1.)).collect());
+        let large: SystemState = SystemState::create_and_build((0..1000)
+            .map(|id| models::StateLink(id, id + 1, "Step".to_string(), 1., 1.)).collect());
+        let footprint = large.memory_footprint();
+        assert!(footprint.transitions > 1000*size_of::<(usize,f64)>());
+        assert!(footprint.rewards > 1000*size_of::<f64>());
+        assert!(footprint.eval_caches == 0 && footprint.index > 1000*size_of::<i64>());
+
+        // The states are only built when asked for
+        large.get_all_states();
+        let built = large.memory_footprint();
+        assert!(built.eval_caches > 0 && built.transitions > footprint.transitions);
+        let footprint = built;
+        assert_eq!((footprint.policy, footprint.values), (0, 0));
+        assert!(footprint.total() > 50*small.memory_footprint().total());
+
+        let mut agent: Agent = Agent::init_random(large);
+        let before = agent.memory_footprint();
+        assert!(before.policy > 0 && before.values >= 1001*size_of::<f64>());
+        // Evaluating caches the rewards and transitions of the policy
+        agent.evaluate_policy(0.9, 1e-6, 10);
+        let after = agent.memory_footprint();
+        assert!(after.eval_caches > before.eval_caches);
+        assert_eq!(after.transitions, before.transitions);
+        assert!(after.to_string().contains("eval caches"));
+    }
+
+}
@@ -1,24 +1,37 @@
-use std::collections::HashMap;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem::size_of;
+
+use once_cell::race::OnceBox;
+
+use crate::collections::{HashMap, HashSet};
+use crate::error::Error;
+use crate::helper::{self, FastMap};
+use crate::memory::{self, MemoryFootprint};
+use crate::numeric::Real;
 
 // Model states
-#[derive(Debug, PartialEq)]
-pub struct ModelState {
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelState<F: Real = f64> {
     state_id: i64,
-    transition_probs: HashMap<String,HashMap<i64,f64>>,
-    action_rewards: HashMap<String,HashMap<i64,f64>>,
-    state_reward: f64,
-    eval_action_rewards: HashMap<String,f64>,
-    eval_transition_probs: HashMap<i64,HashMap<String,f64>>
+    transition_probs: HashMap<String,HashMap<i64,F>>,
+    action_rewards: HashMap<String,HashMap<i64,F>>,
+    state_reward: F,
+    eval_action_rewards: HashMap<String,F>,
+    eval_transition_probs: HashMap<i64,HashMap<String,F>>
 }
 
-impl ModelState {
+impl<F: Real> ModelState<F> {
 
-    pub fn new(id: i64) -> ModelState {
+    pub fn new(id: i64) -> ModelState<F> {
         let mut state = ModelState {
             state_id: id,
             transition_probs: HashMap::new(),
             action_rewards: HashMap::new(),
-            state_reward: 0.,
+            state_reward: F::zero(),
             eval_action_rewards: HashMap::new(),
             eval_transition_probs: HashMap::new()
         };
@@ -28,17 +41,18 @@ impl ModelState {
         return state
     }
 
-    pub fn insert_link(&mut self, new_state: i64, action: &String, prob: f64, reward: f64) {
+    pub fn insert_link(&mut self, new_state: i64, action: impl Into<String>, prob: F, reward: F) {
+        let action: String = action.into();
         self.transition_probs.entry(action.clone())
-            .or_insert(HashMap::new())
+            .or_default()
             .insert(new_state, prob);
 
-        self.action_rewards.entry(action.clone())
-            .or_insert(HashMap::new())
+        self.action_rewards.entry(action)
+            .or_default()
             .insert(new_state, reward);
     }
 
-    pub fn set_reward(&mut self, new_reward: f64) {
+    pub fn set_reward(&mut self, new_reward: F) {
         self.state_reward = new_reward;
     }
     
@@ -46,32 +60,56 @@ impl ModelState {
         return self.state_id
     }
 
-    pub fn get_all_probs(&self) -> &HashMap<String,HashMap<i64,f64>> {
+    pub fn get_all_probs(&self) -> &HashMap<String,HashMap<i64,F>> {
         return &self.transition_probs
     }
 
-    pub fn get_probs(&self, action: &String) -> Option<&HashMap<i64,f64>> {
+    pub fn get_probs(&self, action: &str) -> Option<&HashMap<i64,F>> {
         return self.transition_probs.get(action)
     }
 
-    pub fn get_all_action_rewards(&self) -> &HashMap<String,HashMap<i64,f64>> {
+    pub fn get_all_action_rewards(&self) -> &HashMap<String,HashMap<i64,F>> {
         return &self.action_rewards
     }
 
-    pub fn get_action_reward(&self, action: &String) -> Option<&HashMap<i64,f64>> {
+    pub fn get_action_reward(&self, action: &str) -> Option<&HashMap<i64,F>> {
         return self.action_rewards.get(action)
     }
 
-    pub fn get_reward(&self) -> f64 {
+    pub fn get_reward(&self) -> F {
         return self.state_reward
     }
 
+    // Action names in name order
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        let mut actions: Vec<&str> = self.transition_probs.keys().map(|action| action.as_str()).collect();
+        actions.sort();
+        return actions.into_iter()
+    }
+
+    // Links leaving the state, by action name then next state id
+    pub fn transitions(&self) -> impl Iterator<Item = Transition<'_, F>> {
+        return self.actions().flat_map(move |action| {
+            let probs = self.transition_probs.get(action).unwrap();
+            let rewards = self.action_rewards.get(action).unwrap();
+            let mut ids_next: Vec<&i64> = probs.keys().collect();
+            ids_next.sort();
+            ids_next.into_iter().map(move |id_next| Transition {
+                id: self.state_id,
+                action,
+                id_next: *id_next,
+                prob: probs[id_next],
+                reward: rewards[id_next],
+            })
+        })
+    }
+
     // Support functions for Actor
 
-    pub fn get_random_policy(&self) -> HashMap<String,f64> {
+    pub fn get_random_policy(&self) -> HashMap<String,F> {
         self.action_rewards
-            .iter()
-            .map(|(action, _)| (action.clone(), 1./self.action_rewards.len() as f64))
+            .keys()
+            .map(|action| (action.clone(), F::one()/F::from_f64(self.action_rewards.len() as f64)))
             .collect()
     }
 
@@ -80,37 +118,35 @@ impl ModelState {
         .map(|(action, rewards)| {
             (
                 action.clone(),
-                rewards.iter().map(|(id,reward)| {
-                    self.get_probs(action).unwrap().get(id).unwrap()*reward
-                }).sum()
+                helper::match_mul_sum(rewards, self.get_probs(action).unwrap())
             )
         }).collect();
     }
 
     pub fn calc_eval_transition(&mut self) {
-        let mut new_eval_transition: HashMap<i64,HashMap<String,f64>> = HashMap::new();
+        let mut new_eval_transition: HashMap<i64,HashMap<String,F>> = HashMap::new();
 
         for (action, probs) in &self.transition_probs {
             for (id, prob) in probs {
-                new_eval_transition.entry(*id).or_insert(HashMap::new())
+                new_eval_transition.entry(*id).or_default()
                     .insert(action.clone(), *prob);
             }
         }
 
-        for (_, map) in &mut new_eval_transition {
-            for (action, _) in &self.transition_probs {
-                map.entry(action.clone()).or_insert(0.);
+        for map in new_eval_transition.values_mut() {
+            for action in self.transition_probs.keys() {
+                map.entry(action.clone()).or_insert(F::zero());
             }
         }
 
         self.eval_transition_probs = new_eval_transition;
     }
 
-    pub fn get_eval_rewards(&self) -> &HashMap<String,f64> {
+    pub fn get_eval_rewards(&self) -> &HashMap<String,F> {
         return &self.eval_action_rewards
     }
 
-    pub fn get_eval_probs(&self) -> &HashMap<i64,HashMap<String,f64>> {
+    pub fn get_eval_probs(&self) -> &HashMap<i64,HashMap<String,F>> {
         return &self.eval_transition_probs
     }
 
@@ -118,23 +154,281 @@ impl ModelState {
 
 // Transition between states given an action
 // (prev_state, new_state, action, probability, reward)
-#[derive(Debug, PartialEq)]
-pub struct StateLink(pub i64, pub i64, pub String, pub f64, pub f64);
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StateLink<F: Real = f64>(pub i64, pub i64, pub String, pub F, pub F);
+
+impl<F: Real> StateLink<F> {
+
+    pub fn new(prev_state: i64, new_state: i64, action: impl Into<String>, prob: F, reward: F) -> StateLink<F> {
+        return StateLink(prev_state, new_state, action.into(), prob, reward)
+    }
+
+}
+
+// Link of a model as yielded by the transition iterators, borrowing the
+// action name
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transition<'a, F: Real = f64> {
+    pub id: i64,
+    pub action: &'a str,
+    pub id_next: i64,
+    pub prob: F,
+    pub reward: F,
+}
+
+impl<F: Real> Transition<'_, F> {
+
+    pub fn to_link(&self) -> StateLink<F> {
+        return StateLink(self.id, self.id_next, self.action.to_string(), self.prob, self.reward)
+    }
+
+}
+
+// The states with their ids and action names mapped to contiguous indices,
+// built with the system so the solvers' inner loops don't hash. The index of
+// a state is its position in increasing id order, the actions of a state are
+// sorted by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DenseModel<F: Real = f64> {
+    ids: Vec<i64>,
+    positions: FastMap<i64,usize>,
+    actions: Vec<Vec<String>>,
+    // Expected reward of each action of each state
+    rewards: Vec<Vec<F>>,
+    // Successors of each action of each state, as sparse vectors of positions
+    successors: Vec<Vec<Vec<(usize,F)>>>,
+    // Reward of the link to each successor, in the order of successors
+    successor_rewards: Vec<Vec<Vec<F>>>,
+}
+
+impl<F: Real> DenseModel<F> {
+
+    // Built from the links alone, without maps by state and action: the
+    // links are sorted by state, action and next state, and of the links
+    // given more than once the last one is kept
+    fn from_links(links: &[StateLink<F>]) -> DenseModel<F> {
+        let mut ids: Vec<i64> = links.iter().flat_map(|link| [link.0, link.1]).collect();
+        ids.sort();
+        ids.dedup();
+        let positions: FastMap<i64,usize> = ids.iter().enumerate().map(|(pos, id)| (*id, pos)).collect();
+
+        // Stable, so that duplicates stay in the order they were given
+        let mut order: Vec<usize> = (0..links.len()).collect();
+        order.sort_by(|a, b| (links[*a].0, &links[*a].2, links[*a].1).cmp(&(links[*b].0, &links[*b].2, links[*b].1)));
+
+        let n_states = ids.len();
+        let mut model = DenseModel {
+            ids, positions,
+            actions: vec![Vec::new(); n_states],
+            rewards: vec![Vec::new(); n_states],
+            successors: vec![Vec::new(); n_states],
+            successor_rewards: vec![Vec::new(); n_states],
+        };
+        for action_links in order.chunk_by(|a, b| links[*a].0 == links[*b].0 && links[*a].2 == links[*b].2) {
+            let first = &links[action_links[0]];
+            let pos = model.positions[&first.0];
+
+            let mut successors: Vec<(usize,F)> = Vec::new();
+            let mut rewards: Vec<F> = Vec::new();
+            for duplicates in action_links.chunk_by(|a, b| links[*a].1 == links[*b].1) {
+                let link = &links[*duplicates.last().unwrap()];
+                successors.push((model.positions[&link.1], link.3));
+                rewards.push(link.4);
+            }
+
+            model.rewards[pos].push(DenseModel::expected_reward(&successors, &rewards));
+            model.successors[pos].push(successors);
+            model.successor_rewards[pos].push(rewards);
+            model.actions[pos].push(first.2.clone());
+        }
+
+        return model
+    }
+
+    fn expected_reward(successors: &[(usize,F)], rewards: &[F]) -> F {
+        return successors.iter().zip(rewards).map(|((_, prob), reward)| *prob*(*reward)).sum()
+    }
+
+    pub fn n_states(&self) -> usize {
+        return self.ids.len()
+    }
+
+    pub fn get_ids(&self) -> &Vec<i64> {
+        return &self.ids
+    }
+
+    pub fn get_position(&self, id: &i64) -> Option<usize> {
+        return self.positions.get(id).cloned()
+    }
+
+    pub fn get_actions(&self, pos: usize) -> &Vec<String> {
+        return &self.actions[pos]
+    }
 
-#[derive(Debug, PartialEq)]
-pub struct SystemState {
-    states: HashMap<i64,ModelState>,
-    speficication: Vec<StateLink>,
+    pub fn get_action_index(&self, pos: usize, action: &str) -> Option<usize> {
+        return self.actions[pos].binary_search_by(|name| name.as_str().cmp(action)).ok()
+    }
+
+    pub fn get_reward(&self, pos: usize, action: usize) -> F {
+        return self.rewards[pos][action]
+    }
+
+    pub fn get_successors(&self, pos: usize, action: usize) -> &Vec<(usize,F)> {
+        return &self.successors[pos][action]
+    }
+
+    // Rewards of the links to the successors, in their order
+    pub fn get_successor_rewards(&self, pos: usize, action: usize) -> &Vec<F> {
+        return &self.successor_rewards[pos][action]
+    }
+
+    // Links leaving the state at a position, by action name then next state id
+    pub fn transitions(&self, pos: usize) -> impl Iterator<Item = Transition<'_, F>> {
+        return self.actions[pos].iter().enumerate().flat_map(move |(action, name)| {
+            self.successors[pos][action].iter().zip(&self.successor_rewards[pos][action])
+                .map(move |((pos_next, prob), reward)| Transition {
+                    id: self.ids[pos],
+                    action: name,
+                    id_next: self.ids[*pos_next],
+                    prob: *prob,
+                    reward: *reward,
+                })
+        })
+    }
+
+    // Positions that can reach the given states under some policy, the
+    // states themselves included, i.e. those whose values depend on them.
+    // Ids missing from the model are ignored.
+    pub fn reaching(&self, targets: &[i64]) -> Vec<bool> {
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); self.n_states()];
+        for (pos, successors) in self.successors.iter().enumerate() {
+            for (pos_next, _) in successors.iter().flatten() {
+                predecessors[*pos_next].push(pos);
+            }
+        }
+
+        let mut reached = vec![false; self.n_states()];
+        let mut pending: Vec<usize> = targets.iter().filter_map(|id| self.get_position(id)).collect();
+        for pos in &pending {
+            reached[*pos] = true;
+        }
+
+        while let Some(pos) = pending.pop() {
+            for pos_prev in &predecessors[pos] {
+                if !reached[*pos_prev] {
+                    reached[*pos_prev] = true;
+                    pending.push(*pos_prev);
+                }
+            }
+        }
+
+        return reached
+    }
+
+    // Positions reachable from the given states under some policy, the states
+    // themselves included. Ids missing from the model are ignored.
+    pub fn reachable_from(&self, start_states: &[i64]) -> Vec<bool> {
+        let mut reached = vec![false; self.n_states()];
+        let mut pending: Vec<usize> = start_states.iter().filter_map(|id| self.get_position(id)).collect();
+        for pos in &pending {
+            reached[*pos] = true;
+        }
+
+        while let Some(pos) = pending.pop() {
+            for (pos_next, _) in self.successors[pos].iter().flatten() {
+                if !reached[*pos_next] {
+                    reached[*pos_next] = true;
+                    pending.push(*pos_next);
+                }
+            }
+        }
+
+        return reached
+    }
+
+    pub(crate) fn memory_footprint(&self) -> MemoryFootprint {
+        return MemoryFootprint {
+            transitions: memory::vec_bytes(&self.successors) + self.successors.iter()
+                .map(|rows| memory::sparse_rows_bytes(rows) - size_of::<Vec<Vec<(usize,F)>>>())
+                .sum::<usize>(),
+            rewards: memory::vec_bytes(&self.rewards) + self.rewards.iter()
+                .map(|rewards| memory::vec_bytes(rewards) - size_of::<Vec<F>>())
+                .sum::<usize>()
+                + memory::vec_bytes(&self.successor_rewards) + self.successor_rewards.iter()
+                    .map(|rows| memory::vec_bytes(rows) - size_of::<Vec<Vec<F>>>() + rows.iter().map(|rewards| memory::vec_bytes(rewards)).sum::<usize>())
+                    .sum::<usize>(),
+            index: memory::vec_bytes(&self.ids) + memory::map_bytes(&self.positions) + memory::vec_bytes(&self.actions)
+                + self.actions.iter()
+                    .map(|names| memory::vec_bytes(names) - size_of::<Vec<String>>() + names.iter().map(|name| name.capacity()).sum::<usize>())
+                    .sum::<usize>(),
+            ..MemoryFootprint::default()
+        }
+    }
+
+}
+
+// Problem in the links of a model, which build lets through
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkIssue {
+    // Several links for the same state, action and next state, build
+    // keeping the last one
+    Duplicate {state: i64, action: String, next_state: i64},
+    ZeroProbability {state: i64, action: String, next_state: i64},
+    // A next state without links of its own, terminal in the model
+    OrphanState {id: i64},
+}
+
+impl fmt::Display for LinkIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return match self {
+            LinkIssue::Duplicate {state, action, next_state} => write!(f, "several links from {} to {} with {}", state, next_state, action),
+            LinkIssue::ZeroProbability {state, action, next_state} => write!(f, "link from {} to {} with {} has probability zero", state, next_state, action),
+            LinkIssue::OrphanState {id} => write!(f, "state {} has no links of its own", id),
+        }
+    }
+}
+
+// How SystemState::try_create_and_build treats the issues of the links
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BuildMode {
+    // As create_and_build, duplicates replacing each other
+    #[default]
+    Unchecked,
+    // Fail with Error::InvalidLinks, listing every issue
+    Strict,
+    // Merge duplicates into one link, summing their probabilities and
+    // averaging their rewards weighted by probability. Issues are logged as
+    // warnings with the "tracing" feature.
+    Lenient,
+}
+
+// The links are kept as given and the solvers work on the dense model. The
+// states, with their maps by action and next state, are only built from the
+// links when asked for.
+#[derive(Debug, Clone)]
+pub struct SystemState<F: Real = f64> {
+    states: OnceBox<HashMap<i64,ModelState<F>>>,
+    speficication: Vec<StateLink<F>>,
     is_built: bool,
+    dense: DenseModel<F>,
+}
+
+// Equal when built from the same links, whether or not their states have been built
+impl<F: Real> PartialEq for SystemState<F> {
+    fn eq(&self, other: &SystemState<F>) -> bool {
+        return self.speficication == other.speficication && self.is_built == other.is_built && self.dense == other.dense
+    }
 }
 
-impl SystemState {
+impl<F: Real> SystemState<F> {
 
-    pub fn create_and_build(links: Vec<StateLink>) -> SystemState {
+    pub fn create_and_build(links: Vec<StateLink<F>>) -> SystemState<F> {
         let mut system_state = SystemState {
-            states: HashMap::new(),
+            states: OnceBox::new(),
             speficication: links,
-            is_built: false
+            is_built: false,
+            dense: DenseModel::default(),
         };
 
         system_state.build();
@@ -142,41 +436,212 @@ impl SystemState {
         return system_state
     }
     
+    // Issues of the links: duplicates and links of probability zero in link
+    // order, then orphan states by id
+    pub fn check_links(links: &[StateLink<F>]) -> Vec<LinkIssue> {
+        let mut issues: Vec<LinkIssue> = Vec::new();
+        let mut seen: HashMap<(i64,&str,i64),u32> = HashMap::new();
+        for StateLink(state, next_state, action, prob, _) in links {
+            let count = seen.entry((*state, action, *next_state)).or_default();
+            *count += 1;
+            if *count == 2 {
+                issues.push(LinkIssue::Duplicate {state: *state, action: action.clone(), next_state: *next_state});
+            }
+            if *prob == F::zero() {
+                issues.push(LinkIssue::ZeroProbability {state: *state, action: action.clone(), next_state: *next_state});
+            }
+        }
+
+        let sources: HashSet<i64> = links.iter().map(|link| link.0).collect();
+        let mut orphans: Vec<i64> = links.iter().map(|link| link.1).filter(|id| !sources.contains(id)).collect();
+        orphans.sort();
+        orphans.dedup();
+        issues.extend(orphans.into_iter().map(|id| LinkIssue::OrphanState {id}));
+
+        return issues
+    }
+
+    pub fn try_create_and_build(links: Vec<StateLink<F>>, mode: BuildMode) -> Result<SystemState<F>, Error> {
+        if mode == BuildMode::Unchecked {
+            return Ok(SystemState::create_and_build(links))
+        }
+
+        let issues = SystemState::check_links(&links);
+        if mode == BuildMode::Strict {
+            return if issues.is_empty() {Ok(SystemState::create_and_build(links))} else {Err(Error::InvalidLinks {issues})}
+        }
+
+        #[cfg(feature = "tracing")]
+        for issue in &issues {
+            tracing::warn!("{}", issue);
+        }
+        if !issues.iter().any(|issue| matches!(issue, LinkIssue::Duplicate {..})) {
+            return Ok(SystemState::create_and_build(links))
+        }
+
+        // Merged links keep the place of the first of their duplicates
+        let mut merged: Vec<StateLink<F>> = Vec::with_capacity(links.len());
+        let mut positions: HashMap<(i64,String,i64),usize> = HashMap::new();
+        for link in links {
+            match positions.get(&(link.0, link.2.clone(), link.1)) {
+                Some(pos) => {
+                    let first = &mut merged[*pos];
+                    let prob = first.3 + link.3;
+                    if prob > F::zero() {
+                        first.4 = (first.3*first.4 + link.3*link.4)/prob;
+                    }
+                    first.3 = prob;
+                },
+                None => {
+                    positions.insert((link.0, link.2.clone(), link.1), merged.len());
+                    merged.push(link);
+                },
+            }
+        }
+        return Ok(SystemState::create_and_build(merged))
+    }
+
+    // Builds the dense model from the links, the states being built again
+    // when next asked for
     pub fn build(&mut self) {
-        
-        for link in &self.speficication {
+        self.dense = DenseModel::from_links(&self.speficication);
+        self.states = OnceBox::new();
+
+        self.is_built = true;
+    }
+
+    fn build_states(links: &[StateLink<F>]) -> HashMap<i64,ModelState<F>> {
+        let mut states: HashMap<i64,ModelState<F>> = HashMap::new();
+        for link in links {
             // (prev_state, new_state, action, probability, reward)
-            self.states.entry(link.0)
+            states.entry(link.0)
                 .or_insert(ModelState::new(link.0))
                 .insert_link(link.1, &link.2, link.3, link.4);
 
-            self.states.entry(link.1).or_insert(ModelState::new(link.1));
+            states.entry(link.1).or_insert(ModelState::new(link.1));
         }
 
-        for (_, state) in self.states.iter_mut() {
+        for (_, state) in states.iter_mut() {
             state.calc_eval_rewards();
             state.calc_eval_transition();
         }
+        return states
+    }
 
-        self.is_built = true;
+    pub fn get_state(&self, id: &i64) -> Option<&ModelState<F>> {
+        return self.get_all_states().get(id)
+    }
+
+    // Built from the links on the first call
+    pub fn get_all_states(&self) -> &HashMap<i64,ModelState<F>> {
+        return self.states.get_or_init(|| Box::new(SystemState::build_states(&self.speficication)))
+    }
+
+    // The states if they have been built
+    pub(crate) fn built_states(&self) -> Option<&HashMap<i64,ModelState<F>>> {
+        return self.states.get()
+    }
+
+    pub fn get_dense(&self) -> &DenseModel<F> {
+        return &self.dense
     }
 
-    pub fn get_state(&self, id: &i64) -> Option<&ModelState> {
-        return self.states.get(id)
+    // States in id order, built from the links on the first call as
+    // get_all_states
+    pub fn states(&self) -> impl Iterator<Item = &ModelState<F>> {
+        let states = self.get_all_states();
+        return self.dense.get_ids().iter().map(|id| states.get(id).unwrap())
     }
 
-    pub fn get_all_states(&self) -> &HashMap<i64,ModelState> {
-        return &self.states
+    // Every link of the model, by state id, action name and next state id.
+    // Links given more than once appear once, with the last values given.
+    pub fn transitions(&self) -> impl Iterator<Item = Transition<'_, F>> {
+        return (0..self.dense.n_states()).flat_map(|pos| self.dense.transitions(pos))
+    }
+
+    // Links the system was built from
+    pub fn get_specification(&self) -> &Vec<StateLink<F>> {
+        return &self.speficication
+    }
+
+    // Changes the reward of a link of the model, in the links it was built
+    // from too. The states, if built, are dropped and built again when next
+    // asked for.
+    pub fn set_link_reward(&mut self, prev_state: i64, new_state: i64, action: &str, reward: F) -> Result<(), Error> {
+        let pos = self.dense.get_position(&prev_state).ok_or(Error::UnknownState {id: prev_state})?;
+        let index = self.dense.get_action_index(pos, action).ok_or(Error::UnknownAction {state: prev_state, action: action.to_string()})?;
+        let successor = self.dense.get_position(&new_state)
+            .and_then(|pos_next| self.dense.successors[pos][index].binary_search_by_key(&pos_next, |(pos_next, _)| *pos_next).ok())
+            .ok_or(Error::UnknownLink {prev_state, new_state, action: action.to_string()})?;
+        for link in self.speficication.iter_mut().filter(|link| link.0 == prev_state && link.1 == new_state && link.2 == action) {
+            link.4 = reward;
+        }
+
+        let dense = &mut self.dense;
+        dense.successor_rewards[pos][index][successor] = reward;
+        dense.rewards[pos][index] = DenseModel::expected_reward(&dense.successors[pos][index], &dense.successor_rewards[pos][index]);
+        // Built again with the new reward when next asked for
+        self.states = OnceBox::new();
+        return Ok(())
     }
 
 }
 
+// Builds the system from its links, e.g. a filtered or chained iterator
+impl<F: Real> FromIterator<StateLink<F>> for SystemState<F> {
+    fn from_iter<I: IntoIterator<Item = StateLink<F>>>(links: I) -> SystemState<F> {
+        return SystemState::create_and_build(links.into_iter().collect())
+    }
+}
 
-#[cfg(test)]
+// The links the system was built from, in their order
+impl<F: Real> IntoIterator for SystemState<F> {
+    type Item = StateLink<F>;
+    type IntoIter = alloc::vec::IntoIter<StateLink<F>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return self.speficication.into_iter()
+    }
+}
+
+
+#[cfg(all(test, feature = "std"))]
 mod tests {
 
     use super::*;
 
+    #[test]
+    fn build_mode_test() {
+        let links = vec![
+            StateLink(0, 1, "Go".to_string(), 0.25, 4.),
+            StateLink(0, 2, "Go".to_string(), 0., 0.),
+            StateLink(0, 1, "Go".to_string(), 0.75, 0.),
+            StateLink(1, 0, "Back".to_string(), 1., 0.),
+        ];
+        let issues = SystemState::check_links(&links);
+        assert_eq!(issues, vec![
+            LinkIssue::ZeroProbability {state: 0, action: "Go".to_string(), next_state: 2},
+            LinkIssue::Duplicate {state: 0, action: "Go".to_string(), next_state: 1},
+            LinkIssue::OrphanState {id: 2},
+        ]);
+        assert_eq!(issues[1].to_string(), "several links from 0 to 1 with Go");
+
+        let error = SystemState::try_create_and_build(links.clone(), BuildMode::Strict).unwrap_err();
+        assert_eq!(error, Error::InvalidLinks {issues});
+
+        // The last duplicate wins unchecked, duplicates are merged leniently
+        let unchecked = SystemState::try_create_and_build(links.clone(), BuildMode::Unchecked).unwrap();
+        assert_eq!(unchecked.get_state(&0).unwrap().get_probs("Go").unwrap()[&1], 0.75);
+        let lenient = SystemState::try_create_and_build(links.clone(), BuildMode::Lenient).unwrap();
+        assert_eq!(lenient.get_specification().len(), 3);
+        assert_eq!(lenient.get_state(&0).unwrap().get_probs("Go").unwrap()[&1], 1.);
+        assert_eq!(lenient.get_state(&0).unwrap().get_action_reward("Go").unwrap()[&1], 1.);
+
+        assert!(SystemState::try_create_and_build(links[3..].to_vec(), BuildMode::Strict).is_err());
+        let cycle = vec![StateLink(0, 1, "Go".to_string(), 1., 0.), StateLink(1, 0, "Back".to_string(), 1., 0.)];
+        assert!(SystemState::try_create_and_build(cycle, BuildMode::Strict).is_ok());
+    }
+
     // Simple system state creation
     #[test]
     fn creation_test() {
@@ -204,9 +669,10 @@ mod tests {
 
         let links = vec![StateLink(0, 0, "Single_Action".to_string(), 1., 10.)];
         let mut test_system = SystemState{
-            states: HashMap::new(),
+            states: OnceBox::new(),
             speficication: links,
             is_built: false,
+            dense: DenseModel::default(),
         };
 
         test_system.build();
@@ -215,6 +681,59 @@ mod tests {
 
     }
 
+    #[test]
+    fn iterators_test() {
+        let links = vec![
+            StateLink(2, 0, "b".to_string(), 0.5, 1.),
+            StateLink(2, 1, "b".to_string(), 0.5, 0.),
+            StateLink(2, 1, "a".to_string(), 1., 2.),
+            StateLink(0, 2, "a".to_string(), 1., 0.),
+        ];
+        let system_state: SystemState = SystemState::create_and_build(links);
+
+        let ids: Vec<i64> = system_state.states().map(|state| state.get_id()).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+        assert_eq!(system_state.get_state(&2).unwrap().actions().collect::<Vec<&str>>(), vec!["a", "b"]);
+        assert_eq!(system_state.get_state(&1).unwrap().actions().count(), 0);
+
+        let transitions: Vec<Transition> = system_state.transitions().collect();
+        assert_eq!(transitions.len(), 4);
+        assert_eq!(transitions[0], Transition {id: 0, action: "a", id_next: 2, prob: 1., reward: 0.});
+        assert_eq!(transitions[1].to_link(), StateLink(2, 1, "a".to_string(), 1., 2.));
+        assert_eq!((transitions[2].id_next, transitions[3].id_next), (0, 1));
+
+        let expected_reward: f64 = system_state.transitions().filter(|t| t.id == 2 && t.action == "b").map(|t| t.prob*t.reward).sum();
+        assert_eq!(expected_reward, 0.5);
+    }
+
+    #[test]
+    fn str_actions_test() {
+        let mut state: ModelState = ModelState::new(0);
+        state.insert_link(1, "Go", 1., 2.);
+        state.insert_link(0, String::from("Stay"), 1., 0.);
+        assert_eq!(state.get_probs("Go"), Some(&HashMap::from([(1, 1.)])));
+        assert_eq!(state.get_action_reward("Stay"), Some(&HashMap::from([(0, 0.)])));
+        assert_eq!(state.get_probs("Jump"), None);
+
+        assert_eq!(StateLink::new(0, 1, "Go", 1., 2.), StateLink(0, 1, "Go".to_string(), 1., 2.));
+    }
+
+    #[test]
+    fn from_iterator_test() {
+        let system_state: SystemState = (0..4)
+            .map(|id| StateLink(id, id + 1, "Step".to_string(), 1., id as f64))
+            .chain(std::iter::once(StateLink(4, 4, "Stay".to_string(), 1., 0.)))
+            .collect();
+        assert_eq!(system_state.states().count(), 5);
+        assert_eq!(system_state.get_dense().n_states(), 5);
+
+        // Dropping the links without reward
+        let filtered: SystemState = system_state.into_iter().filter(|link| link.4 > 0.).collect();
+        let ids: Vec<i64> = filtered.states().map(|state| state.get_id()).collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+        assert_eq!(filtered.into_iter().count(), 3);
+    }
+
     // Model with initial and final state
     #[test]
     fn transition_test() {
@@ -279,9 +798,10 @@ mod tests {
         ];
 
         let mut test_system = SystemState{
-            states: HashMap::new(),
+            states: OnceBox::new(),
             speficication: links,
             is_built: false,
+            dense: DenseModel::default(),
         };
 
         test_system.build();
@@ -306,21 +826,22 @@ mod tests {
         ];
 
         let mut test_system = SystemState{
-            states: HashMap::new(),
+            states: OnceBox::new(),
             speficication: links,
             is_built: false,
+            dense: DenseModel::default(),
         };
 
         test_system.build();
 
         let expected_rewards: HashMap<String,f64> = [(action_1.clone(), 0.), (action_2.clone(), 1.)]
-            .iter().map(|x| x.clone()).collect();
+            .iter().cloned().collect();
 
         let mut expected_probs: HashMap<i64,HashMap<String,f64>> = HashMap::new();
         let probs_0: HashMap<String,f64> = [(action_1.clone(), 0.), (action_2.clone(), 0.9)]
-            .iter().map(|x| x.clone()).collect();
+            .iter().cloned().collect();
         let probs_1: HashMap<String,f64> = [(action_1.clone(), 1.), (action_2.clone(), 0.1)]
-            .iter().map(|x| x.clone()).collect();
+            .iter().cloned().collect();
 
         expected_probs.insert(0, probs_0);
         expected_probs.insert(1, probs_1);
@@ -330,4 +851,42 @@ mod tests {
 
     }
 
+    #[test]
+    fn dense_model_test() {
+        let links = vec![
+            StateLink(5, 5, "Stay".to_string(), 0.5, 2.),
+            StateLink(5, -1, "Stay".to_string(), 0.5, 0.),
+            StateLink(5, -1, "Leave".to_string(), 1., 1.),
+        ];
+        let system = SystemState::create_and_build(links);
+        let dense = system.get_dense();
+
+        assert_eq!(*dense.get_ids(), vec![-1, 5]);
+        assert_eq!(dense.get_position(&5), Some(1));
+        assert_eq!(dense.get_position(&3), None);
+        assert!(dense.get_actions(0).is_empty());
+        assert_eq!(*dense.get_actions(1), vec!["Leave".to_string(), "Stay".to_string()]);
+
+        let stay = dense.get_action_index(1, "Stay").unwrap();
+        assert_eq!(stay, 1);
+        assert_eq!(dense.get_reward(1, stay), 1.);
+        assert_eq!(*dense.get_successors(1, stay), vec![(0, 0.5), (1, 0.5)]);
+        assert_eq!(dense.get_action_index(1, "Jump"), None);
+        assert_eq!(*dense.get_successor_rewards(1, stay), vec![0., 2.]);
+
+        // Built from the links alone: the last of duplicated links is kept
+        let mut system = SystemState::create_and_build(vec![
+            StateLink(0, 1, "Go".to_string(), 1., 1.),
+            StateLink(0, 1, "Go".to_string(), 1., 3.),
+        ]);
+        assert_eq!(*system.get_dense().get_successors(0, 0), vec![(1, 1.)]);
+        assert_eq!(system.get_dense().get_reward(0, 0), 3.);
+
+        system.set_link_reward(0, 1, "Go", 5.).unwrap();
+        assert_eq!(*system.get_dense().get_successor_rewards(0, 0), vec![5.]);
+        assert_eq!(system.get_dense().get_reward(0, 0), 5.);
+        assert_eq!(system.get_state(&0).unwrap().get_eval_rewards()["Go"], 5.);
+        assert_eq!(system.set_link_reward(0, 0, "Go", 5.), Err(Error::UnknownLink {prev_state: 0, new_state: 0, action: "Go".to_string()}));
+    }
+
 }
@@ -0,0 +1,138 @@
+// NumPy .npy export of value functions and Q tables, to load them in
+// notebooks with numpy.load rather than parsing JSON.
+//
+// Arrays follow the index of the dense model: rows are the states in
+// increasing id order, and the columns of a Q table the actions of each
+// state sorted by name, padded with NaN in states with fewer actions. The
+// ids and action names go in a JSON index (write_index_json, with the "json"
+// feature). Values are always written as little-endian 64 bit floats,
+// whatever the precision the agent was solved with.
+
+use std::io::{self, Write};
+#[cfg(feature = "json")]
+use std::fs::File;
+#[cfg(feature = "json")]
+use std::path::Path;
+
+use crate::numeric::Real;
+use crate::Agent;
+
+// .npy files are aligned so that the data starts at a multiple of this
+const NPY_ALIGNMENT: usize = 64;
+
+// Array of 64 bit floats in C order, in the format version 1.0
+pub fn write_npy_f64<W: Write>(mut writer: W, shape: &[usize], data: &[f64]) -> io::Result<()> {
+    if shape.iter().product::<usize>() != data.len() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the data doesn't match the shape"))
+    }
+
+    let dims: Vec<String> = shape.iter().map(|dim| dim.to_string()).collect();
+    // One-dimensional shapes are written with a trailing comma, as Python tuples
+    let shape = if dims.len() == 1 {format!("({},)", dims[0])} else {format!("({})", dims.join(", "))};
+    let mut header = format!("{{'descr': '<f8', 'fortran_order': False, 'shape': {}, }}", shape);
+    // Magic string, version and header length take 10 bytes, and the header
+    // ends with a newline
+    let padding = NPY_ALIGNMENT - (10 + header.len() + 1) % NPY_ALIGNMENT;
+    header.push_str(&" ".repeat(padding % NPY_ALIGNMENT));
+    header.push('\n');
+
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    for value in data {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    return Ok(())
+}
+
+// Values of the states, in id order
+pub fn write_values_npy<W: Write, F: Real>(writer: W, agent: &Agent<F>) -> io::Result<()> {
+    let ids = agent.get_system_state().get_dense().get_ids();
+    let values: Vec<f64> = ids.iter().map(|id| agent.get_evaluation()[id].as_f64()).collect();
+    return write_npy_f64(writer, &[ids.len()], &values)
+}
+
+// Q table of shape (states, most actions of a state)
+pub fn write_q_values_npy<W: Write, F: Real>(writer: W, agent: &Agent<F>, gamma: F) -> io::Result<()> {
+    let model = agent.get_system_state().get_dense();
+    let q_values = agent.calc_q_values(gamma);
+    let n_cols = (0..model.n_states()).map(|pos| model.get_actions(pos).len()).max().unwrap_or(0);
+
+    let mut table: Vec<f64> = vec![f64::NAN; model.n_states()*n_cols];
+    for (pos, id) in model.get_ids().iter().enumerate() {
+        for (col, action) in model.get_actions(pos).iter().enumerate() {
+            table[pos*n_cols + col] = q_values[id][action].as_f64();
+        }
+    }
+    return write_npy_f64(writer, &[model.n_states(), n_cols], &table)
+}
+
+// {"ids": [...], "actions": [[...], ...]}, the id of each row and the
+// action of each column of every row
+#[cfg(feature = "json")]
+pub fn write_index_json<W: Write, F: Real>(writer: W, agent: &Agent<F>) -> serde_json::Result<()> {
+    let model = agent.get_system_state().get_dense();
+    let actions: Vec<&Vec<String>> = (0..model.n_states()).map(|pos| model.get_actions(pos)).collect();
+    return serde_json::to_writer(writer, &serde_json::json!({"ids": model.get_ids(), "actions": actions}))
+}
+
+// values.npy, q_values.npy and index.json in a directory
+#[cfg(feature = "json")]
+pub fn write_arrays<F: Real, P: AsRef<Path>>(dir: P, agent: &Agent<F>, gamma: F) -> io::Result<()> {
+    let dir = dir.as_ref();
+    write_values_npy(File::create(dir.join("values.npy"))?, agent)?;
+    write_q_values_npy(File::create(dir.join("q_values.npy"))?, agent, gamma)?;
+    write_index_json(File::create(dir.join("index.json"))?, agent)?;
+    return Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::models;
+
+    // Header and data of an .npy file
+    fn read_npy(bytes: &[u8]) -> (String, Vec<f64>) {
+        assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        assert_eq!((10 + header_len) % NPY_ALIGNMENT, 0);
+        let header = String::from_utf8(bytes[10..10 + header_len].to_vec()).unwrap();
+        let data = bytes[10 + header_len..].chunks(8).map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap())).collect();
+        return (header.trim_end().to_string(), data)
+    }
+
+    #[test]
+    fn npy_test() {
+        let links = vec![
+            models::StateLink(5, 2, "Go".to_string(), 1., 1.),
+            models::StateLink(5, 5, "Stay".to_string(), 1., 0.),
+            models::StateLink(2, 2, "Stay".to_string(), 1., 0.5),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        agent.deterministic_policy_improvement(0.5, 1e-12, 100, 1000);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_values_npy(&mut buffer, &agent).unwrap();
+        let (header, values) = read_npy(&buffer);
+        assert_eq!(header, "{'descr': '<f8', 'fortran_order': False, 'shape': (2,), }");
+        assert!((values[0] - 1.).abs() < 1e-9 && (values[1] - 1.5).abs() < 1e-9);
+
+        let mut buffer: Vec<u8> = Vec::new();
+        write_q_values_npy(&mut buffer, &agent, 0.5).unwrap();
+        let (header, table) = read_npy(&buffer);
+        assert!(header.contains("'shape': (2, 2)"));
+        assert!((table[0] - 1.).abs() < 1e-9 && table[1].is_nan());
+        assert!((table[2] - 1.5).abs() < 1e-9 && (table[3] - 0.75).abs() < 1e-9);
+
+        assert!(write_npy_f64(Vec::new(), &[2, 2], &[1.]).is_err());
+
+        #[cfg(feature = "json")]
+        {
+            let mut buffer: Vec<u8> = Vec::new();
+            write_index_json(&mut buffer, &agent).unwrap();
+            assert_eq!(String::from_utf8(buffer).unwrap(), r#"{"actions":[["Stay"],["Go","Stay"]],"ids":[2,5]}"#);
+        }
+    }
+
+}
@@ -0,0 +1,50 @@
+// Numeric type used for probabilities, rewards and values.
+//
+// Implemented for f64, the default everywhere, and for f32, which halves the
+// memory taken by large models and value tables at the cost of precision.
+// The trait is sealed, so the solvers only ever see these two types.
+
+use core::fmt::{Debug, Display};
+use core::iter::Sum;
+
+use num_traits::{Float, NumAssign};
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+pub trait Real: Float + NumAssign + Sum + Default + Debug + Display + Send + Sync + 'static + private::Sealed {
+
+    // Conversion from a f64 constant, rounding when Self is f32
+    fn from_f64(value: f64) -> Self;
+
+    fn as_f64(self) -> f64;
+
+}
+
+impl Real for f32 {
+
+    fn from_f64(value: f64) -> f32 {
+        return value as f32
+    }
+
+    fn as_f64(self) -> f64 {
+        return self as f64
+    }
+
+}
+
+impl Real for f64 {
+
+    fn from_f64(value: f64) -> f64 {
+        return value
+    }
+
+    fn as_f64(self) -> f64 {
+        return self
+    }
+
+}
@@ -0,0 +1,155 @@
+// Heatmaps of the value function of grid-shaped models, enabled with the
+// "plotters" feature.
+//
+// Each state is drawn as a cell colored from blue (lowest value) to red
+// (highest value), with the greedy action on top: an arrow for the usual grid
+// moves (up, down, left, right and the compass directions), a dot for any
+// other action. States without actions only show their value. The position
+// of the states is given by a mapping to (row, column) cells, as for
+// render::render_grid.
+
+use std::path::Path;
+
+use plotters::coord::Shift;
+use plotters::prelude::*;
+
+use crate::numeric::Real;
+use crate::render::greedy_action;
+use crate::Agent;
+
+// Side of a cell in the written images, in pixels
+pub const CELL_SIZE: u32 = 48;
+
+const LOW_COLOR: (f64, f64, f64) = (49., 54., 149.);
+const MID_COLOR: (f64, f64, f64) = (255., 255., 191.);
+const HIGH_COLOR: (f64, f64, f64) = (165., 0., 38.);
+
+fn value_color(fraction: f64) -> RGBColor {
+    let (from, to, t) = if fraction < 0.5 {(LOW_COLOR, MID_COLOR, 2.*fraction)} else {(MID_COLOR, HIGH_COLOR, 2.*fraction - 1.)};
+    let mix = |a: f64, b: f64| (a + (b - a)*t).round() as u8;
+    return RGBColor(mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+// Unit step of the usual grid moves, rows growing downwards
+fn action_direction(action: &str) -> Option<(f64, f64)> {
+    return match action.to_lowercase().as_str() {
+        "up" | "north" => Some((0., -1.)),
+        "down" | "south" => Some((0., 1.)),
+        "left" | "west" => Some((-1., 0.)),
+        "right" | "east" => Some((1., 0.)),
+        _ => None,
+    }
+}
+
+// Draws the heatmap over the whole area, split in n_rows by n_cols cells
+pub fn draw_heatmap<F, C, DB>(agent: &Agent<F>, n_rows: usize, n_cols: usize, coords: C, area: &DrawingArea<DB, Shift>) -> Result<(), DrawingAreaErrorKind<DB::ErrorType>>
+where F: Real, C: Fn(i64) -> Option<(usize, usize)>, DB: DrawingBackend {
+
+    area.fill(&WHITE)?;
+
+    let (width, height) = area.dim_in_pixel();
+    let cell_width = width as f64/n_cols.max(1) as f64;
+    let cell_height = height as f64/n_rows.max(1) as f64;
+
+    let values = agent.get_evaluation();
+    let low = values.values().map(|value| value.as_f64()).fold(f64::INFINITY, f64::min);
+    let high = values.values().map(|value| value.as_f64()).fold(f64::NEG_INFINITY, f64::max);
+
+    let mut ids: Vec<&i64> = values.keys().collect();
+    ids.sort();
+
+    for id in ids {
+        let (row, col) = match coords(*id) {
+            Some((row, col)) if row < n_rows && col < n_cols => (row, col),
+            _ => continue,
+        };
+
+        let x0 = col as f64*cell_width;
+        let y0 = row as f64*cell_height;
+        let fraction = if high > low {(values.get(id).unwrap().as_f64() - low)/(high - low)} else {0.5};
+
+        area.draw(&Rectangle::new(
+            [(x0 as i32, y0 as i32), ((x0 + cell_width) as i32, (y0 + cell_height) as i32)],
+            value_color(fraction).filled(),
+        ))?;
+
+        let action = match agent.get_policy().get(id).and_then(greedy_action) {
+            Some(action) => action,
+            None => continue,
+        };
+
+        let center = (x0 + cell_width/2., y0 + cell_height/2.);
+        let point = |dx: f64, dy: f64| ((center.0 + dx*cell_width) as i32, (center.1 + dy*cell_height) as i32);
+
+        match action_direction(action) {
+            Some((dx, dy)) => {
+                // Shaft through the center and a head made of two strokes
+                let (tip_x, tip_y) = (0.35*dx, 0.35*dy);
+                let head = |side: f64| point(tip_x - 0.15*dx + 0.12*side*dy, tip_y - 0.15*dy + 0.12*side*dx);
+                let style = BLACK.stroke_width(2);
+                area.draw(&PathElement::new(vec![point(-tip_x, -tip_y), point(tip_x, tip_y)], style))?;
+                area.draw(&PathElement::new(vec![head(1.), point(tip_x, tip_y), head(-1.)], style))?;
+            },
+            None => {
+                let radius = (0.1*cell_width.min(cell_height)).max(1.) as i32;
+                area.draw(&Circle::new(point(0., 0.), radius, BLACK.filled()))?;
+            },
+        }
+    }
+
+    return Ok(())
+}
+
+pub fn write_heatmap_svg<F, C, P>(agent: &Agent<F>, n_rows: usize, n_cols: usize, coords: C, path: P) -> Result<(), DrawingAreaErrorKind<std::io::Error>>
+where F: Real, C: Fn(i64) -> Option<(usize, usize)>, P: AsRef<Path> {
+
+    let size = (CELL_SIZE*n_cols as u32, CELL_SIZE*n_rows as u32);
+    let area = SVGBackend::new(path.as_ref(), size).into_drawing_area();
+    draw_heatmap(agent, n_rows, n_cols, coords, &area)?;
+    return area.present()
+}
+
+pub fn write_heatmap_png<F, C, P>(agent: &Agent<F>, n_rows: usize, n_cols: usize, coords: C, path: P) -> Result<(), DrawingAreaErrorKind<<BitMapBackend<'static> as DrawingBackend>::ErrorType>>
+where F: Real, C: Fn(i64) -> Option<(usize, usize)>, P: AsRef<Path> {
+
+    let size = (CELL_SIZE*n_cols as u32, CELL_SIZE*n_rows as u32);
+    let area = BitMapBackend::new(path.as_ref(), size).into_drawing_area();
+    draw_heatmap(agent, n_rows, n_cols, coords, &area)?;
+    return area.present()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::envs::CliffWalking;
+
+    #[test]
+    fn heatmap_test() {
+        let env = CliffWalking::with_size(2, 3).unwrap();
+        let mut agent: Agent = Agent::init_random(env.build());
+        agent.deterministic_policy_improvement(1., 1e-9, 100, 100);
+        let coords = |id: i64| Some((id as usize/3, id as usize%3));
+
+        let mut svg = String::new();
+        {
+            let area = SVGBackend::with_string(&mut svg, (3*CELL_SIZE, 2*CELL_SIZE)).into_drawing_area();
+            draw_heatmap(&agent, 2, 3, coords, &area).unwrap();
+            area.present().unwrap();
+        }
+
+        // Background, five states (the cliff is not one) and four arrows of two strokes
+        assert_eq!(svg.matches("<rect").count(), 1 + 5);
+        assert_eq!(svg.matches("<polyline").count(), 2*4);
+
+        // Highest value at the goal, lowest at the start
+        assert!(svg.contains(&format!("fill=\"#{:02X}{:02X}{:02X}\"", 165, 0, 38)));
+        assert!(svg.contains(&format!("fill=\"#{:02X}{:02X}{:02X}\"", 49, 54, 149)));
+
+        let path = std::env::temp_dir().join("complete_iter_heatmap_test.png");
+        write_heatmap_png(&agent, 2, 3, coords, &path).unwrap();
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+}
@@ -0,0 +1,211 @@
+// Operations on policies, given as maps from state ids to the probabilities
+// of their actions: mixing two of them, and projecting a stochastic one to a
+// deterministic one.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::collections::HashMap;
+use crate::error::Error;
+use crate::numeric::Real;
+use crate::render;
+use crate::Agent;
+
+// The blend (1 - alpha)*policy + alpha*other, as in conservative policy
+// iteration. Both policies must cover the same states, actions missing from
+// one of them count as zero there, and alpha must be in [0, 1]. The error
+// names the smallest id of the states in only one of them.
+pub fn mix<F: Real>(policy: &HashMap<i64,HashMap<String,F>>, other: &HashMap<i64,HashMap<String,F>>, alpha: F) -> Result<HashMap<i64,HashMap<String,F>>, Error> {
+
+    if !(alpha >= F::zero() && alpha <= F::one()) {
+        return Err(Error::InvalidMixingWeight)
+    }
+    let mismatch = policy.keys().filter(|id| !other.contains_key(*id))
+        .chain(other.keys().filter(|id| !policy.contains_key(*id)))
+        .min();
+    if let Some(id) = mismatch {
+        return Err(Error::PolicyMismatch {state: *id})
+    }
+
+    let mixed = policy.iter()
+        .map(|(id, actions)| {
+            let actions_other = other.get(id).unwrap();
+            let mut blend: HashMap<String,F> = actions.iter()
+                .map(|(action, prob)| (action.clone(), (F::one() - alpha)*(*prob)))
+                .collect();
+            for (action, prob) in actions_other {
+                *blend.entry(action.clone()).or_insert(F::zero()) += alpha*(*prob);
+            }
+            (*id, blend)
+        })
+        .collect();
+
+    return Ok(mixed)
+}
+
+// How much a state lost when its policy was made deterministic
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateProjection<F: Real = f64> {
+    pub id: i64,
+    // The action kept, None for states without actions
+    pub action: Option<String>,
+    // Probability of the other actions
+    pub discarded: F,
+    // Actions as likely as the one kept, sorted by name, empty without a tie
+    pub tied: Vec<String>,
+}
+
+// Report of project_deterministic, with one entry per state in id order
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectionReport<F: Real = f64> {
+    pub states: Vec<StateProjection<F>>,
+}
+
+impl<F: Real> ProjectionReport<F> {
+
+    // States that had probability on more than one action
+    pub fn lossy_states(&self) -> impl Iterator<Item = &StateProjection<F>> {
+        return self.states.iter().filter(|state| state.discarded > F::zero())
+    }
+
+    // States where the action kept was chosen among ties
+    pub fn tied_states(&self) -> impl Iterator<Item = &StateProjection<F>> {
+        return self.states.iter().filter(|state| !state.tied.is_empty())
+    }
+
+    pub fn max_discarded(&self) -> F {
+        return self.states.iter().map(|state| state.discarded).fold(F::zero(), |max, discarded| if discarded > max {discarded} else {max})
+    }
+
+}
+
+// The deterministic policy playing the most likely action of each state, the
+// first by name among ties, with the probability mass and the ties lost on
+// the way
+pub fn project_deterministic<F: Real>(policy: &HashMap<i64,HashMap<String,F>>) -> (HashMap<i64,HashMap<String,F>>, ProjectionReport<F>) {
+
+    let mut ids: Vec<&i64> = policy.keys().collect();
+    ids.sort();
+
+    let mut projected: HashMap<i64,HashMap<String,F>> = HashMap::new();
+    let mut states: Vec<StateProjection<F>> = Vec::new();
+    for id in ids {
+        let actions = &policy[id];
+        let kept = render::greedy_action(actions).cloned();
+
+        let (discarded, tied) = match &kept {
+            Some(kept) => {
+                let prob = actions[kept];
+                let discarded: F = actions.iter().filter(|(action, _)| *action != kept).map(|(_, prob)| *prob).sum();
+                let mut tied: Vec<String> = actions.iter()
+                    .filter(|(action, other)| *action != kept && **other == prob)
+                    .map(|(action, _)| action.clone())
+                    .collect();
+                tied.sort();
+                (discarded, tied)
+            },
+            None => (F::zero(), Vec::new()),
+        };
+
+        projected.insert(*id, actions.keys()
+            .map(|action| (action.clone(), if Some(action) == kept.as_ref() {F::one()} else {F::zero()}))
+            .collect());
+        states.push(StateProjection {id: *id, action: kept, discarded, tied});
+    }
+
+    return (projected, ProjectionReport {states})
+}
+
+impl<F: Real> Agent<F> {
+
+    // Replaces the policy with its deterministic projection, see
+    // project_deterministic
+    pub fn make_deterministic(&mut self) -> ProjectionReport<F> {
+        let (projected, report) = project_deterministic(self.get_policy());
+        self.set_polity(projected);
+        return report
+    }
+
+    // Moves the policy of the agent by alpha towards the other policy
+    pub fn mix_policy(&mut self, other: &HashMap<i64,HashMap<String,F>>, alpha: F) -> Result<(), Error> {
+        let mixed = mix(self.get_policy(), other, alpha)?;
+        self.set_polity(mixed);
+        return Ok(())
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::models;
+
+    #[test]
+    fn mix_test() {
+        let policy: HashMap<i64,HashMap<String,f64>> = HashMap::from([
+            (0, HashMap::from([("Left".to_string(), 1.), ("Right".to_string(), 0.)])),
+            (1, HashMap::new()),
+        ]);
+        let other: HashMap<i64,HashMap<String,f64>> = HashMap::from([
+            (0, HashMap::from([("Right".to_string(), 1.)])),
+            (1, HashMap::new()),
+        ]);
+
+        let mixed = mix(&policy, &other, 0.25).unwrap();
+        assert_eq!(mixed[&0], HashMap::from([("Left".to_string(), 0.75), ("Right".to_string(), 0.25)]));
+        assert!(mixed[&1].is_empty());
+        assert_eq!(mix(&policy, &other, 0.).unwrap(), policy);
+        assert_eq!(mix(&policy, &other, 1.).unwrap()[&0]["Right"], 1.);
+
+        assert_eq!(mix(&policy, &other, 1.5), Err(Error::InvalidMixingWeight));
+        assert_eq!(mix(&policy, &other, f64::NAN), Err(Error::InvalidMixingWeight));
+        let partial: HashMap<i64,HashMap<String,f64>> = HashMap::from([(0, HashMap::new())]);
+        assert_eq!(mix(&policy, &partial, 0.5), Err(Error::PolicyMismatch {state: 1}));
+        assert_eq!(mix(&partial, &policy, 0.5), Err(Error::PolicyMismatch {state: 1}));
+    }
+
+    #[test]
+    fn projection_test() {
+        let policy: HashMap<i64,HashMap<String,f64>> = HashMap::from([
+            (0, HashMap::from([("Left".to_string(), 0.7), ("Right".to_string(), 0.3)])),
+            (1, HashMap::from([("Up".to_string(), 0.4), ("Down".to_string(), 0.4), ("Left".to_string(), 0.2)])),
+            (2, HashMap::from([("Stay".to_string(), 1.)])),
+            (3, HashMap::new()),
+        ]);
+        let (projected, report) = project_deterministic(&policy);
+
+        assert_eq!(projected[&0], HashMap::from([("Left".to_string(), 1.), ("Right".to_string(), 0.)]));
+        assert_eq!(projected[&1]["Down"], 1.);
+        assert!(projected[&3].is_empty());
+
+        assert_eq!(report.states.len(), 4);
+        assert_eq!(report.states[1], StateProjection {id: 1, action: Some("Down".to_string()), discarded: 0.6000000000000001, tied: vec!["Up".to_string()]});
+        assert_eq!(report.states[3].action, None);
+        assert_eq!(report.lossy_states().map(|state| state.id).collect::<Vec<i64>>(), vec![0, 1]);
+        assert_eq!(report.tied_states().count(), 1);
+        assert!((report.max_discarded() - 0.6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn mix_policy_test() {
+        let links = vec![
+            models::StateLink(0, 1, "Arm_1".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Arm_2".to_string(), 1., 3.),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        agent.set_state_action(0, "Arm_1").unwrap();
+        let mut greedy = agent.clone();
+        greedy.set_state_action(0, "Arm_2").unwrap();
+
+        agent.mix_policy(greedy.get_policy(), 0.5).unwrap();
+        agent.evaluate_policy(1., 1e-9, 100);
+        assert_eq!(agent[0], 2.);
+
+        let report = agent.make_deterministic();
+        assert_eq!(report.states[0].tied, vec!["Arm_2".to_string()]);
+        agent.evaluate_policy(1., 1e-9, 100);
+        assert_eq!(agent[0], 1.);
+    }
+
+}
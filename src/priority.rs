@@ -0,0 +1,235 @@
+// Priority queue of states by Bellman error, the bookkeeping of prioritized
+// sweeping: back up the state with the largest error first, then raise the
+// priority of its predecessors by how much the change can move their values.
+//
+// The queue is public so solvers written on top of the crate can drive their
+// own backups with it. Agent::evaluate_policy_prioritized is one of them.
+
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use crate::collections::HashMap;
+use crate::{helper, linalg};
+use crate::numeric::Real;
+use crate::Agent;
+
+// Heap entry, stale once the priority of its state has changed
+struct Entry<F: Real> {
+    priority: F,
+    id: i64,
+}
+
+impl<F: Real> PartialEq for Entry<F> {
+    fn eq(&self, other: &Entry<F>) -> bool {
+        return self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<F: Real> Eq for Entry<F> {}
+
+impl<F: Real> PartialOrd for Entry<F> {
+    fn partial_cmp(&self, other: &Entry<F>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Larger priorities first, then smaller ids
+impl<F: Real> Ord for Entry<F> {
+    fn cmp(&self, other: &Entry<F>) -> Ordering {
+        return self.priority.partial_cmp(&other.priority).unwrap_or(Ordering::Equal)
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+// States waiting for a backup, by Bellman error. States are only queued with
+// a priority of at least the threshold, and a state is queued once, with the
+// largest priority it was given.
+pub struct BellmanQueue<F: Real = f64> {
+    heap: BinaryHeap<Entry<F>>,
+    priorities: HashMap<i64,F>,
+    // Predecessors of each state, with the probability of moving to it
+    predecessors: HashMap<i64,Vec<(i64,F)>>,
+    threshold: F,
+}
+
+impl<F: Real> BellmanQueue<F> {
+
+    pub fn new(predecessors: HashMap<i64,Vec<(i64,F)>>, threshold: F) -> BellmanQueue<F> {
+        return BellmanQueue {heap: BinaryHeap::new(), priorities: HashMap::new(), predecessors, threshold}
+    }
+
+    // Predecessors taken from transition probabilities by state, such as
+    // those of Agent::calc_policy_transitions
+    pub fn from_transitions(transitions: &HashMap<i64,HashMap<i64,F>>, threshold: F) -> BellmanQueue<F> {
+        let mut predecessors: HashMap<i64,Vec<(i64,F)>> = HashMap::new();
+        for (id_prev, probs) in transitions {
+            for (id_next, prob) in probs {
+                if *prob > F::zero() {
+                    predecessors.entry(*id_next).or_default().push((*id_prev, *prob));
+                }
+            }
+        }
+        for preds in predecessors.values_mut() {
+            preds.sort_by_key(|pred| pred.0);
+        }
+
+        return BellmanQueue::new(predecessors, threshold)
+    }
+
+    // Queues a state with its Bellman error, keeping the larger priority if
+    // it is already queued. Errors below the threshold are ignored.
+    pub fn push(&mut self, id: i64, error: F) {
+        let priority = error.abs();
+        if priority < self.threshold || self.priorities.get(&id).is_some_and(|current| *current >= priority) {
+            return
+        }
+        self.priorities.insert(id, priority);
+        self.heap.push(Entry {priority, id});
+    }
+
+    // State with the largest priority, removed from the queue
+    pub fn pop(&mut self) -> Option<(i64, F)> {
+        while let Some(entry) = self.heap.pop() {
+            if self.priorities.get(&entry.id) == Some(&entry.priority) {
+                self.priorities.remove(&entry.id);
+                return Some((entry.id, entry.priority))
+            }
+        }
+        return None
+    }
+
+    // After the value of a state changed by change, queues each predecessor
+    // with gamma times its probability of moving there times the change,
+    // a bound on how much its Bellman error grew
+    pub fn propagate(&mut self, id: i64, change: F, gamma: F) {
+        let raised: Vec<(i64, F)> = match self.predecessors.get(&id) {
+            Some(preds) => preds.iter().map(|(pred, prob)| (*pred, gamma*(*prob)*change.abs())).collect(),
+            None => return,
+        };
+        for (pred, priority) in raised {
+            self.push(pred, priority);
+        }
+    }
+
+    pub fn get_priority(&self, id: i64) -> Option<F> {
+        return self.priorities.get(&id).cloned()
+    }
+
+    pub fn get_predecessors(&self, id: i64) -> &[(i64,F)] {
+        return self.predecessors.get(&id).map(|preds| preds.as_slice()).unwrap_or(&[])
+    }
+
+    pub fn len(&self) -> usize {
+        return self.priorities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.priorities.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.priorities.clear();
+    }
+
+}
+
+impl<F: Real> Agent<F> {
+
+    // Evaluates the current policy by prioritized sweeping: one state at a
+    // time, largest Bellman error first, until every error is below epsilon
+    // or max_backups backups were done. Changes spread along the
+    // predecessors only, so models where few values move take far fewer
+    // backups than full sweeps. Returns the number of backups.
+    pub fn evaluate_policy_prioritized(&mut self, gamma: F, epsilon: F, max_backups: usize) -> usize {
+
+        let rewards = self.calc_policy_rewards();
+        let transitions = self.calc_policy_transitions();
+        let mut queue = BellmanQueue::from_transitions(&transitions, epsilon);
+
+        let backup = |id: &i64, values: &HashMap<i64,F>| -> F {
+            let future = transitions.get(id).map(|probs| helper::match_mul_sum_compensated(probs, values)).unwrap_or(F::zero());
+            return *rewards.get(id).unwrap_or(&F::zero()) + gamma*future
+        };
+
+        let mut values: HashMap<i64,F> = self.get_evaluation().clone();
+        let ids: Vec<i64> = self.system_state.get_dense().get_ids().clone();
+        for id in &ids {
+            let error = backup(id, &values) - values[id];
+            queue.push(*id, error);
+        }
+
+        let mut n_backups: usize = 0;
+        while n_backups < max_backups {
+            let id = match queue.pop() {
+                Some((id, _)) => id,
+                None => break,
+            };
+            let new_value = backup(&id, &values);
+            let change = new_value - values[&id];
+            values.insert(id, new_value);
+            n_backups += 1;
+
+            queue.propagate(id, change, gamma);
+        }
+        self.replace_values(linalg::to_dense_vector(&values, &ids));
+
+        return n_backups
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::models;
+
+    #[test]
+    fn bellman_queue_test() {
+        let transitions: HashMap<i64,HashMap<i64,f64>> = HashMap::from([
+            (0, HashMap::from([(2, 0.5), (1, 0.5)])),
+            (1, HashMap::from([(2, 1.)])),
+        ]);
+        let mut queue = BellmanQueue::from_transitions(&transitions, 0.1);
+        assert_eq!(queue.get_predecessors(2), &[(0, 0.5), (1, 1.)]);
+
+        queue.push(0, -0.5);
+        queue.push(1, 0.05);
+        queue.push(2, 2.);
+        queue.push(0, 0.3);
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.get_priority(0), Some(0.5));
+
+        assert_eq!(queue.pop(), Some((2, 2.)));
+        queue.propagate(2, -1., 0.9);
+        assert_eq!(queue.get_priority(0), Some(0.5));
+        assert_eq!(queue.get_priority(1), Some(0.9));
+        assert_eq!(queue.pop(), Some((1, 0.9)));
+        assert_eq!(queue.pop(), Some((0, 0.5)));
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn prioritized_evaluation_test() {
+        // A long chain with a single reward at its end
+        let links: Vec<models::StateLink> = (0..100)
+            .map(|id| models::StateLink(id, id + 1, "Step".to_string(), 1., if id == 99 {1.} else {0.}))
+            .collect();
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links.clone()));
+        let n_backups = agent.evaluate_policy_prioritized(0.9, 1e-12, 10_000);
+
+        // Each state is backed up once, from the end of the chain
+        assert_eq!(n_backups, 100);
+        let mut swept: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        swept.evaluate_policy(0.9, 1e-12, 1000);
+        for (id, value) in swept.get_evaluation() {
+            assert!((agent.get_evaluation().get(id).unwrap() - value).abs() < 1e-12);
+        }
+
+        assert_eq!(agent.evaluate_policy_prioritized(0.9, 1e-12, 10_000), 0);
+    }
+
+}
@@ -0,0 +1,209 @@
+// Progress of long solves, reported after every evaluation sweep.
+//
+// Observers receive a Progress, either through a closure or by polling a
+// SharedProgress updated by the solve running in another thread. A
+// CancellationToken stops such a solve from the other thread.
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+
+use num_traits::Float;
+
+use crate::numeric::Real;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progress<F: Real = f64> {
+    // Improvement step, starting at 0 for the evaluation of the initial policy
+    pub policy_iteration: u32,
+    // Sweep of the current evaluation, starting at 1
+    pub sweep: u32,
+    // Largest value change of the sweep
+    pub delta: F,
+    pub epsilon: F,
+    // Sweeps left before delta falls below epsilon, extrapolated from the
+    // contraction of delta over the last sweep. None until a contraction has
+    // been seen, and bounded by the sweep limit.
+    pub estimated_remaining: Option<u32>,
+    // Since the start of the solve, zero without the "std" feature
+    pub elapsed: Duration,
+}
+
+impl<F: Real> Progress<F> {
+
+    // Sweeps needed to go from delta to epsilon at the given contraction rate
+    pub(crate) fn extrapolate(delta: F, previous_delta: Option<F>, epsilon: F, sweeps_left: u32) -> Option<u32> {
+        if delta < epsilon {
+            return Some(0)
+        }
+
+        let rate = (delta/previous_delta?).as_f64();
+        if !(rate > 0. && rate < 1.) {
+            return None
+        }
+
+        // Through num-traits, f64::ln being std only
+        let remaining = (Float::ln((epsilon/delta).as_f64())/Float::ln(rate)).ceil();
+        return Some(remaining.min(sweeps_left as f64) as u32)
+    }
+
+}
+
+pub trait ProgressObserver<F: Real = f64> {
+    fn on_progress(&mut self, progress: &Progress<F>);
+}
+
+impl<F: Real, P: FnMut(&Progress<F>)> ProgressObserver<F> for P {
+    fn on_progress(&mut self, progress: &Progress<F>) {
+        self(progress)
+    }
+}
+
+// Observer ignoring every report
+pub struct NoProgress;
+
+impl<F: Real> ProgressObserver<F> for NoProgress {
+    fn on_progress(&mut self, _progress: &Progress<F>) {}
+}
+
+// Last report of a solve, shared between the solving thread and the ones polling it
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct SharedProgress<F: Real = f64> {
+    latest: Arc<Mutex<Option<Progress<F>>>>,
+}
+
+#[cfg(feature = "std")]
+impl<F: Real> SharedProgress<F> {
+
+    pub fn new() -> SharedProgress<F> {
+        return SharedProgress {latest: Arc::new(Mutex::new(None))}
+    }
+
+    // None before the first sweep
+    pub fn latest(&self) -> Option<Progress<F>> {
+        return self.latest.lock().unwrap().clone()
+    }
+
+}
+
+#[cfg(feature = "std")]
+impl<F: Real> ProgressObserver<F> for SharedProgress<F> {
+    fn on_progress(&mut self, progress: &Progress<F>) {
+        *self.latest.lock().unwrap() = Some(progress.clone());
+    }
+}
+
+// Flag set by another thread to stop the solves given it in their config,
+// checked before every sweep and between chunks of the sweeps of large
+// models. Clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+
+    pub fn new() -> CancellationToken {
+        return CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.cancelled.load(Ordering::Relaxed)
+    }
+
+}
+
+// Equal when sharing the flag
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &CancellationToken) -> bool {
+        return Arc::ptr_eq(&self.cancelled, &other.cancelled)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::{models, Agent, Error, SolverConfig};
+
+    #[test]
+    fn progress_test() {
+        // Geometric decay of delta at rate gamma
+        assert_eq!(Progress::extrapolate(1e-2, Some(1e-1), 1.5e-6, 100), Some(4));
+        assert_eq!(Progress::extrapolate(1e-2, Some(1e-1), 1.5e-6, 3), Some(3));
+        assert_eq!(Progress::extrapolate(1e-2, None, 1e-6, 100), None);
+        assert_eq!(Progress::extrapolate(1e-7, Some(1e-1), 1e-6, 100), Some(0));
+
+        // A state looping on itself, delta shrinks by gamma each sweep
+        let links = vec![
+            models::StateLink(0, 0, "Stay".to_string(), 1., 1.),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+
+        let mut reports: Vec<Progress> = Vec::new();
+        agent.evaluate_policy_with_progress(0.5, 1e-6, 1000, &mut |progress: &Progress| reports.push(progress.clone()));
+
+        assert_eq!(reports.len(), 21);
+        assert_eq!(reports[0].estimated_remaining, None);
+        assert_eq!(reports[1].estimated_remaining, Some(19));
+        assert!(reports.iter().enumerate().all(|(pos, progress)| progress.sweep == pos as u32 + 1 && progress.policy_iteration == 0));
+        assert_eq!(reports.last().unwrap().estimated_remaining, Some(0));
+
+        let mut shared = SharedProgress::new();
+        agent.solve_with_progress(0.5, &SolverConfig::new(), &mut shared);
+        assert!(shared.latest().unwrap().policy_iteration >= 1);
+    }
+
+    #[test]
+    fn cancellation_test() {
+        // With an epsilon of zero the evaluations only stop when cancelled
+        let links = vec![
+            models::StateLink(0, 1, "Go".to_string(), 1., 1.),
+            models::StateLink(0, 0, "Stay".to_string(), 1., 0.),
+            models::StateLink(1, 0, "Back".to_string(), 1., 0.),
+        ];
+        let model = models::SystemState::create_and_build(links);
+        let config = SolverConfig::new().with_epsilon(0.).with_eval_iters(u32::MAX);
+
+        let cancelled = CancellationToken::new();
+        cancelled.cancel();
+        let mut agent: Agent = Agent::init_random(model.clone());
+        let before = agent.get_evaluation().clone();
+        assert_eq!(agent.try_solve(0.9, &config.clone().with_cancellation(cancelled.clone())), Err(Error::Cancelled {policy_iteration: 0, sweep: 1}));
+        assert_eq!(agent.get_evaluation(), &before);
+
+        // The infallible solves run to the end
+        let finite = SolverConfig::new().with_eval_iters(20).with_cancellation(cancelled);
+        agent.solve(0.9, &finite);
+        assert!(agent.get_evaluation().values().all(|value| *value > 0.));
+
+        let token = CancellationToken::new();
+        let config = config.with_divergence_window(u32::MAX).with_cancellation(token.clone());
+        let mut shared = SharedProgress::new();
+        let polled = shared.clone();
+        let solving = std::thread::spawn(move || {
+            let mut agent: Agent = Agent::init_random(model);
+            let result = agent.try_solve_with_progress(0.9, &config, &mut shared);
+            (agent, result)
+        });
+        while polled.latest().is_none_or(|progress| progress.sweep < 10) {
+            std::thread::yield_now();
+        }
+        token.cancel();
+
+        // Values of the last complete sweep
+        let (agent, result) = solving.join().unwrap();
+        assert!(matches!(result, Err(Error::Cancelled {policy_iteration: 0, ..})));
+        assert!(agent.get_evaluation().values().all(|value| value.is_finite() && *value > 0.));
+        assert_eq!(token, token.clone());
+        assert_ne!(token, CancellationToken::new());
+    }
+
+}
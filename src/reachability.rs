@@ -0,0 +1,299 @@
+// Policies maximizing or minimizing the probability of ever reaching a set of
+// target states, the values of the agent becoming those probabilities.
+//
+// The states where the optimum is 0 or 1 are found on the graph of the model
+// first, the others by value iteration from 0, which converges to the optimal
+// probabilities even when some policy can stay forever in a set of states
+// (an end component). The maximizing policy is then chosen so it leaves such
+// sets whenever the targets can be reached from them.
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::numeric::Real;
+use crate::{helper, Agent};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReachObjective {
+    Maximize,
+    Minimize,
+}
+
+// Model in state id order, the actions of each state sorted by name with
+// their successors as sparse vectors of positions with positive probability
+struct ActionGraph<F: Real> {
+    ids: Vec<i64>,
+    actions: Vec<Vec<Action<F>>>,
+}
+
+// Name and successors of an action
+type Action<F> = (String, Vec<(usize,F)>);
+
+impl<F: Real> ActionGraph<F> {
+
+    fn len(&self) -> usize {
+        return self.ids.len()
+    }
+
+    fn q_value(&self, pos: usize, action: usize, values: &[F]) -> F {
+        return helper::sparse_dot_dense_compensated(&self.actions[pos][action].1, values)
+    }
+
+    // Positions with a path to one of the sources through some actions, only
+    // going through states that are not blocked (sources are always included)
+    fn backward_reachable(&self, sources: &[bool], blocked: &[bool]) -> Vec<bool> {
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); self.len()];
+        for (pos, actions) in self.actions.iter().enumerate() {
+            for (pos_next, _) in actions.iter().flat_map(|(_, successors)| successors) {
+                predecessors[*pos_next].push(pos);
+            }
+        }
+
+        let mut reached: Vec<bool> = sources.to_vec();
+        let mut pending: Vec<usize> = (0..self.len()).filter(|pos| sources[*pos]).collect();
+        while let Some(pos) = pending.pop() {
+            for prev in &predecessors[pos] {
+                if !reached[*prev] && !blocked[*prev] {
+                    reached[*prev] = true;
+                    pending.push(*prev);
+                }
+            }
+        }
+
+        return reached
+    }
+
+    // States reaching the targets surely under some policy, with the action
+    // taking them one step closer
+    fn max_one(&self, targets: &[bool]) -> (Vec<bool>, Vec<Option<usize>>) {
+        let mut allowed = vec![true; self.len()];
+
+        loop {
+            // States reaching the targets with actions that never leave the allowed states
+            let mut reached: Vec<bool> = targets.to_vec();
+            let mut chosen: Vec<Option<usize>> = vec![None; self.len()];
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for pos in 0..self.len() {
+                    if !allowed[pos] || reached[pos] {
+                        continue;
+                    }
+                    let progress = self.actions[pos].iter().position(|(_, successors)| {
+                        successors.iter().all(|(pos_next, _)| allowed[*pos_next])
+                            && successors.iter().any(|(pos_next, _)| reached[*pos_next])
+                    });
+                    if progress.is_some() {
+                        reached[pos] = true;
+                        chosen[pos] = progress;
+                        changed = true;
+                    }
+                }
+            }
+
+            if reached == allowed {
+                return (reached, chosen)
+            }
+            allowed = reached;
+        }
+    }
+
+    // States where some policy avoids the targets forever, with the action
+    // keeping them there. States without actions stay where they are.
+    fn min_zero(&self, targets: &[bool]) -> (Vec<bool>, Vec<Option<usize>>) {
+        let mut avoiding: Vec<bool> = targets.iter().map(|target| !target).collect();
+        let mut chosen: Vec<Option<usize>> = vec![None; self.len()];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for pos in 0..self.len() {
+                if !avoiding[pos] || self.actions[pos].is_empty() {
+                    continue;
+                }
+                chosen[pos] = self.actions[pos].iter()
+                    .position(|(_, successors)| successors.iter().all(|(pos_next, _)| avoiding[*pos_next]));
+                if chosen[pos].is_none() {
+                    avoiding[pos] = false;
+                    changed = true;
+                }
+            }
+        }
+
+        return (avoiding, chosen)
+    }
+
+    // Gauss-Seidel sweeps of the Bellman equations of the objective over the
+    // given positions, until the largest change is below epsilon
+    fn iterate(&self, values: &mut [F], positions: &[usize], objective: ReachObjective, epsilon: F, n_iter: u32) {
+        let mut counter: u32 = 0;
+
+        while !positions.is_empty() && counter < n_iter {
+            let mut delta = F::zero();
+            for pos in positions {
+                let q_values = (0..self.actions[*pos].len()).map(|action| self.q_value(*pos, action, values));
+                let new_value = match objective {
+                    ReachObjective::Maximize => q_values.fold(F::zero(), |best, q_value| best.max(q_value)),
+                    ReachObjective::Minimize => q_values.fold(F::one(), |best, q_value| best.min(q_value)),
+                };
+                delta = delta.max((new_value - values[*pos]).abs());
+                values[*pos] = new_value;
+            }
+
+            counter += 1;
+            if delta < epsilon {
+                break
+            }
+        }
+    }
+
+    // Best action by q value, the first by name among those within epsilon
+    fn best_action(&self, pos: usize, values: &[F], objective: ReachObjective, epsilon: F) -> Option<usize> {
+        let q_values: Vec<F> = (0..self.actions[pos].len()).map(|action| self.q_value(pos, action, values)).collect();
+        let best = match objective {
+            ReachObjective::Maximize => q_values.iter().fold(F::neg_infinity(), |best, q_value| best.max(*q_value)),
+            ReachObjective::Minimize => q_values.iter().fold(F::infinity(), |best, q_value| best.min(*q_value)),
+        };
+        return q_values.iter().position(|q_value| (*q_value - best).abs() <= epsilon)
+    }
+
+}
+
+impl<F: Real> Agent<F> {
+
+    fn action_graph(&self) -> ActionGraph<F> {
+        let model = self.system_state.get_dense();
+
+        let actions: Vec<Vec<Action<F>>> = (0..model.n_states())
+            .map(|pos| model.get_actions(pos).iter().enumerate()
+                .map(|(action, name)| {
+                    let successors: Vec<(usize,F)> = model.get_successors(pos, action).iter()
+                        .filter(|(_, prob)| *prob > F::zero())
+                        .cloned().collect();
+                    (name.clone(), successors)
+                }).collect())
+            .collect();
+
+        return ActionGraph {ids: model.get_ids().clone(), actions}
+    }
+
+    // Replaces the policy with a deterministic one optimizing the probability
+    // of ever reaching one of the targets, and the values with those
+    // probabilities (1 in the targets). epsilon and n_iter bound the value
+    // iteration of the states not decided on the graph.
+    pub fn solve_reachability(&mut self, targets: &[i64], objective: ReachObjective, epsilon: F, n_iter: u32) {
+
+        let graph = self.action_graph();
+        let is_target: Vec<bool> = graph.ids.iter().map(|id| targets.contains(id)).collect();
+        let n = graph.len();
+
+        // Values known from the graph, and the action to play in the sure states
+        let (mut values, mut chosen, undecided) = match objective {
+            ReachObjective::Maximize => {
+                let zero: Vec<bool> = graph.backward_reachable(&is_target, &vec![false; n]).iter().map(|reached| !reached).collect();
+                let (one, chosen) = graph.max_one(&is_target);
+                let values: Vec<F> = one.iter().map(|sure| if *sure {F::one()} else {F::zero()}).collect();
+                let undecided: Vec<usize> = (0..n).filter(|pos| !zero[*pos] && !one[*pos]).collect();
+                (values, chosen, undecided)
+            },
+            ReachObjective::Minimize => {
+                let (zero, chosen) = graph.min_zero(&is_target);
+                let one: Vec<bool> = graph.backward_reachable(&zero, &is_target).iter().map(|reached| !reached).collect();
+                let values: Vec<F> = one.iter().map(|sure| if *sure {F::one()} else {F::zero()}).collect();
+                let undecided: Vec<usize> = (0..n).filter(|pos| !zero[*pos] && !one[*pos]).collect();
+                (values, chosen, undecided)
+            },
+        };
+
+        graph.iterate(&mut values, &undecided, objective, epsilon, n_iter);
+
+        // Maximizing, an optimal action may stay in an end component without
+        // ever reaching the targets: the states are settled backwards from
+        // the targets, each with an optimal action leading to settled states
+        if objective == ReachObjective::Maximize {
+            let mut settled: Vec<bool> = (0..n).map(|pos| is_target[pos] || chosen[pos].is_some()).collect();
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for pos in 0..n {
+                    if settled[pos] || values[pos] == F::zero() {
+                        continue;
+                    }
+                    let progress = graph.actions[pos].iter().enumerate()
+                        .position(|(action, (_, successors))| {
+                            graph.q_value(pos, action, &values) >= values[pos] - epsilon
+                                && successors.iter().any(|(pos_next, _)| settled[*pos_next])
+                        });
+                    if progress.is_some() {
+                        chosen[pos] = progress;
+                        settled[pos] = true;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        let policy: Vec<Vec<F>> = (0..n)
+            .map(|pos| {
+                let action = chosen[pos].or_else(|| graph.best_action(pos, &values, objective, epsilon));
+                (0..graph.actions[pos].len()).map(|index| if Some(index) == action {F::one()} else {F::zero()}).collect()
+            }).collect();
+        self.replace_policy(policy);
+        self.replace_values(values);
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::envs::GamblersProblem;
+    use crate::models;
+
+    #[test]
+    fn solve_reachability_test() {
+        // 2 is the target and 3 a failure. State 1 can wait forever, which
+        // ties with the way out when maximizing.
+        let links = vec![
+            models::StateLink(0, 1, "Safe".to_string(), 1., 0.),
+            models::StateLink(0, 2, "Risky".to_string(), 0.5, 0.),
+            models::StateLink(0, 3, "Risky".to_string(), 0.5, 0.),
+            models::StateLink(1, 1, "Again".to_string(), 1., 0.),
+            models::StateLink(1, 2, "Exit".to_string(), 0.9, 0.),
+            models::StateLink(1, 3, "Exit".to_string(), 0.1, 0.),
+            models::StateLink(4, 2, "Go".to_string(), 1., 0.),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+
+        agent.solve_reachability(&[2], ReachObjective::Maximize, 1e-12, 1000);
+        let values = agent.get_evaluation();
+        assert!((values.get(&0).unwrap() - 0.9).abs() < 1e-9);
+        assert!((values.get(&1).unwrap() - 0.9).abs() < 1e-9);
+        assert_eq!(*values.get(&2).unwrap(), 1.);
+        assert_eq!(*values.get(&3).unwrap(), 0.);
+        assert_eq!(*values.get(&4).unwrap(), 1.);
+        assert_eq!(agent.get_best_action(0).unwrap().0, "Safe");
+        assert_eq!(agent.get_best_action(1).unwrap().0, "Exit");
+        assert!((agent.reach_probability(&[2]).get(&0).unwrap() - 0.9).abs() < 1e-9);
+
+        agent.solve_reachability(&[2], ReachObjective::Minimize, 1e-12, 1000);
+        let values = agent.get_evaluation();
+        assert_eq!(*values.get(&0).unwrap(), 0.);
+        assert_eq!(*values.get(&4).unwrap(), 1.);
+        assert_eq!(agent.get_best_action(1).unwrap().0, "Again");
+
+        // Reaching the goal of the gambler, iterated rather than decided on the graph
+        let mut agent: Agent = Agent::init_random(GamblersProblem::new(10, 0.4).unwrap().build());
+        agent.solve_reachability(&[10], ReachObjective::Maximize, 1e-12, 10000);
+        let mut solved: Agent = Agent::init_random(GamblersProblem::new(10, 0.4).unwrap().build());
+        solved.deterministic_policy_improvement(1., 1e-12, 100, 1000);
+        for state in 1..10 {
+            let probability = agent.get_evaluation().get(&state).unwrap();
+            assert!((probability - solved.get_evaluation().get(&state).unwrap()).abs() < 1e-6);
+            assert!((probability - agent.reach_probability(&[10]).get(&state).unwrap()).abs() < 1e-6);
+        }
+    }
+
+}
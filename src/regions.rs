@@ -0,0 +1,128 @@
+// Solves region by region, for models split between workers or too large to
+// sweep whole.
+//
+// A region is a set of states solved by policy iteration with the values of
+// the other states held fixed, those on its boundary standing for the rest
+// of the model. Agent::solve_by_regions solves the blocks of a partition in
+// turn, round after round, until a round changes no value by epsilon or more
+// and no action. The regions of a round can also be solved by separate
+// workers, each with Agent::solve_region on a copy of the agent, and stitched
+// back with Agent::merge_region before the next round.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::Error;
+use crate::numeric::Real;
+use crate::progress::NoProgress;
+use crate::solver::{Partition, SolveContext, SolverConfig};
+use crate::Agent;
+
+impl<F: Real> Agent<F> {
+
+    // Policy iteration on the states of the region only, ids missing from
+    // the model being skipped. The divergence and key checks of the config
+    // apply, and its start states restrict the region further.
+    pub fn solve_region(&mut self, gamma: F, config: &SolverConfig<F>, region: &[i64]) -> Result<(), Error> {
+        self.check_keys(config.key_mismatch)?;
+        let model = self.system_state.get_dense();
+        let positions: Vec<usize> = region.iter().filter_map(|id| model.get_position(id)).collect();
+        return self.solve_positions(gamma, config, &positions)
+    }
+
+    // Solves the blocks of the partition as regions, in turn, until a round
+    // changes no value by epsilon or more and no action. Returns the number
+    // of rounds, or Error::RegionsNotConverged after max_rounds rounds, the
+    // agent keeping the policy and values of the last one.
+    pub fn solve_by_regions(&mut self, gamma: F, config: &SolverConfig<F>, partition: &Partition, max_rounds: u32) -> Result<u32, Error> {
+        self.check_keys(config.key_mismatch)?;
+        let blocks = partition.blocks(self.system_state.get_dense());
+
+        let mut max_diff = F::zero();
+        for round in 1..=max_rounds {
+            let old_values = self.policy_evaluation.clone();
+            let old_policy = self.policy.clone();
+            for block in &blocks {
+                self.solve_positions(gamma, config, block)?;
+            }
+
+            max_diff = self.policy_evaluation.iter().zip(&old_values)
+                .map(|(value, old_value)| (*value - *old_value).abs())
+                .fold(F::zero(), |a, b| a.max(b));
+            #[cfg(feature = "tracing")]
+            tracing::debug!(round, max_diff = max_diff.as_f64(), n_regions = blocks.len(), "regions solved");
+            if max_diff < config.epsilon && self.policy == old_policy {
+                return Ok(round)
+            }
+        }
+        return Err(Error::RegionsNotConverged {rounds: max_rounds, max_diff: max_diff.as_f64()})
+    }
+
+    // Policy and values of the region's states taken from another agent of
+    // the same model, as left by Agent::solve_region
+    pub fn merge_region(&mut self, other: &Agent<F>, region: &[i64]) {
+        let mut policy = core::mem::take(&mut self.policy);
+        let mut values = core::mem::take(&mut self.policy_evaluation);
+        for pos in region.iter().filter_map(|id| self.system_state.get_dense().get_position(id)) {
+            policy[pos] = other.policy[pos].clone();
+            values[pos] = other.policy_evaluation[pos];
+        }
+        self.replace_policy(policy);
+        self.replace_values(values);
+    }
+
+    fn solve_positions(&mut self, gamma: F, config: &SolverConfig<F>, positions: &[usize]) -> Result<(), Error> {
+        let model = self.system_state.get_dense();
+        let mut region = vec![false; model.n_states()];
+        positions.iter().for_each(|pos| region[*pos] = true);
+
+        let mut observer = NoProgress;
+        let mut context = SolveContext::new(&mut observer, Some(config)).with_config(config, model);
+        if let Some(active) = &context.active {
+            region.iter_mut().zip(active).for_each(|(region, active)| *region &= *active);
+        }
+        context.active = Some(region);
+        return self.improve_observed(gamma, config.epsilon, config.policy_iters, config.eval_iters, &mut context)
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::envs::GamblersProblem;
+
+    #[test]
+    fn solve_by_regions_test() {
+        let model = GamblersProblem::new(20, 0.4).unwrap().build();
+        let config = SolverConfig::new().with_epsilon(1e-12).with_eval_iters(1000);
+        let mut expected: Agent = Agent::init_random(model.clone());
+        expected.try_solve(0.9, &config).unwrap();
+
+        let mut agent: Agent = Agent::init_random(model.clone());
+        let rounds = agent.solve_by_regions(0.9, &config, &Partition::Chunks(5), 100).unwrap();
+        assert!(rounds > 1 && rounds < 100);
+        for (id, value) in expected.get_evaluation() {
+            assert!((agent[*id] - *value).abs() < 1e-9, "{} {} {}", id, agent[*id], value);
+        }
+
+        // A round solved by two workers and stitched back
+        let ids: Vec<i64> = model.get_dense().get_ids().clone();
+        let (low, high) = ids.split_at(ids.len()/2);
+        let start: Agent = Agent::init_random(model);
+        let mut stitched = start.clone();
+        for region in [low, high] {
+            let mut worker = start.clone();
+            worker.solve_region(0.9, &config, region).unwrap();
+            assert!(ids.iter().filter(|id| !region.contains(id)).all(|id| worker[*id] == start[*id]));
+            stitched.merge_region(&worker, region);
+        }
+        assert!(stitched.solve_by_regions(0.9, &config, &Partition::Chunks(5), 100).unwrap() < 100);
+        assert!(stitched.get_evaluation().iter().all(|(id, value)| (expected[*id] - *value).abs() < 1e-9));
+
+        let mut agent: Agent = Agent::init_random(GamblersProblem::new(20, 0.4).unwrap().build());
+        assert!(matches!(agent.solve_by_regions(0.9, &config, &Partition::Chunks(5), 1), Err(Error::RegionsNotConverged {rounds: 1, ..})));
+    }
+
+}
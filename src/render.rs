@@ -0,0 +1,399 @@
+// Text rendering of models, value functions and policies.
+//
+// ValueTable and PolicyTable implement Display as aligned tables, sorted by
+// state id, or by decreasing value when only the top states are kept. Grid
+// worlds are rendered with render_grid, from a mapping of state ids to cells.
+// Links, states and value summaries implement Display too. Numbers are shown
+// with 4 decimals unless the format gives a precision, e.g. {:.2}.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::collections::HashMap;
+use crate::models::{ModelState, StateLink};
+use crate::helper;
+use crate::numeric::Real;
+use crate::Agent;
+
+const DEFAULT_PRECISION: usize = 4;
+
+pub struct ValueTable<'a, F: Real = f64> {
+    values: &'a HashMap<i64,F>,
+    top_n: Option<usize>,
+    precision: usize,
+    labels: Option<&'a HashMap<i64,String>>,
+}
+
+pub struct PolicyTable<'a, F: Real = f64> {
+    policy: &'a HashMap<i64,HashMap<String,F>>,
+    values: Option<&'a HashMap<i64,F>>,
+    top_n: Option<usize>,
+    precision: usize,
+    labels: Option<&'a HashMap<i64,String>>,
+}
+
+// Min, max and mean of a value function, with the states of the extremes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValueSummary<F: Real = f64> {
+    pub n_states: usize,
+    pub mean: F,
+    // (state id, value), the smallest id among ties, None without states
+    pub min: Option<(i64, F)>,
+    pub max: Option<(i64, F)>,
+}
+
+// State ids in id order, or the top_n ones with the largest values
+fn select_states<F: Real>(ids: Vec<i64>, values: Option<&HashMap<i64,F>>, top_n: Option<usize>) -> Vec<i64> {
+    let mut ids = ids;
+    ids.sort();
+
+    if let (Some(values), Some(top_n)) = (values, top_n) {
+        let value = |id: &i64| values.get(id).cloned().unwrap_or(F::neg_infinity());
+        ids.sort_by(|a, b| value(b).partial_cmp(&value(a)).unwrap().then(a.cmp(b)));
+        ids.truncate(top_n);
+    }
+
+    return ids
+}
+
+// Header and first cells of the rows of a table, with a label column when
+// labels are given
+fn state_cells(id: i64, labels: Option<&HashMap<i64,String>>) -> Vec<String> {
+    let mut cells = vec![id.to_string()];
+    if let Some(labels) = labels {
+        cells.push(labels.get(&id).cloned().unwrap_or_default());
+    }
+    return cells
+}
+
+fn state_header(labels: Option<&HashMap<i64,String>>) -> Vec<String> {
+    return if labels.is_some() {vec!["state".to_string(), "label".to_string()]} else {vec!["state".to_string()]}
+}
+
+// Rows of cells, the columns padded to their widest cell
+fn write_table(f: &mut fmt::Formatter, rows: &[Vec<String>]) -> fmt::Result {
+    let n_cols = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..n_cols)
+        .map(|col| rows.iter().filter_map(|row| row.get(col)).map(|cell| cell.chars().count()).max().unwrap_or(0))
+        .collect();
+
+    for row in rows {
+        let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+        writeln!(f, "{}", cells.join("  ").trim_end())?;
+    }
+    return Ok(())
+}
+
+impl<'a, F: Real> ValueTable<'a, F> {
+
+    pub fn new(values: &'a HashMap<i64,F>) -> ValueTable<'a, F> {
+        return ValueTable {values, top_n: None, precision: DEFAULT_PRECISION, labels: None}
+    }
+
+    // Keeps the top_n states with the largest values
+    pub fn top(mut self, top_n: usize) -> ValueTable<'a, F> {
+        self.top_n = Some(top_n);
+        return self
+    }
+
+    // Decimals of the values, unless the format gives a precision
+    pub fn with_precision(mut self, precision: usize) -> ValueTable<'a, F> {
+        self.precision = precision;
+        return self
+    }
+
+    // Adds a column with the label of each state
+    pub fn with_labels(mut self, labels: &'a HashMap<i64,String>) -> ValueTable<'a, F> {
+        self.labels = Some(labels);
+        return self
+    }
+
+}
+
+impl<F: Real> fmt::Display for ValueTable<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ids = select_states(self.values.keys().cloned().collect(), Some(self.values), self.top_n);
+
+        let precision = f.precision().unwrap_or(self.precision);
+
+        let mut rows = vec![[state_header(self.labels), vec!["value".to_string()]].concat()];
+        rows.extend(ids.iter().map(|id| [state_cells(*id, self.labels), vec![format!("{:.*}", precision, self.values.get(id).unwrap())]].concat()));
+
+        return write_table(f, &rows)
+    }
+}
+
+impl<'a, F: Real> PolicyTable<'a, F> {
+
+    pub fn new(policy: &'a HashMap<i64,HashMap<String,F>>) -> PolicyTable<'a, F> {
+        return PolicyTable {policy, values: None, top_n: None, precision: DEFAULT_PRECISION, labels: None}
+    }
+
+    // Adds a value column
+    pub fn with_values(mut self, values: &'a HashMap<i64,F>) -> PolicyTable<'a, F> {
+        self.values = Some(values);
+        return self
+    }
+
+    // Keeps the top_n states with the largest values, which must be given
+    pub fn top(mut self, top_n: usize) -> PolicyTable<'a, F> {
+        self.top_n = Some(top_n);
+        return self
+    }
+
+    // Decimals of the values, unless the format gives a precision. The
+    // probabilities of the actions keep 2.
+    pub fn with_precision(mut self, precision: usize) -> PolicyTable<'a, F> {
+        self.precision = precision;
+        return self
+    }
+
+    // Adds a column with the label of each state
+    pub fn with_labels(mut self, labels: &'a HashMap<i64,String>) -> PolicyTable<'a, F> {
+        self.labels = Some(labels);
+        return self
+    }
+
+}
+
+impl<F: Real> fmt::Display for PolicyTable<'_, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ids = select_states(self.policy.keys().cloned().collect(), self.values, self.top_n);
+
+        let precision = f.precision().unwrap_or(self.precision);
+
+        let mut header = state_header(self.labels);
+        if self.values.is_some() {
+            header.push("value".to_string());
+        }
+        header.push("actions".to_string());
+        let mut rows = vec![header];
+
+        for id in ids {
+            // Actions played with some probability, the most likely first
+            let mut actions: Vec<(&String,&F)> = self.policy.get(&id).unwrap().iter()
+                .filter(|(_, prob)| **prob > F::zero())
+                .collect();
+            actions.sort_by(|a, b| b.1.partial_cmp(a.1).unwrap().then(a.0.cmp(b.0)));
+
+            let actions = match actions.as_slice() {
+                [] => "-".to_string(),
+                [(action, _)] => action.to_string(),
+                _ => actions.iter().map(|(action, prob)| format!("{} ({:.2})", action, prob)).collect::<Vec<String>>().join(", "),
+            };
+
+            let mut row = state_cells(id, self.labels);
+            if let Some(values) = self.values {
+                row.push(values.get(&id).map(|value| format!("{:.*}", precision, value)).unwrap_or_default());
+            }
+            row.push(actions);
+            rows.push(row);
+        }
+
+        return write_table(f, &rows)
+    }
+}
+
+impl<F: Real> Agent<F> {
+
+    pub fn value_table(&self) -> ValueTable<'_, F> {
+        return ValueTable::new(self.get_evaluation())
+    }
+
+    pub fn policy_table(&self) -> PolicyTable<'_, F> {
+        return PolicyTable::new(self.get_policy()).with_values(self.get_evaluation())
+    }
+
+    pub fn value_summary(&self) -> ValueSummary<F> {
+        return ValueSummary::new(self.get_evaluation())
+    }
+
+}
+
+impl<F: Real> ValueSummary<F> {
+
+    pub fn new(values: &HashMap<i64,F>) -> ValueSummary<F> {
+        let mut ids: Vec<&i64> = values.keys().collect();
+        ids.sort();
+
+        let mut min: Option<(i64, F)> = None;
+        let mut max: Option<(i64, F)> = None;
+        for id in ids {
+            let value = values[id];
+            if min.is_none_or(|(_, min_value)| value < min_value) {
+                min = Some((*id, value));
+            }
+            if max.is_none_or(|(_, max_value)| value > max_value) {
+                max = Some((*id, value));
+            }
+        }
+
+        let mean = if values.is_empty() {F::zero()} else {helper::compensated_sum(values.values().cloned())/F::from_f64(values.len() as f64)};
+        return ValueSummary {n_states: values.len(), mean, min, max}
+    }
+
+}
+
+impl<F: Real> fmt::Display for ValueSummary<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let precision = f.precision().unwrap_or(DEFAULT_PRECISION);
+        return match (self.min, self.max) {
+            (Some((min_id, min)), Some((max_id, max))) => write!(f, "{} states, mean {:.*}, min {:.*} (state {}), max {:.*} (state {})",
+                self.n_states, precision, self.mean, precision, min, min_id, precision, max, max_id),
+            _ => write!(f, "no states"),
+        }
+    }
+}
+
+impl<F: Real> fmt::Display for StateLink<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let precision = f.precision().unwrap_or(DEFAULT_PRECISION);
+        return write!(f, "{} -> {} with {} (probability {:.*}, reward {:.*})", self.0, self.1, self.2, precision, self.3, precision, self.4)
+    }
+}
+
+// The state and its actions sorted by name, one per line, each with its
+// successors in id order as next_id (probability, reward)
+impl<F: Real> fmt::Display for ModelState<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let precision = f.precision().unwrap_or(DEFAULT_PRECISION);
+
+        write!(f, "state {}", self.get_id())?;
+        if self.get_all_probs().is_empty() {
+            return write!(f, " (no actions)")
+        }
+
+        for action in self.actions() {
+            let successors: Vec<String> = self.transitions()
+                .filter(|transition| transition.action == action)
+                .map(|transition| format!("{} ({:.*}, {:.*})", transition.id_next, precision, transition.prob, precision, transition.reward))
+                .collect();
+            write!(f, "\n  {}: {}", action, successors.join(", "))?;
+        }
+        return Ok(())
+    }
+}
+
+// Most likely action of a state, the first by name among ties
+pub(crate) fn greedy_action<F: Real>(actions: &HashMap<String,F>) -> Option<&String> {
+    return actions.iter()
+        .filter(|(_, prob)| **prob > F::zero())
+        .max_by(|a, b| a.1.partial_cmp(b.1).unwrap().then(b.0.cmp(a.0)))
+        .map(|(action, _)| action)
+}
+
+// Arrow of the usual grid moves, the first letter of other actions
+pub fn action_arrow(action: &str) -> char {
+    return match action.to_lowercase().as_str() {
+        "up" | "north" => '↑',
+        "down" | "south" => '↓',
+        "left" | "west" => '←',
+        "right" | "east" => '→',
+        _ => action.chars().next().unwrap_or('?'),
+    }
+}
+
+// Greedy actions as arrows next to the values, in a grid of n_rows by n_cols
+// cells. coords gives the (row, column) of a state, None for states off the
+// grid. Cells without a state are left blank and states without actions,
+// e.g. terminal ones, are shown with a dot.
+pub fn render_grid<F, C>(agent: &Agent<F>, n_rows: usize, n_cols: usize, coords: C) -> String
+where F: Real, C: Fn(i64) -> Option<(usize, usize)> {
+
+    let mut arrows: Vec<Vec<String>> = vec![vec![" ".to_string(); n_cols]; n_rows];
+    let mut values: Vec<Vec<String>> = vec![vec![String::new(); n_cols]; n_rows];
+
+    for (id, actions) in agent.get_policy() {
+        let (row, col) = match coords(*id) {
+            Some((row, col)) if row < n_rows && col < n_cols => (row, col),
+            _ => continue,
+        };
+
+        arrows[row][col] = match greedy_action(actions) {
+            Some(action) => action_arrow(action).to_string(),
+            None => ".".to_string(),
+        };
+        values[row][col] = agent.get_evaluation().get(id).map(|value| format!("{:.2}", value)).unwrap_or_default();
+    }
+
+    let width = values.iter().flatten().map(|value| value.chars().count()).max().unwrap_or(0);
+
+    return (0..n_rows)
+        .map(|row| {
+            let arrow_row: String = arrows[row].join(" ");
+            let value_row: Vec<String> = values[row].iter().map(|value| format!("{:>width$}", value, width = width)).collect();
+            format!("{}    {}", arrow_row, value_row.join(" ")).trim_end().to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::envs::CliffWalking;
+    use crate::models;
+
+    #[test]
+    fn tables_test() {
+        let links = vec![
+            models::StateLink(0, 2, "Now".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Later".to_string(), 1., 0.),
+            models::StateLink(1, 2, "Take".to_string(), 1., 3.),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        agent.evaluate_policy(1., 1e-9, 100);
+
+        assert_eq!(agent.value_table().to_string(), "state  value\n0      2.0000\n1      3.0000\n2      0.0000\n");
+        assert_eq!(agent.value_table().top(1).to_string(), "state  value\n1      3.0000\n");
+
+        let table = agent.policy_table().to_string();
+        assert_eq!(table.lines().nth(1).unwrap(), "0      2.0000  Later (0.50), Now (0.50)");
+        assert_eq!(table.lines().nth(3).unwrap(), "2      0.0000  -");
+
+        // Precision and labels
+        let labels: HashMap<i64,String> = HashMap::from([(0, "start".to_string()), (2, "end".to_string())]);
+        assert_eq!(format!("{:.1}", agent.value_table().with_labels(&labels)), "state  label  value\n0      start  2.0\n1             3.0\n2      end    0.0\n");
+        assert_eq!(agent.policy_table().with_precision(2).to_string().lines().nth(1).unwrap(), "0      2.00   Later (0.50), Now (0.50)");
+
+        assert_eq!(agent.value_summary().to_string(), "3 states, mean 1.6667, min 0.0000 (state 2), max 3.0000 (state 1)");
+        assert_eq!(ValueSummary::<f64>::new(&HashMap::new()).to_string(), "no states");
+    }
+
+    #[test]
+    fn model_display_test() {
+        let link = models::StateLink(0, 1, "Go".to_string(), 0.5, 2.);
+        assert_eq!(link.to_string(), "0 -> 1 with Go (probability 0.5000, reward 2.0000)");
+        assert_eq!(format!("{:.1}", link), "0 -> 1 with Go (probability 0.5, reward 2.0)");
+
+        let system_state: models::SystemState = models::SystemState::create_and_build(vec![
+            link,
+            models::StateLink(0, 0, "Go".to_string(), 0.5, 0.),
+            models::StateLink(0, 1, "Always".to_string(), 1., 1.),
+        ]);
+        assert_eq!(format!("{:.2}", system_state.get_state(&0).unwrap()), "state 0\n  Always: 1 (1.00, 1.00)\n  Go: 0 (0.50, 0.00), 1 (0.50, 2.00)");
+        assert_eq!(system_state.get_state(&1).unwrap().to_string(), "state 1 (no actions)");
+    }
+
+    #[test]
+    fn grid_test() {
+        let env = CliffWalking::with_size(2, 3).unwrap();
+        let mut agent: Agent = Agent::init_random(env.build());
+        agent.deterministic_policy_improvement(1., 1e-9, 100, 100);
+
+        let (n_rows, n_cols) = env.get_size();
+        let grid = render_grid(&agent, n_rows, n_cols, |id| Some((id as usize/n_cols, id as usize%n_cols)));
+        let lines: Vec<&str> = grid.lines().collect();
+
+        // The cliff cell is not a state, the goal has no actions
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("→ → ↓"));
+        assert!(lines[1].starts_with("↑   ."));
+        assert!(lines[1].ends_with("0.00"));
+    }
+
+}
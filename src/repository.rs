@@ -0,0 +1,291 @@
+// SQLite storage of models and their solutions, enabled with the "sqlite"
+// feature.
+//
+// A repository is a single database file holding models by name, as their
+// links, and for each model any number of named solutions: the policy and
+// values of a solved agent with the settings and time of the solve. Saving
+// under an existing name replaces what was there, and replacing or deleting
+// a model deletes its solutions, which were found for the old links.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+use crate::solver::{Checkpoint, SolverConfig};
+use crate::Agent;
+
+const SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+    CREATE TABLE IF NOT EXISTS models (
+        name TEXT PRIMARY KEY
+    );
+    CREATE TABLE IF NOT EXISTS links (
+        model TEXT NOT NULL REFERENCES models(name) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        prev_state INTEGER NOT NULL,
+        new_state INTEGER NOT NULL,
+        action TEXT NOT NULL,
+        probability REAL NOT NULL,
+        reward REAL NOT NULL,
+        PRIMARY KEY (model, position)
+    );
+    CREATE TABLE IF NOT EXISTS solutions (
+        id INTEGER PRIMARY KEY,
+        model TEXT NOT NULL REFERENCES models(name) ON DELETE CASCADE,
+        name TEXT NOT NULL,
+        gamma REAL NOT NULL,
+        epsilon REAL NOT NULL,
+        policy_iters INTEGER NOT NULL,
+        eval_iters INTEGER NOT NULL,
+        elapsed_secs REAL NOT NULL,
+        saved_at INTEGER NOT NULL,
+        UNIQUE (model, name)
+    );
+    CREATE TABLE IF NOT EXISTS policies (
+        solution INTEGER NOT NULL REFERENCES solutions(id) ON DELETE CASCADE,
+        state INTEGER NOT NULL,
+        action TEXT NOT NULL,
+        probability REAL NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS solution_values (
+        solution INTEGER NOT NULL REFERENCES solutions(id) ON DELETE CASCADE,
+        state INTEGER NOT NULL,
+        value REAL NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS policies_by_solution ON policies (solution);
+    CREATE INDEX IF NOT EXISTS values_by_solution ON solution_values (solution);
+";
+
+// Settings and duration of a solve, kept with its solution
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolveMetadata {
+    pub gamma: f64,
+    pub epsilon: f64,
+    pub policy_iters: u32,
+    pub eval_iters: u32,
+    pub elapsed: Duration,
+    // Seconds since the Unix epoch when the solution was saved, set by the
+    // repository
+    pub saved_at: u64,
+}
+
+impl SolveMetadata {
+
+    pub fn new<F: Real>(gamma: F, config: &SolverConfig<F>, elapsed: Duration) -> SolveMetadata {
+        return SolveMetadata {
+            gamma: gamma.as_f64(),
+            epsilon: config.epsilon.as_f64(),
+            policy_iters: config.policy_iters,
+            eval_iters: config.eval_iters,
+            elapsed,
+            saved_at: 0,
+        }
+    }
+
+}
+
+// A solution as saved, its policy and values in a checkpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredSolution<F: Real = f64> {
+    pub checkpoint: Checkpoint<F>,
+    pub metadata: SolveMetadata,
+}
+
+pub struct ModelRepository {
+    connection: Connection,
+}
+
+impl ModelRepository {
+
+    // Opens the database, creating it and its tables if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> rusqlite::Result<ModelRepository> {
+        return ModelRepository::with_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> rusqlite::Result<ModelRepository> {
+        return ModelRepository::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> rusqlite::Result<ModelRepository> {
+        connection.execute_batch(SCHEMA)?;
+        return Ok(ModelRepository {connection})
+    }
+
+    // Saves the links of the model, replacing the model of the same name and
+    // its solutions
+    pub fn save_model<F: Real>(&mut self, name: &str, system_state: &SystemState<F>) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM models WHERE name = ?1", params![name])?;
+        transaction.execute("INSERT INTO models (name) VALUES (?1)", params![name])?;
+        {
+            let mut insert = transaction.prepare("INSERT INTO links VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)")?;
+            for (position, StateLink(prev_state, new_state, action, prob, reward)) in system_state.get_specification().iter().enumerate() {
+                insert.execute(params![name, position as i64, prev_state, new_state, action, prob.as_f64(), reward.as_f64()])?;
+            }
+        }
+        return transaction.commit()
+    }
+
+    // The model built from its links in their saved order, None if there is
+    // no model of that name
+    pub fn load_model<F: Real>(&self, name: &str) -> rusqlite::Result<Option<SystemState<F>>> {
+        if !self.has_model(name)? {
+            return Ok(None)
+        }
+
+        let mut select = self.connection.prepare("SELECT prev_state, new_state, action, probability, reward FROM links WHERE model = ?1 ORDER BY position")?;
+        let links = select.query_map(params![name], |row| Ok(StateLink(row.get(0)?, row.get(1)?, row.get(2)?, F::from_f64(row.get(3)?), F::from_f64(row.get(4)?))))?
+            .collect::<rusqlite::Result<Vec<StateLink<F>>>>()?;
+        return Ok(Some(SystemState::create_and_build(links)))
+    }
+
+    pub fn has_model(&self, name: &str) -> rusqlite::Result<bool> {
+        return self.connection.query_row("SELECT COUNT(*) FROM models WHERE name = ?1", params![name], |row| row.get::<_, i64>(0))
+            .map(|count| count > 0)
+    }
+
+    // Sorted names of the models
+    pub fn model_names(&self) -> rusqlite::Result<Vec<String>> {
+        let mut select = self.connection.prepare("SELECT name FROM models ORDER BY name")?;
+        return select.query_map([], |row| row.get(0))?.collect()
+    }
+
+    // Deletes the model and its solutions, false if there was no such model
+    pub fn delete_model(&mut self, name: &str) -> rusqlite::Result<bool> {
+        return self.connection.execute("DELETE FROM models WHERE name = ?1", params![name]).map(|n_deleted| n_deleted > 0)
+    }
+
+    // Saves the policy and values of the agent as a solution of a saved
+    // model, replacing the solution of the same name. The time of saving
+    // replaces the one of the metadata.
+    pub fn save_solution<F: Real>(&mut self, model: &str, name: &str, agent: &Agent<F>, metadata: &SolveMetadata) -> rusqlite::Result<()> {
+        let saved_at = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM solutions WHERE model = ?1 AND name = ?2", params![model, name])?;
+        transaction.execute(
+            "INSERT INTO solutions (model, name, gamma, epsilon, policy_iters, eval_iters, elapsed_secs, saved_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![model, name, metadata.gamma, metadata.epsilon, metadata.policy_iters, metadata.eval_iters, metadata.elapsed.as_secs_f64(), saved_at as i64],
+        )?;
+        let solution = transaction.last_insert_rowid();
+        {
+            let mut insert_policy = transaction.prepare("INSERT INTO policies VALUES (?1, ?2, ?3, ?4)")?;
+            for (id, actions) in agent.get_policy() {
+                for (action, prob) in actions {
+                    insert_policy.execute(params![solution, id, action, prob.as_f64()])?;
+                }
+            }
+            let mut insert_value = transaction.prepare("INSERT INTO solution_values VALUES (?1, ?2, ?3)")?;
+            for (id, value) in agent.get_evaluation() {
+                insert_value.execute(params![solution, id, value.as_f64()])?;
+            }
+        }
+        return transaction.commit()
+    }
+
+    // None if the model has no solution of that name
+    pub fn load_solution<F: Real>(&self, model: &str, name: &str) -> rusqlite::Result<Option<StoredSolution<F>>> {
+        let found = self.connection.query_row(
+            "SELECT id, gamma, epsilon, policy_iters, eval_iters, elapsed_secs, saved_at FROM solutions WHERE model = ?1 AND name = ?2",
+            params![model, name],
+            |row| Ok((row.get::<_, i64>(0)?, SolveMetadata {
+                gamma: row.get(1)?,
+                epsilon: row.get(2)?,
+                policy_iters: row.get(3)?,
+                eval_iters: row.get(4)?,
+                elapsed: Duration::from_secs_f64(row.get(5)?),
+                saved_at: row.get::<_, i64>(6)? as u64,
+            })),
+        ).optional()?;
+        let (solution, metadata) = match found {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+
+        // States without actions have an empty policy
+        let mut values: HashMap<i64,F> = HashMap::new();
+        let mut select = self.connection.prepare("SELECT state, value FROM solution_values WHERE solution = ?1")?;
+        for row in select.query_map(params![solution], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?)))? {
+            let (id, value) = row?;
+            values.insert(id, F::from_f64(value));
+        }
+        let mut policy: HashMap<i64,HashMap<String,F>> = values.keys().map(|id| (*id, HashMap::new())).collect();
+        let mut select = self.connection.prepare("SELECT state, action, probability FROM policies WHERE solution = ?1")?;
+        for row in select.query_map(params![solution], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?, row.get::<_, f64>(2)?)))? {
+            let (id, action, prob) = row?;
+            policy.entry(id).or_default().insert(action, F::from_f64(prob));
+        }
+
+        return Ok(Some(StoredSolution {checkpoint: Checkpoint {policy, values}, metadata}))
+    }
+
+    // Agent of the saved model with the policy and values of the solution,
+    // None if either is missing
+    pub fn load_agent<F: Real>(&self, model: &str, solution: &str) -> rusqlite::Result<Option<Agent<F>>> {
+        let (system_state, stored) = match (self.load_model(model)?, self.load_solution(model, solution)?) {
+            (Some(system_state), Some(stored)) => (system_state, stored),
+            _ => return Ok(None),
+        };
+        let mut agent = Agent::init_random(system_state);
+        agent.restore(&stored.checkpoint);
+        return Ok(Some(agent))
+    }
+
+    // Sorted names of the solutions of a model
+    pub fn solution_names(&self, model: &str) -> rusqlite::Result<Vec<String>> {
+        let mut select = self.connection.prepare("SELECT name FROM solutions WHERE model = ?1 ORDER BY name")?;
+        return select.query_map(params![model], |row| row.get(0))?.collect()
+    }
+
+    // False if the model had no such solution
+    pub fn delete_solution(&mut self, model: &str, name: &str) -> rusqlite::Result<bool> {
+        return self.connection.execute("DELETE FROM solutions WHERE model = ?1 AND name = ?2", params![model, name]).map(|n_deleted| n_deleted > 0)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn repository_test() {
+        let links = vec![
+            StateLink(0, 1, "Go".to_string(), 1., 1.),
+            StateLink(0, 0, "Stay".to_string(), 1., 0.5),
+            StateLink(1, 1, "Stay".to_string(), 1., 0.),
+        ];
+        let system_state: SystemState = SystemState::create_and_build(links);
+        let mut repository = ModelRepository::open_in_memory().unwrap();
+        repository.save_model("loop", &system_state).unwrap();
+        assert_eq!(repository.load_model::<f64>("loop").unwrap().unwrap(), system_state);
+        assert_eq!(repository.load_model::<f64>("other").unwrap(), None);
+
+        let mut agent: Agent = Agent::init_random(system_state.clone());
+        let config = SolverConfig::new().with_epsilon(1e-9);
+        agent.solve(0.9, &config);
+        let metadata = SolveMetadata::new(0.9, &config, Duration::from_millis(20));
+        repository.save_solution("loop", "gamma_0.9", &agent, &metadata).unwrap();
+        assert!(repository.save_solution("missing", "gamma_0.9", &agent, &metadata).is_err());
+
+        let stored = repository.load_solution::<f64>("loop", "gamma_0.9").unwrap().unwrap();
+        assert_eq!(stored.checkpoint, agent.checkpoint());
+        assert_eq!((stored.metadata.gamma, stored.metadata.elapsed), (0.9, Duration::from_millis(20)));
+        assert!(stored.metadata.saved_at > 0);
+        assert!(repository.load_agent::<f64>("loop", "gamma_0.9").unwrap().unwrap() == agent);
+        assert_eq!(repository.solution_names("loop").unwrap(), vec!["gamma_0.9"]);
+
+        // Replacing the model drops its solutions
+        repository.save_model("loop", &system_state).unwrap();
+        assert!(repository.solution_names("loop").unwrap().is_empty());
+        assert_eq!(repository.model_names().unwrap(), vec!["loop"]);
+        assert!(repository.delete_model("loop").unwrap());
+        assert!(repository.model_names().unwrap().is_empty());
+    }
+
+}
@@ -0,0 +1,88 @@
+// One seed for a whole experiment.
+//
+// Every stochastic part of the crate takes its own seed or rng: the random
+// initial policies (PolicyInit::Random), random sweep orders
+// (SweepStrategy::RandomOrder, or SolverConfig::seed), the simulator
+// (Simulator::with_seed or with_rng), the environments and the model
+// generators (e.g. Garnet::seed). A SeedSequence derives the seeds of all of
+// them from a single one, a different stream per named part, so that an
+// experiment is reproduced from that seed alone and changing how one part
+// draws leaves the others as they were.
+//
+// Derived seeds only depend on the seed and the stream name, on every
+// platform. The rngs built from them are rand's StdRng, whose algorithm may
+// change with the version of rand.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SeedSequence {
+    seed: u64,
+}
+
+// SplitMix64 finalizer, spreading close inputs over the whole range
+fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    return z ^ (z >> 31)
+}
+
+// FNV-1a hash of a stream name
+fn hash_name(name: &str) -> u64 {
+    return name.bytes().fold(0xCBF29CE484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001B3))
+}
+
+impl SeedSequence {
+
+    pub fn new(seed: u64) -> SeedSequence {
+        return SeedSequence {seed}
+    }
+
+    pub fn get_seed(&self) -> u64 {
+        return self.seed
+    }
+
+    // Seed of the named stream, e.g. "garnet", "simulator" or "environment"
+    pub fn derive(&self, stream: &str) -> u64 {
+        return mix(self.seed ^ mix(hash_name(stream)))
+    }
+
+    pub fn rng(&self, stream: &str) -> StdRng {
+        return StdRng::seed_from_u64(self.derive(stream))
+    }
+
+    // Sequence of one run among several of the same experiment, e.g. one per
+    // repetition, independent of the others and of this one
+    pub fn fork(&self, index: u64) -> SeedSequence {
+        return SeedSequence::new(mix(mix(self.seed) ^ mix(index.wrapping_add(1))))
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn seed_sequence_test() {
+        let seeds = SeedSequence::new(42);
+        assert_eq!(seeds.derive("simulator"), SeedSequence::new(42).derive("simulator"));
+        assert_ne!(seeds.derive("simulator"), seeds.derive("environment"));
+        assert_ne!(seeds.derive("simulator"), SeedSequence::new(43).derive("simulator"));
+
+        // Derived seeds are fixed once and for all
+        assert_eq!(mix(0), 0xE220A8397B1DCDAF);
+        assert_eq!(hash_name(""), 0xCBF29CE484222325);
+
+        let draws = |seeds: SeedSequence| -> Vec<u32> {seeds.rng("garnet").random_iter().take(5).collect()};
+        assert_eq!(draws(seeds), draws(seeds));
+        assert_ne!(draws(seeds.fork(0)), draws(seeds.fork(1)));
+        assert_ne!(seeds.fork(0), seeds);
+    }
+
+}
@@ -0,0 +1,611 @@
+// Lightweight HTTP solver service, enabled with the "http" feature.
+//
+// Endpoints (request and response bodies are JSON):
+//   POST   /models               upload a list of links, returns {"model": id}
+//   DELETE /models/{id}          drop an uploaded model
+//   POST   /models/{id}/solve    launch a solve job, returns {"job": id}
+//   GET    /jobs/{id}            status, elapsed time and progress of a job
+//   POST   /jobs/{id}/cancel     stop a queued or running job
+//   GET    /jobs/{id}/policy     policy found by a finished job
+//   GET    /jobs/{id}/values     state values found by a finished job
+//
+// Links are uploaded as arrays [prev_state, new_state, action, probability, reward].
+// Solves run on a fixed pool of worker threads, so requests are answered while
+// jobs are running, and jobs beyond the workers and their queue are refused
+// with 503. Request bodies are capped in size, and only the latest finished
+// jobs and the most recently used models are kept.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::progress::{CancellationToken, Progress, ProgressObserver, SharedProgress};
+use crate::{models, Agent, Error, SolverConfig};
+
+const MAX_BODY_BYTES: u64 = 64 << 20;
+const MAX_FINISHED_JOBS: usize = 1000;
+const MAX_MODELS: usize = 100;
+const MAX_QUEUED_JOBS: usize = 64;
+const MAX_POLICY_ITERS: u32 = 1000;
+const MAX_EVAL_ITERS: u32 = 100_000;
+
+// Parameters of a solve job, missing fields take the default values
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SolveRequest {
+    pub gamma: f64,
+    pub epsilon: f64,
+    pub policy_iters: u32,
+    pub eval_iters: u32,
+    // Sweeps without delta shrinking after which the job fails as divergent,
+    // None for the number of states plus one
+    pub divergence_window: Option<u32>,
+}
+
+impl Default for SolveRequest {
+    fn default() -> SolveRequest {
+        return SolveRequest {gamma: 1., epsilon: 0.01, policy_iters: 100, eval_iters: 100, divergence_window: None}
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        return !matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+struct Job {
+    model_id: u64,
+    status: JobStatus,
+    started: Instant,
+    elapsed: Option<Duration>,
+    agent: Option<Agent>,
+    // Why a failed job failed
+    error: Option<String>,
+    // Last sweep of the solve, and the number of sweeps so far
+    progress: SharedProgress,
+    sweeps: Arc<AtomicU64>,
+    cancellation: CancellationToken,
+}
+
+// An uploaded model, with the tick of its last upload or solve
+struct StoredModel {
+    links: Arc<Vec<models::StateLink>>,
+    last_used: u64,
+}
+
+type Task = Box<dyn FnOnce() + Send>;
+
+pub struct SolverService {
+    models: Mutex<HashMap<u64,StoredModel>>,
+    jobs: Mutex<HashMap<u64,Arc<Mutex<Job>>>>,
+    next_id: AtomicU64,
+    // Ticks of the model uses, the least recently used model being dropped first
+    uses: AtomicU64,
+    // Queue of the worker threads, started with the first job
+    workers: OnceLock<SyncSender<Task>>,
+    max_body_bytes: u64,
+    max_finished_jobs: usize,
+    max_models: usize,
+    n_workers: usize,
+    max_queued_jobs: usize,
+    max_policy_iters: u32,
+    max_eval_iters: u32,
+}
+
+impl Default for SolverService {
+    fn default() -> SolverService {
+        SolverService::new()
+    }
+}
+
+impl SolverService {
+
+    pub fn new() -> SolverService {
+        return SolverService {
+            models: Mutex::new(HashMap::new()),
+            jobs: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+            uses: AtomicU64::new(0),
+            workers: OnceLock::new(),
+            max_body_bytes: MAX_BODY_BYTES,
+            max_finished_jobs: MAX_FINISHED_JOBS,
+            max_models: MAX_MODELS,
+            n_workers: thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            max_queued_jobs: MAX_QUEUED_JOBS,
+            max_policy_iters: MAX_POLICY_ITERS,
+            max_eval_iters: MAX_EVAL_ITERS,
+        }
+    }
+
+    // Larger request bodies are refused with 413, 64 MiB by default
+    pub fn with_max_body_bytes(mut self, max_body_bytes: u64) -> SolverService {
+        self.max_body_bytes = max_body_bytes;
+        return self
+    }
+
+    // Finished jobs kept, the oldest being dropped first, 1000 by default
+    pub fn with_max_finished_jobs(mut self, max_finished_jobs: usize) -> SolverService {
+        self.max_finished_jobs = max_finished_jobs;
+        return self
+    }
+
+    // Models kept, the least recently uploaded or solved being dropped first,
+    // 100 by default. Jobs already launched on a dropped model carry on.
+    pub fn with_max_models(mut self, max_models: usize) -> SolverService {
+        self.max_models = max_models.max(1);
+        return self
+    }
+
+    // Jobs solved at the same time, one per available core by default
+    pub fn with_workers(mut self, n_workers: usize) -> SolverService {
+        self.n_workers = n_workers.max(1);
+        return self
+    }
+
+    // Jobs waiting for a worker, 64 by default. Further jobs are refused with
+    // 503 until some finish.
+    pub fn with_max_queued_jobs(mut self, max_queued_jobs: usize) -> SolverService {
+        self.max_queued_jobs = max_queued_jobs;
+        return self
+    }
+
+    // Largest policy_iters and eval_iters of a solve, 1000 and 100000 by default
+    pub fn with_max_iters(mut self, max_policy_iters: u32, max_eval_iters: u32) -> SolverService {
+        self.max_policy_iters = max_policy_iters;
+        self.max_eval_iters = max_eval_iters;
+        return self
+    }
+
+    // Serves requests on the given address (e.g. "0.0.0.0:8080") until the process stops
+    pub fn serve(self: Arc<Self>, addr: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let server = tiny_http::Server::http(addr)?;
+        let content_type = tiny_http::Header::from_bytes("Content-Type", "application/json").unwrap();
+
+        for mut request in server.incoming_requests() {
+            let declared = request.body_length().unwrap_or(0) as u64;
+            let (code, response) = match read_body(request.as_reader(), declared, self.max_body_bytes) {
+                Ok(body) => self.handle(request.method().as_str(), request.url(), &body),
+                Err(error) => error,
+            };
+
+            let response = tiny_http::Response::from_string(response.to_string())
+                .with_status_code(code)
+                .with_header(content_type.clone());
+
+            // A client hanging up is not a reason to stop serving
+            let _ = request.respond(response);
+        }
+
+        return Ok(())
+    }
+
+    // Routes a request and returns the status code and JSON response
+    pub fn handle(&self, method: &str, url: &str, body: &str) -> (u16, Value) {
+        let path = url.split('?').next().unwrap_or("");
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+        match (method, segments.as_slice()) {
+            ("POST", ["models"]) => self.upload_model(body),
+            ("DELETE", ["models", id]) => match id.parse().ok().and_then(|id: u64| self.models.lock().unwrap().remove(&id).map(|_| id)) {
+                Some(id) => (200, json!({"deleted": id})),
+                None => not_found("model"),
+            },
+            ("POST", ["models", id, "solve"]) => match id.parse() {
+                Ok(id) => self.launch_job(id, body),
+                Err(_) => not_found("model"),
+            },
+            ("GET", ["jobs", id]) => self.with_job(id, |job| (200, job_status(job))),
+            // A queued job is cancelled at once, a running one at its next sweep
+            ("POST", ["jobs", id, "cancel"]) => self.with_job(id, |job| {
+                if job.status.is_finished() {
+                    return unfinished(job)
+                }
+                job.cancellation.cancel();
+                if job.status == JobStatus::Queued {
+                    job.status = JobStatus::Cancelled;
+                    job.elapsed = Some(job.started.elapsed());
+                }
+                (202, job_status(job))
+            }),
+            ("GET", ["jobs", id, "policy"]) => self.with_job(id, |job| match &job.agent {
+                Some(agent) => (200, json!(agent.get_policy())),
+                None => unfinished(job),
+            }),
+            ("GET", ["jobs", id, "values"]) => self.with_job(id, |job| match &job.agent {
+                Some(agent) => (200, json!(agent.get_evaluation())),
+                None => unfinished(job),
+            }),
+            _ => (404, json!({"error": "unknown endpoint"})),
+        }
+    }
+
+    fn upload_model(&self, body: &str) -> (u16, Value) {
+        let links: Vec<models::StateLink> = match serde_json::from_str(body) {
+            Ok(links) => links,
+            Err(error) => return (400, json!({"error": format!("invalid links: {}", error)})),
+        };
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut models = self.models.lock().unwrap();
+        while models.len() >= self.max_models {
+            let oldest = models.iter().min_by_key(|(_, model)| model.last_used).map(|(id, _)| *id).unwrap();
+            models.remove(&oldest);
+        }
+        models.insert(id, StoredModel {links: Arc::new(links), last_used: self.uses.fetch_add(1, Ordering::Relaxed)});
+
+        return (201, json!({"model": id}))
+    }
+
+    fn launch_job(&self, model_id: u64, body: &str) -> (u16, Value) {
+        let links = match self.models.lock().unwrap().get_mut(&model_id) {
+            Some(model) => {
+                model.last_used = self.uses.fetch_add(1, Ordering::Relaxed);
+                model.links.clone()
+            },
+            None => return not_found("model"),
+        };
+
+        let params: SolveRequest = if body.trim().is_empty() {
+            SolveRequest::default()
+        } else {
+            match serde_json::from_str(body) {
+                Ok(params) => params,
+                Err(error) => return (400, json!({"error": format!("invalid solve parameters: {}", error)})),
+            }
+        };
+        if !(0. ..=1.).contains(&params.gamma) {
+            return (400, json!({"error": format!("gamma must be in [0, 1], got {}", params.gamma)}))
+        }
+        if params.epsilon.is_nan() || params.epsilon <= 0. {
+            return (400, json!({"error": format!("epsilon must be positive, got {}", params.epsilon)}))
+        }
+        if params.policy_iters > self.max_policy_iters {
+            return (400, json!({"error": format!("policy_iters must be at most {}, got {}", self.max_policy_iters, params.policy_iters)}))
+        }
+        if params.eval_iters > self.max_eval_iters {
+            return (400, json!({"error": format!("eval_iters must be at most {}, got {}", self.max_eval_iters, params.eval_iters)}))
+        }
+
+        let mut config = SolverConfig::new()
+            .with_epsilon(params.epsilon)
+            .with_policy_iters(params.policy_iters)
+            .with_eval_iters(params.eval_iters)
+            .with_cancellation(CancellationToken::new());
+        config.divergence_window = params.divergence_window;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let progress = SharedProgress::new();
+        let sweeps = Arc::new(AtomicU64::new(0));
+        let job = Arc::new(Mutex::new(Job {
+            model_id,
+            status: JobStatus::Queued,
+            started: Instant::now(),
+            elapsed: None,
+            agent: None,
+            error: None,
+            progress: progress.clone(),
+            sweeps: sweeps.clone(),
+            cancellation: config.cancellation.clone().unwrap(),
+        }));
+        let mut jobs = self.jobs.lock().unwrap();
+        evict_finished(&mut jobs, self.max_finished_jobs);
+        jobs.insert(id, job.clone());
+        drop(jobs);
+
+        let task = {
+            let job = job.clone();
+            Box::new(move || run_job(&job, &links, params.gamma, &config, progress, &sweeps))
+        };
+        if self.submit(task).is_err() {
+            self.jobs.lock().unwrap().remove(&id);
+            return (503, json!({"error": "too many jobs, retry once some have finished"}))
+        }
+
+        return (202, json!({"job": id}))
+    }
+
+    // Queues a task for the workers, started on the first call. Fails when
+    // the queue is full.
+    fn submit(&self, task: Task) -> Result<(), mpsc::TrySendError<Task>> {
+        let sender = self.workers.get_or_init(|| {
+            let (sender, receiver) = mpsc::sync_channel::<Task>(self.max_queued_jobs);
+            let receiver = Arc::new(Mutex::new(receiver));
+            for _ in 0..self.n_workers {
+                let receiver = receiver.clone();
+                // Workers stop once the service, and with it the sender, is dropped
+                thread::spawn(move || loop {
+                    let task = receiver.lock().unwrap().recv();
+                    match task {
+                        Ok(task) => task(),
+                        Err(_) => return,
+                    }
+                });
+            }
+            sender
+        });
+        return sender.try_send(task)
+    }
+
+    fn with_job<F: FnOnce(&mut Job) -> (u16, Value)>(&self, id: &str, f: F) -> (u16, Value) {
+        let job = match id.parse().ok().and_then(|id: u64| self.jobs.lock().unwrap().get(&id).cloned()) {
+            Some(job) => job,
+            None => return not_found("job"),
+        };
+
+        let mut job = job.lock().unwrap();
+        return f(&mut job)
+    }
+
+}
+
+// Solves a job on a worker thread, unless it was cancelled while queued
+fn run_job(job: &Mutex<Job>, links: &[models::StateLink], gamma: f64, config: &SolverConfig, progress: SharedProgress, sweeps: &AtomicU64) {
+    {
+        let mut job = job.lock().unwrap();
+        if job.cancellation.is_cancelled() {
+            job.status = JobStatus::Cancelled;
+            job.elapsed = Some(job.started.elapsed());
+            return
+        }
+        job.status = JobStatus::Running;
+        job.started = Instant::now();
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let system_state = models::SystemState::create_and_build(links.to_vec());
+        let mut agent = Agent::init_random(system_state);
+        let mut progress = progress;
+        let mut observer = |report: &Progress| {
+            sweeps.fetch_add(1, Ordering::Relaxed);
+            progress.on_progress(report);
+        };
+        agent.try_solve_with_progress(gamma, config, &mut observer).map(|_| agent)
+    }));
+
+    let mut job = job.lock().unwrap();
+    job.elapsed = Some(job.started.elapsed());
+    match result {
+        Ok(Ok(agent)) => {
+            job.status = JobStatus::Done;
+            job.agent = Some(agent);
+        },
+        Ok(Err(Error::Cancelled {..})) => job.status = JobStatus::Cancelled,
+        Ok(Err(error)) => {
+            job.status = JobStatus::Failed;
+            job.error = Some(error.to_string());
+        },
+        Err(_) => {
+            job.status = JobStatus::Failed;
+            job.error = Some("the solve panicked".to_string());
+        },
+    }
+}
+
+// Reads a body of at most max_bytes bytes, or the status code and error to answer
+fn read_body<R: Read>(reader: R, declared: u64, max_bytes: u64) -> Result<String, (u16, Value)> {
+    let too_large = (413, json!({"error": format!("request body is larger than {} bytes", max_bytes)}));
+    if declared > max_bytes {
+        return Err(too_large)
+    }
+    let mut body = String::new();
+    match reader.take(max_bytes + 1).read_to_string(&mut body) {
+        Ok(read) if read as u64 > max_bytes => return Err(too_large),
+        Ok(_) => (),
+        Err(_) => return Err((400, json!({"error": "request body is not valid UTF-8"}))),
+    }
+    return Ok(body)
+}
+
+// Drops the oldest finished jobs beyond max_finished
+fn evict_finished(jobs: &mut HashMap<u64,Arc<Mutex<Job>>>, max_finished: usize) {
+    let mut finished: Vec<u64> = jobs.iter()
+        .filter(|(_, job)| job.lock().unwrap().status.is_finished())
+        .map(|(id, _)| *id)
+        .collect();
+    if finished.len() <= max_finished {
+        return
+    }
+    finished.sort();
+    for id in &finished[..finished.len() - max_finished] {
+        jobs.remove(id);
+    }
+}
+
+fn job_status(job: &Job) -> Value {
+    let elapsed = job.elapsed.unwrap_or_else(|| job.started.elapsed());
+    return json!({
+        "model": job.model_id,
+        "status": job.status.as_str(),
+        "elapsed_ms": elapsed.as_millis() as u64,
+        "error": job.error,
+        "progress": job.progress.latest().map(|progress| progress_status(&progress, job.sweeps.load(Ordering::Relaxed))),
+    })
+}
+
+// Last sweep of a job. The time left in the current evaluation is its
+// estimated sweeps left at the mean duration of the sweeps so far.
+fn progress_status(progress: &Progress, sweeps: u64) -> Value {
+    let eta_ms = progress.estimated_remaining
+        .map(|remaining| (progress.elapsed.as_secs_f64()*1000.*remaining as f64/sweeps.max(1) as f64) as u64);
+    return json!({
+        "policy_iteration": progress.policy_iteration,
+        "sweep": progress.sweep,
+        "delta": progress.delta,
+        "estimated_remaining_sweeps": progress.estimated_remaining,
+        "eta_ms": eta_ms,
+    })
+}
+
+fn unfinished(job: &Job) -> (u16, Value) {
+    return (409, json!({"error": format!("job is {}", job.status.as_str())}))
+}
+
+fn not_found(what: &str) -> (u16, Value) {
+    return (404, json!({"error": format!("{} not found", what)}))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn wait_for(service: &SolverService, job: &Value) -> Value {
+        let url = format!("/jobs/{}", job["job"]);
+        loop {
+            let (code, status) = service.handle("GET", &url, "");
+            assert_eq!(code, 200);
+            if status["status"] != "queued" && status["status"] != "running" {
+                return status
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn service_solve_test() {
+        let service = SolverService::new();
+        let links = r#"[[0, 1, "Arm_1", 1.0, 1.0], [0, 1, "Arm_2", 1.0, 2.0], [0, 1, "Arm_3", 1.0, 3.0]]"#;
+
+        let (code, model) = service.handle("POST", "/models", links);
+        assert_eq!(code, 201);
+
+        let url = format!("/models/{}/solve", model["model"]);
+        let (code, job) = service.handle("POST", &url, r#"{"gamma": 1.0, "epsilon": 0.01}"#);
+        assert_eq!(code, 202);
+
+        let status = wait_for(&service, &job);
+        assert_eq!(status["status"], "done");
+        assert!(status["progress"]["sweep"].as_u64().unwrap() >= 1);
+        assert_eq!(status["progress"]["estimated_remaining_sweeps"], 0);
+        assert_eq!(status["progress"]["eta_ms"], 0);
+
+        let (code, values) = service.handle("GET", &format!("/jobs/{}/values", job["job"]), "");
+        assert_eq!(code, 200);
+        assert!((values["0"].as_f64().unwrap() - 3.).abs() < 0.02);
+
+        let (code, policy) = service.handle("GET", &format!("/jobs/{}/policy", job["job"]), "");
+        assert_eq!(code, 200);
+        assert_eq!(policy["0"]["Arm_3"], 1.);
+    }
+
+    #[test]
+    fn service_error_test() {
+        let service = SolverService::new();
+
+        assert_eq!(service.handle("POST", "/models", "not json").0, 400);
+        assert_eq!(service.handle("POST", "/models/7/solve", "").0, 404);
+        assert_eq!(service.handle("GET", "/jobs/7", "").0, 404);
+        assert_eq!(service.handle("DELETE", "/models", "").0, 404);
+
+        let (_, model) = service.handle("POST", "/models", "[]");
+        let url = format!("/models/{}/solve", model["model"]);
+        assert_eq!(service.handle("POST", &url, r#"{"gamma": "high"}"#).0, 400);
+        assert_eq!(service.handle("POST", &url, r#"{"gamma": 1.5}"#).0, 400);
+        assert_eq!(service.handle("POST", &url, r#"{"epsilon": 0.0}"#).0, 400);
+        assert_eq!(service.handle("POST", &url, r#"{"policy_iters": 1001}"#).0, 400);
+        assert_eq!(service.handle("POST", &url, r#"{"eval_iters": 100001}"#).0, 400);
+        assert_eq!(service.handle("POST", "/jobs/7/cancel", "").0, 404);
+
+        // Divergent evaluations fail the job rather than running to the end
+        let (_, model) = service.handle("POST", "/models", r#"[[0, 0, "Loop", 1.0, 1.0]]"#);
+        let url = format!("/models/{}/solve", model["model"]);
+        let (_, job) = service.handle("POST", &url, r#"{"eval_iters": 100000, "divergence_window": 10}"#);
+        let status = wait_for(&service, &job);
+        assert_eq!(status["status"], "failed");
+        assert!(status["error"].as_str().unwrap().contains("diverged"));
+
+        assert_eq!(read_body("[]".as_bytes(), 2, 2), Ok("[]".to_string()));
+        assert_eq!(read_body("[[]]".as_bytes(), 0, 2).unwrap_err().0, 413);
+        assert_eq!(read_body("".as_bytes(), 3, 2).unwrap_err().0, 413);
+    }
+
+    #[test]
+    fn service_eviction_test() {
+        let service = SolverService::new().with_max_finished_jobs(2);
+        let (_, model) = service.handle("POST", "/models", r#"[[0, 1, "Go", 1.0, 1.0]]"#);
+        let url = format!("/models/{}/solve", model["model"]);
+
+        let jobs: Vec<Value> = (0..4).map(|_| {
+            let (_, job) = service.handle("POST", &url, "");
+            wait_for(&service, &job);
+            job
+        }).collect();
+
+        // Launching the fourth job dropped the first one
+        let codes: Vec<u16> = jobs.iter().map(|job| service.handle("GET", &format!("/jobs/{}", job["job"]), "").0).collect();
+        assert_eq!(codes, vec![404, 200, 200, 200]);
+    }
+
+    #[test]
+    fn service_models_test() {
+        let service = SolverService::new().with_max_models(2);
+        let upload = || service.handle("POST", "/models", r#"[[0, 1, "Go", 1.0, 1.0]]"#).1["model"].as_u64().unwrap();
+        let solve = |model: u64| service.handle("POST", &format!("/models/{}/solve", model), "");
+
+        let first = upload();
+        let second = upload();
+        let (_, job) = solve(first);
+        wait_for(&service, &job);
+
+        // The second model was used least recently
+        let third = upload();
+        assert_eq!(solve(second).0, 404);
+        assert_eq!(solve(third).0, 202);
+
+        assert_eq!(service.handle("DELETE", &format!("/models/{}", first), "").0, 200);
+        assert_eq!(service.handle("DELETE", &format!("/models/{}", first), "").0, 404);
+        assert_eq!(solve(first).0, 404);
+    }
+
+    #[test]
+    fn service_workers_test() {
+        let service = SolverService::new().with_workers(1).with_max_queued_jobs(1);
+
+        // Self-loops converging slowly enough to keep the worker busy
+        let links: Vec<models::StateLink> = (0..2000).map(|id| models::StateLink(id, id, "Stay".to_string(), 1., 1.)).collect();
+        let (_, model) = service.handle("POST", "/models", &serde_json::to_string(&links).unwrap());
+        let url = format!("/models/{}/solve", model["model"]);
+        let body = r#"{"gamma": 0.999999, "epsilon": 1e-12, "eval_iters": 100000}"#;
+
+        // One job running and one queued at most
+        let launched: Vec<(u16, Value)> = (0..3).map(|_| service.handle("POST", &url, body)).collect();
+        assert_eq!(launched[0].0, 202);
+        assert!(launched.iter().any(|(code, _)| *code == 503));
+
+        for (_, job) in launched.iter().filter(|(code, _)| *code == 202) {
+            assert_eq!(service.handle("POST", &format!("/jobs/{}/cancel", job["job"]), "").0, 202);
+            assert_eq!(wait_for(&service, job)["status"], "cancelled");
+            assert_eq!(service.handle("POST", &format!("/jobs/{}/cancel", job["job"]), "").0, 409);
+            assert_eq!(service.handle("GET", &format!("/jobs/{}/values", job["job"]), "").0, 409);
+        }
+    }
+
+}
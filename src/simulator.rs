@@ -0,0 +1,254 @@
+// Rollouts of a policy in an environment.
+//
+// The simulator draws the policy's actions and runs complete episodes, which
+// gives an empirical check of a solved policy. Environments built from a
+// SystemState are available through envs::ModelEnvironment.
+
+use std::collections::HashMap;
+
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+
+use crate::envs::Environment;
+use crate::episode::{Episode, EpisodeBatch, Transition};
+use crate::numeric::Real;
+use crate::{helper, Agent};
+
+// Chooses actions during rollouts, None ends the episode early
+pub trait RolloutPolicy<S, A> {
+    fn choose(&self, state: &S, legal_actions: &[A], rng: &mut dyn RngCore) -> Option<A>;
+}
+
+// Closures receiving the state, its legal actions and the simulator's rng
+impl<S, A, P: Fn(&S, &[A], &mut dyn RngCore) -> Option<A>> RolloutPolicy<S, A> for P {
+    fn choose(&self, state: &S, legal_actions: &[A], rng: &mut dyn RngCore) -> Option<A> {
+        return self(state, legal_actions, rng)
+    }
+}
+
+// Stochastic policies as stored by Agent, actions are drawn by probability
+impl<F: Real> RolloutPolicy<i64, String> for HashMap<i64,HashMap<String,F>> {
+    fn choose(&self, state: &i64, _legal_actions: &[String], rng: &mut dyn RngCore) -> Option<String> {
+        return self.get(state)
+            .and_then(|actions| helper::sample_weighted(actions, rng))
+            .cloned()
+    }
+}
+
+// Drawn over the position-indexed policy, whose actions are sorted by name,
+// so seeded rollouts repeat
+impl<F: Real> RolloutPolicy<i64, String> for Agent<F> {
+    fn choose(&self, state: &i64, _legal_actions: &[String], rng: &mut dyn RngCore) -> Option<String> {
+        let model = self.system_state.get_dense();
+        let pos = model.get_position(state)?;
+        let action = helper::sample_index(self.policy[pos].iter().copied(), rng)?;
+        return Some(model.get_actions(pos)[action].clone())
+    }
+}
+
+// Monte Carlo estimate of the discounted return from the start state
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReturnEstimate {
+    pub n_episodes: usize,
+    pub mean: f64,
+    pub std_error: f64,
+    // Bounds of the 95% confidence interval, from the normal approximation
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl ReturnEstimate {
+
+    // Whether a value, e.g. from Agent::evaluate_policy, is inside the confidence interval
+    pub fn contains(&self, value: f64) -> bool {
+        return self.lower <= value && value <= self.upper
+    }
+
+}
+
+// Two-sided 95% quantile of the standard normal distribution
+pub(crate) const Z_95: f64 = 1.959963984540054;
+
+pub struct Simulator<E: Environment, R: Rng = StdRng> {
+    env: E,
+    rng: R,
+    max_steps: usize,
+}
+
+impl<E: Environment> Simulator<E, StdRng> {
+
+    // Seeded from the operating system, so runs can't be reproduced. Use
+    // with_seed, e.g. with a seed from a seed::SeedSequence, for that.
+    pub fn new(env: E) -> Simulator<E, StdRng> {
+        return Simulator::with_rng(env, StdRng::from_os_rng())
+    }
+
+    pub fn with_seed(env: E, seed: u64) -> Simulator<E, StdRng> {
+        return Simulator::with_rng(env, StdRng::seed_from_u64(seed))
+    }
+
+}
+
+impl<E: Environment, R: Rng> Simulator<E, R> {
+
+    pub fn with_rng(env: E, rng: R) -> Simulator<E, R> {
+        return Simulator {env, rng, max_steps: 10_000}
+    }
+
+    // Episodes longer than this are truncated
+    pub fn set_max_steps(&mut self, max_steps: usize) {
+        self.max_steps = max_steps;
+    }
+
+    pub fn get_env(&self) -> &E {
+        return &self.env
+    }
+
+    pub fn get_env_mut(&mut self) -> &mut E {
+        return &mut self.env
+    }
+
+}
+
+impl<E: Environment, R: Rng> Simulator<E, R> where E::State: Clone, E::Action: Clone {
+
+    pub fn run_episode<P: RolloutPolicy<E::State, E::Action> + ?Sized>(&mut self, policy: &P) -> Episode<E::State, E::Action> {
+        let mut state = self.env.reset();
+        let mut episode = Episode::new(state.clone());
+
+        while episode.len() < self.max_steps {
+            let legal_actions = self.env.legal_actions(&state);
+
+            let action = match policy.choose(&state, &legal_actions, &mut self.rng) {
+                Some(action) => action,
+                None => break,
+            };
+
+            let (next_state, reward, done) = self.env.step(action.clone());
+            episode.push(Transition {state, action, reward, next_state: next_state.clone(), done});
+            state = next_state;
+
+            if done {
+                break;
+            }
+        }
+
+        return episode
+    }
+
+    pub fn run_episodes<P: RolloutPolicy<E::State, E::Action> + ?Sized>(&mut self, policy: &P, n_episodes: usize) -> EpisodeBatch<E::State, E::Action> {
+        return (0..n_episodes).map(|_| self.run_episode(policy)).collect()
+    }
+
+    // Truncated episodes count with the rewards collected so far, so the step
+    // limit should be well above the typical episode length
+    pub fn estimate_return<P: RolloutPolicy<E::State, E::Action> + ?Sized>(&mut self, policy: &P, n_episodes: usize, gamma: f64) -> ReturnEstimate {
+        let stats = self.run_episodes(policy, n_episodes).summary(gamma);
+        let std_error = stats.std_return/(stats.n_episodes.max(1) as f64).sqrt();
+
+        return ReturnEstimate {
+            n_episodes,
+            mean: stats.mean_return,
+            std_error,
+            lower: stats.mean_return - Z_95*std_error,
+            upper: stats.mean_return + Z_95*std_error,
+        }
+    }
+
+    // Estimates for several policies, each over its own n_episodes episodes,
+    // in the order of the policies
+    pub fn compare_returns(&mut self, policies: &[&dyn RolloutPolicy<E::State, E::Action>], n_episodes: usize, gamma: f64) -> Vec<ReturnEstimate> {
+        return policies.iter().map(|policy| self.estimate_return(*policy, n_episodes, gamma)).collect()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::envs::ModelEnvironment;
+    use crate::models;
+
+    fn test_system() -> models::SystemState {
+        // An initial state and an end state
+        // Two actions, one leads to end without reward
+        // Other leads to either same state or end with a reward
+        let links = vec![
+            models::StateLink(0, 1, "First_Action".to_string(), 1., 0.),
+            models::StateLink(0, 0, "Second_Action".to_string(), 0.9, 0.),
+            models::StateLink(0, 1, "Second_Action".to_string(), 0.1, 10.),
+        ];
+        return models::SystemState::create_and_build(links)
+    }
+
+    #[test]
+    fn model_rollout_test() {
+        let system_state = test_system();
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(system_state.get_specification().clone()));
+        agent.deterministic_policy_improvement(1., 1e-6, 100, 1000);
+
+        let env = ModelEnvironment::new(&system_state, 0, StdRng::seed_from_u64(1));
+        let mut simulator = Simulator::with_seed(env, 2);
+        let episodes = simulator.run_episodes(&agent, 200);
+
+        for episode in &episodes {
+            assert!(episode.is_terminated());
+            assert_eq!(*episode.get_last_state(), 1);
+            assert!(episode.actions().all(|action| action == "Second_Action"));
+            assert_eq!(episode.total_return(1.), 10.);
+        }
+
+        let stats = episodes.summary(1.);
+        assert_eq!(stats.mean_return, 10.);
+        assert!((stats.mean_length - 10.).abs() < 2.);
+    }
+
+    #[test]
+    fn estimate_return_test() {
+        let system_state = test_system();
+        let gamma = 0.9;
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(system_state.get_specification().clone()));
+        agent.evaluate_policy(gamma, 1e-10, 10_000);
+        let value = *agent.get_evaluation().get(&0).unwrap();
+
+        let env = ModelEnvironment::new(&system_state, 0, StdRng::seed_from_u64(6));
+        let estimate = Simulator::with_seed(env, 7).estimate_return(&agent, 2000, gamma);
+
+        assert_eq!(estimate.n_episodes, 2000);
+        assert!(estimate.std_error > 0.);
+        assert!(estimate.contains(value));
+        assert!((estimate.upper - estimate.lower - 2.*Z_95*estimate.std_error).abs() < 1e-12);
+
+        // Leaving at once is worth nothing next to the agent's policy
+        let leave = |_: &i64, _: &[String], _: &mut dyn RngCore| Some("First_Action".to_string());
+        let env = ModelEnvironment::new(&system_state, 0, StdRng::seed_from_u64(8));
+        let mut simulator = Simulator::with_seed(env, 9);
+        simulator.set_max_steps(50);
+        let estimates = simulator.compare_returns(&[&agent, &leave], 500, gamma);
+        assert_eq!(estimates.len(), 2);
+        assert!(estimates[1].upper < estimates[0].lower);
+    }
+
+    #[test]
+    fn truncation_and_seed_test() {
+        let system_state = test_system();
+        let env = ModelEnvironment::new(&system_state, 0, StdRng::seed_from_u64(3));
+        let mut simulator = Simulator::with_seed(env, 4);
+        simulator.set_max_steps(3);
+
+        // Always stay, the reward only comes when leaving
+        let stay = |_: &i64, _: &[String], _: &mut dyn RngCore| Some("Second_Action".to_string());
+        let episodes = simulator.run_episodes(&stay, 50);
+        assert!(episodes.iter().any(|episode| !episode.is_terminated() && episode.len() == 3));
+
+        // Same seeds give the same episodes
+        let run = |seed: u64| {
+            let env = ModelEnvironment::new(&system_state, 0, StdRng::seed_from_u64(seed));
+            let agent: Agent = Agent::init_random(models::SystemState::create_and_build(system_state.get_specification().clone()));
+            Simulator::with_seed(env, seed).run_episodes(&agent, 20)
+        };
+        assert_eq!(run(5), run(5));
+    }
+
+}
@@ -0,0 +1,1451 @@
+// Solver settings, and solves repeated over several discount factors.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::analysis::PolicyChain;
+use crate::collections::HashMap;
+use crate::error::{DivergenceCause, Error};
+use crate::helper;
+use crate::models::DenseModel;
+use crate::numeric::Real;
+use crate::progress::{CancellationToken, NoProgress, ProgressObserver};
+#[cfg(feature = "std")]
+use crate::sweep_checkpoint::{SweepCheckpoint, SweepCheckpointer, SweepCheckpointing};
+use crate::Agent;
+
+// Tolerance and iteration limits of Agent::deterministic_policy_improvement,
+// and the divergence checks of the fallible solves (Agent::try_solve and
+// Agent::try_evaluate_policy)
+#[derive(Debug, Clone, PartialEq)]
+pub struct SolverConfig<F: Real = f64> {
+    pub epsilon: F,
+    pub policy_iters: u32,
+    pub eval_iters: u32,
+    // Largest absolute value allowed, None for no bound
+    pub value_bound: Option<F>,
+    // Sweeps without delta shrinking, while some values keep moving the same
+    // way, after which the evaluation is considered divergent. None stands
+    // for the number of states plus one, the longest a finite model can take
+    // to propagate rewards along a path.
+    pub divergence_window: Option<u32>,
+    // Pool sweeping the values in parallel with the "rayon" feature, None
+    // for rayon's global pool. Built once by with_threads and shared by the
+    // clones of the config and every solve run with them.
+    #[cfg(feature = "rayon")]
+    pub pool: Option<SolverPool>,
+    // States the solves start from, only the states they can reach under
+    // some policy being evaluated and improved. The values and policy of the
+    // others are left as they are. None for all the states.
+    pub start_states: Option<Vec<i64>>,
+    // Blocks of states swept one after the other, None to sweep all the
+    // states at once
+    pub partition: Option<Partition>,
+    // Discount factors of the improvement rounds leading to the one solved
+    // for, None to use it from the start
+    pub gamma_schedule: Option<GammaSchedule<F>>,
+    // Order in which the states of a sweep are updated
+    pub sweep_strategy: SweepStrategy,
+    // Over-relaxation factor w of the updates, each value moving to
+    // (1 - w)*value + w*backup. Between 1 and 2 it overshoots the backups
+    // and can converge in far fewer sweeps, mostly with Gauss-Seidel sweeps.
+    // None for plain backups.
+    pub relaxation: Option<F>,
+    // Seed of the random parts of the solve, the random sweep order drawing
+    // from its "sweep_order" stream of a SeedSequence rather than from its
+    // own seed. None to leave them their own. Ignored without the "rand"
+    // feature.
+    pub seed: Option<u64>,
+    // What the fallible solves do when the policy's states don't match the
+    // model's
+    pub key_mismatch: KeyMismatch,
+    // Improvement rounds whose policy and values are kept for
+    // Agent::rollback, the oldest dropped first. None to keep none.
+    pub policy_history: Option<usize>,
+    // File the evaluation sweeps are checkpointed to, at most once per
+    // interval, for Agent::resume_from_sweep_checkpoint. Only the try_
+    // solves write them, the others having no way to report a failed write.
+    // None for no checkpoints.
+    #[cfg(feature = "std")]
+    pub sweep_checkpoint: Option<SweepCheckpointing>,
+    // Token stopping the solve with Error::Cancelled once cancelled, the
+    // agent left with the policy being evaluated and the values of its last
+    // complete sweep. Only the try_ solves can be cancelled, the others run
+    // to the end. None to run to the end.
+    pub cancellation: Option<CancellationToken>,
+}
+
+// Handling of a policy with states or actions the model doesn't have, or
+// missing some of the model's states with actions, as after
+// Agent::set_polity with a policy of another model
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyMismatch {
+    // Fail with Error::PolicyKeyMismatch, listing all the mismatched ids, or
+    // with Error::UnknownAction for an action of the policy its state doesn't
+    // have (see Agent::validate_policy)
+    Strict,
+    // Skip the unknown states and actions, and evaluate the states without a
+    // policy as if they had no action, with a warning with the "tracing"
+    // feature
+    #[default]
+    Lenient,
+}
+
+// How the values of the states are updated within an evaluation sweep
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum SweepStrategy {
+    // All the states from the values of the previous sweep, in parallel with
+    // the "rayon" feature. Within the blocks of a partition, if any.
+    #[default]
+    Jacobi,
+    // One state at a time in id order, each reading the values already
+    // updated in the sweep
+    GaussSeidel,
+    // Gauss-Seidel in the order of the given ids, the states missing from it
+    // updated afterwards in id order
+    GaussSeidelOrder(Vec<i64>),
+    // Gauss-Seidel in a random order drawn for every sweep, from the seed
+    #[cfg(feature = "rand")]
+    RandomOrder(u64),
+}
+
+// Discount factors annealed towards the one of a solve across the rounds of
+// policy improvement, the first evaluation using start. Policies for a lower
+// discount factor are found in fewer sweeps and are a good start for the
+// higher ones. The solve doesn't stop before reaching its discount factor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GammaSchedule<F: Real = f64> {
+    // Evenly spaced discount factors, reaching the solve's after rounds rounds
+    Linear {start: F, rounds: u32},
+    // Evenly spaced on a log scale of the horizon 1/(1 - gamma), which grows
+    // slowly at first, reaching the solve's after rounds rounds
+    Geometric {start: F, rounds: u32},
+}
+
+impl<F: Real> GammaSchedule<F> {
+
+    // Discount factor of an improvement round, 0 for the first evaluation
+    pub fn gamma(&self, round: u32, target: F) -> F {
+        let (start, rounds) = match self {
+            GammaSchedule::Linear {start, rounds} | GammaSchedule::Geometric {start, rounds} => (*start, *rounds),
+        };
+        if round >= rounds || start >= target {
+            return target
+        }
+
+        let progress = F::from_f64(round as f64/rounds as f64);
+        return match self {
+            GammaSchedule::Linear {..} => start + (target - start)*progress,
+            GammaSchedule::Geometric {..} => F::one() - (F::one() - start)*((F::one() - target)/(F::one() - start)).powf(progress),
+        }
+    }
+
+}
+
+// How the states are split into blocks for the sweeps. Each block is updated
+// from the values the blocks before it just computed, so values propagate
+// within a sweep and each block mostly reads values close to its own.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Partition {
+    // Consecutive states in id order, this many per block
+    Chunks(usize),
+    // Strongly connected components of the model under all actions, those of
+    // the successors swept first. Acyclic models are solved in one sweep.
+    Components,
+}
+
+impl Partition {
+
+    // Blocks of positions of the model, in sweep order
+    pub(crate) fn blocks<F: Real>(&self, model: &DenseModel<F>) -> Vec<Vec<usize>> {
+        return match self {
+            Partition::Chunks(size) => (0..model.n_states()).collect::<Vec<usize>>()
+                .chunks((*size).max(1))
+                .map(|chunk| chunk.to_vec())
+                .collect(),
+            Partition::Components => {
+                let successors: Vec<Vec<(usize,F)>> = (0..model.n_states())
+                    .map(|pos| helper::to_sparse((0..model.get_actions(pos).len())
+                        .flat_map(|action| model.get_successors(pos, action).iter().cloned())))
+                    .collect();
+                // Tarjan's algorithm finds the components of the successors first
+                PolicyChain {ids: model.get_ids().clone(), successors}.components()
+            },
+        }
+    }
+
+}
+
+// Thread pool of the parallel sweeps. Clones share the pool, and compare
+// equal when they do.
+#[cfg(feature = "rayon")]
+#[derive(Debug, Clone)]
+pub struct SolverPool(Arc<rayon::ThreadPool>);
+
+#[cfg(feature = "rayon")]
+impl SolverPool {
+
+    pub fn new(threads: usize) -> Result<SolverPool, Error> {
+        return rayon::ThreadPoolBuilder::new().num_threads(threads).build()
+            .map(|pool| SolverPool(Arc::new(pool)))
+            .map_err(|error| Error::ThreadPoolFailed {message: error.to_string()})
+    }
+
+    pub fn threads(&self) -> usize {
+        return self.0.current_num_threads()
+    }
+
+}
+
+#[cfg(feature = "rayon")]
+impl PartialEq for SolverPool {
+    fn eq(&self, other: &SolverPool) -> bool {
+        return Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<F: Real> Default for SolverConfig<F> {
+    fn default() -> SolverConfig<F> {
+        return SolverConfig {epsilon: F::from_f64(1e-6), policy_iters: 100, eval_iters: 100, value_bound: None, divergence_window: None, #[cfg(feature = "rayon")] pool: None, start_states: None, partition: None, gamma_schedule: None, sweep_strategy: SweepStrategy::Jacobi, relaxation: None, seed: None, key_mismatch: KeyMismatch::Lenient, policy_history: None, #[cfg(feature = "std")] sweep_checkpoint: None, cancellation: None}
+    }
+}
+
+impl<F: Real> SolverConfig<F> {
+
+    pub fn new() -> SolverConfig<F> {
+        return SolverConfig::default()
+    }
+
+    pub fn with_epsilon(mut self, epsilon: F) -> SolverConfig<F> {
+        self.epsilon = epsilon;
+        return self
+    }
+
+    pub fn with_policy_iters(mut self, policy_iters: u32) -> SolverConfig<F> {
+        self.policy_iters = policy_iters;
+        return self
+    }
+
+    pub fn with_eval_iters(mut self, eval_iters: u32) -> SolverConfig<F> {
+        self.eval_iters = eval_iters;
+        return self
+    }
+
+    pub fn with_value_bound(mut self, value_bound: F) -> SolverConfig<F> {
+        self.value_bound = Some(value_bound);
+        return self
+    }
+
+    pub fn with_divergence_window(mut self, divergence_window: u32) -> SolverConfig<F> {
+        self.divergence_window = Some(divergence_window);
+        return self
+    }
+
+    // Sweeps on a pool of that many threads, built here rather than for
+    // every solve. Fails when the pool can't be built. Without the "rayon"
+    // feature the threads are ignored.
+    pub fn with_threads(self, threads: usize) -> Result<SolverConfig<F>, Error> {
+        #[cfg(feature = "rayon")]
+        return Ok(self.with_pool(SolverPool::new(threads)?));
+
+        #[cfg(not(feature = "rayon"))]
+        {
+            let _ = threads;
+            return Ok(self)
+        }
+    }
+
+    // Sweeps on a pool shared with other configs, e.g. those of the solves
+    // of a service
+    #[cfg(feature = "rayon")]
+    pub fn with_pool(mut self, pool: SolverPool) -> SolverConfig<F> {
+        self.pool = Some(pool);
+        return self
+    }
+
+    pub fn with_start_states(mut self, start_states: &[i64]) -> SolverConfig<F> {
+        self.start_states = Some(start_states.to_vec());
+        return self
+    }
+
+    pub fn with_partition(mut self, partition: Partition) -> SolverConfig<F> {
+        self.partition = Some(partition);
+        return self
+    }
+
+    pub fn with_gamma_schedule(mut self, gamma_schedule: GammaSchedule<F>) -> SolverConfig<F> {
+        self.gamma_schedule = Some(gamma_schedule);
+        return self
+    }
+
+    pub fn with_sweep_strategy(mut self, sweep_strategy: SweepStrategy) -> SolverConfig<F> {
+        self.sweep_strategy = sweep_strategy;
+        return self
+    }
+
+    pub fn with_relaxation(mut self, relaxation: F) -> SolverConfig<F> {
+        self.relaxation = Some(relaxation);
+        return self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> SolverConfig<F> {
+        self.seed = Some(seed);
+        return self
+    }
+
+    pub fn with_key_mismatch(mut self, key_mismatch: KeyMismatch) -> SolverConfig<F> {
+        self.key_mismatch = key_mismatch;
+        return self
+    }
+
+    pub fn with_policy_history(mut self, rounds: usize) -> SolverConfig<F> {
+        self.policy_history = Some(rounds);
+        return self
+    }
+
+    #[cfg(feature = "std")]
+    pub fn with_sweep_checkpoint<P: Into<PathBuf>>(mut self, path: P, interval: Duration) -> SolverConfig<F> {
+        self.sweep_checkpoint = Some(SweepCheckpointing {path: path.into(), interval});
+        return self
+    }
+
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> SolverConfig<F> {
+        self.cancellation = Some(cancellation);
+        return self
+    }
+
+}
+
+// State carried through the evaluations of a solve
+pub(crate) struct SolveContext<'a, F: Real> {
+    pub(crate) policy_iteration: u32,
+    #[cfg(feature = "std")]
+    pub(crate) start: Instant,
+    pub(crate) observer: &'a mut dyn ProgressObserver<F>,
+    // Config whose divergence checks apply, None to skip them
+    pub(crate) divergence: Option<&'a SolverConfig<F>>,
+    // Pool of the parallel sweeps, None for rayon's global pool
+    #[cfg(feature = "rayon")]
+    pub(crate) pool: Option<Arc<rayon::ThreadPool>>,
+    // Improves the policy from the current values rather than evaluating it first
+    pub(crate) resume: bool,
+    // Positions swept and improved, None for all of them
+    pub(crate) active: Option<Vec<bool>>,
+    // Blocks of positions swept in turn, None for a single sweep of all of them
+    pub(crate) blocks: Option<Vec<Vec<usize>>>,
+    // Discount factors of the improvement rounds, None for the solve's throughout
+    pub(crate) gamma_schedule: Option<GammaSchedule<F>>,
+    // Positions updated one at a time in this order, None for Jacobi sweeps
+    pub(crate) order: Option<Vec<usize>>,
+    // Shuffles the order before every sweep
+    #[cfg(feature = "rand")]
+    pub(crate) shuffle: Option<rand::rngs::StdRng>,
+    // Over-relaxation factor of the updates, None for plain backups
+    pub(crate) relaxation: Option<F>,
+    // Rounds of policy iteration kept for a rollback, None for none
+    pub(crate) history: Option<usize>,
+    // Writes the sweeps to disk, None for no checkpoints
+    #[cfg(feature = "std")]
+    pub(crate) checkpointer: Option<SweepCheckpointer<F>>,
+    // Sweep the first evaluation carries on from
+    #[cfg(feature = "std")]
+    pub(crate) resume_sweep: Option<SweepCheckpoint>,
+    // Stops the sweeps once cancelled, None to run to the end
+    pub(crate) cancellation: Option<CancellationToken>,
+}
+
+impl<'a, F: Real> SolveContext<'a, F> {
+
+    pub(crate) fn new(observer: &'a mut dyn ProgressObserver<F>, divergence: Option<&'a SolverConfig<F>>) -> SolveContext<'a, F> {
+        return SolveContext {policy_iteration: 0, #[cfg(feature = "std")] start: Instant::now(), observer, divergence, #[cfg(feature = "rayon")] pool: None, resume: false, active: None, blocks: None, gamma_schedule: None, order: None, #[cfg(feature = "rand")] shuffle: None, relaxation: None, history: None, #[cfg(feature = "std")] checkpointer: None, #[cfg(feature = "std")] resume_sweep: None, cancellation: None}
+    }
+
+    // Threads, start states, partition, discount schedule, sweep strategy,
+    // relaxation, seed, checkpoints and cancellation of the config.
+    // Only the states reachable from the start states are swept and improved.
+    pub(crate) fn with_config(self, config: &SolverConfig<F>, model: &DenseModel<F>) -> SolveContext<'a, F> {
+        return SolveContext {
+            active: config.start_states.as_ref().map(|start_states| model.reachable_from(start_states)),
+            blocks: config.partition.as_ref().map(|partition| partition.blocks(model)),
+            gamma_schedule: config.gamma_schedule,
+            order: match &config.sweep_strategy {
+                SweepStrategy::Jacobi => None,
+                SweepStrategy::GaussSeidel => Some((0..model.n_states()).collect()),
+                SweepStrategy::GaussSeidelOrder(ids) => {
+                    let mut listed = vec![false; model.n_states()];
+                    let mut order: Vec<usize> = ids.iter()
+                        .filter_map(|id| model.get_position(id))
+                        .filter(|pos| !core::mem::replace(&mut listed[*pos], true))
+                        .collect();
+                    order.extend((0..model.n_states()).filter(|pos| !listed[*pos]));
+                    Some(order)
+                },
+                #[cfg(feature = "rand")]
+                SweepStrategy::RandomOrder(_) => Some((0..model.n_states()).collect()),
+            },
+            #[cfg(feature = "rand")]
+            shuffle: match &config.sweep_strategy {
+                SweepStrategy::RandomOrder(seed) => Some(match config.seed {
+                    Some(config_seed) => crate::seed::SeedSequence::new(config_seed).rng("sweep_order"),
+                    None => rand::SeedableRng::seed_from_u64(*seed),
+                }),
+                _ => None,
+            },
+            relaxation: config.relaxation,
+            history: config.policy_history,
+            #[cfg(feature = "std")]
+            checkpointer: config.sweep_checkpoint.clone().map(SweepCheckpointer::new),
+            cancellation: config.cancellation.clone(),
+            #[cfg(feature = "rayon")]
+            pool: config.pool.as_ref().map(|pool| pool.0.clone()),
+            ..self
+        }
+    }
+
+    // Runs to the end whatever the cancellation token of the config, and
+    // without sweep checkpoints, for the solves that can't report an
+    // interruption or a failed write
+    pub(crate) fn uninterrupted(self) -> SolveContext<'a, F> {
+        return SolveContext {cancellation: None, #[cfg(feature = "std")] checkpointer: None, ..self}
+    }
+
+    // Since the start of the solve, zero without std
+    pub(crate) fn elapsed(&self) -> Duration {
+        #[cfg(feature = "std")]
+        return self.start.elapsed();
+        #[cfg(not(feature = "std"))]
+        return Duration::ZERO
+    }
+
+    // Policy written with the sweep checkpoints from now on
+    pub(crate) fn checkpoint_policy(&mut self, improving: bool, policy: &[Vec<F>]) {
+        #[cfg(feature = "std")]
+        if let Some(checkpointer) = self.checkpointer.as_mut() {
+            checkpointer.improving = improving;
+            checkpointer.policy = policy.to_vec();
+        }
+        #[cfg(not(feature = "std"))]
+        let _ = (improving, policy);
+    }
+
+    // Round of policy iteration of the sweep checkpoint the solve carries
+    // on from, None when not resumed or resumed in the first evaluation
+    pub(crate) fn resumed_round(&self) -> Option<u32> {
+        #[cfg(feature = "std")]
+        return self.resume_sweep.as_ref()
+            .map(|checkpoint| checkpoint.policy_iteration)
+            .filter(|round| *round > 0);
+        #[cfg(not(feature = "std"))]
+        return None
+    }
+
+    // Sweep, cursor and values already written of the sweep the first
+    // evaluation carries on from
+    pub(crate) fn take_resumed_sweep(&mut self) -> Option<(u32, usize, Vec<F>)> {
+        #[cfg(feature = "std")]
+        return self.resume_sweep.take()
+            .map(|checkpoint| (checkpoint.sweep, checkpoint.cursor, checkpoint.new_values.iter().map(|value| F::from_f64(*value)).collect()));
+        #[cfg(not(feature = "std"))]
+        return None
+    }
+
+    // f over the positions 0..n. With the rayon feature, the positions are
+    // split between threads when a pool is set or n is large.
+    pub(crate) fn map_positions<T: Send>(&self, n: usize, f: impl Fn(usize) -> T + Send + Sync) -> Vec<T> {
+        #[cfg(feature = "rayon")]
+        if self.pool.is_some() || n >= helper::PARALLEL_THRESHOLD {
+            let par_map = || (0..n).into_par_iter().map(&f).collect();
+            return match &self.pool {
+                Some(pool) => pool.install(par_map),
+                None => par_map(),
+            }
+        }
+
+        return (0..n).map(f).collect()
+    }
+
+    // Same as map_positions, writing into out rather than allocating
+    pub(crate) fn fill_positions<T: Send>(&self, out: &mut [T], f: impl Fn(usize) -> T + Send + Sync) {
+        #[cfg(feature = "rayon")]
+        if self.pool.is_some() || out.len() >= helper::PARALLEL_THRESHOLD {
+            let par_fill = |out: &mut [T]| out.par_iter_mut().enumerate().for_each(|(pos, item)| *item = f(pos));
+            return match &self.pool {
+                Some(pool) => pool.install(|| par_fill(out)),
+                None => par_fill(out),
+            }
+        }
+
+        for (pos, item) in out.iter_mut().enumerate() {
+            *item = f(pos);
+        }
+    }
+
+    // Sweep of an evaluation into new_values. A Jacobi sweep without blocks
+    // has each new value only depend on the previous ones, otherwise each
+    // block reads the new values of the blocks before it. Gauss-Seidel
+    // sweeps update one position at a time in their order. Inactive
+    // positions keep their value, the others move towards their backup by
+    // the relaxation factor.
+    // The sweep starts from the cursor, new_values holding the values
+    // written before it, and is checkpointed between chunks of positions
+    // when due. Sweeps by blocks or in random order always start over and
+    // are only checkpointed before they start. Cancellation is checked
+    // before the sweep and between chunks, or blocks, and leaves values as
+    // they were.
+    pub(crate) fn sweep(&mut self, values: &[F], matrices: &PolicyMatrices<F>, gamma: F, new_values: &mut [F], sweep: u32, cursor: usize) -> Result<(), Error> {
+
+        let PolicyMatrices {rewards, successors} = matrices;
+
+        #[cfg(feature = "rand")]
+        let shuffled = self.shuffle.is_some();
+        #[cfg(not(feature = "rand"))]
+        let shuffled = false;
+        let resumable = self.blocks.is_none() && !shuffled;
+        let mut cursor = if resumable {cursor} else {0};
+        let policy_iteration = self.policy_iteration;
+        check_cancelled(&self.cancellation, policy_iteration, sweep)?;
+        #[cfg(feature = "std")]
+        if cursor == 0 {
+            checkpoint_if_due(&mut self.checkpointer, policy_iteration, sweep, 0, values, values)?;
+        }
+
+        #[cfg(feature = "rand")]
+        if let (Some(order), Some(rng)) = (self.order.as_mut(), self.shuffle.as_mut()) {
+            rand::seq::SliceRandom::shuffle(order.as_mut_slice(), rng);
+        }
+
+        let active = self.active.as_deref();
+        let relaxation = self.relaxation;
+        let backup = |pos: usize, values: &[F]| -> F {
+            if active.is_some_and(|active| !active[pos]) {
+                return values[pos]
+            }
+            let backup = rewards[pos] + gamma*helper::sparse_dot_dense_compensated(&successors[pos], values);
+            match relaxation {
+                Some(relaxation) => values[pos] + relaxation*(backup - values[pos]),
+                None => backup,
+            }
+        };
+        let n = new_values.len();
+        #[cfg(feature = "std")]
+        let checkpointed = self.checkpointer.is_some();
+        #[cfg(not(feature = "std"))]
+        let checkpointed = false;
+        let chunk = if checkpointed || self.cancellation.is_some() {SWEEP_CHUNK} else {n.max(1)};
+
+        if let Some(order) = &self.order {
+            if cursor == 0 {
+                new_values.copy_from_slice(values);
+            }
+            while cursor < order.len() {
+                let end = (cursor + chunk).min(order.len());
+                for pos in &order[cursor..end] {
+                    new_values[*pos] = backup(*pos, new_values);
+                }
+                cursor = end;
+                if cursor < n {
+                    check_cancelled(&self.cancellation, policy_iteration, sweep)?;
+                }
+                #[cfg(feature = "std")]
+                if resumable && cursor < n {
+                    checkpoint_if_due(&mut self.checkpointer, policy_iteration, sweep, cursor, values, new_values)?;
+                }
+            }
+            return Ok(())
+        }
+
+        match &self.blocks {
+            None => while cursor < n {
+                let end = (cursor + chunk).min(n);
+                let start = cursor;
+                self.fill_positions(&mut new_values[start..end], |index| backup(start + index, values));
+                cursor = end;
+                if cursor < n {
+                    check_cancelled(&self.cancellation, policy_iteration, sweep)?;
+                    #[cfg(feature = "std")]
+                    checkpoint_if_due(&mut self.checkpointer, policy_iteration, sweep, cursor, values, new_values)?;
+                }
+            },
+            Some(blocks) => {
+                new_values.copy_from_slice(values);
+                for block in blocks {
+                    check_cancelled(&self.cancellation, policy_iteration, sweep)?;
+                    let block_values = self.map_positions(block.len(), |index| backup(block[index], new_values));
+                    for (pos, value) in block.iter().zip(block_values) {
+                        new_values[*pos] = value;
+                    }
+                }
+            },
+        }
+        return Ok(())
+    }
+
+}
+
+// Positions swept between two checks of the checkpoint interval and of
+// cancellation
+const SWEEP_CHUNK: usize = 4*helper::PARALLEL_THRESHOLD;
+
+fn check_cancelled(cancellation: &Option<CancellationToken>, policy_iteration: u32, sweep: u32) -> Result<(), Error> {
+    return match cancellation.as_ref().is_some_and(|cancellation| cancellation.is_cancelled()) {
+        true => Err(Error::Cancelled {policy_iteration, sweep}),
+        false => Ok(()),
+    }
+}
+
+#[cfg(feature = "std")]
+fn checkpoint_if_due<F: Real>(checkpointer: &mut Option<SweepCheckpointer<F>>, policy_iteration: u32, sweep: u32, cursor: usize, values: &[F], new_values: &[F]) -> Result<(), Error> {
+    return match checkpointer.as_mut().filter(|checkpointer| checkpointer.is_due()) {
+        Some(checkpointer) => checkpointer.write(policy_iteration, sweep, cursor, values, new_values),
+        None => Ok(()),
+    }
+}
+
+// Expected reward and successors of each state under a policy, by position
+// in the dense model, the successors as sparse vectors of positions
+#[derive(Clone)]
+pub(crate) struct PolicyMatrices<F: Real> {
+    pub(crate) rewards: Vec<F>,
+    pub(crate) successors: Vec<Vec<(usize,F)>>,
+}
+
+impl<F: Real> PolicyMatrices<F> {
+
+    // policy holds the probabilities of each state by action index
+    pub(crate) fn build(model: &DenseModel<F>, policy: &[Vec<F>], context: &SolveContext<F>) -> PolicyMatrices<F> {
+        let rewards: Vec<F> = context.map_positions(model.n_states(), |pos| helper::compensated_sum(policy[pos].iter().enumerate()
+            .map(|(action, prob)| *prob*model.get_reward(pos, action))));
+        let successors: Vec<Vec<(usize,F)>> = context.map_positions(model.n_states(), |pos| helper::to_sparse(policy[pos].iter().enumerate()
+            .filter(|(_, prob)| **prob != F::zero())
+            .flat_map(|(action, prob)| model.get_successors(pos, action).iter().map(move |(pos_next, prob_next)| (*pos_next, *prob*(*prob_next))))));
+
+        return PolicyMatrices {rewards, successors}
+    }
+
+}
+
+// Watches the sweeps of an evaluation for the divergence checks of a config
+pub(crate) struct DivergenceMonitor<F: Real> {
+    value_bound: Option<F>,
+    window: u32,
+    epsilon: F,
+    // Consecutive sweeps each state moved by at least epsilon in the same direction
+    streaks: Vec<u32>,
+    rising: Vec<bool>,
+    non_contracting: u32,
+    previous_delta: Option<F>,
+}
+
+impl<F: Real> DivergenceMonitor<F> {
+
+    pub(crate) fn new(config: &SolverConfig<F>, n_states: usize) -> DivergenceMonitor<F> {
+        return DivergenceMonitor {
+            value_bound: config.value_bound,
+            window: config.divergence_window.unwrap_or(n_states as u32 + 1).max(1),
+            epsilon: config.epsilon,
+            streaks: vec![0; n_states],
+            rising: vec![false; n_states],
+            non_contracting: 0,
+            previous_delta: None,
+        }
+    }
+
+    // Called after each sweep with the values before and after it, ids giving
+    // the state of each position
+    pub(crate) fn check(&mut self, sweep: u32, old_values: &[F], new_values: &[F], delta: F, ids: &[i64]) -> Result<(), Error> {
+
+        if let Some(bound) = self.value_bound {
+            let states: Vec<i64> = new_values.iter().zip(ids)
+                .filter(|(value, _)| value.is_nan() || value.abs() > bound)
+                .map(|(_, id)| *id)
+                .collect();
+            if !states.is_empty() {
+                return Err(Error::EvaluationDiverged {sweep, cause: DivergenceCause::ValueBound, states})
+            }
+        }
+
+        for (pos, (old_value, new_value)) in old_values.iter().zip(new_values).enumerate() {
+            let change = *new_value - *old_value;
+            let rising = change > F::zero();
+            if change.abs() < self.epsilon {
+                self.streaks[pos] = 0;
+            } else if self.streaks[pos] > 0 && self.rising[pos] == rising {
+                self.streaks[pos] += 1;
+            } else {
+                self.streaks[pos] = 1;
+                self.rising[pos] = rising;
+            }
+        }
+
+        // Rounding may shave a little off a constant delta
+        let contracting = self.previous_delta.is_some_and(|previous| delta < previous*F::from_f64(1. - 1e-9));
+        self.non_contracting = if contracting || delta < self.epsilon {0} else {self.non_contracting + 1};
+        self.previous_delta = Some(delta);
+
+        if self.non_contracting >= self.window {
+            let states: Vec<i64> = self.streaks.iter().zip(ids)
+                .filter(|(streak, _)| **streak >= self.window)
+                .map(|(_, id)| *id)
+                .collect();
+            if !states.is_empty() {
+                return Err(Error::EvaluationDiverged {sweep, cause: DivergenceCause::Growth, states})
+            }
+        }
+
+        return Ok(())
+    }
+
+}
+
+// Policy and values of an agent, saved to resume solving later, possibly with
+// other settings. Serializable with the "serde" feature.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpoint<F: Real = f64> {
+    pub policy: HashMap<i64,HashMap<String,F>>,
+    pub values: HashMap<i64,F>,
+}
+
+// Solution for one discount factor of a sweep, compared to the previous one
+#[derive(Debug, Clone, PartialEq)]
+pub struct GammaSweepStep<F: Real = f64> {
+    pub gamma: F,
+    pub values: HashMap<i64,F>,
+    // Action of the deterministic policy in each state with actions
+    pub greedy_actions: HashMap<i64,String>,
+    // Largest value difference with the previous discount factor, zero for the first
+    pub max_value_change: F,
+    // Sorted ids of the states whose greedy action differs from the previous discount factor
+    pub policy_changes: Vec<i64>,
+}
+
+impl<F: Real> Agent<F> {
+
+    pub fn solve(&mut self, gamma: F, config: &SolverConfig<F>) {
+        self.solve_with_progress(gamma, config, &mut NoProgress);
+    }
+
+    pub fn solve_with_progress(&mut self, gamma: F, config: &SolverConfig<F>, observer: &mut dyn ProgressObserver<F>) {
+        // Without divergence checks, cancellation or checkpoints the evaluations can't fail
+        let mut context = SolveContext::new(observer, None).with_config(config, self.system_state.get_dense()).uninterrupted();
+        self.improve_observed(gamma, config.epsilon, config.policy_iters, config.eval_iters, &mut context).unwrap();
+    }
+
+    // Solves with the divergence checks of the config on every evaluation.
+    // On divergence the values are those of the last sweep.
+    pub fn try_solve(&mut self, gamma: F, config: &SolverConfig<F>) -> Result<(), Error> {
+        return self.try_solve_with_progress(gamma, config, &mut NoProgress)
+    }
+
+    // Same as try_solve, reporting the progress of the sweeps to the observer
+    pub fn try_solve_with_progress(&mut self, gamma: F, config: &SolverConfig<F>, observer: &mut dyn ProgressObserver<F>) -> Result<(), Error> {
+        self.check_keys(config.key_mismatch)?;
+        let mut context = SolveContext::new(observer, Some(config)).with_config(config, self.system_state.get_dense());
+        return self.improve_observed(gamma, config.epsilon, config.policy_iters, config.eval_iters, &mut context)
+    }
+
+    // Evaluates the current policy with the divergence checks of the config
+    pub fn try_evaluate_policy(&mut self, gamma: F, config: &SolverConfig<F>) -> Result<(), Error> {
+        self.check_keys(config.key_mismatch)?;
+        let mut observer = NoProgress;
+        let mut context = SolveContext::new(&mut observer, Some(config)).with_config(config, self.system_state.get_dense());
+        return self.evaluate_policy_observed(gamma, config.epsilon, config.eval_iters, &mut context)
+    }
+
+    // Ids of the policy's states missing from the model, and of the model's
+    // states with actions missing from the policy, as an
+    // Error::PolicyKeyMismatch
+    pub fn check_policy_keys(&self) -> Result<(), Error> {
+        let unknown: Vec<i64> = self.unknown_keys.states.clone();
+        let missing: Vec<i64> = self.system_state.get_dense().get_ids().iter().zip(&self.policy)
+            .filter(|(_, probs)| !Agent::listed(probs))
+            .map(|(id, _)| *id)
+            .collect();
+        if unknown.is_empty() && missing.is_empty() {
+            return Ok(())
+        }
+        return Err(Error::PolicyKeyMismatch {unknown, missing})
+    }
+
+    // Checks the states of the policy with check_policy_keys, then the
+    // actions given a positive probability, the first one its state doesn't
+    // have (by state id and action name) being an Error::UnknownAction.
+    // Evaluations would drop the probability of such actions without notice.
+    pub fn validate_policy(&self) -> Result<(), Error> {
+        self.check_policy_keys()?;
+
+        return match self.unknown_keys.actions.first() {
+            Some((state, action)) => Err(Error::UnknownAction {state: *state, action: action.clone()}),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_keys(&self, key_mismatch: KeyMismatch) -> Result<(), Error> {
+        return match (self.validate_policy(), key_mismatch) {
+            (Err(error), KeyMismatch::Strict) => Err(error),
+            #[cfg(feature = "tracing")]
+            (Err(error), KeyMismatch::Lenient) => {
+                tracing::warn!("{}", error);
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint<F> {
+        return Checkpoint {policy: self.get_policy().clone(), values: self.get_evaluation().clone()}
+    }
+
+    // Takes the policy and values of a checkpoint for the states of the
+    // model it has, the other states keeping theirs
+    pub fn restore(&mut self, checkpoint: &Checkpoint<F>) {
+        let system_state = Arc::clone(&self.system_state);
+        let model = system_state.get_dense();
+        let mut policy = core::mem::take(&mut self.policy);
+        for (id, actions) in &checkpoint.policy {
+            if let Some(pos) = model.get_position(id) {
+                policy[pos] = Agent::policy_row(model, pos, actions).0;
+            }
+        }
+        let mut values = core::mem::take(&mut self.policy_evaluation);
+        for (id, value) in &checkpoint.values {
+            if let Some(pos) = model.get_position(id) {
+                values[pos] = *value;
+            }
+        }
+        self.replace_policy(policy);
+        self.replace_values(values);
+        self.unknown_keys.actions.retain(|(id, _)| !checkpoint.policy.contains_key(id));
+    }
+
+    // Policies and values of the last rounds of the latest improvement,
+    // recorded with SolverConfig::with_policy_history, the current policy
+    // last
+    pub fn get_policy_history(&self) -> &Vec<Checkpoint<F>> {
+        return &self.policy_history
+    }
+
+    // Restores the policy and values of rounds improvement rounds before the
+    // current ones, e.g. the last stable policy when the late rounds
+    // oscillate between tied actions. The later rounds are dropped from the
+    // history, so rollbacks add up.
+    pub fn rollback(&mut self, rounds: usize) -> Result<(), Error> {
+        let recorded = self.policy_history.len();
+        if rounds >= recorded {
+            return Err(Error::NoPolicyHistory {rounds, recorded})
+        }
+        self.policy_history.truncate(recorded - rounds);
+        let checkpoint = self.policy_history.last().unwrap().clone();
+        self.restore(&checkpoint);
+        return Ok(())
+    }
+
+    // Changes the reward of a link, as SystemState::set_link_reward, for a
+    // what-if re-solve with resolve. Other agents sharing the model keep
+    // theirs, the agent taking its own copy first.
+    pub fn set_link_reward(&mut self, prev_state: i64, new_state: i64, action: &str, reward: F) -> Result<(), Error> {
+        Arc::make_mut(&mut self.system_state).set_link_reward(prev_state, new_state, action, reward)?;
+        self.changed_states.push(prev_state);
+        // The cached rewards of the policy are out of date
+        self.policy_version += 1;
+        return Ok(())
+    }
+
+    // Solves again after set_link_reward, from the current values and
+    // policy. Only the states that can reach a changed link under some
+    // policy are swept and improved, the values of the others not depending
+    // on it. Returns the number of states re-solved.
+    pub fn resolve(&mut self, gamma: F, config: &SolverConfig<F>) -> usize {
+        let mut observer = NoProgress;
+        let context = SolveContext::new(&mut observer, None).with_config(config, self.system_state.get_dense()).uninterrupted();
+        // Without divergence checks, cancellation or checkpoints the evaluations can't fail
+        return self.resolve_observed(gamma, config, context).unwrap()
+    }
+
+    // Same as resolve, with the divergence and key checks of try_solve
+    pub fn try_resolve(&mut self, gamma: F, config: &SolverConfig<F>) -> Result<usize, Error> {
+        self.check_keys(config.key_mismatch)?;
+        let mut observer = NoProgress;
+        let context = SolveContext::new(&mut observer, Some(config)).with_config(config, self.system_state.get_dense());
+        return self.resolve_observed(gamma, config, context)
+    }
+
+    // The context being set up with the config
+    fn resolve_observed(&mut self, gamma: F, config: &SolverConfig<F>, mut context: SolveContext<F>) -> Result<usize, Error> {
+        if self.changed_states.is_empty() {
+            return Ok(0)
+        }
+        let model = self.system_state.get_dense();
+        // Within the start states' region too, if the config has one
+        let mut affected = model.reaching(&self.changed_states);
+        if let Some(active) = &context.active {
+            affected.iter_mut().zip(active).for_each(|(affected, active)| *affected &= *active);
+        }
+        let n_affected = affected.iter().filter(|affected| **affected).count();
+
+        context.active = Some(affected);
+        self.improve_observed(gamma, config.epsilon, config.policy_iters, config.eval_iters, &mut context)?;
+        self.changed_states.clear();
+        return Ok(n_affected)
+    }
+
+    // Same as solve, starting with an improvement of the current policy from
+    // the current values instead of a full evaluation, as after restoring a
+    // checkpoint. The config may differ from the one the values came from.
+    pub fn resume_solve(&mut self, gamma: F, config: &SolverConfig<F>) {
+        let mut observer = NoProgress;
+        let context = SolveContext::new(&mut observer, None).with_config(config, self.system_state.get_dense()).uninterrupted();
+        // Without divergence checks, cancellation or checkpoints the evaluations can't fail
+        self.improve_observed(gamma, config.epsilon, config.policy_iters, config.eval_iters, &mut SolveContext {resume: true, ..context}).unwrap();
+    }
+
+    // Same as try_solve, resuming as resume_solve does
+    pub fn try_resume_solve(&mut self, gamma: F, config: &SolverConfig<F>) -> Result<(), Error> {
+        self.check_keys(config.key_mismatch)?;
+        let mut observer = NoProgress;
+        let context = SolveContext::new(&mut observer, Some(config)).with_config(config, self.system_state.get_dense());
+        return self.improve_observed(gamma, config.epsilon, config.policy_iters, config.eval_iters, &mut SolveContext {resume: true, ..context})
+    }
+
+    // Carries on the solve, or the evaluation of try_evaluate_policy, that
+    // wrote a sweep checkpoint (see SolverConfig::with_sweep_checkpoint)
+    // from within the sweep it stopped in. The gamma and config should be
+    // those it was started with, the config checking for divergence and
+    // checkpointing again. Fails with Error::CheckpointFailed if the file
+    // can't be read or doesn't match the model.
+    #[cfg(feature = "std")]
+    pub fn resume_from_sweep_checkpoint<P: AsRef<Path>>(&mut self, path: P, gamma: F, config: &SolverConfig<F>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let checkpoint = SweepCheckpoint::load(path)
+            .map_err(|error| Error::CheckpointFailed {message: format!("{}: {}", path.display(), error)})?;
+        let system_state = Arc::clone(&self.system_state);
+        let model = system_state.get_dense();
+        let n_states = model.n_states();
+        let matches = checkpoint.sweep > 0 && checkpoint.cursor <= n_states
+            && [checkpoint.policy.len(), checkpoint.values.len(), checkpoint.new_values.len()].iter().all(|len| *len == n_states)
+            && (0..n_states).all(|pos| checkpoint.policy[pos].len() == model.get_actions(pos).len());
+        if !matches {
+            return Err(Error::CheckpointFailed {message: format!("{} doesn't match the model", path.display())})
+        }
+
+        self.replace_policy(checkpoint.policy.iter().map(|probs| probs.iter().map(|prob| F::from_f64(*prob)).collect()).collect());
+        self.replace_values(checkpoint.values.iter().map(|value| F::from_f64(*value)).collect());
+        self.unknown_keys = Default::default();
+        self.check_keys(config.key_mismatch)?;
+
+        let mut observer = NoProgress;
+        let mut context = SolveContext::new(&mut observer, Some(config)).with_config(config, model);
+        context.policy_iteration = checkpoint.policy_iteration;
+        let improving = checkpoint.improving;
+        context.resume_sweep = Some(checkpoint);
+        return match improving {
+            true => self.improve_observed(gamma, config.epsilon, config.policy_iters, config.eval_iters, &mut context),
+            false => self.evaluate_policy_observed(gamma, config.epsilon, config.eval_iters, &mut context),
+        }
+    }
+
+    // Solves for each discount factor in turn, each solve starting from the
+    // policy and values of the previous one. Nearby discount factors share most
+    // of their solution, so sorted factors need fewer iterations. The agent is
+    // left solved for the last one.
+    pub fn gamma_sweep(&mut self, gammas: &[F], config: &SolverConfig<F>) -> Vec<GammaSweepStep<F>> {
+
+        let mut steps: Vec<GammaSweepStep<F>> = Vec::with_capacity(gammas.len());
+
+        for gamma in gammas {
+            self.solve(*gamma, config);
+
+            let values = self.get_evaluation().clone();
+            let greedy_actions: HashMap<i64,String> = self.get_policy().iter()
+                .filter_map(|(id, actions)| actions.iter()
+                    .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+                    .map(|(action, _)| (*id, action.clone())))
+                .collect();
+
+            let (max_value_change, policy_changes) = match steps.last() {
+                Some(previous) => {
+                    let max_value_change = values.iter()
+                        .map(|(id, value)| (*value - *previous.values.get(id).unwrap_or(value)).abs())
+                        .fold(F::zero(), |a, b| a.max(b));
+
+                    let mut policy_changes: Vec<i64> = greedy_actions.iter()
+                        .filter(|(id, action)| previous.greedy_actions.get(*id) != Some(action))
+                        .map(|(id, _)| *id)
+                        .collect();
+                    policy_changes.sort();
+
+                    (max_value_change, policy_changes)
+                },
+                None => (F::zero(), Vec::new()),
+            };
+
+            steps.push(GammaSweepStep {gamma: *gamma, values, greedy_actions, max_value_change, policy_changes});
+        }
+
+        return steps
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+    use crate::envs::GamblersProblem;
+    use crate::models;
+    use crate::progress::Progress;
+
+    #[test]
+    fn gamma_sweep_test() {
+        // Taking 1 now or waiting a step for 3, worth it when gamma > 1/3
+        let links = vec![
+            models::StateLink(0, 2, "Now".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Later".to_string(), 1., 0.),
+            models::StateLink(1, 2, "Take".to_string(), 1., 3.),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        let config = SolverConfig::new().with_epsilon(1e-9);
+
+        let steps = agent.gamma_sweep(&[0.2, 0.5, 0.9], &config);
+        assert_eq!(steps.len(), 3);
+
+        assert_eq!(steps[0].greedy_actions.get(&0).unwrap(), "Now");
+        assert!((steps[0].values.get(&0).unwrap() - 1.).abs() < 1e-6);
+        assert_eq!(steps[0].max_value_change, 0.);
+
+        assert_eq!(steps[1].greedy_actions.get(&0).unwrap(), "Later");
+        assert!((steps[1].values.get(&0).unwrap() - 1.5).abs() < 1e-6);
+        assert_eq!(steps[1].policy_changes, vec![0]);
+
+        assert!(steps[2].policy_changes.is_empty());
+        assert!((steps[2].max_value_change - 1.2).abs() < 1e-6);
+        assert!(!steps[2].greedy_actions.contains_key(&2));
+
+        // Left solved for the last discount factor
+        assert!((agent.get_evaluation().get(&0).unwrap() - 2.7).abs() < 1e-6);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_sweep_test() {
+        // A chain long enough to be swept in parallel on the global pool
+        let links: Vec<models::StateLink> = (0..20_000).map(|id| models::StateLink(id, id + 1, "Step".to_string(), 1., 1.)).collect();
+        let new_agent = || -> Agent {Agent::init_random(models::SystemState::create_and_build(links.clone()))};
+
+        let mut agent = new_agent();
+        agent.evaluate_policy(0.5, 1e-12, 1000);
+        assert!((agent.get_evaluation().get(&0).unwrap() - 2.).abs() < 1e-9);
+
+        // The split between threads doesn't change the values
+        for threads in [1, 4] {
+            let mut threaded = new_agent();
+            threaded.try_evaluate_policy(0.5, &SolverConfig::new().with_epsilon(1e-12).with_eval_iters(1000).with_threads(threads).unwrap()).unwrap();
+            assert_eq!(threaded.get_evaluation(), agent.get_evaluation());
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_improvement_test() {
+        let new_agent = || -> Agent {Agent::init_random(GamblersProblem::new(100, 0.4).unwrap().build())};
+        let config = SolverConfig::new().with_epsilon(1e-12).with_eval_iters(1000);
+
+        let mut agent = new_agent();
+        agent.solve(1., &config);
+
+        // Built once, the clones of the config sharing the pool
+        let threaded_config = config.clone().with_threads(4).unwrap();
+        assert_eq!(threaded_config.pool.as_ref().map(|pool| pool.threads()), Some(4));
+        assert_eq!(threaded_config.clone(), threaded_config);
+        assert_ne!(config.clone().with_threads(4).unwrap(), threaded_config);
+
+        let mut threaded = new_agent();
+        threaded.solve(1., &threaded_config);
+        assert!(threaded.certify_optimality(1., 1e-9).is_ok());
+
+        // Ties between actions may be broken differently, not the values
+        for (id, value) in agent.get_evaluation() {
+            assert!((threaded.get_evaluation().get(id).unwrap() - value).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn policy_history_test() {
+        let mut agent: Agent = Agent::init_random(GamblersProblem::new(20, 0.4).unwrap().build());
+        agent.solve(1., &SolverConfig::new().with_epsilon(1e-12).with_eval_iters(1000).with_policy_history(3));
+
+        let history = agent.get_policy_history().clone();
+        assert_eq!(history.len(), 3);
+        assert_eq!(*history.last().unwrap(), agent.checkpoint());
+
+        agent.rollback(1).unwrap();
+        assert_eq!(agent.checkpoint(), history[1]);
+        agent.rollback(1).unwrap();
+        assert_eq!(agent.checkpoint(), history[0]);
+        assert_eq!(agent.get_policy_history().len(), 1);
+        assert_eq!(agent.rollback(1), Err(Error::NoPolicyHistory {rounds: 1, recorded: 1}));
+
+        // Nothing recorded by default
+        agent.solve(1., &SolverConfig::new());
+        assert!(agent.get_policy_history().is_empty());
+    }
+
+    #[test]
+    fn resolve_test() {
+        let links = |reward: f64| vec![
+            models::StateLink(0, 1, "Left".to_string(), 1., 0.),
+            models::StateLink(0, 2, "Right".to_string(), 1., 1.),
+            models::StateLink(1, 1, "Stay".to_string(), 1., reward),
+            models::StateLink(2, 2, "Stay".to_string(), 1., 0.5),
+            models::StateLink(3, 2, "Go".to_string(), 1., 0.),
+        ];
+        let config = SolverConfig::new().with_epsilon(1e-12).with_eval_iters(1000);
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links(0.)));
+        agent.solve(0.5, &config);
+        assert_eq!(agent.get_best_action(0).unwrap().0, "Right");
+        assert_eq!(agent.resolve(0.5, &config), 0);
+
+        // Only states 0 and 1 reach the changed link
+        let shared = agent.clone();
+        agent.set_link_reward(1, 1, "Stay", 2.).unwrap();
+        assert_eq!(agent.resolve(0.5, &config), 2);
+        assert_eq!(agent.get_best_action(0).unwrap().0, "Left");
+
+        let mut full: Agent = Agent::init_random(models::SystemState::create_and_build(links(2.)));
+        full.solve(0.5, &config);
+        assert_eq!(agent.get_system_state(), full.get_system_state());
+        for (id, value) in full.get_evaluation() {
+            assert!((agent[*id] - value).abs() < 1e-9);
+        }
+        // The clone kept the model as it was
+        assert_eq!(shared.get_system_state().get_specification()[2].4, 0.);
+
+        assert_eq!(agent.set_link_reward(1, 2, "Stay", 1.), Err(Error::UnknownLink {prev_state: 1, new_state: 2, action: "Stay".to_string()}));
+        assert_eq!(agent.set_link_reward(1, 1, "Jump", 1.), Err(Error::UnknownAction {state: 1, action: "Jump".to_string()}));
+        assert_eq!(agent.try_resolve(0.5, &config), Ok(0));
+    }
+
+    #[test]
+    fn sweep_checkpoint_test() {
+        // Going right for a reward of 1 or staying put, past the size of a chunk
+        let n_states = SWEEP_CHUNK as i64 + 100;
+        let links: Vec<models::StateLink> = (0..n_states)
+            .flat_map(|id| [models::StateLink(id, id + 1, "Go".to_string(), 1., 1.), models::StateLink(id, id, "Stay".to_string(), 1., 0.)])
+            .collect();
+        let model = models::SystemState::create_and_build(links);
+        let path = std::env::temp_dir().join(format!("complete_iter_sweep_{}.bin", std::process::id()));
+        let config = SolverConfig::new().with_policy_iters(1).with_eval_iters(3).with_sweep_checkpoint(&path, Duration::ZERO);
+
+        let mut agent: Agent = Agent::init_random(model.clone());
+        agent.try_solve(0.9, &config).unwrap();
+
+        // Last written within the last sweep of the first round
+        let checkpoint = SweepCheckpoint::load(&path).unwrap();
+        assert!(checkpoint.improving);
+        assert_eq!((checkpoint.policy_iteration, checkpoint.sweep, checkpoint.cursor), (1, 3, SWEEP_CHUNK));
+
+        let mut resumed: Agent = Agent::init_random(model);
+        resumed.resume_from_sweep_checkpoint(&path, 0.9, &config).unwrap();
+        assert_eq!(resumed.get_evaluation(), agent.get_evaluation());
+        assert_eq!(resumed.get_policy(), agent.get_policy());
+
+        // Gauss-Seidel evaluation of a smaller model, checkpointed between sweeps
+        let config = SolverConfig::new().with_eval_iters(5).with_sweep_strategy(SweepStrategy::GaussSeidel).with_sweep_checkpoint(&path, Duration::ZERO);
+        let mut agent: Agent = Agent::init_random(GamblersProblem::new(20, 0.4).unwrap().build());
+        let mut resumed = agent.clone();
+        agent.try_evaluate_policy(1., &config).unwrap();
+        let checkpoint = SweepCheckpoint::load(&path).unwrap();
+        assert!(!checkpoint.improving);
+        assert_eq!((checkpoint.sweep, checkpoint.cursor), (5, 0));
+        resumed.resume_from_sweep_checkpoint(&path, 1., &config).unwrap();
+        assert_eq!(resumed.get_evaluation(), agent.get_evaluation());
+
+        // Written for another model
+        let mut other: Agent = Agent::init_random(GamblersProblem::new(10, 0.4).unwrap().build());
+        assert!(matches!(other.resume_from_sweep_checkpoint(&path, 1., &config), Err(Error::CheckpointFailed {..})));
+        std::fs::remove_file(&path).unwrap();
+
+        // Not written by the infallible solves, nor in a missing directory
+        other.solve(1., &config);
+        assert!(!path.exists());
+        let missing = SolverConfig::new().with_sweep_checkpoint(path.join("missing"), Duration::ZERO);
+        assert!(matches!(other.try_solve(1., &missing), Err(Error::CheckpointFailed {..})));
+    }
+
+    #[test]
+    fn start_states_test() {
+        // 0 and 1 reach 2, state 3 is never reached from them
+        let links = vec![
+            models::StateLink(0, 1, "Wait".to_string(), 1., 0.),
+            models::StateLink(0, 2, "Go".to_string(), 1., 1.),
+            models::StateLink(1, 2, "Go".to_string(), 1., 2.),
+            models::StateLink(3, 2, "Left".to_string(), 1., 5.),
+            models::StateLink(3, 0, "Right".to_string(), 1., 0.),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        assert_eq!(agent.get_system_state().get_dense().reachable_from(&[0, 7]), vec![true, true, true, false]);
+
+        let config = SolverConfig::new().with_epsilon(1e-9).with_start_states(&[0]);
+        agent.try_solve(0.9, &config).unwrap();
+        assert_eq!(agent.get_best_action(0).unwrap().0, "Wait");
+        assert!((agent.get_evaluation().get(&0).unwrap() - 1.8).abs() < 1e-9);
+        // Left untouched
+        assert_eq!(*agent.get_evaluation().get(&3).unwrap(), 0.);
+        assert_eq!(*agent.get_policy().get(&3).unwrap().get("Left").unwrap(), 0.5);
+
+        agent.solve(0.9, &SolverConfig::new().with_epsilon(1e-9));
+        assert!((agent.get_evaluation().get(&3).unwrap() - 5.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn partition_test() {
+        // A long chain, where values propagate one state per Jacobi sweep
+        let links: Vec<models::StateLink> = (0..200).map(|id| models::StateLink(id, id + 1, "Step".to_string(), 1., 1.)).collect();
+        let solve = |config: SolverConfig| -> (u32, Agent) {
+            let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links.clone()));
+            let mut sweeps: u32 = 0;
+            agent.solve_with_progress(1., &config.with_epsilon(1e-9).with_eval_iters(1000), &mut |_: &Progress| sweeps += 1);
+            (sweeps, agent)
+        };
+
+        let (jacobi_sweeps, jacobi) = solve(SolverConfig::new());
+        let (component_sweeps, components) = solve(SolverConfig::new().with_partition(Partition::Components));
+        let (_, chunks) = solve(SolverConfig::new().with_partition(Partition::Chunks(16)));
+        assert!(jacobi_sweeps > 200);
+        // Swept from the end of the chain: the values are found in one sweep,
+        // seen settled in the next, then kept by the improvement's evaluation
+        assert_eq!(component_sweeps, 3);
+        for id in 0..=200 {
+            assert!((components.get_evaluation().get(&id).unwrap() - jacobi.get_evaluation().get(&id).unwrap()).abs() < 1e-9);
+            assert!((chunks.get_evaluation().get(&id).unwrap() - jacobi.get_evaluation().get(&id).unwrap()).abs() < 1e-9);
+        }
+        assert_eq!(*components.get_evaluation().get(&0).unwrap(), 200.);
+
+        let model = jacobi.get_system_state().get_dense();
+        assert_eq!(Partition::Chunks(150).blocks(model), vec![(0..150).collect::<Vec<usize>>(), (150..201).collect()]);
+        assert_eq!(Partition::Components.blocks(model)[0], vec![200]);
+    }
+
+    #[test]
+    fn gamma_schedule_test() {
+        let linear: GammaSchedule = GammaSchedule::Linear {start: 0.5, rounds: 4};
+        assert_eq!(linear.gamma(0, 0.9), 0.5);
+        assert!((linear.gamma(2, 0.9) - 0.7).abs() < 1e-12);
+        assert_eq!(linear.gamma(4, 0.9), 0.9);
+        assert_eq!(linear.gamma(9, 0.9), 0.9);
+        // Horizons of 10, 100 and 1000 steps
+        let geometric: GammaSchedule = GammaSchedule::Geometric {start: 0.9, rounds: 2};
+        assert!((geometric.gamma(1, 0.999) - 0.99).abs() < 1e-12);
+        assert_eq!(geometric.gamma(2, 0.999), 0.999);
+        // Never above the solve's discount factor
+        assert_eq!(linear.gamma(0, 0.3), 0.3);
+
+        let new_agent = || -> Agent {Agent::init_random(GamblersProblem::new(30, 0.4).unwrap().build())};
+        let config = SolverConfig::new().with_epsilon(1e-10).with_eval_iters(10_000);
+
+        let mut agent = new_agent();
+        agent.solve(0.999, &config);
+        let mut annealed = new_agent();
+        annealed.solve(0.999, &config.clone().with_gamma_schedule(GammaSchedule::Geometric {start: 0.9, rounds: 3}));
+        assert!(annealed.certify_optimality(0.999, 1e-6).is_ok());
+        for (id, value) in agent.get_evaluation() {
+            assert!((annealed.get_evaluation().get(id).unwrap() - value).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn sweep_strategy_test() {
+        // A chain, where Gauss-Seidel sweeps along the links take as many
+        // sweeps as Jacobi ones, and against them one to find the values and
+        // one to see them settled (plus one for the improvement's evaluation)
+        let links: Vec<models::StateLink> = (0..50).map(|id| models::StateLink(id, id + 1, "Step".to_string(), 1., 1.)).collect();
+        let evaluate = |sweep_strategy: SweepStrategy| -> (u32, Agent) {
+            let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links.clone()));
+            let mut sweeps: u32 = 0;
+            let config = SolverConfig::new().with_epsilon(1e-12).with_eval_iters(1000).with_policy_iters(1).with_sweep_strategy(sweep_strategy);
+            agent.solve_with_progress(0.9, &config, &mut |_: &Progress| sweeps += 1);
+            (sweeps, agent)
+        };
+
+        let (jacobi_sweeps, jacobi) = evaluate(SweepStrategy::Jacobi);
+        let (forward_sweeps, forward) = evaluate(SweepStrategy::GaussSeidel);
+        let (backward_sweeps, backward) = evaluate(SweepStrategy::GaussSeidelOrder((0..=50).rev().collect()));
+        assert_eq!(forward_sweeps, jacobi_sweeps);
+        assert_eq!(backward_sweeps, 3);
+        for id in 0..=50 {
+            let value = jacobi.get_evaluation().get(&id).unwrap();
+            assert!((forward.get_evaluation().get(&id).unwrap() - value).abs() < 1e-9);
+            assert!((backward.get_evaluation().get(&id).unwrap() - value).abs() < 1e-9);
+        }
+
+        #[cfg(feature = "rand")]
+        {
+            let (random_sweeps, random) = evaluate(SweepStrategy::RandomOrder(3));
+            assert!(random_sweeps <= jacobi_sweeps);
+            assert_eq!(evaluate(SweepStrategy::RandomOrder(3)).1.get_evaluation(), random.get_evaluation());
+            assert!((random.get_evaluation().get(&0).unwrap() - jacobi.get_evaluation().get(&0).unwrap()).abs() < 1e-9);
+
+            // The config's seed replaces the strategy's
+            let seeded = |strategy_seed: u64| -> Agent {
+                let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links.clone()));
+                let config = SolverConfig::new().with_epsilon(1e-10).with_eval_iters(10_000).with_policy_iters(1).with_sweep_strategy(SweepStrategy::RandomOrder(strategy_seed)).with_seed(5);
+                agent.solve(0.95, &config);
+                agent
+            };
+            assert_eq!(seeded(1).get_evaluation(), seeded(2).get_evaluation());
+        }
+    }
+
+    #[test]
+    fn relaxation_test() {
+        // States looping on themselves, where over-relaxed Gauss-Seidel
+        // sweeps make up for the slow contraction
+        let links: Vec<models::StateLink> = (0..20).flat_map(|id| vec![
+            models::StateLink(id, id, "Step".to_string(), 0.8, 1.),
+            models::StateLink(id, id + 1, "Step".to_string(), 0.2, 1.),
+        ]).collect();
+        let evaluate = |config: SolverConfig| -> (u32, Agent) {
+            let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links.clone()));
+            let mut sweeps: u32 = 0;
+            let config = config.with_epsilon(1e-10).with_eval_iters(10_000).with_policy_iters(1).with_sweep_strategy(SweepStrategy::GaussSeidelOrder((0..=20).rev().collect()));
+            agent.solve_with_progress(0.95, &config, &mut |_: &Progress| sweeps += 1);
+            (sweeps, agent)
+        };
+
+        let (plain_sweeps, plain) = evaluate(SolverConfig::new());
+        let (relaxed_sweeps, relaxed) = evaluate(SolverConfig::new().with_relaxation(1.9));
+        assert!(relaxed_sweeps*2 < plain_sweeps);
+        for id in 0..=20 {
+            assert!((relaxed.get_evaluation().get(&id).unwrap() - plain.get_evaluation().get(&id).unwrap()).abs() < 1e-8);
+        }
+    }
+
+    #[test]
+    fn resume_test() {
+        let new_agent = || -> Agent {Agent::init_random(GamblersProblem::new(50, 0.4).unwrap().build())};
+        let tight = SolverConfig::new().with_epsilon(1e-10).with_eval_iters(10_000);
+        let count_sweeps = |agent: &mut Agent, resume: bool| -> u32 {
+            let mut sweeps: u32 = 0;
+            let mut observer = |_: &Progress| sweeps += 1;
+            let context = SolveContext::new(&mut observer, None).with_config(&tight, agent.get_system_state().get_dense());
+            agent.improve_observed(1., tight.epsilon, tight.policy_iters, tight.eval_iters, &mut SolveContext {resume, ..context}).unwrap();
+            sweeps
+        };
+
+        // A rough solve, checkpointed and resumed in another agent with tighter settings
+        let mut rough = new_agent();
+        rough.solve(1., &SolverConfig::new().with_epsilon(1e-3).with_eval_iters(10_000));
+        let checkpoint = rough.checkpoint();
+
+        let mut resumed = new_agent();
+        resumed.restore(&checkpoint);
+        assert_eq!(resumed.get_evaluation(), rough.get_evaluation());
+        let resumed_sweeps = count_sweeps(&mut resumed, true);
+        let mut fresh = new_agent();
+        let fresh_sweeps = count_sweeps(&mut fresh, false);
+        assert!(resumed_sweeps < fresh_sweeps);
+        for (id, value) in fresh.get_evaluation() {
+            assert!((resumed.get_evaluation().get(id).unwrap() - value).abs() < 1e-8);
+        }
+
+        let mut resumed = new_agent();
+        resumed.restore(&checkpoint);
+        resumed.try_resume_solve(1., &tight).unwrap();
+        assert!(resumed.certify_optimality(1., 1e-8).is_ok());
+    }
+
+    #[test]
+    fn divergence_test() {
+        // Two states rewarding each other forever, next to a single step
+        let links = vec![
+            models::StateLink(0, 1, "Go".to_string(), 1., 1.),
+            models::StateLink(1, 0, "Back".to_string(), 1., 1.),
+            models::StateLink(2, 3, "End".to_string(), 1., 5.),
+        ];
+        let new_agent = || -> Agent {Agent::init_random(models::SystemState::create_and_build(links.clone()))};
+        let mut agent = new_agent();
+        let config = SolverConfig::new().with_eval_iters(1000);
+
+        let error = agent.try_evaluate_policy(1., &config).unwrap_err();
+        assert!(matches!(&error, Error::EvaluationDiverged {cause: DivergenceCause::Growth, states, ..} if *states == vec![0, 1]));
+        assert!(error.to_string().contains("in 2 states: 0, 1"));
+
+        // The values of the last sweep are kept, starting over
+        let mut agent = new_agent();
+        let error = agent.try_evaluate_policy(1., &config.clone().with_value_bound(3.)).unwrap_err();
+        assert!(matches!(error, Error::EvaluationDiverged {sweep: 1, cause: DivergenceCause::ValueBound, states} if states == vec![2]));
+
+        let mut agent = new_agent();
+        assert_eq!(agent.try_evaluate_policy(0.9, &config), Ok(()));
+
+        // Rewards propagating along a long path are not mistaken for divergence
+        let links: Vec<models::StateLink> = (0..50).map(|id| models::StateLink(id, id + 1, "Step".to_string(), 1., 1.)).collect();
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        assert_eq!(agent.try_evaluate_policy(1., &config), Ok(()));
+        assert!((agent.get_evaluation().get(&0).unwrap() - 50.).abs() < 1e-9);
+
+        // Staying is better than leaving, for ever
+        let links = vec![
+            models::StateLink(0, 0, "Stay".to_string(), 1., 1.),
+            models::StateLink(0, 1, "Leave".to_string(), 1., 0.),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        assert!(matches!(agent.try_solve(1., &config), Err(Error::EvaluationDiverged {states, ..}) if states == vec![0]));
+    }
+
+    #[test]
+    fn key_mismatch_test() {
+        let links = vec![
+            models::StateLink(0, 1, "Go".to_string(), 1., 1.),
+            models::StateLink(1, 2, "Go".to_string(), 1., 1.),
+        ];
+        let mut agent: Agent = Agent::init_random(models::SystemState::create_and_build(links));
+        assert_eq!(agent.check_policy_keys(), Ok(()));
+
+        // A policy of another model, missing state 0 and with states 7 and 9
+        let policy: HashMap<i64,HashMap<String,f64>> = [1, 7, 9].into_iter()
+            .map(|id| (id, HashMap::from([("Go".to_string(), 1.)])))
+            .collect();
+        agent.set_polity(policy);
+        assert_eq!(agent.calc_policy_rewards().get(&1), Some(&1.));
+        // The terminal state 2 is kept, without transitions
+        let transitions = agent.calc_policy_transitions();
+        assert_eq!(transitions.len(), 2);
+        assert!(transitions[&2].is_empty() && !transitions.contains_key(&0));
+
+        let strict = SolverConfig::new().with_key_mismatch(KeyMismatch::Strict);
+        let error = agent.try_evaluate_policy(0.9, &strict).unwrap_err();
+        assert_eq!(error, Error::PolicyKeyMismatch {unknown: vec![7, 9], missing: vec![0]});
+        assert!(error.to_string().contains("2 states not in the model (7, 9), 1 states without a policy (0)"));
+        assert!(agent.try_solve(0.9, &strict).is_err());
+
+        // Lenient, state 0 is evaluated without actions until improved
+        agent.try_evaluate_policy(0.9, &SolverConfig::new()).unwrap();
+        assert_eq!(agent.get_evaluation()[&0], 0.);
+        agent.try_solve(0.9, &SolverConfig::new()).unwrap();
+        assert!((agent.get_evaluation()[&0] - 1.9).abs() < 1e-6);
+        assert_eq!(agent.check_policy_keys(), Ok(()));
+
+        // Probability on actions the states don't have, the smallest state
+        // and action name reported
+        let mut policy = agent.get_policy().clone();
+        policy.insert(1, HashMap::from([("Go".to_string(), 0.5), ("Run".to_string(), 0.3), ("Jump".to_string(), 0.2), ("Fly".to_string(), 0.)]));
+        agent.set_polity(policy);
+        let error = Error::UnknownAction {state: 1, action: "Jump".to_string()};
+        assert_eq!(agent.validate_policy(), Err(error.clone()));
+        assert_eq!(agent.try_solve(0.9, &strict), Err(error.clone()));
+        assert_eq!(agent.try_resume_solve(0.9, &strict), Err(error));
+        assert!(agent.try_evaluate_policy(0.9, &SolverConfig::new()).is_ok());
+    }
+
+}
@@ -0,0 +1,194 @@
+// Checkpoints written in the middle of the evaluation sweeps of long solves,
+// so that a crash or a preemption loses at most an interval of work.
+//
+// A checkpoint holds what the sweep needs to carry on: the policy being
+// evaluated, the values the sweep started from, the values it has written so
+// far and the cursor, the number of positions of the sweep already updated.
+// Jacobi sweeps without a partition and Gauss-Seidel sweeps in a fixed order
+// are written mid-sweep. Sweeps by blocks and in random order are only
+// written between sweeps, with a cursor of zero.
+//
+// Files are binary, little-endian, with 64 bit floats whatever the precision
+// of the solve, written to a temporary file renamed over the previous
+// checkpoint so that a crash while writing keeps it.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+use crate::numeric::Real;
+
+const MAGIC: &[u8; 8] = b"CISWEEP\x01";
+
+// Where and how often the sweeps of a solve are checkpointed, see
+// SolverConfig::with_sweep_checkpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepCheckpointing {
+    pub path: PathBuf,
+    pub interval: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepCheckpoint {
+    // Written by a solve rather than an evaluation of the policy
+    pub improving: bool,
+    // Round of policy iteration, 0 for the evaluation of the initial policy
+    pub policy_iteration: u32,
+    // Sweep in progress, counted from 1
+    pub sweep: u32,
+    // Positions of the sweep already updated
+    pub cursor: usize,
+    // Probabilities of the policy by state position and action index
+    pub policy: Vec<Vec<f64>>,
+    // Values the sweep started from
+    pub values: Vec<f64>,
+    // Values written by the sweep so far
+    pub new_values: Vec<f64>,
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> io::Result<()> {
+    return writer.write_all(&value.to_le_bytes())
+}
+
+fn write_f64s<W: Write>(writer: &mut W, values: &[f64]) -> io::Result<()> {
+    write_u64(writer, values.len() as u64)?;
+    for value in values {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    return Ok(())
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    return Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_f64s<R: Read>(reader: &mut R) -> io::Result<Vec<f64>> {
+    let len = read_u64(reader)? as usize;
+    // Read in full before allocating, against corrupted lengths
+    let mut bytes: Vec<u8> = Vec::new();
+    reader.take(8*len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != 8*len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated checkpoint"))
+    }
+    return Ok(bytes.chunks(8).map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap())).collect())
+}
+
+impl SweepCheckpoint {
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        write_u64(&mut writer, self.improving as u64)?;
+        write_u64(&mut writer, self.policy_iteration as u64)?;
+        write_u64(&mut writer, self.sweep as u64)?;
+        write_u64(&mut writer, self.cursor as u64)?;
+        write_u64(&mut writer, self.policy.len() as u64)?;
+        for probs in &self.policy {
+            write_f64s(&mut writer, probs)?;
+        }
+        write_f64s(&mut writer, &self.values)?;
+        write_f64s(&mut writer, &self.new_values)?;
+        return writer.flush()
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<SweepCheckpoint> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a sweep checkpoint"))
+        }
+        let improving = read_u64(&mut reader)? != 0;
+        let policy_iteration = read_u64(&mut reader)? as u32;
+        let sweep = read_u64(&mut reader)? as u32;
+        let cursor = read_u64(&mut reader)? as usize;
+        let n_states = read_u64(&mut reader)?;
+        let policy = (0..n_states).map(|_| read_f64s(&mut reader)).collect::<io::Result<Vec<Vec<f64>>>>()?;
+        let values = read_f64s(&mut reader)?;
+        let new_values = read_f64s(&mut reader)?;
+        return Ok(SweepCheckpoint {improving, policy_iteration, sweep, cursor, policy, values, new_values})
+    }
+
+    // Written to path.tmp first and synced to disk, then renamed to path
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        self.write_to(&mut writer)?;
+        writer.into_inner().map_err(|error| error.into_error())?.sync_all()?;
+        return fs::rename(&temporary, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SweepCheckpoint> {
+        return SweepCheckpoint::read_from(BufReader::new(File::open(path)?))
+    }
+
+}
+
+// Writes the checkpoints of a solve, once the interval has passed since the
+// last one
+pub(crate) struct SweepCheckpointer<F: Real> {
+    pub(crate) checkpointing: SweepCheckpointing,
+    pub(crate) last: Instant,
+    pub(crate) improving: bool,
+    // Policy being evaluated
+    pub(crate) policy: Vec<Vec<F>>,
+}
+
+impl<F: Real> SweepCheckpointer<F> {
+
+    pub(crate) fn new(checkpointing: SweepCheckpointing) -> SweepCheckpointer<F> {
+        return SweepCheckpointer {checkpointing, last: Instant::now(), improving: false, policy: Vec::new()}
+    }
+
+    pub(crate) fn is_due(&self) -> bool {
+        return self.last.elapsed() >= self.checkpointing.interval
+    }
+
+    pub(crate) fn write(&mut self, policy_iteration: u32, sweep: u32, cursor: usize, values: &[F], new_values: &[F]) -> Result<(), Error> {
+        let as_f64 = |values: &[F]| -> Vec<f64> {values.iter().map(|value| value.as_f64()).collect()};
+        let checkpoint = SweepCheckpoint {
+            improving: self.improving,
+            policy_iteration,
+            sweep,
+            cursor,
+            policy: self.policy.iter().map(|probs| as_f64(probs)).collect(),
+            values: as_f64(values),
+            new_values: as_f64(new_values),
+        };
+        checkpoint.save(&self.checkpointing.path)
+            .map_err(|error| Error::CheckpointFailed {message: format!("{}: {}", self.checkpointing.path.display(), error)})?;
+        self.last = Instant::now();
+        return Ok(())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn sweep_checkpoint_format_test() {
+        let checkpoint = SweepCheckpoint {
+            improving: true,
+            policy_iteration: 3,
+            sweep: 7,
+            cursor: 1,
+            policy: vec![vec![0.5, 0.5], vec![]],
+            values: vec![1., 2.],
+            new_values: vec![1.5, 2.],
+        };
+        let mut buffer: Vec<u8> = Vec::new();
+        checkpoint.write_to(&mut buffer).unwrap();
+        assert_eq!(SweepCheckpoint::read_from(buffer.as_slice()).unwrap(), checkpoint);
+
+        assert!(SweepCheckpoint::read_from(&buffer[..buffer.len() - 4]).is_err());
+        assert!(SweepCheckpoint::read_from(&b"CISWEEP\x02"[..]).is_err());
+    }
+
+}
@@ -0,0 +1,205 @@
+// Transforms of the rewards of a model, for rewards in units that make
+// poorly conditioned solves, such as raw dollar amounts.
+//
+// Each transform returns a new model along with the affine map its rewards
+// went through, r -> scale*r + shift, which takes the values solved on the
+// new model back to the original units. Scaling doesn't change the optimal
+// policy. A shift adds shift per step taken, so it changes the policy of
+// models where some paths end, in states without actions, earlier than
+// others.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::collections::HashMap;
+use crate::linalg;
+use crate::models::{StateLink, SystemState};
+use crate::numeric::Real;
+use crate::Agent;
+
+const TOLERANCE: f64 = 1e-12;
+const MAX_SWEEPS: u32 = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RewardTransform<F: Real = f64> {
+    Scale(F),
+    Shift(F),
+    // Rewards below min or above max are set to the bound
+    Clip {min: F, max: F},
+    // To zero mean and unit standard deviation over the links of the model,
+    // only shifted if all the rewards are equal
+    Standardize,
+}
+
+// Affine map the rewards of a model went through. Clipping isn't affine:
+// after some reward was clipped, the map is the identity and is_exact is
+// false.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueTransform<F: Real = f64> {
+    scale: F,
+    shift: F,
+    exact: bool,
+}
+
+impl<F: Real> Default for ValueTransform<F> {
+    fn default() -> ValueTransform<F> {
+        return ValueTransform {scale: F::one(), shift: F::zero(), exact: true}
+    }
+}
+
+impl<F: Real> ValueTransform<F> {
+
+    pub fn get_scale(&self) -> F {
+        return self.scale
+    }
+
+    pub fn get_shift(&self) -> F {
+        return self.shift
+    }
+
+    // False if some reward was clipped, the original values being lost
+    pub fn is_exact(&self) -> bool {
+        return self.exact
+    }
+
+    // This map followed by next, for chained transforms
+    pub fn then(&self, next: &ValueTransform<F>) -> ValueTransform<F> {
+        return ValueTransform {
+            scale: next.scale*self.scale,
+            shift: next.scale*self.shift + next.shift,
+            exact: self.exact && next.exact,
+        }
+    }
+
+    pub fn apply_reward(&self, reward: F) -> F {
+        return self.scale*reward + self.shift
+    }
+
+    pub fn invert_reward(&self, reward: F) -> F {
+        return (reward - self.shift)/self.scale
+    }
+
+    // Values of the agent, solved on the transformed model, in the units of
+    // the original one. The shift was collected once per step: the expected
+    // discounted number of steps of each state under the agent's policy,
+    // until a state without actions, is taken off first. With gamma = 1 and
+    // a shift, every path of the policy must end.
+    pub fn invert_values(&self, agent: &Agent<F>, gamma: F) -> HashMap<i64,F> {
+        let values = agent.get_evaluation();
+        if self.shift == F::zero() {
+            return values.iter().map(|(id, value)| (*id, *value/self.scale)).collect()
+        }
+
+        let steps = linalg::from_dense_vector(&discounted_steps(agent, gamma), &agent.policy_chain().ids);
+        return values.iter()
+            .map(|(id, value)| (*id, (*value - self.shift*(*steps.get(id).unwrap_or(&F::zero())))/self.scale))
+            .collect()
+    }
+
+}
+
+// Expected discounted number of steps from each state of the policy chain,
+// in its id order
+fn discounted_steps<F: Real>(agent: &Agent<F>, gamma: F) -> Vec<F> {
+    let chain = agent.policy_chain();
+    let mut steps = vec![F::zero(); chain.ids.len()];
+    for _ in 0..MAX_SWEEPS {
+        let new_steps: Vec<F> = chain.successors.iter()
+            .map(|successors| match successors.is_empty() {
+                true => F::zero(),
+                false => F::one() + gamma*successors.iter().map(|(pos_next, prob)| *prob*steps[*pos_next]).sum::<F>(),
+            })
+            .collect();
+        let delta = new_steps.iter().zip(&steps).map(|(new, old)| (*new - *old).abs()).fold(F::zero(), |a, b| a.max(b));
+        steps = new_steps;
+        if delta < F::from_f64(TOLERANCE) {
+            break
+        }
+    }
+    return steps
+}
+
+impl<F: Real> SystemState<F> {
+
+    // Model with the rewards transformed, built from the same links, and the
+    // map of its rewards
+    pub fn transform_rewards(&self, transform: RewardTransform<F>) -> (SystemState<F>, ValueTransform<F>) {
+        let map = match transform {
+            RewardTransform::Scale(scale) => ValueTransform {scale, ..ValueTransform::default()},
+            RewardTransform::Shift(shift) => ValueTransform {shift, ..ValueTransform::default()},
+            RewardTransform::Clip {min, max} => ValueTransform {
+                exact: self.transitions().all(|transition| transition.reward >= min && transition.reward <= max),
+                ..ValueTransform::default()
+            },
+            RewardTransform::Standardize => {
+                let rewards: Vec<F> = self.transitions().map(|transition| transition.reward).collect();
+                let n = F::from_f64(rewards.len().max(1) as f64);
+                let mean = rewards.iter().cloned().sum::<F>()/n;
+                let std = (rewards.iter().map(|reward| (*reward - mean).powi(2)).sum::<F>()/n).sqrt();
+                let scale = if std > F::zero() {F::one()/std} else {F::one()};
+                ValueTransform {scale, shift: -mean*scale, exact: true}
+            },
+        };
+
+        let links: Vec<StateLink<F>> = self.get_specification().iter()
+            .map(|StateLink(prev_state, new_state, action, prob, reward)| {
+                let reward = match transform {
+                    RewardTransform::Clip {min, max} => reward.max(min).min(max),
+                    _ => map.apply_reward(*reward),
+                };
+                StateLink(*prev_state, *new_state, action.clone(), *prob, reward)
+            })
+            .collect();
+
+        return (SystemState::create_and_build(links), map)
+    }
+
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+
+    use super::*;
+
+    fn dollars() -> SystemState {
+        return SystemState::create_and_build(vec![
+            StateLink(0, 1, "Sell".to_string(), 1., 5000.),
+            StateLink(0, 0, "Hold".to_string(), 0.5, 1000.),
+            StateLink(0, 1, "Hold".to_string(), 0.5, 1000.),
+            StateLink(1, 2, "Close".to_string(), 1., -3000.),
+        ])
+    }
+
+    fn solved(model: SystemState, gamma: f64) -> Agent {
+        let mut agent = Agent::init_random(model);
+        agent.deterministic_policy_improvement(gamma, 1e-12, 100, 10_000);
+        return agent
+    }
+
+    #[test]
+    fn reward_transform_test() {
+        let original = solved(dollars(), 0.9);
+
+        for transform in [RewardTransform::Scale(1e-3), RewardTransform::Standardize] {
+            let (model, map) = dollars().transform_rewards(transform);
+            let agent = solved(model, 0.9);
+            assert_eq!(agent.get_best_action(0).unwrap().0, original.get_best_action(0).unwrap().0);
+            for (id, value) in map.invert_values(&agent, 0.9) {
+                assert!((value - original[id]).abs() < 1e-6, "{:?} {} {}", transform, id, value);
+            }
+        }
+
+        let (model, map) = dollars().transform_rewards(RewardTransform::Standardize);
+        let rewards: Vec<f64> = model.transitions().map(|transition| transition.reward).collect();
+        assert!(rewards.iter().sum::<f64>().abs() < 1e-9);
+        assert!((map.invert_reward(map.apply_reward(5000.)) - 5000.).abs() < 1e-9);
+
+        let (model, map) = dollars().transform_rewards(RewardTransform::Clip {min: -1000., max: 2000.});
+        assert!(!map.is_exact());
+        assert_eq!(model.get_specification()[0].4, 2000.);
+
+        let chained = ValueTransform {scale: 2., shift: 1., exact: true}.then(&ValueTransform {scale: 3., shift: -1., exact: true});
+        assert_eq!(chained.apply_reward(1.), 8.);
+    }
+
+}